@@ -0,0 +1,627 @@
+use log::{info, warn};
+use tiff_locator::controller::{Controller, MatchRequest, MatchScope};
+use tiff_locator::database::MatchStatus;
+use tiff_locator::match_engine::MatchEngineKind;
+use tiff_locator::matcher::{ScoreCombination, DEFAULT_PATH_COMPONENTS, MAX_PATH_COMPONENTS};
+
+use crate::server;
+
+pub const USAGE: &str = "Usage: tiff_locator [--headless] [--db <cache.db>] <command>
+
+Commands:
+  scan [--follow-links] [--metadata] [<dir>]
+                                     Scan a folder for TIFF files and register it;
+                                     without <dir> every registered folder is rescanned;
+                                     --follow-links descends into symlinked folders and
+                                     --metadata records page counts and image sizes
+  import <csv|xlsx>                  Import household IDs from a CSV or the first sheet
+                                     of an Excel workbook with an hh_id column
+  match [--gpu | --cosine] [--threshold <0-1>] [--paths [--folders <n>]]
+        [--case-sensitive] [--combine <max|weighted|agree:N>] [--dry-run]
+        [--ids <id,id,...> | --since <YYYY-MM-DD[ HH:MM]> | --new-files]
+        [--output <matches.db>]
+                                     Match imported IDs against scanned files;
+                                     --cosine scores on the CPU exactly as --gpu
+                                     does, for GPU-equivalent results anywhere;
+                                     --paths also matches the nearest 3 (or
+                                     --folders <n>) folder names and
+                                     --case-sensitive keeps HH001a and HH001A apart
+                                     (both CPU only); --combine weighted or agree:N
+                                     favour files matched by several of their
+                                     name, stem, extracted ID and folder
+                                     candidates over the best single score
+                                     (CPU only); --dry-run only prints the
+                                     match count without storing matches;
+                                     --ids or --since rematch only the listed IDs
+                                     or those first imported since that time;
+                                     --new-files matches every ID against only the
+                                     files scanned since the last full match;
+                                     --output stores the matches in that
+                                     database instead of the cache
+  export [--status <pending|confirmed|rejected>] <out.csv|out.json>
+                                     Export every stored match, or only those
+                                     reviewed as --status
+  serve [--bind <addr>]              Answer GET /search?id=<id>&threshold=<0-1> with
+                                     JSON matches; read-only, on 127.0.0.1:8080
+                                     unless --bind gives another address
+
+Without arguments the GUI is started.";
+
+const DEFAULT_DB_PATH: &str = "cache.db";
+const DEFAULT_THRESHOLD: f64 = 0.7;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Scan {
+        dir: Option<String>,
+        follow_links: bool,
+        metadata: bool,
+    },
+    Import {
+        path: String,
+    },
+    Match {
+        engine: MatchEngineKind,
+        scope: MatchScope,
+        threshold: f64,
+        paths: bool,
+        folders: usize,
+        case_sensitive: bool,
+        combine: ScoreCombination,
+        dry_run: bool,
+        new_files: bool,
+        output: Option<String>,
+    },
+    Export {
+        out: String,
+        status: Option<MatchStatus>,
+    },
+    Serve {
+        bind: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invocation {
+    pub db_path: String,
+    pub command: Command,
+}
+
+/// Parse command-line arguments (without the program name). Returns `Ok(None)`
+/// when no command was given, meaning the GUI should start.
+pub fn parse(args: &[String]) -> Result<Option<Invocation>, String> {
+    let mut headless = false;
+    let mut db_path = DEFAULT_DB_PATH.to_string();
+    let mut rest = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--headless" => headless = true,
+            "--db" => {
+                db_path = iter
+                    .next()
+                    .ok_or_else(|| "--db requires a path".to_string())?
+                    .clone();
+            }
+            _ => rest.push(arg.as_str()),
+        }
+    }
+
+    let Some((name, params)) = rest.split_first() else {
+        return if headless {
+            Err("--headless requires a command".to_string())
+        } else {
+            Ok(None)
+        };
+    };
+
+    let command = match *name {
+        "scan" => parse_scan(params)?,
+        "import" => Command::Import {
+            path: single_param(name, params, "<csv|xlsx>")?,
+        },
+        "export" => parse_export(params)?,
+        "match" => parse_match(params)?,
+        "serve" => parse_serve(params)?,
+        other => return Err(format!("Unknown command: {}", other)),
+    };
+
+    Ok(Some(Invocation { db_path, command }))
+}
+
+fn single_param(command: &str, params: &[&str], label: &str) -> Result<String, String> {
+    match params {
+        [value] => Ok(value.to_string()),
+        _ => Err(format!(
+            "{} expects exactly one argument: {}",
+            command, label
+        )),
+    }
+}
+
+fn parse_scan(params: &[&str]) -> Result<Command, String> {
+    let flags = ["--follow-links", "--metadata"];
+    let dirs: Vec<&str> = params
+        .iter()
+        .copied()
+        .filter(|param| !flags.contains(param))
+        .collect();
+    let dir = match dirs.as_slice() {
+        [] => None,
+        dirs => Some(single_param("scan", dirs, "<dir>")?),
+    };
+    Ok(Command::Scan {
+        dir,
+        follow_links: params.contains(&"--follow-links"),
+        metadata: params.contains(&"--metadata"),
+    })
+}
+
+fn parse_match(params: &[&str]) -> Result<Command, String> {
+    let mut engine = MatchEngineKind::Cpu;
+    let mut paths = false;
+    let mut folders = DEFAULT_PATH_COMPONENTS;
+    let mut case_sensitive = false;
+    let mut combine = ScoreCombination::default();
+    let mut dry_run = false;
+    let mut new_files = false;
+    let mut output = None;
+    let mut threshold = DEFAULT_THRESHOLD;
+    let mut scope = MatchScope::All;
+
+    let mut iter = params.iter();
+    while let Some(param) = iter.next() {
+        match *param {
+            "--gpu" | "--cosine" => {
+                if engine != MatchEngineKind::Cpu {
+                    return Err("--gpu and --cosine cannot be combined".to_string());
+                }
+                engine = if *param == "--gpu" {
+                    MatchEngineKind::Gpu
+                } else {
+                    MatchEngineKind::CpuCosine
+                };
+            }
+            "--paths" => paths = true,
+            "--folders" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| "--folders requires a value".to_string())?;
+                folders = value
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|n| (1..=MAX_PATH_COMPONENTS).contains(n))
+                    .ok_or_else(|| {
+                        format!(
+                            "Invalid folder count '{}': expected 1-{}",
+                            value, MAX_PATH_COMPONENTS
+                        )
+                    })?;
+            }
+            "--case-sensitive" => case_sensitive = true,
+            "--combine" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| "--combine requires a value".to_string())?;
+                combine = ScoreCombination::from_label(value).ok_or_else(|| {
+                    format!(
+                        "Invalid combination '{}': expected max, weighted or agree:N",
+                        value
+                    )
+                })?;
+            }
+            "--dry-run" => dry_run = true,
+            "--new-files" => new_files = true,
+            "--output" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| "--output requires a value".to_string())?;
+                output = Some(value.to_string());
+            }
+            "--threshold" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| "--threshold requires a value".to_string())?;
+                threshold = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|t| (0.0..=1.0).contains(t))
+                    .ok_or_else(|| format!("Invalid threshold '{}': expected 0.0-1.0", value))?;
+            }
+            "--ids" | "--since" => {
+                if scope != MatchScope::All {
+                    return Err("--ids and --since cannot be combined".to_string());
+                }
+                let value = iter
+                    .next()
+                    .ok_or_else(|| format!("{} requires a value", param))?;
+                scope = if *param == "--ids" {
+                    MatchScope::Ids(MatchScope::parse_ids(value))
+                } else {
+                    MatchScope::ImportedSince(MatchScope::parse_since(value)?)
+                };
+            }
+            other => return Err(format!("Unknown match option: {}", other)),
+        }
+    }
+    if folders != DEFAULT_PATH_COMPONENTS && !paths {
+        return Err("--folders only applies with --paths".to_string());
+    }
+    if new_files && scope != MatchScope::All {
+        return Err(
+            "--new-files matches every ID; it cannot be combined with --ids or --since".to_string(),
+        );
+    }
+    if new_files && output.is_some() {
+        return Err(
+            "--new-files adds to the cache's matches; it cannot be combined with --output"
+                .to_string(),
+        );
+    }
+
+    Ok(Command::Match {
+        engine,
+        scope,
+        threshold,
+        paths,
+        folders,
+        case_sensitive,
+        combine,
+        dry_run,
+        new_files,
+        output,
+    })
+}
+
+fn parse_export(params: &[&str]) -> Result<Command, String> {
+    let mut status = None;
+    let mut outs = Vec::new();
+
+    let mut iter = params.iter();
+    while let Some(param) = iter.next() {
+        match *param {
+            "--status" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| "--status requires a status".to_string())?;
+                status = Some(MatchStatus::from_label(value).ok_or_else(|| {
+                    format!(
+                        "Invalid --status '{}': expected pending, confirmed or rejected",
+                        value
+                    )
+                })?);
+            }
+            out => outs.push(out),
+        }
+    }
+    Ok(Command::Export {
+        out: single_param("export", &outs, "<out.csv|out.json>")?,
+        status,
+    })
+}
+
+fn parse_serve(params: &[&str]) -> Result<Command, String> {
+    match params {
+        [] => Ok(Command::Serve {
+            bind: server::DEFAULT_BIND.to_string(),
+        }),
+        ["--bind", bind] => Ok(Command::Serve {
+            bind: bind.to_string(),
+        }),
+        ["--bind"] => Err("--bind requires an address".to_string()),
+        _ => Err("serve expects at most: --bind <addr>".to_string()),
+    }
+}
+
+/// Run one headless command. Progress is logged to stderr; the final summary
+/// goes to stdout.
+pub fn run(invocation: &Invocation) -> Result<(), String> {
+    let mut controller = Controller::new(&invocation.db_path);
+
+    match &invocation.command {
+        Command::Scan {
+            dir,
+            follow_links,
+            metadata,
+        } => {
+            controller.set_follow_links(*follow_links);
+            controller.set_read_metadata(*metadata);
+            let summary = match dir {
+                Some(dir) => controller.scan(dir, None::<fn(usize, usize)>)?,
+                None => controller.scan_all_roots(None::<fn(usize, usize)>)?,
+            };
+            println!(
+                "Scanned {} TIFF files, removed {} missing ({} in cache)",
+                summary.discovered, summary.pruned, summary.total_files
+            );
+            for folder in &summary.empty_folders {
+                println!(
+                    "No TIFF files found under {}; check the folder, and that the scans end in .tif or .tiff",
+                    folder
+                );
+            }
+        }
+        Command::Import { path } => {
+            let summary = controller.import_ids(path, None, None::<fn(usize, u64, u64)>)?;
+            for error in &summary.report.errors {
+                warn!("{}", error);
+            }
+            println!(
+                "Imported {} new IDs ({} rows, {} skipped, {} total)",
+                summary.report.inserted,
+                summary.report.processed,
+                summary.report.skipped,
+                summary.total
+            );
+        }
+        Command::Match {
+            engine,
+            scope,
+            threshold,
+            paths,
+            folders,
+            case_sensitive,
+            combine,
+            dry_run,
+            new_files,
+            output,
+        } => {
+            controller.set_path_components(*folders);
+            controller.set_score_combination(*combine);
+            let request = MatchRequest {
+                engine: *engine,
+                scope: scope.clone(),
+                threshold: *threshold,
+                include_path_components: *paths,
+                case_sensitive: *case_sensitive,
+                dry_run: *dry_run,
+                new_files_only: *new_files,
+                output: output.clone(),
+            };
+            // No progress callback: the engines fall back to logging progress
+            let summary =
+                controller.run_match(&request, None, None, |notice| warn!("{}", notice))?;
+            if *dry_run {
+                println!(
+                    "Dry run using {:?}: {} candidate matches at threshold {} (nothing stored)",
+                    summary.engine, summary.match_count, threshold
+                );
+            } else if let Some(output) = output {
+                println!(
+                    "Matching complete using {:?}: {} candidate matches stored in {}",
+                    summary.engine, summary.match_count, output
+                );
+            } else {
+                println!(
+                    "Matching complete using {:?}: {} candidate matches stored",
+                    summary.engine, summary.match_count
+                );
+            }
+        }
+        Command::Export { out, status } => {
+            let rows = controller.export(out, *status, |rows, total| {
+                info!("Export progress: {} / {} rows", rows, total)
+            })?;
+            println!("Exported {} matches to {}", rows, out);
+        }
+        Command::Serve { bind } => server::serve(&controller, bind)?,
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn no_arguments_starts_the_gui() {
+        assert_eq!(parse(&[]).unwrap(), None);
+        assert!(parse(&args(&["--headless"])).is_err());
+    }
+
+    #[test]
+    fn match_options_are_parsed() {
+        let invocation = parse(&args(&[
+            "--db",
+            "/tmp/x.db",
+            "match",
+            "--gpu",
+            "--threshold",
+            "0.85",
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(invocation.db_path, "/tmp/x.db");
+        assert_eq!(
+            invocation.command,
+            Command::Match {
+                engine: MatchEngineKind::Gpu,
+                scope: MatchScope::All,
+                threshold: 0.85,
+                paths: false,
+                folders: 3,
+                case_sensitive: false,
+                combine: ScoreCombination::Max,
+                dry_run: false,
+                new_files: false,
+                output: None,
+            }
+        );
+
+        let invocation = parse(&args(&["match", "--new-files"])).unwrap().unwrap();
+        assert!(matches!(
+            invocation.command,
+            Command::Match {
+                new_files: true,
+                ..
+            }
+        ));
+        assert!(parse(&args(&["match", "--new-files", "--ids", "HH001"])).is_err());
+
+        let invocation = parse(&args(&["match", "--paths", "--folders", "5"]))
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            invocation.command,
+            Command::Match {
+                paths: true,
+                folders: 5,
+                ..
+            }
+        ));
+        assert!(parse(&args(&["match", "--folders", "5"])).is_err());
+        assert!(parse(&args(&["match", "--paths", "--folders", "0"])).is_err());
+
+        let invocation = parse(&args(&["match", "--combine", "agree:2"]))
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            invocation.command,
+            Command::Match {
+                combine: ScoreCombination::Agreement(2),
+                ..
+            }
+        ));
+        assert!(parse(&args(&["match", "--combine", "mean"])).is_err());
+
+        let invocation = parse(&args(&["match", "--cosine"])).unwrap().unwrap();
+        assert!(matches!(
+            invocation.command,
+            Command::Match {
+                engine: MatchEngineKind::CpuCosine,
+                ..
+            }
+        ));
+        assert!(parse(&args(&["match", "--gpu", "--cosine"])).is_err());
+
+        let invocation = parse(&args(&["match", "--output", "run1.db"]))
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            invocation.command,
+            Command::Match {
+                output: Some(ref path),
+                ..
+            } if path == "run1.db"
+        ));
+        assert!(parse(&args(&["match", "--output"])).is_err());
+        assert!(parse(&args(&["match", "--new-files", "--output", "run1.db"])).is_err());
+    }
+
+    #[test]
+    fn export_takes_an_optional_status() {
+        let export = |values: &[&str]| parse(&args(values)).map(|i| i.unwrap().command);
+        assert_eq!(
+            export(&["export", "out.csv"]),
+            Ok(Command::Export {
+                out: "out.csv".to_string(),
+                status: None,
+            })
+        );
+        assert_eq!(
+            export(&["export", "--status", "Confirmed", "out.json"]),
+            Ok(Command::Export {
+                out: "out.json".to_string(),
+                status: Some(MatchStatus::Confirmed),
+            })
+        );
+        assert_eq!(
+            export(&["export", "out.csv", "--status", "rejected"]),
+            Ok(Command::Export {
+                out: "out.csv".to_string(),
+                status: Some(MatchStatus::Rejected),
+            })
+        );
+        assert!(export(&["export", "--status", "maybe", "out.csv"]).is_err());
+        assert!(export(&["export", "--status"]).is_err());
+        assert!(export(&["export", "--status", "rejected"]).is_err());
+    }
+
+    #[test]
+    fn serve_binds_to_localhost_by_default() {
+        let invocation = parse(&args(&["serve"])).unwrap().unwrap();
+        assert_eq!(
+            invocation.command,
+            Command::Serve {
+                bind: "127.0.0.1:8080".to_string()
+            }
+        );
+        let invocation = parse(&args(&["serve", "--bind", "0.0.0.0:9000"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            invocation.command,
+            Command::Serve {
+                bind: "0.0.0.0:9000".to_string()
+            }
+        );
+        assert!(parse(&args(&["serve", "--bind"])).is_err());
+        assert!(parse(&args(&["serve", "8080"])).is_err());
+    }
+
+    #[test]
+    fn match_scope_options_are_parsed() {
+        let invocation = parse(&args(&["match", "--ids", "HH001,HH002"]))
+            .unwrap()
+            .unwrap();
+        let Command::Match { scope, .. } = invocation.command else {
+            panic!("expected a match command");
+        };
+        assert_eq!(
+            scope,
+            MatchScope::Ids(vec!["HH001".to_string(), "HH002".to_string()])
+        );
+
+        let invocation = parse(&args(&["match", "--since", "2024-05-01"]))
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            invocation.command,
+            Command::Match {
+                scope: MatchScope::ImportedSince(_),
+                ..
+            }
+        ));
+
+        assert!(parse(&args(&["match", "--since", "yesterday"])).is_err());
+        assert!(parse(&args(&["match", "--ids", "HH001", "--since", "2024-05-01"])).is_err());
+    }
+
+    #[test]
+    fn scan_without_a_folder_rescans_registered_roots() {
+        let invocation = parse(&args(&["scan"])).unwrap().unwrap();
+        assert_eq!(
+            invocation.command,
+            Command::Scan {
+                dir: None,
+                follow_links: false,
+                metadata: false
+            }
+        );
+
+        let invocation = parse(&args(&["scan", "/archive", "--follow-links", "--metadata"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            invocation.command,
+            Command::Scan {
+                dir: Some("/archive".to_string()),
+                follow_links: true,
+                metadata: true
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_input_is_rejected() {
+        assert!(parse(&args(&["match", "--threshold", "1.5"])).is_err());
+        assert!(parse(&args(&["scan", "a", "b"])).is_err());
+        assert!(parse(&args(&["frobnicate"])).is_err());
+    }
+}
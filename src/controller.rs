@@ -0,0 +1,2364 @@
+use crate::database::{
+    find_stale_files, resolve_file_path, Database, DuplicateName, FileRecord, MatchRecord,
+    MatchRun, MatchStatus, ResultOrder, ScanRoot, SearchHistoryEntry, SearchResult, StoredMatch,
+};
+use crate::match_engine::{self, MatchCountCallback, MatchEngineKind, MatchProgressCallback};
+use crate::matcher::{
+    parse_matched_via, CandidateKind, MatchExplanation, Matcher, ScoreCombination,
+    DEFAULT_PATH_COMPONENTS, MAX_PATH_COMPONENTS,
+};
+use crate::reference_loader::{ReferenceLoadReport, ReferenceLoader};
+use crate::scanner::{Scanner, SIZE_BUCKET_LIMITS};
+use crate::searcher::{SearchProgressCallback, SearchQuery, Searcher, SharedSearchIndex};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use log::{info, warn};
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use rusqlite::OptionalExtension;
+use rust_xlsxwriter::{Format, Workbook, XlsxError};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// Export progress is reported every this many rows.
+pub const EXPORT_PROGRESS_STEP: usize = 10_000;
+
+/// Opens of the cache retry while another thread's commit holds it locked,
+/// backing off from 50 ms and giving up after about 6 s in all.
+const OPEN_ATTEMPTS: u32 = 8;
+const OPEN_BACKOFF: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone)]
+pub struct ScanSummary {
+    pub discovered: usize,
+    /// Files removed because they disappeared from a scanned root.
+    pub pruned: usize,
+    pub added: usize,
+    pub changed: usize,
+    /// Files an incremental scan left untouched.
+    pub skipped: usize,
+    pub total_files: usize,
+    /// Files found per lowercase extension, over every scanned root.
+    pub by_extension: HashMap<String, usize>,
+    pub total_bytes: u64,
+    /// Files found per size bucket (see `scanner::SIZE_BUCKET_LIMITS`).
+    pub by_size: [usize; SIZE_BUCKET_LIMITS.len() + 1],
+    /// Scanned folders without a single TIFF file (see `ScanReport::empty`).
+    pub empty_folders: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportSummary {
+    pub report: ReferenceLoadReport,
+    pub total: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchSummary {
+    pub match_count: usize,
+    pub engine: MatchEngineKind,
+}
+
+/// Which imported reference IDs a match run covers. Stored matches of IDs
+/// outside the scope are left as they are.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MatchScope {
+    All,
+    /// These IDs; any that were never imported are skipped.
+    Ids(Vec<String>),
+    /// IDs first imported at or after this time.
+    ImportedSince(DateTime<Utc>),
+}
+
+impl MatchScope {
+    /// Split a typed list of IDs on commas, semicolons and whitespace.
+    pub fn parse_ids(text: &str) -> Vec<String> {
+        text.split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+            .filter(|id| !id.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Parse `2024-05-01`, `2024-05-01 14:30` or `2024-05-01 14:30:15` in
+    /// local time, or an RFC 3339 timestamp.
+    pub fn parse_since(text: &str) -> Result<DateTime<Utc>, String> {
+        let text = text.trim();
+        if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+            return Ok(time.with_timezone(&Utc));
+        }
+        let naive = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S")
+            .or_else(|_| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M"))
+            .or_else(|_| {
+                NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                    .map(|date| date.and_hms_opt(0, 0, 0).unwrap_or_default())
+            })
+            .map_err(|_| {
+                format!(
+                    "Invalid date '{}': expected YYYY-MM-DD or YYYY-MM-DD HH:MM",
+                    text
+                )
+            })?;
+        Local
+            .from_local_datetime(&naive)
+            .earliest()
+            .map(|time| time.with_timezone(&Utc))
+            .ok_or_else(|| format!("'{}' does not exist in the local time zone", text))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchRequest {
+    pub engine: MatchEngineKind,
+    pub scope: MatchScope,
+    pub threshold: f64,
+    pub include_path_components: bool,
+    pub case_sensitive: bool,
+    /// Only count the matches the run would produce; stored matches are left
+    /// untouched.
+    pub dry_run: bool,
+    /// Score every reference ID against just the files scanned in since the
+    /// last full run, keeping the stored matches of older files. Needs
+    /// `MatchScope::All` and an earlier stored run.
+    pub new_files_only: bool,
+    /// Store the matches in this SQLite file (created when missing) instead
+    /// of the cache, e.g. one file per run for auditing. The cache's files
+    /// and vectors are still matched against, and its matches are untouched.
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchRequest {
+    pub hh_id: String,
+    pub min_similarity: f64,
+    pub max_similarity: f64,
+    pub include_path_components: bool,
+    /// Keep `HH001a` and `HH001A` apart instead of lowercasing both sides.
+    pub case_sensitive: bool,
+    /// When set, stored matches are returned one page of this size at a time;
+    /// fetch the rest with `Controller::stored_matches_page`.
+    pub page_size: Option<usize>,
+    /// How results with the same score are ordered.
+    pub order: ResultOrder,
+}
+
+impl SearchRequest {
+    /// The household ID searched for, without the file name terms `hh_id`
+    /// may carry (see [`SearchQuery`]).
+    pub fn household_id(&self) -> String {
+        SearchQuery::parse(&self.hh_id).id
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchOutcome {
+    pub results: Vec<SearchResult>,
+    /// Every match for the request; more than `results.len()` when only the
+    /// first page of stored matches was read.
+    pub total: usize,
+    pub from_cache: bool,
+    /// Set when fresh results were found but could not be cached.
+    pub cache_error: Option<String>,
+}
+
+/// Stored matches from one run above which the cache is optimized afterwards,
+/// so the planner's statistics reflect the new rows.
+const OPTIMIZE_AFTER_MATCHES: usize = 100_000;
+
+/// Share of sampled files that must be missing before a rescan is suggested.
+const MOSTLY_MISSING: f64 = 0.5;
+
+/// Missing paths kept in a `CacheCheck` to show the user.
+const MISSING_EXAMPLES: usize = 5;
+
+/// Result of `Controller::validate_cache`: how many of a random sample of
+/// cached paths no longer exist.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheCheck {
+    pub sampled: usize,
+    pub missing: usize,
+    /// Sampled paths written with the other platform's separators, e.g. a
+    /// cache built on Windows and opened on Linux.
+    pub foreign_separators: usize,
+    /// A few of the missing paths, resolved as "Open Location" would.
+    pub missing_examples: Vec<String>,
+}
+
+impl CacheCheck {
+    pub fn missing_fraction(&self) -> f64 {
+        if self.sampled == 0 {
+            0.0
+        } else {
+            self.missing as f64 / self.sampled as f64
+        }
+    }
+
+    /// Whether most sampled files are gone, which usually means the archive
+    /// was moved or re-mounted at another path.
+    pub fn suggests_rescan(&self) -> bool {
+        self.sampled > 0 && self.missing_fraction() > MOSTLY_MISSING
+    }
+}
+
+/// Environment and cache facts for bug reports, gathered by
+/// `Controller::diagnostics`.
+#[derive(Debug, Clone)]
+pub struct Diagnostics {
+    pub version: &'static str,
+    /// Operating system and CPU architecture, e.g. "linux/x86_64".
+    pub platform: String,
+    pub cache_path: String,
+    /// `None` when the cache file cannot be read (e.g. an in-memory cache).
+    pub cache_bytes: Option<u64>,
+    pub file_count: usize,
+    pub reference_id_count: usize,
+    pub match_count: usize,
+    /// The GPU adapter, or why the GPU matcher is unavailable.
+    pub gpu: Result<String, String>,
+}
+
+impl std::fmt::Display for Diagnostics {
+    /// One "Label: value" line per fact, for pasting into an issue.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "TiffLocator version: {}", self.version)?;
+        writeln!(f, "Platform: {}", self.platform)?;
+        writeln!(f, "Cache: {}", self.cache_path)?;
+        match self.cache_bytes {
+            Some(bytes) => writeln!(f, "Cache size: {} bytes", bytes)?,
+            None => writeln!(f, "Cache size: unknown")?,
+        }
+        writeln!(f, "Cached files: {}", self.file_count)?;
+        writeln!(f, "Reference IDs: {}", self.reference_id_count)?;
+        writeln!(f, "Stored matches: {}", self.match_count)?;
+        match &self.gpu {
+            Ok(adapter) => write!(f, "GPU: {}", adapter),
+            Err(reason) => write!(f, "GPU: unavailable ({})", reason),
+        }
+    }
+}
+
+/// What the Summary sheet of an Excel export reports besides the row count.
+#[derive(Debug, Clone)]
+pub struct ExportStats {
+    /// Household ID that was searched.
+    pub hh_id: String,
+    /// Lowest similarity among the exported results' band.
+    pub threshold: f64,
+    pub exported_at: DateTime<Local>,
+}
+
+/// Runs the scan, import, match, search and export steps against the cache
+/// database at `cache_path`. Every call opens its own connection, so a clone
+/// can be moved into a worker thread. Used by the GUI and the headless CLI.
+#[derive(Clone)]
+pub struct Controller {
+    cache_path: String,
+    search_index: SharedSearchIndex,
+    // Worker threads for the parallel scan, match and search steps; `None`
+    // uses rayon's global pool, one thread per core
+    thread_limit: usize,
+    pool: Option<Arc<ThreadPool>>,
+    // Whether scans descend into symlinked folders (see `Scanner::set_follow_links`)
+    follow_links: bool,
+    // Whether scans read page counts and sizes (see `Scanner::set_read_metadata`)
+    read_metadata: bool,
+    // Byte bounds on scanned files (see `Scanner::set_size_range`)
+    size_range: (Option<u64>, Option<u64>),
+    // Whether scans only pick up files modified since each folder's last scan
+    since_last_scan: bool,
+    // Enclosing folders tried by folder-name matching and searches
+    path_components: usize,
+    // How match runs combine each file's candidate scores
+    score_combination: ScoreCombination,
+    // Whether fresh search results carry a `MatchExplanation`
+    explain_scores: bool,
+}
+
+impl Controller {
+    pub fn new(cache_path: &str) -> Self {
+        Self::with_search_index(cache_path, SharedSearchIndex::default())
+    }
+
+    /// Create a controller whose searches share `search_index` with others.
+    /// `TIFF_THREADS` limits its worker threads (see `set_thread_limit`).
+    pub fn with_search_index(cache_path: &str, search_index: SharedSearchIndex) -> Self {
+        let mut controller = Controller {
+            cache_path: cache_path.to_string(),
+            search_index,
+            thread_limit: 0,
+            pool: None,
+            follow_links: false,
+            read_metadata: false,
+            size_range: (None, None),
+            since_last_scan: false,
+            path_components: DEFAULT_PATH_COMPONENTS,
+            score_combination: ScoreCombination::Max,
+            explain_scores: false,
+        };
+        let env_limit = std::env::var("TIFF_THREADS")
+            .ok()
+            .and_then(|value| value.trim().parse::<usize>().ok());
+        if let Some(limit) = env_limit {
+            if let Err(e) = controller.set_thread_limit(limit) {
+                warn!("Ignoring TIFF_THREADS={}: {}", limit, e);
+            }
+        }
+        controller
+    }
+
+    /// Run the parallel steps on at most `threads` worker threads so other
+    /// jobs on a shared machine keep some cores; 0 uses every core. Runs
+    /// already started keep the limit they began with.
+    pub fn set_thread_limit(&mut self, threads: usize) -> Result<(), String> {
+        self.pool = if threads == 0 {
+            None
+        } else {
+            let pool = ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .thread_name(|index| format!("tiff-worker-{}", index))
+                .build()
+                .map_err(|e| format!("Failed to start {} worker threads: {}", threads, e))?;
+            Some(Arc::new(pool))
+        };
+        self.thread_limit = threads;
+        Ok(())
+    }
+
+    /// Follow symlinks in later scans; loops are detected and skipped.
+    pub fn set_follow_links(&mut self, follow: bool) {
+        self.follow_links = follow;
+    }
+
+    /// Record page counts and sizes of scanned TIFFs in later scans.
+    pub fn set_read_metadata(&mut self, read: bool) {
+        self.read_metadata = read;
+    }
+
+    /// Only scan files within these byte bounds in later scans.
+    pub fn set_size_range(&mut self, min: Option<u64>, max: Option<u64>) {
+        self.size_range = (min, max);
+    }
+
+    /// Only pick up files modified since each folder's previous scan began
+    /// in later scans (see `Scanner::set_modified_after`). Folders never
+    /// scanned before are scanned in full.
+    pub fn set_since_last_scan(&mut self, since_last_scan: bool) {
+        self.since_last_scan = since_last_scan;
+    }
+
+    /// How many enclosing folders, nearest first, later folder-name matches
+    /// and searches try (see `Matcher::set_path_components`).
+    pub fn set_path_components(&mut self, depth: usize) {
+        self.path_components = depth.clamp(1, MAX_PATH_COMPONENTS);
+    }
+
+    /// How later match runs combine each file's candidate scores (see
+    /// `Matcher::set_score_combination`). Anything but `Max` is CPU-only.
+    pub fn set_score_combination(&mut self, combination: ScoreCombination) {
+        self.score_combination = combination;
+    }
+
+    /// Have freshly scored search results explain their score (see
+    /// `Searcher::set_explain`). Off by default.
+    pub fn set_explain_scores(&mut self, explain: bool) {
+        self.explain_scores = explain;
+    }
+
+    /// Why `result` matched the search `request`: its own explanation when
+    /// it has one, otherwise the matcher's with the current settings at the
+    /// result's threshold (see `Matcher::explain_match`). `None` for vector
+    /// scores, which have no single candidate, or when the file no longer
+    /// matches under these settings.
+    pub fn explain_match(
+        &self,
+        request: &SearchRequest,
+        result: &SearchResult,
+    ) -> Option<MatchExplanation> {
+        if result.explanation.is_some() {
+            return result.explanation.clone();
+        }
+        let kind = result
+            .matched_via
+            .as_deref()
+            .and_then(parse_matched_via)
+            .map(|(kind, _)| kind);
+        if kind == Some(CandidateKind::Vector) {
+            return None;
+        }
+        let mut matcher = Matcher::new();
+        matcher.set_case_sensitive(request.case_sensitive);
+        matcher.set_include_path_components(
+            request.include_path_components || kind == Some(CandidateKind::Folder),
+        );
+        matcher.set_path_components(self.path_components);
+        matcher.set_score_combination(self.score_combination);
+        let record = FileRecord {
+            id: 0,
+            file_path: result.file_path.clone(),
+            file_name: result.file_name.clone(),
+            scan_date: String::new(),
+            display_name: result.display_name.clone(),
+            scan_root: result.scan_root.clone(),
+        };
+        let threshold = result.threshold.unwrap_or(request.min_similarity);
+        matcher.explain_match(&request.household_id(), &record, threshold)
+    }
+
+    /// Path of the SQLite cache this controller opens.
+    pub fn cache_path(&self) -> &str {
+        &self.cache_path
+    }
+
+    /// The configured limit, 0 meaning every core.
+    pub fn thread_limit(&self) -> usize {
+        self.thread_limit
+    }
+
+    /// Run `op` on this controller's worker threads, so the rayon iterators
+    /// inside it respect the thread limit.
+    fn install<R, OP>(&self, op: OP) -> R
+    where
+        OP: FnOnce() -> R + Send,
+        R: Send,
+    {
+        match &self.pool {
+            Some(pool) => pool.install(op),
+            None => op(),
+        }
+    }
+
+    fn open_db(&self, activity: &str) -> Result<Database, String> {
+        Database::open_with_retry(&self.cache_path, OPEN_ATTEMPTS, OPEN_BACKOFF)
+            .map_err(|e| format!("Database access error while {}: {}", activity, e))
+    }
+
+    /// Scan `folder` for TIFF files, persist them and warm the search index.
+    /// The folder is registered as a scan root. The optional callback receives
+    /// (entries processed, total entries).
+    pub fn scan<F>(&self, folder: &str, progress: Option<F>) -> Result<ScanSummary, String>
+    where
+        F: FnMut(usize, usize) + Send + 'static,
+    {
+        self.scan_folders(&[folder.to_string()], false, progress)
+    }
+
+    /// Rescan every registered root into the shared files table. Progress
+    /// restarts from zero for each root.
+    pub fn scan_all_roots<F>(&self, progress: Option<F>) -> Result<ScanSummary, String>
+    where
+        F: FnMut(usize, usize) + Send + 'static,
+    {
+        self.scan_folders(&self.registered_roots()?, false, progress)
+    }
+
+    /// Rescan every registered root, storing only new and modified files.
+    pub fn rescan_changed<F>(&self, progress: Option<F>) -> Result<ScanSummary, String>
+    where
+        F: FnMut(usize, usize) + Send + 'static,
+    {
+        self.scan_folders(&self.registered_roots()?, true, progress)
+    }
+
+    fn registered_roots(&self) -> Result<Vec<String>, String> {
+        let roots: Vec<String> = self
+            .scan_roots()?
+            .into_iter()
+            .map(|root| root.path)
+            .collect();
+        if roots.is_empty() {
+            return Err("No scan folders registered. Add a folder first.".to_string());
+        }
+        Ok(roots)
+    }
+
+    fn scan_folders<F>(
+        &self,
+        folders: &[String],
+        incremental: bool,
+        progress: Option<F>,
+    ) -> Result<ScanSummary, String>
+    where
+        F: FnMut(usize, usize) + Send + 'static,
+    {
+        self.install(|| self.scan_folders_on_workers(folders, incremental, progress))
+    }
+
+    fn scan_folders_on_workers<F>(
+        &self,
+        folders: &[String],
+        incremental: bool,
+        progress: Option<F>,
+    ) -> Result<ScanSummary, String>
+    where
+        F: FnMut(usize, usize) + Send + 'static,
+    {
+        let mut db = self.open_db("scanning")?;
+        let progress = progress.map(|callback| Arc::new(Mutex::new(callback)));
+        info!(
+            "{} started for {} folder(s): {}",
+            if incremental {
+                "Incremental scan"
+            } else {
+                "Scan"
+            },
+            folders.len(),
+            folders.join(", ")
+        );
+
+        let mut summary = ScanSummary {
+            discovered: 0,
+            pruned: 0,
+            added: 0,
+            changed: 0,
+            skipped: 0,
+            total_files: 0,
+            by_extension: HashMap::new(),
+            total_bytes: 0,
+            by_size: [0; SIZE_BUCKET_LIMITS.len() + 1],
+            empty_folders: Vec::new(),
+        };
+        let last_scan_started: HashMap<String, String> = if self.since_last_scan {
+            db.get_scan_roots()
+                .map_err(|e| format!("Failed to read scan folders: {}", e))?
+                .into_iter()
+                .filter_map(|root| Some((root.path, root.last_scan_started?)))
+                .collect()
+        } else {
+            HashMap::new()
+        };
+        for folder in folders {
+            let mut scanner = Scanner::new();
+            if self.since_last_scan {
+                let cutoff = Scanner::scan_root(folder).ok().and_then(|root| {
+                    last_scan_started
+                        .get(root.to_string_lossy().as_ref())
+                        .cloned()
+                });
+                match cutoff.and_then(|started| DateTime::parse_from_rfc3339(&started).ok()) {
+                    Some(started) => {
+                        info!("Scanning {} for files modified since {}", folder, started);
+                        scanner.set_modified_after(Some(SystemTime::from(started)));
+                    }
+                    None => info!("{} has no previous scan; scanning it in full", folder),
+                }
+            }
+            scanner.set_follow_links(self.follow_links);
+            scanner.set_read_metadata(self.read_metadata);
+            scanner.set_size_range(self.size_range.0, self.size_range.1);
+            if let Some(callback) = &progress {
+                let callback = Arc::clone(callback);
+                scanner.set_progress_callback(move |processed, total| {
+                    if let Ok(mut cb) = callback.lock() {
+                        cb(processed, total);
+                    }
+                });
+            }
+            let report = if incremental {
+                scanner.scan_incremental(folder, &mut db)?
+            } else {
+                scanner.scan_and_store(folder, &mut db)?
+            };
+            summary.discovered += report.discovered;
+            summary.pruned += report.pruned;
+            summary.added += report.added;
+            summary.changed += report.changed;
+            summary.skipped += report.skipped;
+            for (extension, count) in report.by_extension {
+                *summary.by_extension.entry(extension).or_insert(0) += count;
+            }
+            summary.total_bytes += report.total_bytes;
+            for (total, count) in summary.by_size.iter_mut().zip(report.by_size) {
+                *total += count;
+            }
+            if report.empty {
+                warn!("No TIFF files found under {}", folder);
+                summary.empty_folders.push(folder.clone());
+            }
+        }
+
+        summary.total_files = db
+            .get_file_count()
+            .map_err(|e| format!("Failed to refresh cached file count: {}", e))?;
+
+        // Pre-build the search index so the first search after a scan is fast
+        let searcher = Searcher::with_shared_index(Arc::clone(&self.search_index));
+        if let Err(e) = searcher.refresh_index(&db) {
+            warn!("Failed to warm search index: {}", e);
+        }
+
+        info!(
+            "Scan finished: {} discovered, {} added, {} changed, {} pruned, {} files cached",
+            summary.discovered, summary.added, summary.changed, summary.pruned, summary.total_files
+        );
+        Ok(summary)
+    }
+
+    pub fn scan_roots(&self) -> Result<Vec<ScanRoot>, String> {
+        self.open_db("listing scan folders")?
+            .get_scan_roots()
+            .map_err(|e| format!("Failed to read scan folders: {}", e))
+    }
+
+    /// Register `folder` for scanning without scanning it yet. Returns the
+    /// absolute path it is stored under.
+    pub fn add_scan_root(&self, folder: &str) -> Result<String, String> {
+        let root = Scanner::scan_root(folder)?.to_string_lossy().to_string();
+        self.open_db("adding a scan folder")?
+            .add_scan_root(&root)
+            .map_err(|e| format!("Failed to add scan folder: {}", e))?;
+        Ok(root)
+    }
+
+    /// Unregister a root and drop its cached files and their matches. Returns
+    /// the number of files removed.
+    pub fn remove_scan_root(&self, root: &str) -> Result<usize, String> {
+        let removed = self
+            .open_db("removing a scan folder")?
+            .remove_scan_root(root)
+            .map_err(|e| format!("Failed to remove scan folder: {}", e))?;
+        Ok(removed)
+    }
+
+    /// Blocked file paths, sorted.
+    pub fn blocklist(&self) -> Result<Vec<String>, String> {
+        self.open_db("reading the blocklist")?
+            .get_blocklist()
+            .map_err(|e| format!("Failed to read the blocklist: {}", e))
+    }
+
+    /// Keep these paths out of searches and matching from now on; their
+    /// stored matches are hidden until unblocked. Returns how many were newly blocked.
+    pub fn block_files(&self, file_paths: &[String]) -> Result<usize, String> {
+        self.open_db("updating the blocklist")?
+            .block_files(file_paths)
+            .map_err(|e| format!("Failed to block files: {}", e))
+    }
+
+    /// Record an analyst's review of the stored match of `hh_id` and the file
+    /// at `file_path`. Fails when that match isn't stored, e.g. for results of
+    /// a search that bypassed the match cache.
+    pub fn set_match_status(
+        &self,
+        hh_id: &str,
+        file_path: &str,
+        status: MatchStatus,
+    ) -> Result<(), String> {
+        let db = self.open_db("reviewing matches")?;
+        let file_id = db
+            .get_file_id(file_path)
+            .optional()
+            .map_err(|e| format!("Failed to look up {}: {}", file_path, e))?;
+        let updated = match file_id {
+            Some(file_id) => db
+                .set_match_status(hh_id, file_id, status)
+                .map_err(|e| format!("Failed to update match status: {}", e))?,
+            None => false,
+        };
+        if !updated {
+            return Err(format!(
+                "{} is not a stored match of {}; run Match IDs first",
+                file_path, hh_id
+            ));
+        }
+        info!("Marked {} for {} as {}", file_path, hh_id, status.label());
+        Ok(())
+    }
+
+    /// Returns false when the path wasn't blocked.
+    pub fn unblock_file(&self, file_path: &str) -> Result<bool, String> {
+        self.open_db("updating the blocklist")?
+            .unblock_file(file_path)
+            .map_err(|e| format!("Failed to unblock {}: {}", file_path, e))
+    }
+
+    /// Write the blocklist to `path`, one file path per line. Returns how
+    /// many paths were written.
+    pub fn export_blocklist(&self, path: &str) -> Result<usize, String> {
+        let paths = self.blocklist()?;
+        let mut writer = BufWriter::new(
+            File::create(path).map_err(|e| format!("Failed to create file: {}", e))?,
+        );
+        for file_path in &paths {
+            writeln!(writer, "{}", file_path)
+                .map_err(|e| format!("Failed to write blocklist: {}", e))?;
+        }
+        writer
+            .flush()
+            .map_err(|e| format!("Failed to write blocklist: {}", e))?;
+        Ok(paths.len())
+    }
+
+    /// Block every path listed in `path` (as written by `export_blocklist`).
+    /// Returns how many were newly blocked.
+    pub fn import_blocklist(&self, path: &str) -> Result<usize, String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        self.block_files(&parse_blocklist(&text))
+    }
+
+    /// File names cached at more than one path.
+    pub fn duplicate_names(&self) -> Result<Vec<DuplicateName>, String> {
+        self.open_db("listing duplicate names")?
+            .find_duplicate_names()
+            .map_err(|e| format!("Failed to find duplicate names: {}", e))
+    }
+
+    /// Refresh the cache's query statistics; see `Database::optimize`.
+    pub fn optimize(&self) -> Result<(), String> {
+        self.open_db("optimizing")?
+            .optimize()
+            .map_err(|e| format!("Failed to optimize the cache: {}", e))
+    }
+
+    /// Gather cache counts, the GPU adapter and version information. Probing
+    /// the GPU can take a moment, so call this off the UI thread.
+    pub fn diagnostics(&self) -> Result<Diagnostics, String> {
+        let db = self.open_db("reading diagnostics")?;
+        let read = |what: &str, count: rusqlite::Result<usize>| {
+            count.map_err(|e| format!("Failed to count {}: {}", what, e))
+        };
+        Ok(Diagnostics {
+            version: env!("CARGO_PKG_VERSION"),
+            platform: format!("{}/{}", std::env::consts::OS, std::env::consts::ARCH),
+            cache_path: self.cache_path.clone(),
+            cache_bytes: std::fs::metadata(&self.cache_path)
+                .ok()
+                .map(|meta| meta.len()),
+            file_count: read("cached files", db.get_file_count())?,
+            reference_id_count: read("reference IDs", db.get_reference_id_count())?,
+            match_count: read("stored matches", db.get_match_count())?,
+            gpu: match_engine::gpu_adapter(),
+        })
+    }
+
+    /// Check whether up to `sample` random cached files still exist on disk,
+    /// to catch an archive that moved since it was scanned.
+    pub fn validate_cache(&self, sample: usize) -> Result<CacheCheck, String> {
+        let paths = self
+            .open_db("validating the cache")?
+            .sample_file_paths(sample)
+            .map_err(|e| format!("Failed to sample cached files: {}", e))?;
+        info!("Validating {} sampled cached path(s)", paths.len());
+
+        // Network shares answer slowly, so the paths are checked in parallel
+        let missing: Vec<String> = self.install(|| {
+            paths
+                .par_iter()
+                .map(|(path, root)| resolve_file_path(path, root.as_deref()))
+                .filter(|path| !path.exists())
+                .map(|path| path.to_string_lossy().to_string())
+                .collect()
+        });
+        let check = CacheCheck {
+            sampled: paths.len(),
+            missing: missing.len(),
+            foreign_separators: paths
+                .iter()
+                .filter(|(path, _)| has_foreign_separators(path))
+                .count(),
+            missing_examples: missing.into_iter().take(MISSING_EXAMPLES).collect(),
+        };
+        if check.suggests_rescan() {
+            warn!(
+                "{} of {} sampled cached files are missing; the archive may have moved",
+                check.missing, check.sampled
+            );
+        }
+        Ok(check)
+    }
+
+    /// Number of cached files `remap_paths` would change for `old_prefix`.
+    pub fn remap_preview(&self, old_prefix: &str) -> Result<usize, String> {
+        let old_prefix = remap_prefix(old_prefix)?;
+        self.open_db("previewing a path remap")?
+            .count_paths_with_prefix(old_prefix)
+            .map_err(|e| format!("Failed to count paths under '{}': {}", old_prefix, e))
+    }
+
+    /// Rewrite cached paths under `old_prefix` to start with `new_prefix`, for
+    /// an archive that moved (see `Database::remap_paths`). Trailing
+    /// separators on either prefix are ignored. Returns the files changed.
+    pub fn remap_paths(&self, old_prefix: &str, new_prefix: &str) -> Result<usize, String> {
+        let old_prefix = remap_prefix(old_prefix)?;
+        let new_prefix = remap_prefix(new_prefix)?;
+        if old_prefix == new_prefix {
+            return Err("The old and new prefixes are the same".to_string());
+        }
+        let changed = self
+            .open_db("remapping paths")?
+            .remap_paths(old_prefix, new_prefix)
+            .map_err(|e| {
+                format!(
+                    "Failed to remap '{}' to '{}' (does a file already exist at a new path?): {}",
+                    old_prefix, new_prefix, e
+                )
+            })?;
+        info!(
+            "Remapped {} cached path(s) from {} to {}",
+            changed, old_prefix, new_prefix
+        );
+        Ok(changed)
+    }
+
+    /// Check every cached path and return the files that no longer exist, as
+    /// `(id, file_path)`. The callback receives (paths checked, total).
+    pub fn verify_cache<F>(&self, progress: F) -> Result<Vec<(i64, String)>, String>
+    where
+        F: Fn(usize, usize) + Sync + Send,
+    {
+        let db = self.open_db("verifying the cache")?;
+        let files = db
+            .file_locations()
+            .map_err(|e| format!("Failed to read cached paths: {}", e))?;
+        let stale = self.install(|| find_stale_files(files, progress));
+        info!("Cache verified: {} cached file(s) missing", stale.len());
+        Ok(stale)
+    }
+
+    /// Drop cached files (e.g. those `verify_cache` found missing) and their
+    /// matches. Returns the number of files removed.
+    pub fn prune_files(&self, files: &[(i64, String)]) -> Result<usize, String> {
+        let mut db = self.open_db("pruning missing files")?;
+        let mut session = db
+            .start_file_import()
+            .map_err(|e| format!("Failed to start pruning: {}", e))?;
+        let mut removed = 0;
+        for (_, path) in files {
+            removed += session
+                .remove_file(path)
+                .map_err(|e| format!("Failed to prune {}: {}", path, e))?;
+        }
+        session
+            .commit()
+            .map_err(|e| format!("Failed to commit pruning: {}", e))?;
+        db.cleanup_orphan_vectors()
+            .map_err(|e| format!("Failed to drop vectors of pruned files: {}", e))?;
+        info!("Pruned {} missing cached file(s)", removed);
+        Ok(removed)
+    }
+
+    /// Stored matches whose household ID or file name contains `query` (see
+    /// `Database::search_matches`).
+    pub fn search_stored_matches(
+        &self,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<StoredMatch>, String> {
+        self.open_db("searching stored matches")?
+            .search_matches(query, limit)
+            .map_err(|e| format!("Failed to search stored matches: {}", e))
+    }
+
+    /// Stored matches of every household ID starting with `prefix`, grouped by
+    /// ID (see `Database::matches_for_id_prefix`). Only reads what match runs
+    /// stored.
+    pub fn search_id_prefix(
+        &self,
+        prefix: &str,
+        min_similarity: f64,
+        limit: usize,
+    ) -> Result<Vec<StoredMatch>, String> {
+        self.open_db("searching stored matches")?
+            .matches_for_id_prefix(prefix, min_similarity, limit)
+            .map_err(|e| format!("Failed to search stored matches: {}", e))
+    }
+
+    /// The last match run that stored its results, if any.
+    pub fn last_match_run(&self) -> Result<Option<MatchRun>, String> {
+        self.open_db("reading the last match run")?
+            .last_match_run()
+            .map_err(|e| format!("Failed to read the last match run: {}", e))
+    }
+
+    /// Add a Quick Search query to the persisted history (see
+    /// `Database::record_search`).
+    pub fn record_search(&self, query: &str, result_count: usize) -> Result<(), String> {
+        self.open_db("recording the search")?
+            .record_search(query, result_count)
+            .map_err(|e| format!("Failed to record the search: {}", e))
+    }
+
+    /// Up to `limit` past searches, most recent first.
+    pub fn search_history(&self, limit: usize) -> Result<Vec<SearchHistoryEntry>, String> {
+        self.open_db("reading the search history")?
+            .recent_searches(limit)
+            .map_err(|e| format!("Failed to read the search history: {}", e))
+    }
+
+    /// Stored matches per similarity bucket as `(low, high, count)` (see
+    /// `Database::score_histogram`).
+    pub fn score_histogram(&self, buckets: usize) -> Result<Vec<(f64, f64, usize)>, String> {
+        self.open_db("reading match scores")?
+            .score_histogram(buckets)
+            .map_err(|e| format!("Failed to read match scores: {}", e))
+    }
+
+    /// Import household IDs from `path`, a CSV or (by its `.xlsx` extension) an
+    /// Excel workbook. A cancelled import is returned as `Ok` with
+    /// `report.cancelled` set and nothing persisted.
+    pub fn import_ids<F>(
+        &self,
+        path: &str,
+        cancel_flag: Option<Arc<AtomicBool>>,
+        progress: Option<F>,
+    ) -> Result<ImportSummary, String>
+    where
+        F: FnMut(usize, u64, u64),
+    {
+        let mut db = self.open_db("loading IDs")?;
+        let mut loader = ReferenceLoader::new();
+        if let Some(flag) = cancel_flag {
+            loader.set_cancel_flag(flag);
+        }
+
+        let report = loader.load_with_progress(path, &mut db, progress)?;
+        let total = db
+            .get_reference_id_count()
+            .map_err(|e| format!("Failed to refresh reference ID count: {}", e))?;
+
+        Ok(ImportSummary { report, total })
+    }
+
+    /// Match every imported reference ID against the cached files and store the
+    /// results. A GPU request falls back to the CPU matcher when no GPU is usable;
+    /// that fallback and any mid-run notice from the engine go to `on_notice`.
+    /// Folder-name matching (`include_path_components`) and case-sensitive
+    /// matching are CPU-only, as are weighted and agreement scoring (see
+    /// `set_score_combination`): they switch the GPU and cosine engines to the
+    /// fuzzy CPU matcher. A `dry_run` only reports the match count.
+    /// `found` receives the running match count while the engine works.
+    pub fn run_match<N>(
+        &self,
+        request: &MatchRequest,
+        progress: Option<MatchProgressCallback>,
+        found: Option<MatchCountCallback>,
+        mut on_notice: N,
+    ) -> Result<MatchSummary, String>
+    where
+        N: FnMut(String),
+    {
+        let mut db = self.open_db("matching")?;
+        let hh_ids = match &request.scope {
+            MatchScope::All => db.get_all_reference_ids(),
+            MatchScope::Ids(ids) => db.known_reference_ids(ids),
+            MatchScope::ImportedSince(since) => db.reference_ids_since(*since),
+        }
+        .map_err(|e| format!("Failed to read reference IDs: {}", e))?;
+        if let MatchScope::Ids(ids) = &request.scope {
+            let listed: HashSet<&String> = ids.iter().collect();
+            let unknown = listed.len() - hh_ids.len();
+            if unknown > 0 {
+                on_notice(format!(
+                    "{} of the listed IDs are not imported reference IDs and were skipped",
+                    unknown
+                ));
+            }
+        }
+        if hh_ids.is_empty() && request.scope != MatchScope::All {
+            return Err("No imported reference IDs match the selection".to_string());
+        }
+        // Read before matching, so files scanned in meanwhile count as new next time
+        let generation = db
+            .get_scan_generation()
+            .map_err(|e| format!("Failed to read scan generation: {}", e))?;
+        let new_files_since = if request.new_files_only {
+            if request.scope != MatchScope::All {
+                return Err(
+                    "Matching only new files covers every reference ID; clear the ID selection"
+                        .to_string(),
+                );
+            }
+            let since = db
+                .matched_generation()
+                .map_err(|e| format!("Failed to read the last match run: {}", e))?
+                .ok_or_else(|| {
+                    "No earlier match run to add new files to; run a full match first".to_string()
+                })?;
+            Some(since)
+        } else {
+            None
+        };
+        let output = match &request.output {
+            Some(_) if request.new_files_only => {
+                return Err(
+                    "Matching only new files adds to the cache's stored matches; \
+                     it can't store them in a separate database"
+                        .to_string(),
+                )
+            }
+            Some(path) if !request.dry_run => Some(
+                Database::new(path)
+                    .map_err(|e| format!("Failed to open output database {}: {}", path, e))?,
+            ),
+            _ => None,
+        };
+
+        let mut preferred = request.engine;
+        if request.include_path_components && preferred != MatchEngineKind::Cpu {
+            on_notice("Folder name matching is CPU-only. Using CPU matcher.".to_string());
+            preferred = MatchEngineKind::Cpu;
+        }
+        if request.case_sensitive && preferred != MatchEngineKind::Cpu {
+            on_notice("Case-sensitive matching is CPU-only. Using CPU matcher.".to_string());
+            preferred = MatchEngineKind::Cpu;
+        }
+        if self.score_combination != ScoreCombination::Max && preferred != MatchEngineKind::Cpu {
+            on_notice(
+                "Weighted and agreement scoring are CPU-only. Using CPU matcher.".to_string(),
+            );
+            preferred = MatchEngineKind::Cpu;
+        }
+
+        let mut engine = match match_engine::create_engine(preferred) {
+            Ok(engine) => engine,
+            Err(err) if preferred == MatchEngineKind::Gpu => {
+                warn!("GPU matcher unavailable, falling back to CPU: {}", err);
+                on_notice(format!(
+                    "GPU matcher unavailable ({}). Falling back to CPU matcher.",
+                    err
+                ));
+                match_engine::create_engine(MatchEngineKind::Cpu).map_err(|cpu_err| {
+                    format!(
+                        "Failed to initialize CPU matcher after GPU fallback: {}",
+                        cpu_err
+                    )
+                })?
+            }
+            Err(err) => return Err(err),
+        };
+        engine.set_include_path_components(request.include_path_components);
+        engine.set_path_components(self.path_components);
+        engine.set_case_sensitive(request.case_sensitive);
+        engine.set_score_combination(self.score_combination);
+        engine.set_dry_run(request.dry_run);
+        engine.set_new_files_since(new_files_since);
+        let output_path = output.is_some().then(|| request.output.clone()).flatten();
+        engine.set_output(output);
+        if let Some(callback) = found {
+            engine.set_match_count_handle(callback);
+        }
+        info!(
+            "Matching {} reference ID(s) with the {} matcher at threshold {:.2}{}",
+            hh_ids.len(),
+            engine.kind().label(),
+            request.threshold,
+            if request.dry_run { " (dry run)" } else { "" }
+        );
+
+        let result =
+            self.install(|| engine.match_and_store(&hh_ids, &mut db, request.threshold, progress));
+        if let Some(message) = engine.take_notice() {
+            on_notice(message);
+        }
+        let match_count = result?;
+        info!("Matching finished: {} match(es)", match_count);
+        // The run is recorded with its matches; the cache's are unchanged
+        // when they went to an output database
+        if let Some(path) = &output_path {
+            engine.set_output(None);
+            info!("Stored {} match(es) in {}", match_count, path);
+            let recorded = Database::new(path).and_then(|output| {
+                output.record_match_run(
+                    engine.kind().label(),
+                    request.threshold,
+                    hh_ids.len(),
+                    match_count,
+                )
+            });
+            if let Err(e) = recorded {
+                warn!("Failed to record the match run in {}: {}", path, e);
+            }
+            return Ok(MatchSummary {
+                match_count,
+                engine: engine.kind(),
+            });
+        }
+        if !request.dry_run {
+            if let Err(e) = db.record_match_run(
+                engine.kind().label(),
+                request.threshold,
+                hh_ids.len(),
+                match_count,
+            ) {
+                warn!("Failed to record the match run: {}", e);
+            }
+        }
+        // Only a run over every ID leaves matches a new-files run can build on
+        if !request.dry_run && request.scope == MatchScope::All {
+            if let Err(e) = db.set_matched_generation(&generation) {
+                warn!("Failed to record the matched scan generation: {}", e);
+            }
+        }
+        if !request.dry_run && match_count >= OPTIMIZE_AFTER_MATCHES {
+            match db.optimize() {
+                Ok(()) => info!("Optimized the cache after storing {} matches", match_count),
+                Err(e) => warn!("Failed to optimize the cache: {}", e),
+            }
+        }
+
+        Ok(MatchSummary {
+            match_count,
+            engine: engine.kind(),
+        })
+    }
+
+    /// Search one household ID, serving stored matches when they cover the
+    /// request and otherwise scoring every cached file (and caching the result).
+    pub fn search(
+        &self,
+        request: &SearchRequest,
+        progress: Option<SearchProgressCallback>,
+    ) -> Result<SearchOutcome, String> {
+        self.search_and_cache(request, progress, true)
+    }
+
+    /// Like `search`, but freshly scored results are not written back, so
+    /// callers that must not change the cache (e.g. the HTTP server) can use it.
+    pub fn search_read_only(&self, request: &SearchRequest) -> Result<SearchOutcome, String> {
+        self.search_and_cache(request, None, false)
+    }
+
+    fn search_and_cache(
+        &self,
+        request: &SearchRequest,
+        progress: Option<SearchProgressCallback>,
+        cache: bool,
+    ) -> Result<SearchOutcome, String> {
+        let mut db = self.open_db("searching")?;
+        let mut searcher = Searcher::with_shared_index(Arc::clone(&self.search_index));
+        searcher.set_include_path_components(request.include_path_components);
+        searcher.set_path_components(self.path_components);
+        searcher.set_case_sensitive(request.case_sensitive);
+        searcher.set_result_order(request.order);
+        searcher.set_explain(self.explain_scores);
+
+        // Cached matches only cover case-insensitive file name matching of a
+        // bare ID, so folder, case-sensitive and term searches always recompute
+        let cacheable = !request.include_path_components
+            && !request.case_sensitive
+            && !SearchQuery::parse(&request.hh_id).has_terms();
+        if cacheable {
+            let total = db
+                .count_matches_for_id(
+                    &request.hh_id,
+                    request.min_similarity,
+                    Some(request.max_similarity),
+                )
+                .map_err(|e| format!("Failed to read cached matches: {}", e))?;
+            if total > 0 {
+                let cached = match request.page_size {
+                    Some(page_size) => db.get_matches_for_id_paged(
+                        &request.hh_id,
+                        request.min_similarity,
+                        Some(request.max_similarity),
+                        request.order,
+                        page_size,
+                        0,
+                    ),
+                    None => db.search_single_id(
+                        &request.hh_id,
+                        request.min_similarity,
+                        Some(request.max_similarity),
+                        request.order,
+                    ),
+                }
+                .map_err(|e| format!("Failed to read cached matches: {}", e))?;
+                return Ok(SearchOutcome {
+                    results: cached,
+                    total,
+                    from_cache: true,
+                    cache_error: None,
+                });
+            }
+        }
+
+        // Moved in as `&mut` because a connection can't be shared between threads
+        let scoring_db = &mut db;
+        let scorer = &searcher;
+        let results = self.install(move || {
+            scorer.search_single_id(
+                &request.hh_id,
+                scoring_db,
+                request.min_similarity,
+                Some(request.max_similarity),
+                progress,
+            )
+        })?;
+
+        // A capped band is only a slice of the matches, and folder or
+        // case-sensitive matches differ, so caching them would skew later
+        // filename searches
+        let cache_error = if cache && request.max_similarity >= 1.0 && cacheable {
+            searcher
+                .store_results(&request.hh_id, request.min_similarity, &results, &mut db)
+                .err()
+        } else {
+            None
+        };
+
+        Ok(SearchOutcome {
+            total: results.len(),
+            results,
+            from_cache: false,
+            cache_error,
+        })
+    }
+
+    /// Page `page` (zero-based) of the stored matches for `hh_id` inside the
+    /// similarity band, together with the number of matches in the band.
+    pub fn stored_matches_page(
+        &self,
+        hh_id: &str,
+        min_similarity: f64,
+        max_similarity: f64,
+        order: ResultOrder,
+        page: usize,
+        page_size: usize,
+    ) -> Result<(Vec<SearchResult>, usize), String> {
+        let db = self.open_db("reading matches")?;
+        let total = db
+            .count_matches_for_id(hh_id, min_similarity, Some(max_similarity))
+            .map_err(|e| format!("Failed to count cached matches: {}", e))?;
+        let results = db
+            .get_matches_for_id_paged(
+                hh_id,
+                min_similarity,
+                Some(max_similarity),
+                order,
+                page_size,
+                page * page_size,
+            )
+            .map_err(|e| format!("Failed to read cached matches: {}", e))?;
+        Ok((results, total))
+    }
+
+    /// Export every stored match to `path`, or only those with `status`, as
+    /// JSON when it ends in `.json` and CSV otherwise. `progress` receives
+    /// (rows written, total rows) every `EXPORT_PROGRESS_STEP` rows.
+    pub fn export<F>(
+        &self,
+        path: &str,
+        status: Option<MatchStatus>,
+        mut progress: F,
+    ) -> Result<usize, String>
+    where
+        F: FnMut(usize, usize),
+    {
+        let db = self.open_db("exporting")?;
+        let total = match status {
+            Some(status) => db.count_matches_with_status(status),
+            None => db.get_match_count(),
+        }
+        .map_err(|e| format!("Failed to count matches: {}", e))?;
+        progress(0, total);
+
+        let file = File::create(path).map_err(|e| format!("Failed to create file: {}", e))?;
+        let report = |rows: usize| {
+            if rows.is_multiple_of(EXPORT_PROGRESS_STEP) {
+                progress(rows, total);
+            }
+        };
+
+        if path.to_lowercase().ends_with(".json") {
+            write_matches_json(&db, BufWriter::new(file), status, report)
+        } else {
+            write_matches_csv(&db, file, status, report)
+        }
+    }
+}
+
+/// Write `results` to `path` as one pretty-printed JSON array.
+pub fn export_results_json(path: &str, results: &[SearchResult]) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("Failed to create file: {}", e))?;
+    let mut out = BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut out, results)
+        .map_err(|e| format!("Failed to write JSON: {}", e))?;
+    out.write_all(b"\n")
+        .and_then(|_| out.flush())
+        .map_err(|e| format!("Failed to finish JSON: {}", e))
+}
+
+/// Write `results` to `path` as JSON Lines, one object per result.
+pub fn export_results_jsonl(path: &str, results: &[SearchResult]) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("Failed to create file: {}", e))?;
+    let mut out = BufWriter::new(file);
+    for result in results {
+        serde_json::to_writer(&mut out, result)
+            .map_err(|e| e.to_string())
+            .and_then(|_| out.write_all(b"\n").map_err(|e| e.to_string()))
+            .map_err(|e| format!("Failed to write record: {}", e))?;
+    }
+    out.flush()
+        .map_err(|e| format!("Failed to finish JSON Lines: {}", e))
+}
+
+/// Write `results` to `path` as an Excel workbook: a "Matches" sheet with the
+/// file name, path, a `file://` link and the similarity formatted as a
+/// percentage, and a "Summary" sheet built from `stats`. Slow for large
+/// result sets, so callers should run it off the UI thread.
+pub fn export_results_xlsx(
+    path: &str,
+    results: &[SearchResult],
+    stats: &ExportStats,
+) -> Result<(), String> {
+    let xlsx_error = |e: XlsxError| format!("Failed to write workbook: {}", e);
+    let bold = Format::new().set_bold();
+    let percent = Format::new().set_num_format("0.0%");
+    let mut workbook = Workbook::new();
+
+    let matches = workbook.add_worksheet();
+    matches.set_name("Matches").map_err(xlsx_error)?;
+    for (col, header) in ["File Name", "Path", "Link", "Similarity"]
+        .iter()
+        .enumerate()
+    {
+        matches
+            .write_string_with_format(0, col as u16, *header, &bold)
+            .map_err(xlsx_error)?;
+    }
+    for (col, width) in [30.0, 70.0, 8.0, 12.0].iter().enumerate() {
+        matches
+            .set_column_width(col as u16, *width)
+            .map_err(xlsx_error)?;
+    }
+    matches.set_freeze_panes(1, 0).map_err(xlsx_error)?;
+    for (index, result) in results.iter().enumerate() {
+        let row = index as u32 + 1;
+        matches
+            .write_string(row, 0, &result.file_name)
+            .and_then(|sheet| sheet.write_string(row, 1, &result.file_path))
+            .and_then(|sheet| sheet.write_url_with_text(row, 2, file_url(result).as_str(), "Open"))
+            .and_then(|sheet| {
+                sheet.write_number_with_format(row, 3, result.similarity_score, &percent)
+            })
+            .map_err(xlsx_error)?;
+    }
+
+    let summary = workbook.add_worksheet();
+    summary.set_name("Summary").map_err(xlsx_error)?;
+    summary.set_column_width(0, 18.0).map_err(xlsx_error)?;
+    summary.set_column_width(1, 22.0).map_err(xlsx_error)?;
+    summary
+        .write_string_with_format(0, 0, "Household ID", &bold)
+        .and_then(|sheet| sheet.write_string(0, 1, &stats.hh_id))
+        .and_then(|sheet| sheet.write_string_with_format(1, 0, "Total matches", &bold))
+        .and_then(|sheet| sheet.write_number(1, 1, results.len() as f64))
+        .and_then(|sheet| sheet.write_string_with_format(2, 0, "Threshold", &bold))
+        .and_then(|sheet| sheet.write_number_with_format(2, 1, stats.threshold, &percent))
+        .and_then(|sheet| sheet.write_string_with_format(3, 0, "Exported", &bold))
+        .and_then(|sheet| {
+            sheet.write_string(
+                3,
+                1,
+                stats.exported_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+            )
+        })
+        .map_err(xlsx_error)?;
+
+    workbook.save(path).map_err(xlsx_error)
+}
+
+// `file://` link to a result. Paths older caches stored relative to their scan
+// root are resolved first. Excel wants `file:///C:\dir\file.tif` on Windows;
+// for `file:////dir/file.tif` the writer stores the absolute `/dir/file.tif`,
+// which spreadsheet apps resolve to `file:///dir/file.tif`. Spaces and the
+// like are percent-escaped by the writer.
+fn file_url(result: &SearchResult) -> String {
+    let resolved = resolve_file_path(&result.file_path, result.scan_root.as_deref());
+    format!("file:///{}", resolved.display())
+}
+
+/// A remap prefix without surrounding whitespace or trailing separators.
+fn remap_prefix(prefix: &str) -> Result<&str, String> {
+    let trimmed = prefix.trim().trim_end_matches(['/', '\\']);
+    if trimmed.is_empty() {
+        return Err(format!(
+            "'{}' is not a folder prefix; enter the folder the archive was moved from or to",
+            prefix.trim()
+        ));
+    }
+    Ok(trimmed)
+}
+
+/// Whether `path` was written on the other kind of platform: backslashes or a
+/// drive letter off Windows, a rooted `/` path on Windows.
+fn has_foreign_separators(path: &str) -> bool {
+    if cfg!(windows) {
+        path.starts_with('/') && !path.starts_with("//")
+    } else {
+        let bytes = path.as_bytes();
+        path.contains('\\')
+            || (bytes.len() >= 2 && bytes[1] == b':' && bytes[0].is_ascii_alphabetic())
+    }
+}
+
+fn write_matches_csv<F>(
+    db: &Database,
+    file: File,
+    status: Option<MatchStatus>,
+    report: F,
+) -> Result<usize, String>
+where
+    F: FnMut(usize),
+{
+    let mut writer = csv::Writer::from_writer(file);
+    db.export_matches_csv(&mut writer, 0.0, status, report)
+}
+
+fn write_matches_json<F>(
+    db: &Database,
+    mut out: BufWriter<File>,
+    status: Option<MatchStatus>,
+    mut report: F,
+) -> Result<usize, String>
+where
+    F: FnMut(usize),
+{
+    out.write_all(b"[")
+        .map_err(|e| format!("Failed to write JSON: {}", e))?;
+
+    let mut written = 0usize;
+    let mut write_error = None;
+    db.get_all_matches(0.0, status, |record: MatchRecord| {
+        let separator: &[u8] = if written == 0 { b"\n" } else { b",\n" };
+        let result = out
+            .write_all(separator)
+            .map_err(|e| e.to_string())
+            .and_then(|_| serde_json::to_writer(&mut out, &record).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            write_error = Some(format!("Failed to write record: {}", e));
+            return false;
+        }
+        written += 1;
+        report(written);
+        true
+    })
+    .map_err(|e| format!("Failed to read matches: {}", e))?;
+
+    if let Some(e) = write_error {
+        return Err(e);
+    }
+
+    out.write_all(b"\n]\n")
+        .and_then(|_| out.flush())
+        .map_err(|e| format!("Failed to finish JSON: {}", e))?;
+
+    Ok(written)
+}
+
+/// Paths of a blocklist file: one per line, ignoring blank lines and lines
+/// starting with `#`.
+fn parse_blocklist(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::format_matched_via;
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn fixture(path: &str) -> String {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join(path)
+            .to_string_lossy()
+            .to_string()
+    }
+
+    fn temp_db(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "tiff_locator_controller_{}_{}.db",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn search_request(hh_id: &str) -> SearchRequest {
+        SearchRequest {
+            hh_id: hh_id.to_string(),
+            min_similarity: 0.4,
+            max_similarity: 1.0,
+            include_path_components: false,
+            case_sensitive: false,
+            page_size: None,
+            order: ResultOrder::FileName,
+        }
+    }
+
+    fn search(controller: &Controller, hh_id: &str) -> SearchOutcome {
+        controller
+            .search(&search_request(hh_id), None)
+            .expect("search")
+    }
+
+    #[test]
+    fn blocklist_survives_export_and_import() {
+        let db_path = temp_db("blocklist");
+        let controller = Controller::new(db_path.to_str().unwrap());
+        controller
+            .scan(&fixture("test_data/tiff_files"), None::<fn(usize, usize)>)
+            .expect("scan");
+        let first = search(&controller, "ABC123").results;
+        let blocked = first[0].file_path.clone();
+
+        assert_eq!(
+            controller
+                .block_files(std::slice::from_ref(&blocked))
+                .unwrap(),
+            1
+        );
+        let after = search(&controller, "ABC123").results;
+        assert!(after.iter().all(|result| result.file_path != blocked));
+
+        let list_path = db_path.with_extension("txt");
+        let list = list_path.to_str().unwrap();
+        assert_eq!(controller.export_blocklist(list).unwrap(), 1);
+        let other_path = temp_db("blocklist_import");
+        let other = Controller::new(other_path.to_str().unwrap());
+        assert_eq!(other.import_blocklist(list).unwrap(), 1);
+        assert_eq!(other.blocklist().unwrap(), std::slice::from_ref(&blocked));
+
+        assert!(controller.unblock_file(&blocked).unwrap());
+        assert!(controller.blocklist().unwrap().is_empty());
+        let restored = search(&controller, "ABC123");
+        assert!(restored.from_cache);
+        assert!(restored
+            .results
+            .iter()
+            .any(|result| result.file_path == blocked));
+        assert_eq!(
+            parse_blocklist("# blocked\n/a.tif\r\n\n  /b.tif  \n"),
+            ["/a.tif", "/b.tif"]
+        );
+
+        for path in [&db_path, &list_path, &other_path] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    #[test]
+    fn full_cycle_on_fixtures() {
+        let db_path = temp_db("cycle");
+        let controller = Controller::new(db_path.to_str().unwrap());
+
+        let scan = controller
+            .scan(&fixture("test_data/tiff_files"), None::<fn(usize, usize)>)
+            .expect("scan");
+        assert_eq!(scan.discovered, 15);
+        assert_eq!(scan.total_files, 15);
+
+        let import = controller
+            .import_ids(
+                &fixture("sample_ids.csv"),
+                None,
+                None::<fn(usize, u64, u64)>,
+            )
+            .expect("import");
+        assert_eq!(import.report.inserted, 10);
+        assert_eq!(import.total, 10);
+
+        let mut request = MatchRequest {
+            engine: MatchEngineKind::Cpu,
+            scope: MatchScope::All,
+            threshold: 0.4,
+            include_path_components: false,
+            case_sensitive: false,
+            dry_run: true,
+            new_files_only: false,
+            output: None,
+        };
+        let preview = controller
+            .run_match(&request, None, None, |_| {})
+            .expect("dry run");
+        let stored = Database::new(db_path.to_str().unwrap())
+            .unwrap()
+            .get_match_count()
+            .unwrap();
+        assert_eq!(stored, 0);
+
+        let mut notices = Vec::new();
+        let found = Arc::new(AtomicUsize::new(0));
+        let running = Arc::clone(&found);
+        request.dry_run = false;
+        let matched = controller
+            .run_match(
+                &request,
+                None,
+                Some(Arc::new(Mutex::new(move |so_far| {
+                    running.store(so_far, Ordering::Relaxed)
+                }))),
+                |n| notices.push(n),
+            )
+            .expect("match");
+        assert_eq!(matched.engine, MatchEngineKind::Cpu);
+        assert!(matched.match_count > 0);
+        assert_eq!(matched.match_count, preview.match_count);
+        assert_eq!(found.load(Ordering::Relaxed), matched.match_count);
+        assert!(notices.is_empty());
+
+        // Matching stored XYZ789's files, so the search is served from the cache
+        let cached = search(&controller, "XYZ789");
+        assert!(cached.from_cache);
+        assert!(cached
+            .results
+            .iter()
+            .any(|r| r.file_name == "XYZ789_report.tif"));
+        assert_eq!(cached.total, cached.results.len());
+
+        // Paged requests read one page and report the full count
+        let paged = controller
+            .search(
+                &SearchRequest {
+                    page_size: Some(1),
+                    ..search_request("XYZ789")
+                },
+                None,
+            )
+            .expect("paged search");
+        assert!(paged.from_cache);
+        assert_eq!(paged.total, cached.total);
+        assert_eq!(paged.results.len(), 1);
+        let (last_page, total) = controller
+            .stored_matches_page(
+                "XYZ789",
+                0.4,
+                1.0,
+                ResultOrder::FileName,
+                cached.total - 1,
+                1,
+            )
+            .expect("page");
+        assert_eq!(total, cached.total);
+        assert_eq!(
+            last_page[0].file_path,
+            cached.results.last().unwrap().file_path
+        );
+
+        // An ID that was never imported is scored fresh and then cached
+        let fresh = search(&controller, "unrelated_file");
+        assert!(!fresh.from_cache);
+        assert!(fresh.cache_error.is_none());
+        assert!(fresh
+            .results
+            .iter()
+            .any(|r| r.file_name == "unrelated_file.tif"));
+        assert!(search(&controller, "unrelated_file").from_cache);
+
+        // Terms after the ID narrow a fresh scoring and are never cached
+        let narrowed = search(&controller, "XYZ789 -report");
+        assert!(!narrowed.from_cache);
+        assert!(narrowed
+            .results
+            .iter()
+            .all(|r| r.file_name != "XYZ789_report.tif"));
+        assert!(!search(&controller, "XYZ789 -report").from_cache);
+
+        let _ = std::fs::remove_file(&db_path);
+    }
+
+    #[test]
+    fn matching_a_subset_leaves_other_ids_alone() {
+        let db_path = temp_db("subset");
+        let controller = Controller::new(db_path.to_str().unwrap());
+        controller
+            .scan(&fixture("test_data/tiff_files"), None::<fn(usize, usize)>)
+            .expect("scan");
+        controller
+            .import_ids(
+                &fixture("sample_ids.csv"),
+                None,
+                None::<fn(usize, u64, u64)>,
+            )
+            .expect("import");
+        let counts = |threshold: f64| -> HashMap<String, usize> {
+            let db = Database::new(db_path.to_str().unwrap()).unwrap();
+            let mut ids = db.get_all_reference_ids().unwrap();
+            ids.push("unrelated_file".to_string());
+            ids.into_iter()
+                .map(|id| {
+                    let count = db.count_matches_for_id(&id, threshold, None).unwrap();
+                    (id, count)
+                })
+                .collect()
+        };
+
+        let mut request = MatchRequest {
+            engine: MatchEngineKind::Cpu,
+            scope: MatchScope::All,
+            threshold: 0.4,
+            include_path_components: false,
+            case_sensitive: false,
+            dry_run: false,
+            new_files_only: false,
+            output: None,
+        };
+        controller.run_match(&request, None, None, |_| {}).unwrap();
+        let before = counts(0.4);
+        assert!(before["XYZ789"] > 0);
+
+        // Rematching one ID at a stricter threshold replaces only its matches
+        request.scope = MatchScope::Ids(MatchScope::parse_ids("XYZ789, NOPE"));
+        request.threshold = 0.5;
+        let mut notices = Vec::new();
+        let subset = controller
+            .run_match(&request, None, None, |n| notices.push(n))
+            .unwrap();
+        assert!(subset.match_count > 0);
+        assert_eq!(notices.len(), 1);
+        let run = controller.last_match_run().unwrap().expect("run recorded");
+        assert_eq!((run.engine.as_str(), run.threshold), ("cpu", 0.5));
+        assert_eq!((run.id_count, run.match_count), (1, subset.match_count));
+        let after = counts(0.4);
+        assert_eq!(after["XYZ789"], 0);
+        assert_eq!(counts(0.5)["XYZ789"], subset.match_count);
+        for (id, count) in &before {
+            if id != "XYZ789" {
+                assert_eq!(after[id], *count, "{} changed", id);
+            }
+        }
+
+        // Only the ID that is new in the second import is matched
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let since = Utc::now();
+        let csv_path = temp_db("subset_ids").with_extension("csv");
+        std::fs::write(&csv_path, "hh_id\nHH001\nunrelated_file\n").unwrap();
+        controller
+            .import_ids(
+                csv_path.to_str().unwrap(),
+                None,
+                None::<fn(usize, u64, u64)>,
+            )
+            .expect("second import");
+        request.scope = MatchScope::ImportedSince(since);
+        request.threshold = 0.4;
+        let recent = controller.run_match(&request, None, None, |_| {}).unwrap();
+        let latest = counts(0.4);
+        assert_eq!(latest["unrelated_file"], recent.match_count);
+        assert!(recent.match_count > 0);
+        assert_eq!(latest["HH001"], before["HH001"]);
+
+        request.scope = MatchScope::Ids(vec!["NOPE".to_string()]);
+        assert!(controller.run_match(&request, None, None, |_| {}).is_err());
+
+        let _ = std::fs::remove_file(&csv_path);
+        let _ = std::fs::remove_file(&db_path);
+    }
+
+    #[test]
+    fn thread_limit_bounds_the_worker_pool() {
+        let db_path = temp_db("threads");
+        let mut controller = Controller::new(db_path.to_str().unwrap());
+        controller.set_thread_limit(2).unwrap();
+        assert_eq!(controller.thread_limit(), 2);
+        assert_eq!(controller.install(rayon::current_num_threads), 2);
+
+        // The parallel steps run to completion inside the smaller pool
+        controller.set_thread_limit(1).unwrap();
+        let scan = controller
+            .scan(&fixture("test_data/tiff_files"), None::<fn(usize, usize)>)
+            .expect("scan");
+        assert_eq!(scan.discovered, 15);
+        assert!(search(&controller, "unrelated_file")
+            .results
+            .iter()
+            .any(|r| r.file_name == "unrelated_file.tif"));
+
+        controller.set_thread_limit(0).unwrap();
+        assert_eq!(
+            controller.install(rayon::current_num_threads),
+            rayon::current_num_threads()
+        );
+
+        let _ = std::fs::remove_file(&db_path);
+    }
+
+    #[test]
+    fn match_scope_parses_ids_and_dates() {
+        assert_eq!(
+            MatchScope::parse_ids(" HH001,HH002;\nHH003  HH004 "),
+            vec!["HH001", "HH002", "HH003", "HH004"]
+        );
+        assert!(MatchScope::parse_ids(" , ").is_empty());
+
+        let utc = MatchScope::parse_since("2024-05-01T10:00:00+02:00").unwrap();
+        assert_eq!(utc.to_rfc3339(), "2024-05-01T08:00:00+00:00");
+        let day = MatchScope::parse_since("2024-05-01").unwrap();
+        let minute = MatchScope::parse_since("2024-05-01 14:30").unwrap();
+        assert_eq!((minute - day).num_minutes(), 14 * 60 + 30);
+        assert!(MatchScope::parse_since("05/01/2024").is_err());
+    }
+
+    #[test]
+    fn registered_roots_are_scanned_together() {
+        let db_path = temp_db("roots");
+        let controller = Controller::new(db_path.to_str().unwrap());
+        assert!(controller.scan_all_roots(None::<fn(usize, usize)>).is_err());
+
+        let root = controller
+            .add_scan_root(&fixture("test_data/tiff_files"))
+            .unwrap();
+        let summary = controller.scan_all_roots(None::<fn(usize, usize)>).unwrap();
+        assert_eq!(summary.discovered, 15);
+        assert_eq!(summary.pruned, 0);
+        let summary = controller.rescan_changed(None::<fn(usize, usize)>).unwrap();
+        assert_eq!((summary.skipped, summary.changed), (15, 0));
+
+        let roots = controller.scan_roots().unwrap();
+        assert_eq!(roots.len(), 1);
+        assert!(roots[0].last_scan.is_some());
+
+        assert_eq!(controller.remove_scan_root(&root).unwrap(), 15);
+        assert!(controller.scan_roots().unwrap().is_empty());
+
+        let _ = std::fs::remove_file(&db_path);
+    }
+
+    #[test]
+    fn new_files_are_matched_without_touching_older_matches() {
+        let db_path = temp_db("new_files");
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("HH001_a.tif"), b"II*\0").unwrap();
+        std::fs::write(dir.path().join("HH002_a.tif"), b"II*\0").unwrap();
+        let ids = dir.path().join("ids.csv");
+        std::fs::write(&ids, "hh_id\nHH001\nHH002\n").unwrap();
+        let folder = dir.path().to_str().unwrap();
+
+        let controller = Controller::new(db_path.to_str().unwrap());
+        controller.scan(folder, None::<fn(usize, usize)>).unwrap();
+        controller
+            .import_ids(ids.to_str().unwrap(), None, None::<fn(usize, u64, u64)>)
+            .unwrap();
+        let mut request = MatchRequest {
+            engine: MatchEngineKind::Cpu,
+            scope: MatchScope::All,
+            threshold: 0.5,
+            include_path_components: false,
+            case_sensitive: false,
+            dry_run: false,
+            new_files_only: true,
+            output: None,
+        };
+        // Nothing to build on before a full run
+        assert!(controller.run_match(&request, None, None, |_| {}).is_err());
+        request.new_files_only = false;
+        let full = controller.run_match(&request, None, None, |_| {}).unwrap();
+        assert_eq!(full.match_count, 2);
+
+        // A match only a rescore of the old files would drop
+        let db = Database::new(db_path.to_str().unwrap()).unwrap();
+        let old_file = db
+            .get_file_id(&dir.path().join("HH002_a.tif").to_string_lossy())
+            .unwrap();
+        db.insert_match("HH001", old_file, 0.51, 0.5, "manual", None)
+            .unwrap();
+
+        std::fs::write(dir.path().join("HH001_b.tif"), b"II*\0").unwrap();
+        controller.scan(folder, None::<fn(usize, usize)>).unwrap();
+        request.new_files_only = true;
+        let added = controller.run_match(&request, None, None, |_| {}).unwrap();
+        assert_eq!(added.match_count, 1);
+
+        let stored: Vec<String> = db
+            .search_single_id("HH001", 0.5, None, ResultOrder::FileName)
+            .unwrap()
+            .into_iter()
+            .map(|result| result.file_name)
+            .collect();
+        assert_eq!(stored, ["HH001_a.tif", "HH001_b.tif", "HH002_a.tif"]);
+        assert_eq!(db.get_match_count().unwrap(), 4);
+
+        // Run again with nothing new: no work and nothing lost
+        let again = controller.run_match(&request, None, None, |_| {}).unwrap();
+        assert_eq!(again.match_count, 0);
+        assert_eq!(db.get_match_count().unwrap(), 4);
+
+        request.scope = MatchScope::Ids(vec!["HH001".to_string()]);
+        assert!(controller.run_match(&request, None, None, |_| {}).is_err());
+
+        let _ = std::fs::remove_file(&db_path);
+    }
+
+    #[test]
+    fn matches_can_be_stored_in_a_separate_database() {
+        let db_path = temp_db("output_cache");
+        let output_path = temp_db("output_run");
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("HH001_a.tif"), b"II*\0").unwrap();
+        std::fs::write(dir.path().join("HH002_a.tif"), b"II*\0").unwrap();
+        std::fs::write(dir.path().join("unrelated.tif"), b"II*\0").unwrap();
+        let ids = dir.path().join("ids.csv");
+        std::fs::write(&ids, "hh_id\nHH001\nHH002\n").unwrap();
+
+        let controller = Controller::new(db_path.to_str().unwrap());
+        controller
+            .scan(dir.path().to_str().unwrap(), None::<fn(usize, usize)>)
+            .unwrap();
+        controller
+            .import_ids(ids.to_str().unwrap(), None, None::<fn(usize, u64, u64)>)
+            .unwrap();
+        let mut request = MatchRequest {
+            engine: MatchEngineKind::Cpu,
+            scope: MatchScope::All,
+            threshold: 0.5,
+            include_path_components: false,
+            case_sensitive: false,
+            dry_run: false,
+            new_files_only: false,
+            output: Some(output_path.to_string_lossy().into_owned()),
+        };
+        let summary = controller.run_match(&request, None, None, |_| {}).unwrap();
+        assert_eq!(summary.match_count, 2);
+
+        // The cache keeps no matches or run; the output holds both, with the
+        // matched files but not the rest of the archive
+        let cache = Database::new(db_path.to_str().unwrap()).unwrap();
+        assert_eq!(cache.get_match_count().unwrap(), 0);
+        assert!(cache.last_match_run().unwrap().is_none());
+        let output = Database::new(output_path.to_str().unwrap()).unwrap();
+        let mut stored = Vec::new();
+        output
+            .get_all_matches(0.0, None, |record| {
+                stored.push((record.hh_id, record.file_name));
+                true
+            })
+            .unwrap();
+        assert_eq!(
+            stored,
+            [
+                ("HH001".to_string(), "HH001_a.tif".to_string()),
+                ("HH002".to_string(), "HH002_a.tif".to_string()),
+            ]
+        );
+        assert_eq!(output.get_file_count().unwrap(), 2);
+        assert_eq!(output.last_match_run().unwrap().unwrap().match_count, 2);
+
+        request.new_files_only = true;
+        assert!(controller.run_match(&request, None, None, |_| {}).is_err());
+
+        let _ = std::fs::remove_file(&db_path);
+        let _ = std::fs::remove_file(&output_path);
+    }
+
+    #[test]
+    fn since_last_scan_only_picks_up_new_files() {
+        let db_path = temp_db("since_last_scan");
+        let dir = tempfile::tempdir().unwrap();
+        let old = dir.path().join("HH001.tif");
+        std::fs::write(&old, b"II*\0").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&old)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(3600))
+            .unwrap();
+        let folder = dir.path().to_str().unwrap();
+
+        let mut controller = Controller::new(db_path.to_str().unwrap());
+        controller.set_since_last_scan(true);
+        // Never scanned, so the whole folder is read
+        let summary = controller.scan(folder, None::<fn(usize, usize)>).unwrap();
+        assert_eq!(summary.discovered, 1);
+
+        std::fs::write(dir.path().join("HH002.tif"), b"II*\0").unwrap();
+        let summary = controller.scan(folder, None::<fn(usize, usize)>).unwrap();
+        assert_eq!((summary.discovered, summary.added), (1, 1));
+        assert_eq!((summary.pruned, summary.total_files), (0, 2));
+
+        // Nothing new is not the same as no TIFFs
+        let summary = controller.scan(folder, None::<fn(usize, usize)>).unwrap();
+        assert_eq!(summary.discovered, 0);
+        assert!(summary.empty_folders.is_empty());
+
+        let _ = std::fs::remove_file(&db_path);
+    }
+
+    #[test]
+    fn stored_matches_are_explained_on_demand() {
+        let db_path = temp_db("explain");
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("HH001_scan.tif"), b"II*\0").unwrap();
+        let ids = dir.path().join("ids.csv");
+        std::fs::write(&ids, "hh_id\nHH001\n").unwrap();
+
+        let mut controller = Controller::new(db_path.to_str().unwrap());
+        controller
+            .scan(dir.path().to_str().unwrap(), None::<fn(usize, usize)>)
+            .unwrap();
+        controller
+            .import_ids(ids.to_str().unwrap(), None, None::<fn(usize, u64, u64)>)
+            .unwrap();
+        let request = MatchRequest {
+            engine: MatchEngineKind::Cpu,
+            scope: MatchScope::All,
+            threshold: 0.5,
+            include_path_components: false,
+            case_sensitive: false,
+            dry_run: false,
+            new_files_only: false,
+            output: None,
+        };
+        controller.run_match(&request, None, None, |_| {}).unwrap();
+
+        controller.set_explain_scores(true);
+        let search = SearchRequest {
+            hh_id: "HH001".to_string(),
+            min_similarity: 0.5,
+            max_similarity: 1.0,
+            include_path_components: false,
+            case_sensitive: false,
+            page_size: None,
+            order: ResultOrder::default(),
+        };
+        let outcome = controller.search(&search, None).unwrap();
+        assert!(outcome.from_cache);
+        let result = &outcome.results[0];
+        assert!(result.explanation.is_none());
+        let explanation = controller
+            .explain_match(&search, result)
+            .expect("explained");
+        assert_eq!(explanation.normalized, result.similarity_score);
+        assert_eq!(
+            result.matched_via.as_deref(),
+            Some(
+                format_matched_via(explanation.candidate_kind, &explanation.matched_candidate)
+                    .as_str()
+            )
+        );
+
+        let _ = std::fs::remove_file(&db_path);
+    }
+
+    #[test]
+    fn scanning_an_empty_folder_is_reported() {
+        let db_path = temp_db("empty_folder");
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().to_str().unwrap();
+
+        let controller = Controller::new(db_path.to_str().unwrap());
+        let summary = controller.scan(folder, None::<fn(usize, usize)>).unwrap();
+        assert_eq!((summary.discovered, summary.total_files), (0, 0));
+        assert_eq!(summary.empty_folders, [folder]);
+
+        let _ = std::fs::remove_file(&db_path);
+    }
+
+    #[test]
+    fn export_writes_json_by_extension() {
+        let db_path = temp_db("export");
+        let controller = Controller::new(db_path.to_str().unwrap());
+        controller
+            .scan(&fixture("test_data/tiff_files"), None::<fn(usize, usize)>)
+            .expect("scan");
+        search(&controller, "ABC123");
+
+        let out = db_path.with_extension("json");
+        let rows = controller
+            .export(out.to_str().unwrap(), None, |_, _| {})
+            .unwrap();
+        let exported: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&out).unwrap()).unwrap();
+        assert!(rows > 0);
+        assert_eq!(exported.as_array().map(|a| a.len()), Some(rows));
+
+        let _ = std::fs::remove_file(&out);
+        let _ = std::fs::remove_file(&db_path);
+    }
+
+    fn exported_results() -> Vec<SearchResult> {
+        vec![
+            SearchResult {
+                file_name: "HH001.tif".to_string(),
+                file_path: "/scans/2019/HH001.tif".to_string(),
+                similarity_score: 0.8125,
+                threshold: Some(0.7),
+                engine: Some("gpu".to_string()),
+                display_name: Some("2019/HH001.tif".to_string()),
+                scan_root: Some("/scans".to_string()),
+                matched_via: Some("stem:hh001".to_string()),
+                status: MatchStatus::Confirmed,
+                explanation: None,
+            },
+            // Legacy rows without run metadata, and a name needing escapes
+            SearchResult {
+                file_name: "HH 001 \"copy\".tif".to_string(),
+                file_path: "scans\\HH 001 \"copy\".tif".to_string(),
+                similarity_score: 1.0,
+                threshold: None,
+                engine: None,
+                display_name: None,
+                scan_root: None,
+                matched_via: None,
+                status: MatchStatus::Pending,
+                explanation: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn results_round_trip_through_json() {
+        let path = std::env::temp_dir().join(format!("results_{}.json", std::process::id()));
+        let results = exported_results();
+        export_results_json(path.to_str().unwrap(), &results).unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(value[0]["similarity_score"].as_f64(), Some(0.8125));
+        let read: Vec<SearchResult> = serde_json::from_str(&text).unwrap();
+        assert_eq!(read, results);
+
+        export_results_json(path.to_str().unwrap(), &[]).unwrap();
+        let read: Vec<SearchResult> =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(read.is_empty());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn results_export_to_a_readable_workbook() {
+        use calamine::{open_workbook, Data, Reader, Xlsx};
+
+        let path = std::env::temp_dir().join(format!("results_{}.xlsx", std::process::id()));
+        let stats = ExportStats {
+            hh_id: "HH001".to_string(),
+            threshold: 0.7,
+            exported_at: Local::now(),
+        };
+        let results = exported_results();
+        export_results_xlsx(path.to_str().unwrap(), &results, &stats).unwrap();
+
+        let mut workbook: Xlsx<_> = open_workbook(&path).expect("valid workbook");
+        assert_eq!(workbook.sheet_names(), ["Matches", "Summary"]);
+
+        let matches = workbook.worksheet_range("Matches").unwrap();
+        assert_eq!(matches.height(), results.len() + 1);
+        assert_eq!(
+            matches.get((0, 0)),
+            Some(&Data::String("File Name".to_string()))
+        );
+        for (row, result) in results.iter().enumerate() {
+            let row = row + 1;
+            assert_eq!(
+                matches.get((row, 0)),
+                Some(&Data::String(result.file_name.clone()))
+            );
+            assert_eq!(
+                matches.get((row, 1)),
+                Some(&Data::String(result.file_path.clone()))
+            );
+            assert_eq!(
+                matches.get((row, 2)),
+                Some(&Data::String("Open".to_string()))
+            );
+            assert_eq!(
+                matches.get((row, 3)),
+                Some(&Data::Float(result.similarity_score))
+            );
+        }
+
+        let summary = workbook.worksheet_range("Summary").unwrap();
+        assert_eq!(
+            summary.get((0, 1)),
+            Some(&Data::String("HH001".to_string()))
+        );
+        assert_eq!(summary.get((1, 1)), Some(&Data::Float(2.0)));
+        assert_eq!(summary.get((2, 1)), Some(&Data::Float(0.7)));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn diagnostics_report_cache_counts() {
+        let db_path = temp_db("diagnostics");
+        let controller = Controller::new(db_path.to_str().unwrap());
+        controller
+            .scan(&fixture("test_data/tiff_files"), None::<fn(usize, usize)>)
+            .expect("scan");
+
+        let diagnostics = controller.diagnostics().unwrap();
+        assert_eq!(diagnostics.version, env!("CARGO_PKG_VERSION"));
+        assert!(diagnostics.file_count > 0);
+        assert_eq!(diagnostics.reference_id_count, 0);
+        assert_eq!(diagnostics.match_count, 0);
+        assert!(diagnostics.cache_bytes.unwrap() > 0);
+
+        let text = diagnostics.to_string();
+        assert!(text.contains(&format!("Cached files: {}", diagnostics.file_count)));
+        assert!(text.lines().last().unwrap().starts_with("GPU: "));
+
+        let _ = std::fs::remove_file(&db_path);
+    }
+
+    #[test]
+    fn validate_cache_reports_files_that_moved() {
+        let db_path = temp_db("validate");
+        let controller = Controller::new(db_path.to_str().unwrap());
+        controller
+            .scan(&fixture("test_data/tiff_files"), None::<fn(usize, usize)>)
+            .expect("scan");
+
+        let check = controller.validate_cache(100).unwrap();
+        assert!(check.sampled > 0);
+        assert_eq!(check.missing, 0);
+        assert_eq!(check.foreign_separators, 0);
+        assert!(!check.suggests_rescan());
+
+        // The archive "moved": most cached paths now point nowhere
+        {
+            let mut db = Database::new(db_path.to_str().unwrap()).unwrap();
+            let mut session = db.start_file_import().unwrap();
+            for i in 0..check.sampled * 2 {
+                let name = format!("HH{:03}.tif", i);
+                session
+                    .upsert_file(&format!("/old_mount/{}", name), &name)
+                    .unwrap();
+            }
+            session.commit().unwrap();
+        }
+        let moved = controller.validate_cache(1000).unwrap();
+        assert_eq!(moved.sampled, check.sampled * 3);
+        assert_eq!(moved.missing, check.sampled * 2);
+        assert!(moved.suggests_rescan());
+        assert_eq!(moved.missing_examples.len(), MISSING_EXAMPLES);
+        assert!(moved
+            .missing_examples
+            .iter()
+            .all(|path| path.starts_with("/old_mount/")));
+
+        let _ = std::fs::remove_file(&db_path);
+    }
+
+    #[test]
+    fn remap_prefixes_drop_trailing_separators() {
+        assert_eq!(remap_prefix(" /mnt/new/ "), Ok("/mnt/new"));
+        assert_eq!(remap_prefix(r"Z:\archive\"), Ok(r"Z:\archive"));
+        assert!(remap_prefix("/").is_err());
+        assert!(remap_prefix("  ").is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn foreign_separators_are_detected() {
+        assert!(has_foreign_separators(r"C:\scans\HH001.tif"));
+        assert!(has_foreign_separators(r"\\server\share\HH001.tif"));
+        assert!(!has_foreign_separators("/scans/HH001.tif"));
+        assert!(!has_foreign_separators("2019/HH001.tif"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn file_urls_keep_paths_absolute() {
+        let mut result = exported_results().remove(0);
+        assert_eq!(file_url(&result), "file:////scans/2019/HH001.tif");
+
+        // Relative paths from older caches hang off their scan root
+        result.file_path = "2019/HH001.tif".to_string();
+        assert_eq!(file_url(&result), "file:////scans/2019/HH001.tif");
+    }
+
+    #[test]
+    fn results_round_trip_through_json_lines() {
+        let path = std::env::temp_dir().join(format!("results_{}.jsonl", std::process::id()));
+        let results = exported_results();
+        export_results_jsonl(path.to_str().unwrap(), &results).unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), results.len());
+        let read: Vec<SearchResult> = lines
+            .iter()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(read, results);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    fn db_with_matches() -> Database {
+        let mut db = Database::new(":memory:").expect("in-memory database");
+        {
+            let mut session = db.start_file_import().unwrap();
+            session
+                .upsert_file("/scans/HH001.tif", "HH001.tif")
+                .unwrap();
+            session
+                .upsert_file("/scans/b, \"quoted\".tif", "b, \"quoted\".tif")
+                .unwrap();
+            session.commit().unwrap();
+        }
+        let a = db.get_file_id("/scans/HH001.tif").unwrap();
+        let b = db.get_file_id("/scans/b, \"quoted\".tif").unwrap();
+        db.insert_match("HH002", b, 0.5, 0.5, "cpu", None).unwrap();
+        db.insert_match("HH001", a, 1.0, 0.5, "cpu", None).unwrap();
+        db.insert_match("HH001", b, 0.75, 0.5, "cpu", None).unwrap();
+        db
+    }
+
+    #[test]
+    fn streamed_exports_round_trip() {
+        let db = db_with_matches();
+        let dir = std::env::temp_dir();
+        let csv_path = dir.join(format!("tiff_locator_export_{}.csv", std::process::id()));
+        let json_path = dir.join(format!("tiff_locator_export_{}.json", std::process::id()));
+        let expected = [
+            ("HH001", "HH001.tif", "/scans/HH001.tif"),
+            ("HH001", "b, \"quoted\".tif", "/scans/b, \"quoted\".tif"),
+            ("HH002", "b, \"quoted\".tif", "/scans/b, \"quoted\".tif"),
+        ];
+
+        let mut reported = Vec::new();
+        let written = write_matches_csv(&db, File::create(&csv_path).unwrap(), None, |rows| {
+            reported.push(rows)
+        })
+        .expect("CSV export");
+        assert_eq!(written, 3);
+        assert_eq!(reported, [1, 2, 3]);
+        let mut reader = csv::Reader::from_path(&csv_path).unwrap();
+        let headers = reader.headers().unwrap().clone();
+        let column = |name: &str| headers.iter().position(|h| h == name).unwrap();
+        let (id, name, path) = (column("hh_id"), column("file_name"), column("file_path"));
+        let rows: Vec<(String, String, String)> = reader
+            .records()
+            .map(|record| {
+                let record = record.unwrap();
+                (
+                    record[id].to_string(),
+                    record[name].to_string(),
+                    record[path].to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            expected.map(|(id, name, path)| (id.to_string(), name.to_string(), path.to_string()))
+        );
+
+        let file = BufWriter::new(File::create(&json_path).unwrap());
+        assert_eq!(
+            write_matches_json(&db, file, None, |_| {}).expect("JSON export"),
+            3
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&json_path).unwrap()).unwrap();
+        let records = json.as_array().expect("a JSON array");
+        assert_eq!(records.len(), expected.len());
+        for (record, (id, name, path)) in records.iter().zip(expected) {
+            assert_eq!(record["hh_id"], id);
+            assert_eq!(record["file_name"], name);
+            assert_eq!(record["file_path"], path);
+        }
+
+        let _ = std::fs::remove_file(csv_path);
+        let _ = std::fs::remove_file(json_path);
+    }
+}
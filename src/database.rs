@@ -1,6 +1,164 @@
+use crate::matcher::MatchExplanation;
 use bytemuck::cast_slice;
-use chrono::Utc;
-use rusqlite::{params, Connection, OptionalExtension, Result, Transaction};
+use chrono::{DateTime, Utc};
+use rayon::prelude::*;
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension, Result, Transaction};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// Existence checks between progress reports of `stale_file_ids_with_progress`.
+const STALE_CHECK_PROGRESS_STEP: usize = 256;
+
+/// Setting holding the `ScanGeneration` (as JSON) the last full match run saw,
+/// so a later run can match only the files added since.
+const MATCHED_GENERATION_SETTING: &str = "matched_generation";
+
+/// Searches kept in `search_history`; older ones are dropped as new ones come in.
+const SEARCH_HISTORY_LIMIT: usize = 100;
+
+/// How long statements wait for another connection's lock; rusqlite's default.
+const DEFAULT_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Columns each table has had since it was introduced. A table of the same name
+// from some other tool would otherwise be kept by `CREATE TABLE IF NOT EXISTS`
+// and only fail later, in whichever query first touched a missing column.
+// Columns added by migrations are left out: they are created when missing.
+const EXPECTED_COLUMNS: &[(&str, &[&str])] = &[
+    ("files", &["id", "file_path", "file_name", "scan_date"]),
+    ("reference_ids", &["id", "hh_id", "import_date"]),
+    (
+        "matches",
+        &["id", "hh_id", "file_id", "similarity_score", "match_date"],
+    ),
+    (
+        "file_vectors",
+        &["file_id", "fingerprint", "vector_blob", "updated_at"],
+    ),
+    ("scan_roots", &["id", "path", "last_scan"]),
+    ("settings", &["key", "value"]),
+    ("blocklist", &["file_path", "added_at"]),
+    (
+        "match_runs",
+        &[
+            "id",
+            "engine",
+            "threshold",
+            "finished_at",
+            "id_count",
+            "match_count",
+        ],
+    ),
+    (
+        "search_history",
+        &["id", "query", "result_count", "searched_at"],
+    ),
+];
+
+// Stored matches for one household ID inside a similarity band, shared by the
+// full, paged and counting queries. `?1` is the ID, `?2`/`?3` the band. The
+// ORDER BY comes from `ResultOrder::order_by`. Matches of blocked files stay
+// stored but are skipped, so unblocking brings them back with their status.
+const MATCHES_FOR_ID_SELECT: &str =
+    "SELECT f.file_name, f.file_path, m.similarity_score, m.threshold, m.engine,
+            f.display_name, f.scan_root, m.matched_via, m.status";
+const MATCHES_FOR_ID_FROM: &str = "FROM matches m JOIN files f ON m.file_id = f.id";
+const MATCHES_FOR_ID_WHERE: &str = "WHERE m.hh_id = ?1 AND m.similarity_score >= ?2
+       AND (?3 >= 1.0 OR m.similarity_score <= ?3)
+       AND m.threshold <= ?2 + 1e-9
+       AND f.file_path NOT IN (SELECT file_path FROM blocklist)";
+
+// Store one match, or refresh the score and run of a stored one while keeping
+// its review status. `?1`–`?7` are hh_id, file_id, similarity_score,
+// match_date, threshold, engine and matched_via.
+const INSERT_MATCH: &str = "INSERT INTO matches
+                 (hh_id, file_id, similarity_score, match_date, threshold, engine, matched_via)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT(hh_id, file_id) DO UPDATE SET similarity_score=excluded.similarity_score,
+                 match_date=excluded.match_date, threshold=excluded.threshold,
+                 engine=excluded.engine, matched_via=excluded.matched_via";
+
+/// How search results with the same similarity score are ordered, so exports
+/// of the same search come out identical between runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResultOrder {
+    /// File name A–Z.
+    #[default]
+    FileName,
+    /// Most recently scanned first, then by file name.
+    ScanDate,
+}
+
+impl ResultOrder {
+    pub const ALL: [ResultOrder; 2] = [ResultOrder::FileName, ResultOrder::ScanDate];
+
+    // The trailing file id only separates one name cached at several paths,
+    // so pages never overlap
+    fn order_by(self) -> &'static str {
+        match self {
+            ResultOrder::FileName => "ORDER BY m.similarity_score DESC, f.file_name, m.file_id",
+            ResultOrder::ScanDate => {
+                "ORDER BY m.similarity_score DESC, f.scan_date DESC, f.file_name, m.file_id"
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ResultOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResultOrder::FileName => write!(f, "File name"),
+            ResultOrder::ScanDate => write!(f, "Scan date"),
+        }
+    }
+}
+
+/// Review state of a stored match, set by an analyst. Re-matching keeps it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchStatus {
+    /// Not reviewed yet.
+    #[default]
+    Pending,
+    Confirmed,
+    Rejected,
+}
+
+impl MatchStatus {
+    pub const ALL: [MatchStatus; 3] = [
+        MatchStatus::Pending,
+        MatchStatus::Confirmed,
+        MatchStatus::Rejected,
+    ];
+
+    /// Name stored in the `status` column and accepted by `from_label`.
+    pub fn label(self) -> &'static str {
+        match self {
+            MatchStatus::Pending => "pending",
+            MatchStatus::Confirmed => "confirmed",
+            MatchStatus::Rejected => "rejected",
+        }
+    }
+
+    pub fn from_label(label: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|status| status.label().eq_ignore_ascii_case(label.trim()))
+    }
+}
+
+impl std::fmt::Display for MatchStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MatchStatus::Pending => write!(f, "Pending"),
+            MatchStatus::Confirmed => write!(f, "Confirmed"),
+            MatchStatus::Rejected => write!(f, "Rejected"),
+        }
+    }
+}
 
 pub struct Database {
     conn: Connection,
@@ -14,14 +172,101 @@ pub struct MatchImportSession<'conn> {
     tx: Transaction<'conn>,
 }
 
+pub struct VectorImportSession<'conn> {
+    tx: Transaction<'conn>,
+}
+
 impl<'conn> FileImportSession<'conn> {
     pub fn upsert_file(&mut self, file_path: &str, file_name: &str) -> Result<()> {
+        self.upsert_scanned_file(file_path, file_name, None, None, None)
+    }
+
+    /// Store a file found by a scan. `file_path` should be absolute;
+    /// `display_name` is the path relative to `scan_root`, for display only.
+    /// `stamp` lets later incremental scans skip the file while it is unchanged.
+    pub fn upsert_scanned_file(
+        &mut self,
+        file_path: &str,
+        file_name: &str,
+        display_name: Option<&str>,
+        scan_root: Option<&str>,
+        stamp: Option<FileStamp>,
+    ) -> Result<()> {
         let scan_date = Utc::now().to_rfc3339();
         let mut stmt = self.tx.prepare_cached(
-            "INSERT INTO files (file_path, file_name, scan_date) VALUES (?1, ?2, ?3)
-             ON CONFLICT(file_path) DO UPDATE SET file_name=excluded.file_name, scan_date=excluded.scan_date",
+            "INSERT INTO files (file_path, file_name, scan_date, display_name, scan_root,
+                                file_size, modified)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT(file_path) DO UPDATE SET file_name=excluded.file_name,
+                 scan_date=excluded.scan_date, display_name=excluded.display_name,
+                 scan_root=excluded.scan_root, file_size=excluded.file_size,
+                 modified=excluded.modified",
+        )?;
+        stmt.execute(params![
+            file_path,
+            file_name,
+            scan_date,
+            display_name,
+            scan_root,
+            stamp.map(|s| s.size),
+            stamp.map(|s| s.modified)
+        ])?;
+        Ok(())
+    }
+
+    /// Record what the scanner's metadata pass read from a file's TIFF header;
+    /// `None` clears it for a file that could not be parsed.
+    pub fn set_file_metadata(
+        &mut self,
+        file_path: &str,
+        metadata: Option<TiffMetadata>,
+    ) -> Result<()> {
+        let mut stmt = self.tx.prepare_cached(
+            "UPDATE files SET page_count = ?2, width = ?3, height = ?4 WHERE file_path = ?1",
+        )?;
+        stmt.execute(params![
+            file_path,
+            metadata.map(|m| m.page_count),
+            metadata.map(|m| m.width),
+            metadata.map(|m| m.height)
+        ])?;
+        Ok(())
+    }
+
+    /// Drop one cached file and its matches. Returns the number of files removed.
+    pub fn remove_file(&mut self, file_path: &str) -> Result<usize> {
+        self.tx.execute(
+            "DELETE FROM matches WHERE file_id IN (SELECT id FROM files WHERE file_path = ?1)",
+            params![file_path],
+        )?;
+        self.tx
+            .execute("DELETE FROM files WHERE file_path = ?1", params![file_path])
+    }
+
+    /// Remove files under `scan_root` that were not upserted since `scan_started`
+    /// (an RFC 3339 timestamp taken before the walk), along with their matches.
+    /// Files from other roots are left alone. Returns the number removed.
+    pub fn prune_root(&mut self, scan_root: &str, scan_started: &str) -> Result<usize> {
+        self.tx.execute(
+            "DELETE FROM matches WHERE file_id IN
+                 (SELECT id FROM files WHERE scan_root = ?1 AND scan_date < ?2)",
+            params![scan_root, scan_started],
+        )?;
+        self.tx.execute(
+            "DELETE FROM files WHERE scan_root = ?1 AND scan_date < ?2",
+            params![scan_root, scan_started],
+        )
+    }
+
+    /// Register `scan_root` (if new) and stamp its last scan time, along with
+    /// `scan_started` (an RFC 3339 timestamp taken before the walk).
+    pub fn touch_scan_root(&mut self, scan_root: &str, scan_started: &str) -> Result<()> {
+        self.tx.execute(
+            "INSERT INTO scan_roots (path, last_scan, last_scan_started) VALUES (?1, ?2, ?3)
+             ON CONFLICT(path) DO UPDATE SET last_scan=excluded.last_scan,
+                 last_scan_started=excluded.last_scan_started",
+            params![scan_root, Utc::now().to_rfc3339(), scan_started],
         )?;
-        stmt.execute(params![file_path, file_name, scan_date])?;
         Ok(())
     }
 
@@ -38,30 +283,140 @@ impl<'conn> MatchImportSession<'conn> {
         Ok(())
     }
 
-    pub fn clear_for_ids(&mut self, hh_ids: &[String]) -> Result<()> {
+    /// Drop the stored matches of these IDs before they are matched again at
+    /// `threshold`. Reviewed (confirmed or rejected) matches are kept, so
+    /// their status survives the rerun; `insert_match` then only refreshes
+    /// their score. Kept matches take the run's threshold even when the run
+    /// doesn't find them again, so they can't pass for a complete cached
+    /// result below it. Matches of blocked files are kept as well, since the
+    /// run can't see those files; they come back once unblocked.
+    pub fn clear_for_ids(&mut self, hh_ids: &[String], threshold: f64) -> Result<()> {
         if hh_ids.is_empty() {
             return Ok(());
         }
 
         // Build placeholders for the IN clause
         let placeholders = hh_ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
-        let query = format!("DELETE FROM matches WHERE hh_id IN ({})", placeholders);
+        let query = format!(
+            "DELETE FROM matches WHERE hh_id IN ({}) AND status = 'pending'
+               AND file_id NOT IN (SELECT f.id FROM files f
+                                   JOIN blocklist b ON b.file_path = f.file_path)",
+            placeholders
+        );
 
         // Convert hh_ids to params
         let params: Vec<&dyn rusqlite::ToSql> =
             hh_ids.iter().map(|s| s as &dyn rusqlite::ToSql).collect();
 
         self.tx.execute(&query, params.as_slice())?;
+        // Only reviewed matches and those of blocked files are left
+        let mut keep = self
+            .tx
+            .prepare_cached("UPDATE matches SET threshold = ?2 WHERE hh_id = ?1")?;
+        for hh_id in hh_ids {
+            keep.execute(params![hh_id, threshold])?;
+        }
+        Ok(())
+    }
+
+    /// Drop every stored match of these files, whatever their ID. Like
+    /// `clear_for_ids`, reviewed matches are kept and take `threshold`.
+    pub fn clear_for_files(&mut self, file_ids: &[i64], threshold: f64) -> Result<()> {
+        let mut clear = self
+            .tx
+            .prepare_cached("DELETE FROM matches WHERE file_id = ?1 AND status = 'pending'")?;
+        for file_id in file_ids {
+            clear.execute(params![file_id])?;
+        }
+        let mut keep = self
+            .tx
+            .prepare_cached("UPDATE matches SET threshold = ?2 WHERE file_id = ?1")?;
+        for file_id in file_ids {
+            keep.execute(params![file_id, threshold])?;
+        }
         Ok(())
     }
 
-    pub fn insert_match(&mut self, hh_id: &str, file_id: i64, similarity_score: f64) -> Result<()> {
+    /// `threshold` and `engine` record the run configuration that produced the
+    /// match, `matched_via` which candidate of the file scored (see
+    /// `matcher::format_matched_via`). An existing match keeps its status.
+    pub fn insert_match(
+        &mut self,
+        hh_id: &str,
+        file_id: i64,
+        similarity_score: f64,
+        threshold: f64,
+        engine: &str,
+        matched_via: Option<&str>,
+    ) -> Result<()> {
+        self.insert_matches(
+            &[(
+                hh_id.to_string(),
+                file_id,
+                similarity_score,
+                matched_via.map(str::to_string),
+            )],
+            threshold,
+            engine,
+        )?;
+        Ok(())
+    }
+
+    /// Store a run's matches as `(hh_id, file_id, similarity_score,
+    /// matched_via)` rows, through one statement prepared up front and
+    /// stamped with one match date. Same upsert as `insert_match`, so the
+    /// rows are identical to inserting them one at a time. Returns the number
+    /// of rows written.
+    pub fn insert_matches(
+        &mut self,
+        matches: &[(String, i64, f64, Option<String>)],
+        threshold: f64,
+        engine: &str,
+    ) -> Result<usize> {
         let match_date = Utc::now().to_rfc3339();
-        self.tx.execute(
-            "INSERT INTO matches (hh_id, file_id, similarity_score, match_date) VALUES (?1, ?2, ?3, ?4)
-             ON CONFLICT(hh_id, file_id) DO UPDATE SET similarity_score=excluded.similarity_score, match_date=excluded.match_date",
-            params![hh_id, file_id, similarity_score, match_date],
+        let mut stmt = self.tx.prepare_cached(INSERT_MATCH)?;
+        for (hh_id, file_id, similarity_score, matched_via) in matches {
+            stmt.execute(params![
+                hh_id,
+                file_id,
+                similarity_score,
+                match_date,
+                threshold,
+                engine,
+                matched_via
+            ])?;
+        }
+        Ok(matches.len())
+    }
+
+    pub fn file_id(&self, file_path: &str) -> Result<i64> {
+        self.tx
+            .prepare_cached("SELECT id FROM files WHERE file_path = ?1")?
+            .query_row(params![file_path], |row| row.get(0))
+    }
+
+    pub fn commit(self) -> Result<()> {
+        self.tx.commit()
+    }
+}
+
+impl<'conn> VectorImportSession<'conn> {
+    pub fn upsert(&mut self, file_id: i64, fingerprint: u64, data: &[f32]) -> Result<()> {
+        let mut stmt = self.tx.prepare_cached(
+            "INSERT INTO file_vectors (file_id, fingerprint, vector_blob, updated_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(file_id) DO UPDATE SET
+                 fingerprint=excluded.fingerprint,
+                 vector_blob=excluded.vector_blob,
+                 updated_at=excluded.updated_at",
         )?;
+        let blob: &[u8] = cast_slice(data);
+        stmt.execute(params![
+            file_id,
+            fingerprint as i64,
+            blob,
+            Utc::now().to_rfc3339()
+        ])?;
         Ok(())
     }
 
@@ -70,18 +425,157 @@ impl<'conn> MatchImportSession<'conn> {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct FileRecord {
     pub id: i64,
     pub file_path: String,
     pub file_name: String,
+    /// RFC 3339 time of the scan that last saw the file.
+    pub scan_date: String,
+    /// Path relative to the scanned folder; `None` for files cached before it
+    /// was recorded.
+    pub display_name: Option<String>,
+    /// Folder the file was scanned from; relative `file_path`s from older
+    /// caches are resolved against it.
+    pub scan_root: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SearchResult {
     pub file_name: String,
     pub file_path: String,
     pub similarity_score: f64,
+    /// Threshold of the run that produced this result; `None` for matches
+    /// cached before thresholds were recorded.
+    pub threshold: Option<f64>,
+    pub engine: Option<String>,
+    pub display_name: Option<String>,
+    pub scan_root: Option<String>,
+    /// Which candidate of the file scored, as `kind:candidate` (see
+    /// `matcher::parse_matched_via`); `None` for matches cached before it
+    /// was recorded.
+    pub matched_via: Option<String>,
+    /// Review state; always pending for results that were never stored.
+    #[serde(default)]
+    pub status: MatchStatus,
+    /// How the score came about, for fresh searches that asked for it (see
+    /// `Searcher::set_explain`). Not stored or exported.
+    #[serde(skip)]
+    pub explanation: Option<MatchExplanation>,
+}
+
+/// A stored match found by [`Database::search_matches`], with the household
+/// ID it was matched to.
+#[derive(Debug, Clone)]
+pub struct StoredMatch {
+    pub hh_id: String,
+    pub result: SearchResult,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MatchRecord {
+    pub hh_id: String,
+    pub file_name: String,
+    pub file_path: String,
+    pub similarity_score: f64,
+    pub match_date: String,
+    pub status: MatchStatus,
+}
+
+/// Size and modification time of a scanned file, used to detect changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    pub size: i64,
+    /// Nanoseconds since the Unix epoch.
+    pub modified: i64,
+}
+
+/// Page count and first-page size read from a TIFF header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TiffMetadata {
+    pub page_count: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A source folder registered for scanning.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanRoot {
+    pub path: String,
+    /// RFC 3339 time of the last completed scan; `None` if never scanned.
+    pub last_scan: Option<String>,
+    /// RFC 3339 time the last completed scan began; files modified since
+    /// then are the ones a "since last scan" pass picks up.
+    pub last_scan_started: Option<String>,
+}
+
+/// A completed match run that stored its results, as recorded in
+/// `match_runs`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchRun {
+    /// Label of the engine that produced the matches, e.g. `cpu` or `gpu`.
+    pub engine: String,
+    pub threshold: f64,
+    /// RFC 3339 time the run finished.
+    pub finished_at: String,
+    /// Reference IDs the run matched.
+    pub id_count: usize,
+    /// Matches it stored.
+    pub match_count: usize,
+}
+
+/// A search from the Quick Search box, as recorded in `search_history`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHistoryEntry {
+    pub query: String,
+    /// Matches the search found.
+    pub result_count: usize,
+    /// RFC 3339 time of the latest search for this query.
+    pub searched_at: String,
+}
+
+/// A file name cached at more than one path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateName {
+    pub file_name: String,
+    /// Every cached path with this name, sorted.
+    pub paths: Vec<String>,
+}
+
+/// Cheap fingerprint of the files table used to detect rescans and cache
+/// clears. `last_scan` changes whenever any file row is upserted. File ids
+/// are never reused, so files added after a generation have larger ids than
+/// its `max_file_id`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanGeneration {
+    pub file_count: usize,
+    pub max_file_id: i64,
+    pub last_scan: String,
+}
+
+/// Which parts of the cache `Database::clear` empties. Files can't outlive
+/// their matches and vectors, so clearing files clears those too.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClearSelection {
+    pub files: bool,
+    pub matches: bool,
+    pub vectors: bool,
+    pub reference_ids: bool,
+}
+
+impl ClearSelection {
+    /// The selection with what clearing files implies added.
+    pub fn effective(self) -> Self {
+        ClearSelection {
+            matches: self.matches || self.files,
+            vectors: self.vectors || self.files,
+            ..self
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !(self.files || self.matches || self.vectors || self.reference_ids)
+    }
 }
 
 pub struct ReferenceImportSession<'conn> {
@@ -105,19 +599,72 @@ impl<'conn> ReferenceImportSession<'conn> {
 
 impl Database {
     pub fn new(db_path: &str) -> Result<Self> {
+        Self::open(db_path, DEFAULT_BUSY_TIMEOUT)
+    }
+
+    /// Opens and migrates the cache, waiting at most `busy_timeout` for
+    /// another connection's lock while doing so.
+    fn open(db_path: &str, busy_timeout: Duration) -> Result<Self> {
         let conn = Connection::open(db_path)?;
+        conn.busy_timeout(busy_timeout)?;
         let db = Database { conn };
         db.create_tables()?;
+        // Caches from before absolute paths get a scan root for their relative paths
+        if db.add_column_if_missing("files", "scan_root", "TEXT")? {
+            db.assign_legacy_scan_roots(db_path)?;
+        }
+        db.conn.busy_timeout(DEFAULT_BUSY_TIMEOUT)?;
         Ok(db)
     }
 
+    /// Like [`Database::new`], but retries up to `attempts` times in all while
+    /// another connection holds the file locked (`SQLITE_BUSY`/`SQLITE_LOCKED`),
+    /// waiting `backoff` before the first retry and twice as long before each
+    /// later one. Other errors are returned at once. Attempts don't wait on
+    /// the lock themselves, so the backoff alone decides how long this takes.
+    pub fn open_with_retry(db_path: &str, attempts: u32, backoff: Duration) -> Result<Self> {
+        let mut delay = backoff;
+        let mut attempt = 1;
+        loop {
+            match Self::open(db_path, Duration::ZERO) {
+                Err(err) if attempt < attempts && Self::is_locked(&err) => {
+                    log::debug!(
+                        "Database {} is locked (attempt {} of {}), retrying in {} ms",
+                        db_path,
+                        attempt,
+                        attempts,
+                        delay.as_millis()
+                    );
+                    thread::sleep(delay);
+                    delay *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn is_locked(err: &rusqlite::Error) -> bool {
+        matches!(
+            err.sqlite_error_code(),
+            Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked)
+        )
+    }
+
     fn create_tables(&self) -> Result<()> {
+        // Before anything is created, so a rejected file is left untouched
+        self.check_columns()?;
+
         self.conn.execute(
             "CREATE TABLE IF NOT EXISTS files (
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
                 file_path TEXT NOT NULL UNIQUE,
                 file_name TEXT NOT NULL,
-                scan_date TEXT NOT NULL
+                scan_date TEXT NOT NULL,
+                display_name TEXT,
+                scan_root TEXT,
+                file_size INTEGER,
+                modified INTEGER
             )",
             [],
         )?;
@@ -138,6 +685,9 @@ impl Database {
                 file_id INTEGER NOT NULL,
                 similarity_score REAL NOT NULL,
                 match_date TEXT NOT NULL,
+                threshold REAL,
+                engine TEXT,
+                matched_via TEXT,
                 FOREIGN KEY (file_id) REFERENCES files(id)
             )",
             [],
@@ -154,6 +704,72 @@ impl Database {
             [],
         )?;
 
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS scan_roots (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                path TEXT NOT NULL UNIQUE,
+                last_scan TEXT
+            )",
+            [],
+        )?;
+
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS settings (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Keyed by path rather than file id so entries survive rescans and
+        // clearing the cached files
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS blocklist (
+                file_path TEXT PRIMARY KEY,
+                added_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // One row per stored match run, oldest first
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS match_runs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                engine TEXT NOT NULL,
+                threshold REAL NOT NULL,
+                finished_at TEXT NOT NULL,
+                id_count INTEGER NOT NULL,
+                match_count INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        // One row per distinct query; repeating one re-inserts it with a new id,
+        // so id order is recency order
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS search_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                query TEXT NOT NULL UNIQUE,
+                result_count INTEGER NOT NULL,
+                searched_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        self.add_column_if_missing("files", "display_name", "TEXT")?;
+        self.add_column_if_missing("files", "file_size", "INTEGER")?;
+        self.add_column_if_missing("files", "modified", "INTEGER")?;
+        // Filled only by scans with the metadata pass enabled
+        self.add_column_if_missing("files", "page_count", "INTEGER")?;
+        self.add_column_if_missing("files", "width", "INTEGER")?;
+        self.add_column_if_missing("files", "height", "INTEGER")?;
+        // Caches created before run metadata was recorded are migrated in place
+        self.add_column_if_missing("matches", "threshold", "REAL")?;
+        self.add_column_if_missing("matches", "engine", "TEXT")?;
+        self.add_column_if_missing("matches", "matched_via", "TEXT")?;
+        self.add_column_if_missing("matches", "status", "TEXT NOT NULL DEFAULT 'pending'")?;
+        self.add_column_if_missing("scan_roots", "last_scan_started", "TEXT")?;
+
         // Create indices for better query performance
         self.conn.execute(
             "CREATE INDEX IF NOT EXISTS idx_files_path ON files(file_path)",
@@ -194,6 +810,89 @@ impl Database {
         Ok(())
     }
 
+    fn table_columns(&self, table: &str) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!("PRAGMA table_info({})", table))?;
+        let columns = stmt.query_map([], |row| row.get::<_, String>(1))?;
+        columns.collect()
+    }
+
+    /// Fails with e.g. `table 'files' missing column 'file_name'` when an
+    /// existing table lacks one of its `EXPECTED_COLUMNS`, as when the cache
+    /// file was created by another program. Tables not created yet pass.
+    fn check_columns(&self) -> Result<()> {
+        for (table, expected) in EXPECTED_COLUMNS {
+            let columns = self.table_columns(table)?;
+            if columns.is_empty() {
+                continue;
+            }
+            let missing: Vec<String> = expected
+                .iter()
+                .filter(|column| !columns.iter().any(|name| name == *column))
+                .map(|column| format!("'{}'", column))
+                .collect();
+            if !missing.is_empty() {
+                let message = format!(
+                    "table '{}' missing column{} {}",
+                    table,
+                    if missing.len() == 1 { "" } else { "s" },
+                    missing.join(", ")
+                );
+                return Err(rusqlite::Error::SqliteFailure(
+                    rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_ERROR),
+                    Some(message),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns true when the column had to be added.
+    fn add_column_if_missing(&self, table: &str, column: &str, definition: &str) -> Result<bool> {
+        let exists = self.table_columns(table)?.iter().any(|name| name == column);
+        if !exists {
+            self.conn.execute(
+                &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+                [],
+            )?;
+        }
+        Ok(!exists)
+    }
+
+    /// Scans before paths were made absolute stored them relative to the working
+    /// directory, which is where the GUI and CLI keep `cache.db` by default.
+    /// Record the cache's folder as the scan root of those rows.
+    fn assign_legacy_scan_roots(&self, db_path: &str) -> Result<()> {
+        if db_path.is_empty() || db_path == ":memory:" {
+            return Ok(());
+        }
+        let Some(root) = std::path::absolute(db_path)
+            .ok()
+            .and_then(|path| path.parent().map(|dir| dir.to_string_lossy().to_string()))
+        else {
+            return Ok(());
+        };
+
+        let relative: Vec<i64> = {
+            let mut stmt = self.conn.prepare("SELECT id, file_path FROM files")?;
+            let rows = stmt.query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?;
+            rows.filter_map(|row| row.ok())
+                .filter(|(_, path)| Path::new(path).is_relative())
+                .map(|(id, _)| id)
+                .collect()
+        };
+        for id in relative {
+            self.conn.execute(
+                "UPDATE files SET scan_root = ?1 WHERE id = ?2",
+                params![root, id],
+            )?;
+        }
+        Ok(())
+    }
+
     pub fn start_file_import(&mut self) -> Result<FileImportSession<'_>> {
         let tx = self.conn.transaction()?;
         Ok(FileImportSession { tx })
@@ -204,6 +903,11 @@ impl Database {
         Ok(MatchImportSession { tx })
     }
 
+    pub fn start_vector_import(&mut self) -> Result<VectorImportSession<'_>> {
+        let tx = self.conn.transaction()?;
+        Ok(VectorImportSession { tx })
+    }
+
     pub fn get_file_id(&self, file_path: &str) -> Result<i64> {
         self.conn.query_row(
             "SELECT id FROM files WHERE file_path = ?1",
@@ -212,94 +916,865 @@ impl Database {
         )
     }
 
-    pub fn insert_match(&self, hh_id: &str, file_id: i64, similarity_score: f64) -> Result<()> {
+    pub fn insert_match(
+        &self,
+        hh_id: &str,
+        file_id: i64,
+        similarity_score: f64,
+        threshold: f64,
+        engine: &str,
+        matched_via: Option<&str>,
+    ) -> Result<()> {
         let match_date = Utc::now().to_rfc3339();
         self.conn.execute(
-            "INSERT INTO matches (hh_id, file_id, similarity_score, match_date) VALUES (?1, ?2, ?3, ?4)
-             ON CONFLICT(hh_id, file_id) DO UPDATE SET similarity_score=excluded.similarity_score, match_date=excluded.match_date",
-            params![hh_id, file_id, similarity_score, match_date],
+            INSERT_MATCH,
+            params![
+                hh_id,
+                file_id,
+                similarity_score,
+                match_date,
+                threshold,
+                engine,
+                matched_via
+            ],
         )?;
         Ok(())
     }
 
+    /// Mark a stored match as reviewed (or back to pending). Returns false
+    /// when no match of `hh_id` and `file_id` is stored.
+    pub fn set_match_status(&self, hh_id: &str, file_id: i64, status: MatchStatus) -> Result<bool> {
+        let updated = self.conn.execute(
+            "UPDATE matches SET status = ?3 WHERE hh_id = ?1 AND file_id = ?2",
+            params![hh_id, file_id, status.label()],
+        )?;
+        Ok(updated > 0)
+    }
+
     pub fn get_all_files(&self) -> Result<Vec<FileRecord>> {
-        let mut stmt = self
-            .conn
-            .prepare("SELECT id, file_path, file_name FROM files ORDER BY file_name")?;
+        self.files_where("")
+    }
+
+    /// Cached files that aren't on the blocklist, i.e. the ones matching
+    /// should consider.
+    pub fn get_matchable_files(&self) -> Result<Vec<FileRecord>> {
+        self.files_where("WHERE file_path NOT IN (SELECT file_path FROM blocklist)")
+    }
+
+    /// Matchable files (see `get_matchable_files`) added after `since` was
+    /// read.
+    pub fn files_since_generation(&self, since: &ScanGeneration) -> Result<Vec<FileRecord>> {
+        self.files_where(&format!(
+            "WHERE id > {} AND file_path NOT IN (SELECT file_path FROM blocklist)",
+            since.max_file_id
+        ))
+    }
+
+    fn files_where(&self, filter: &str) -> Result<Vec<FileRecord>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT id, file_path, file_name, scan_date, display_name, scan_root
+                 FROM files {} ORDER BY file_name",
+            filter
+        ))?;
 
         let files = stmt.query_map([], |row| {
             Ok(FileRecord {
                 id: row.get(0)?,
                 file_path: row.get(1)?,
                 file_name: row.get(2)?,
+                scan_date: row.get(3)?,
+                display_name: row.get(4)?,
+                scan_root: row.get(5)?,
             })
         })?;
 
         files.collect()
     }
 
+    /// Header metadata of a cached file; `None` when no metadata pass has
+    /// read it.
+    pub fn get_file_metadata(&self, file_path: &str) -> Result<Option<TiffMetadata>> {
+        self.conn.query_row(
+            "SELECT page_count, width, height FROM files WHERE file_path = ?1",
+            params![file_path],
+            |row| {
+                Ok(match (row.get(0)?, row.get(1)?, row.get(2)?) {
+                    (Some(page_count), Some(width), Some(height)) => Some(TiffMetadata {
+                        page_count,
+                        width,
+                        height,
+                    }),
+                    _ => None,
+                })
+            },
+        )
+    }
+
     pub fn get_file_count(&self) -> Result<usize> {
         self.conn
             .query_row("SELECT COUNT(*) FROM files", [], |row| row.get(0))
     }
 
-    pub fn clear_matches_for_id(&self, hh_id: &str) -> Result<()> {
-        self.conn
-            .execute("DELETE FROM matches WHERE hh_id = ?1", params![hh_id])?;
-        Ok(())
+    /// Cached files that no longer exist on disk, as `(id, file_path)` sorted
+    /// by path. Every stored path is checked, so this can take a while on a
+    /// network share.
+    pub fn stale_file_ids(&self) -> Result<Vec<(i64, String)>> {
+        self.stale_file_ids_with_progress(|_, _| {})
     }
 
-    pub fn clear_files(&self) -> Result<()> {
-        self.conn.execute("DELETE FROM files", [])?;
-        self.conn.execute("DELETE FROM matches", [])?;
-        Ok(())
+    /// Like `stale_file_ids`, checking paths in parallel on the current rayon
+    /// pool and calling `progress` with (paths checked, total) as it goes.
+    pub fn stale_file_ids_with_progress<F>(&self, progress: F) -> Result<Vec<(i64, String)>>
+    where
+        F: Fn(usize, usize) + Sync,
+    {
+        Ok(find_stale_files(self.file_locations()?, progress))
     }
 
-    // Reference ID management
-    pub fn start_reference_import(&mut self) -> Result<ReferenceImportSession<'_>> {
-        let tx = self.conn.transaction()?;
-        Ok(ReferenceImportSession { tx })
+    /// Every cached file as `(id, file_path, scan_root)`, sorted by path.
+    pub fn file_locations(&self) -> Result<Vec<(i64, String, Option<String>)>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id, file_path, scan_root FROM files ORDER BY file_path")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        rows.collect()
     }
 
-    pub fn get_all_reference_ids(&self) -> Result<Vec<String>> {
+    /// Up to `n` cached files picked at random, as `(file_path, scan_root)`.
+    pub fn sample_file_paths(&self, n: usize) -> Result<Vec<(String, Option<String>)>> {
         let mut stmt = self
             .conn
-            .prepare("SELECT hh_id FROM reference_ids ORDER BY hh_id")?;
+            .prepare("SELECT file_path, scan_root FROM files ORDER BY RANDOM() LIMIT ?1")?;
+        let rows = stmt.query_map(params![n as i64], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
 
-        let ids = stmt.query_map([], |row| row.get(0))?;
+    /// File names cached at more than one path, e.g. the same scan filed in two
+    /// folders, sorted by name.
+    pub fn find_duplicate_names(&self) -> Result<Vec<DuplicateName>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT file_name, file_path FROM files
+             WHERE file_name IN (
+                 SELECT file_name FROM files GROUP BY file_name HAVING COUNT(*) > 1
+             )
+             ORDER BY file_name, file_path",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
 
-        ids.collect()
+        let mut duplicates: Vec<DuplicateName> = Vec::new();
+        for row in rows {
+            let (file_name, file_path) = row?;
+            match duplicates.last_mut() {
+                Some(last) if last.file_name == file_name => last.paths.push(file_path),
+                _ => duplicates.push(DuplicateName {
+                    file_name,
+                    paths: vec![file_path],
+                }),
+            }
+        }
+        Ok(duplicates)
     }
 
-    pub fn get_reference_id_count(&self) -> Result<usize> {
+    pub fn get_scan_generation(&self) -> Result<ScanGeneration> {
+        self.conn.query_row(
+            "SELECT COUNT(*), COALESCE(MAX(id), 0), COALESCE(MAX(scan_date), '') FROM files",
+            [],
+            |row| {
+                Ok(ScanGeneration {
+                    file_count: row.get(0)?,
+                    max_file_id: row.get(1)?,
+                    last_scan: row.get(2)?,
+                })
+            },
+        )
+    }
+
+    /// Generation the last full match run saw; `None` before any run, or
+    /// once stored matches have been cleared.
+    pub fn matched_generation(&self) -> Result<Option<ScanGeneration>> {
+        Ok(self
+            .get_setting(MATCHED_GENERATION_SETTING)?
+            .and_then(|value| serde_json::from_str(&value).ok()))
+    }
+
+    pub fn set_matched_generation(&self, generation: &ScanGeneration) -> Result<()> {
+        let value = serde_json::to_string(generation)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        self.set_setting(MATCHED_GENERATION_SETTING, &value)
+    }
+
+    /// Record a finished match run, stamped with the current time.
+    pub fn record_match_run(
+        &self,
+        engine: &str,
+        threshold: f64,
+        id_count: usize,
+        match_count: usize,
+    ) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO match_runs (engine, threshold, finished_at, id_count, match_count)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                engine,
+                threshold,
+                Utc::now().to_rfc3339(),
+                id_count as i64,
+                match_count as i64
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// The most recently recorded match run; `None` before the first.
+    pub fn last_match_run(&self) -> Result<Option<MatchRun>> {
         self.conn
-            .query_row("SELECT COUNT(*) FROM reference_ids", [], |row| row.get(0))
+            .query_row(
+                "SELECT engine, threshold, finished_at, id_count, match_count
+                 FROM match_runs ORDER BY id DESC LIMIT 1",
+                [],
+                |row| {
+                    Ok(MatchRun {
+                        engine: row.get(0)?,
+                        threshold: row.get(1)?,
+                        finished_at: row.get(2)?,
+                        id_count: row.get::<_, i64>(3)? as usize,
+                        match_count: row.get::<_, i64>(4)? as usize,
+                    })
+                },
+            )
+            .optional()
     }
 
-    // Search for a single household ID against all files
-    pub fn search_single_id(&self, hh_id: &str, min_similarity: f64) -> Result<Vec<SearchResult>> {
-        // This will be called from the matcher with fuzzy-matched results
-        // For now, return matches from the matches table for this specific hh_id
-        let mut stmt = self.conn.prepare(
-            "SELECT f.file_name, f.file_path, m.similarity_score
-             FROM matches m
-             JOIN files f ON m.file_id = f.id
-             WHERE m.hh_id = ?1 AND m.similarity_score >= ?2
-             ORDER BY m.similarity_score DESC",
+    /// Remember a search, stamped with the current time. Repeating a query
+    /// moves it to the top of `recent_searches` with the new count; only the
+    /// latest `SEARCH_HISTORY_LIMIT` queries are kept.
+    pub fn record_search(&self, query: &str, result_count: usize) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO search_history (query, result_count, searched_at)
+             VALUES (?1, ?2, ?3)",
+            params![query.trim(), result_count as i64, Utc::now().to_rfc3339()],
+        )?;
+        self.conn.execute(
+            "DELETE FROM search_history WHERE id NOT IN
+                (SELECT id FROM search_history ORDER BY id DESC LIMIT ?1)",
+            params![SEARCH_HISTORY_LIMIT as i64],
         )?;
+        Ok(())
+    }
 
-        let results = stmt.query_map(params![hh_id, min_similarity], |row| {
-            Ok(SearchResult {
-                file_name: row.get(0)?,
-                file_path: row.get(1)?,
-                similarity_score: row.get(2)?,
+    /// Up to `limit` distinct searches, most recent first.
+    pub fn recent_searches(&self, limit: usize) -> Result<Vec<SearchHistoryEntry>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT query, result_count, searched_at FROM search_history
+             ORDER BY id DESC LIMIT ?1",
+        )?;
+        let entries = stmt.query_map(params![limit as i64], |row| {
+            Ok(SearchHistoryEntry {
+                query: row.get(0)?,
+                result_count: row.get::<_, i64>(1)? as usize,
+                searched_at: row.get(2)?,
             })
         })?;
+        entries.collect()
+    }
 
-        results.collect()
+    /// Empty the selected tables (see `ClearSelection::effective`) in one
+    /// transaction, so a failure leaves everything as it was. Clearing what
+    /// is already empty is a no-op.
+    pub fn clear(&mut self, selection: ClearSelection) -> Result<()> {
+        let selection = selection.effective();
+        let tx = self.conn.transaction()?;
+        if selection.matches {
+            tx.execute("DELETE FROM matches", [])?;
+            // Nothing is left for a new-files run to add to
+            tx.execute(
+                "DELETE FROM settings WHERE key = ?1",
+                params![MATCHED_GENERATION_SETTING],
+            )?;
+        }
+        if selection.vectors {
+            tx.execute("DELETE FROM file_vectors", [])?;
+        }
+        if selection.files {
+            tx.execute("DELETE FROM files", [])?;
+            // Roots stay registered but need a fresh scan
+            tx.execute(
+                "UPDATE scan_roots SET last_scan = NULL, last_scan_started = NULL",
+                [],
+            )?;
+        }
+        if selection.reference_ids {
+            tx.execute("DELETE FROM reference_ids", [])?;
+        }
+        tx.commit()
     }
 
-    pub fn get_file_vector(&self, file_id: i64, fingerprint: u64) -> Result<Option<Vec<f32>>> {
+    /// Drop every cached file, with its matches and vectors.
+    pub fn clear_files(&mut self) -> Result<()> {
+        self.clear(ClearSelection {
+            files: true,
+            ..Default::default()
+        })
+    }
+
+    pub fn clear_matches(&mut self) -> Result<()> {
+        self.clear(ClearSelection {
+            matches: true,
+            ..Default::default()
+        })
+    }
+
+    pub fn clear_vectors(&mut self) -> Result<()> {
+        self.clear(ClearSelection {
+            vectors: true,
+            ..Default::default()
+        })
+    }
+
+    pub fn clear_reference_ids(&mut self) -> Result<()> {
+        self.clear(ClearSelection {
+            reference_ids: true,
+            ..Default::default()
+        })
+    }
+
+    pub fn get_vector_count(&self) -> Result<usize> {
+        self.conn
+            .query_row("SELECT COUNT(*) FROM file_vectors", [], |row| row.get(0))
+    }
+
+    // Scan root management
+    pub fn add_scan_root(&self, path: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO scan_roots (path) VALUES (?1)",
+            params![path],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_scan_roots(&self) -> Result<Vec<ScanRoot>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT path, last_scan, last_scan_started FROM scan_roots ORDER BY id")?;
+        let roots = stmt.query_map([], |row| {
+            Ok(ScanRoot {
+                path: row.get(0)?,
+                last_scan: row.get(1)?,
+                last_scan_started: row.get(2)?,
+            })
+        })?;
+        roots.collect()
+    }
+
+    /// Stamps of the files cached under `scan_root`, keyed by path. Files
+    /// cached before stamps were recorded map to `None`.
+    pub fn get_file_stamps(&self, scan_root: &str) -> Result<HashMap<String, Option<FileStamp>>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT file_path, file_size, modified FROM files WHERE scan_root = ?1")?;
+        let stamps = stmt.query_map(params![scan_root], |row| {
+            let size: Option<i64> = row.get(1)?;
+            let modified: Option<i64> = row.get(2)?;
+            let stamp = size
+                .zip(modified)
+                .map(|(size, modified)| FileStamp { size, modified });
+            Ok((row.get::<_, String>(0)?, stamp))
+        })?;
+        stamps.collect()
+    }
+
+    /// Unregister a root and drop the files scanned from it (and their matches).
+    /// Returns the number of files removed.
+    pub fn remove_scan_root(&mut self, path: &str) -> Result<usize> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "DELETE FROM matches WHERE file_id IN (SELECT id FROM files WHERE scan_root = ?1)",
+            params![path],
+        )?;
+        let removed = tx.execute("DELETE FROM files WHERE scan_root = ?1", params![path])?;
+        tx.execute("DELETE FROM scan_roots WHERE path = ?1", params![path])?;
+        tx.commit()?;
+        self.cleanup_orphan_vectors()?;
+        Ok(removed)
+    }
+
+    /// Files whose path is `prefix` or lies under it (see `remap_paths`).
+    pub fn count_paths_with_prefix(&self, prefix: &str) -> Result<usize> {
+        self.conn.query_row(
+            &format!(
+                "SELECT COUNT(*) FROM files WHERE {}",
+                under_prefix("file_path")
+            ),
+            params![prefix],
+            |row| row.get(0),
+        )
+    }
+
+    /// Point files under `old_prefix` at `new_prefix` after an archive moved,
+    /// e.g. `/mnt/old/2019/a.tif` to `/mnt/new/2019/a.tif`, without rescanning.
+    /// Only whole leading path components match, so `/mnt/old` leaves
+    /// `/mnt/older` alone. Scan roots under the prefix move too. Remapped
+    /// files get a fresh `scan_date` so search indexes reload them. Returns
+    /// the number of files changed; nothing changes if any path would clash.
+    pub fn remap_paths(&mut self, old_prefix: &str, new_prefix: &str) -> Result<usize> {
+        let tx = self.conn.transaction()?;
+        let changed = tx.execute(
+            &format!(
+                "UPDATE files SET file_path = {}, scan_date = ?3 WHERE {}",
+                replace_prefix("file_path"),
+                under_prefix("file_path")
+            ),
+            params![old_prefix, new_prefix, Utc::now().to_rfc3339()],
+        )?;
+        tx.execute(
+            &format!(
+                "UPDATE files SET scan_root = {} WHERE {}",
+                replace_prefix("scan_root"),
+                under_prefix("scan_root")
+            ),
+            params![old_prefix, new_prefix],
+        )?;
+        tx.execute(
+            &format!(
+                "UPDATE scan_roots SET path = {} WHERE {}",
+                replace_prefix("path"),
+                under_prefix("path")
+            ),
+            params![old_prefix, new_prefix],
+        )?;
+        tx.commit()?;
+        Ok(changed)
+    }
+
+    // User preferences, kept across sessions as plain key/value strings
+    pub fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        self.conn
+            .query_row(
+                "SELECT value FROM settings WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+
+    pub fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO settings (key, value) VALUES (?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET value=excluded.value",
+            params![key, value],
+        )?;
+        Ok(())
+    }
+
+    /// Put file paths on the blocklist, so searches and matching skip them.
+    /// Their stored matches are kept but hidden until they're unblocked.
+    /// Returns how many were newly blocked.
+    pub fn block_files<S: AsRef<str>>(&mut self, file_paths: &[S]) -> Result<usize> {
+        let added_at = Utc::now().to_rfc3339();
+        let tx = self.conn.transaction()?;
+        let mut added = 0;
+        for file_path in file_paths {
+            let file_path = file_path.as_ref();
+            added += tx.execute(
+                "INSERT OR IGNORE INTO blocklist (file_path, added_at) VALUES (?1, ?2)",
+                params![file_path, added_at],
+            )?;
+        }
+        tx.commit()?;
+        Ok(added)
+    }
+
+    /// Returns false when the path wasn't blocked.
+    pub fn unblock_file(&self, file_path: &str) -> Result<bool> {
+        let removed = self.conn.execute(
+            "DELETE FROM blocklist WHERE file_path = ?1",
+            params![file_path],
+        )?;
+        Ok(removed > 0)
+    }
+
+    /// Blocked file paths, sorted.
+    pub fn get_blocklist(&self) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT file_path FROM blocklist ORDER BY file_path")?;
+        let paths = stmt.query_map([], |row| row.get(0))?;
+        paths.collect()
+    }
+
+    // Reference ID management
+    pub fn start_reference_import(&mut self) -> Result<ReferenceImportSession<'_>> {
+        let tx = self.conn.transaction()?;
+        Ok(ReferenceImportSession { tx })
+    }
+
+    pub fn get_all_reference_ids(&self) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT hh_id FROM reference_ids ORDER BY hh_id")?;
+
+        let ids = stmt.query_map([], |row| row.get(0))?;
+
+        ids.collect()
+    }
+
+    /// Reference IDs first imported at or after `since`. Re-importing an ID
+    /// keeps its original date, so these are the IDs new since then.
+    pub fn reference_ids_since(&self, since: DateTime<Utc>) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT hh_id FROM reference_ids WHERE import_date >= ?1 ORDER BY hh_id")?;
+
+        let ids = stmt.query_map(params![since.to_rfc3339()], |row| row.get(0))?;
+
+        ids.collect()
+    }
+
+    /// The entries of `hh_ids` that are imported reference IDs, in the given
+    /// order without repeats.
+    pub fn known_reference_ids(&self, hh_ids: &[String]) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT 1 FROM reference_ids WHERE hh_id = ?1")?;
+        let mut seen = HashSet::new();
+        let mut known = Vec::new();
+        for hh_id in hh_ids {
+            if seen.insert(hh_id.as_str()) && stmt.exists(params![hh_id])? {
+                known.push(hh_id.clone());
+            }
+        }
+        Ok(known)
+    }
+
+    pub fn get_reference_id_count(&self) -> Result<usize> {
+        self.conn
+            .query_row("SELECT COUNT(*) FROM reference_ids", [], |row| row.get(0))
+    }
+
+    // Search for a single household ID against all files
+    // `max_similarity` defaults to 1.0; an upper bound of 1.0 or more is open-ended
+    // so GPU scores a hair above 1.0 are still returned.
+    // Matches stored by a run with a higher threshold than `min_similarity` (or with
+    // no recorded threshold) are skipped, since that run would have dropped scores
+    // the caller still wants; an empty result then signals a recompute.
+    // Equal scores are ordered by `order`.
+    pub fn search_single_id(
+        &self,
+        hh_id: &str,
+        min_similarity: f64,
+        max_similarity: Option<f64>,
+        order: ResultOrder,
+    ) -> Result<Vec<SearchResult>> {
+        // This will be called from the matcher with fuzzy-matched results
+        // For now, return matches from the matches table for this specific hh_id
+        let max_similarity = max_similarity.unwrap_or(1.0);
+        let mut stmt = self.conn.prepare(&format!(
+            "{} {} {} {}",
+            MATCHES_FOR_ID_SELECT,
+            MATCHES_FOR_ID_FROM,
+            MATCHES_FOR_ID_WHERE,
+            order.order_by()
+        ))?;
+
+        let results = stmt.query_map(
+            params![hh_id, min_similarity, max_similarity],
+            Self::search_result_from_row,
+        )?;
+
+        results.collect()
+    }
+
+    /// One page of the matches `search_single_id` would return, in the same
+    /// order: `limit` rows starting at row `offset`.
+    pub fn get_matches_for_id_paged(
+        &self,
+        hh_id: &str,
+        min_similarity: f64,
+        max_similarity: Option<f64>,
+        order: ResultOrder,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<SearchResult>> {
+        let max_similarity = max_similarity.unwrap_or(1.0);
+        let mut stmt = self.conn.prepare(&format!(
+            "{} {} {} {} LIMIT ?4 OFFSET ?5",
+            MATCHES_FOR_ID_SELECT,
+            MATCHES_FOR_ID_FROM,
+            MATCHES_FOR_ID_WHERE,
+            order.order_by()
+        ))?;
+
+        let results = stmt.query_map(
+            params![
+                hh_id,
+                min_similarity,
+                max_similarity,
+                limit as i64,
+                offset as i64
+            ],
+            Self::search_result_from_row,
+        )?;
+
+        results.collect()
+    }
+
+    /// Number of matches `search_single_id` would return.
+    pub fn count_matches_for_id(
+        &self,
+        hh_id: &str,
+        min_similarity: f64,
+        max_similarity: Option<f64>,
+    ) -> Result<usize> {
+        let max_similarity = max_similarity.unwrap_or(1.0);
+        self.conn.query_row(
+            &format!(
+                "SELECT COUNT(*) {} {}",
+                MATCHES_FOR_ID_FROM, MATCHES_FOR_ID_WHERE
+            ),
+            params![hh_id, min_similarity, max_similarity],
+            |row| row.get(0),
+        )
+    }
+
+    /// Up to `limit` stored matches whose household ID or file name contains
+    /// `query` (ignoring ASCII case), best first. Reads what earlier match runs
+    /// stored without scoring anything; an empty query lists the best matches.
+    pub fn search_matches(&self, query: &str, limit: usize) -> Result<Vec<StoredMatch>> {
+        let escaped = query
+            .trim()
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        let mut stmt = self.conn.prepare(&format!(
+            "{}, m.hh_id {}
+             WHERE (m.hh_id LIKE ?1 ESCAPE '\\' OR f.file_name LIKE ?1 ESCAPE '\\')
+               AND f.file_path NOT IN (SELECT file_path FROM blocklist)
+             ORDER BY m.similarity_score DESC, m.hh_id, f.file_name, m.file_id
+             LIMIT ?2",
+            MATCHES_FOR_ID_SELECT, MATCHES_FOR_ID_FROM
+        ))?;
+
+        let matches = stmt.query_map(params![format!("%{}%", escaped), limit as i64], |row| {
+            Ok(StoredMatch {
+                hh_id: row.get(9)?,
+                result: Self::search_result_from_row(row)?,
+            })
+        })?;
+
+        matches.collect()
+    }
+
+    /// Up to `limit` stored matches at or above `min_similarity` for every
+    /// household ID starting with `prefix` (compared as written), grouped by
+    /// ID and best first within each. A range over `hh_id`, so the index on
+    /// it is used instead of scanning every match.
+    pub fn matches_for_id_prefix(
+        &self,
+        prefix: &str,
+        min_similarity: f64,
+        limit: usize,
+    ) -> Result<Vec<StoredMatch>> {
+        // Sorts after every ID that starts with `prefix`
+        let upper = format!("{}{}", prefix, char::MAX);
+        let mut stmt = self.conn.prepare(&format!(
+            "{}, m.hh_id {}
+             WHERE m.hh_id >= ?1 AND m.hh_id < ?2 AND m.similarity_score >= ?3
+               AND f.file_path NOT IN (SELECT file_path FROM blocklist)
+             ORDER BY m.hh_id, m.similarity_score DESC, f.file_name, m.file_id
+             LIMIT ?4",
+            MATCHES_FOR_ID_SELECT, MATCHES_FOR_ID_FROM
+        ))?;
+
+        let matches = stmt.query_map(
+            params![prefix, upper, min_similarity, limit as i64],
+            |row| {
+                Ok(StoredMatch {
+                    hh_id: row.get(9)?,
+                    result: Self::search_result_from_row(row)?,
+                })
+            },
+        )?;
+
+        matches.collect()
+    }
+
+    fn search_result_from_row(row: &rusqlite::Row) -> Result<SearchResult> {
+        Ok(SearchResult {
+            file_name: row.get(0)?,
+            file_path: row.get(1)?,
+            similarity_score: row.get(2)?,
+            threshold: row.get(3)?,
+            engine: row.get(4)?,
+            display_name: row.get(5)?,
+            scan_root: row.get(6)?,
+            matched_via: row.get(7)?,
+            status: Self::status_from_row(row, 8)?,
+            explanation: None,
+        })
+    }
+
+    // Unknown labels (from a newer version) read as pending
+    fn status_from_row(row: &rusqlite::Row, index: usize) -> Result<MatchStatus> {
+        let label: String = row.get(index)?;
+        Ok(MatchStatus::from_label(&label).unwrap_or_default())
+    }
+
+    /// Stored matches per similarity bucket as `(low, high, count)`:
+    /// `buckets` equal-width bins over 0–1, the last one including a perfect
+    /// 1.0.
+    pub fn score_histogram(&self, buckets: usize) -> Result<Vec<(f64, f64, usize)>> {
+        let width = 1.0 / buckets as f64;
+        let mut histogram: Vec<_> = (0..buckets)
+            .map(|bin| (bin as f64 * width, (bin + 1) as f64 * width, 0))
+            .collect();
+        if buckets == 0 {
+            return Ok(histogram);
+        }
+        // The small offset keeps e.g. 0.7 * 20 = 13.999… out of the bin below
+        let mut stmt = self.conn.prepare(
+            "SELECT MAX(0, MIN(CAST(similarity_score * ?1 + 1e-9 AS INTEGER), ?1 - 1)) AS bin,
+                    COUNT(*)
+             FROM matches
+             GROUP BY bin",
+        )?;
+        let rows = stmt.query_map(params![buckets as i64], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, usize>(1)?))
+        })?;
+        for row in rows {
+            let (bin, count) = row?;
+            histogram[bin as usize].2 = count;
+        }
+        Ok(histogram)
+    }
+
+    pub fn get_match_count(&self) -> Result<usize> {
+        self.conn
+            .query_row("SELECT COUNT(*) FROM matches", [], |row| row.get(0))
+    }
+
+    /// Number of stored matches with `status`.
+    pub fn count_matches_with_status(&self, status: MatchStatus) -> Result<usize> {
+        self.conn.query_row(
+            "SELECT COUNT(*) FROM matches WHERE status = ?1",
+            params![status.label()],
+            |row| row.get(0),
+        )
+    }
+
+    /// Stream every stored match at or above `min_similarity` (and with
+    /// `status`, when given), joined with its file and ordered by hh_id, score
+    /// and file name. Matches of blocked files are left out. Rows are handed
+    /// to `visit` one at a time so large tables never sit in memory; return
+    /// `false` from the visitor to stop early. Returns the number of rows
+    /// visited.
+    pub fn get_all_matches<F>(
+        &self,
+        min_similarity: f64,
+        status: Option<MatchStatus>,
+        mut visit: F,
+    ) -> Result<usize>
+    where
+        F: FnMut(MatchRecord) -> bool,
+    {
+        let mut stmt = self.conn.prepare(
+            "SELECT m.hh_id, f.file_name, f.file_path, m.similarity_score, m.match_date,
+                    m.status
+             FROM matches m
+             JOIN files f ON m.file_id = f.id
+             WHERE m.similarity_score >= ?1 AND (?2 IS NULL OR m.status = ?2)
+               AND f.file_path NOT IN (SELECT file_path FROM blocklist)
+             ORDER BY m.hh_id, m.similarity_score DESC, f.file_name, m.file_id",
+        )?;
+
+        let mut rows = stmt.query(params![min_similarity, status.map(MatchStatus::label)])?;
+        let mut visited = 0;
+        while let Some(row) = rows.next()? {
+            visited += 1;
+            let record = MatchRecord {
+                hh_id: row.get(0)?,
+                file_name: row.get(1)?,
+                file_path: row.get(2)?,
+                similarity_score: row.get(3)?,
+                match_date: row.get(4)?,
+                status: Self::status_from_row(row, 5)?,
+            };
+            if !visit(record) {
+                break;
+            }
+        }
+
+        Ok(visited)
+    }
+
+    /// Write the whole matches table (at or above `min_similarity`, with
+    /// `status` when given) to `writer` as `hh_id, file_name, file_path,
+    /// similarity_score, match_date, status` rows, streaming straight from the
+    /// query. `on_row` receives the running row count so callers can report
+    /// progress. Returns the number of rows written.
+    pub fn export_matches_csv<W, F>(
+        &self,
+        writer: &mut csv::Writer<W>,
+        min_similarity: f64,
+        status: Option<MatchStatus>,
+        mut on_row: F,
+    ) -> std::result::Result<usize, String>
+    where
+        W: std::io::Write,
+        F: FnMut(usize),
+    {
+        writer
+            .write_record([
+                "hh_id",
+                "file_name",
+                "file_path",
+                "similarity_score",
+                "match_date",
+                "status",
+            ])
+            .map_err(|e| format!("Failed to write headers: {}", e))?;
+
+        let mut written = 0usize;
+        let mut write_error = None;
+        self.get_all_matches(min_similarity, status, |record| {
+            let score = format!("{:.4}", record.similarity_score);
+            if let Err(e) = writer.write_record([
+                record.hh_id.as_str(),
+                record.file_name.as_str(),
+                record.file_path.as_str(),
+                score.as_str(),
+                record.match_date.as_str(),
+                record.status.label(),
+            ]) {
+                write_error = Some(format!("Failed to write record: {}", e));
+                return false;
+            }
+            written += 1;
+            on_row(written);
+            true
+        })
+        .map_err(|e| format!("Failed to read matches: {}", e))?;
+
+        if let Some(e) = write_error {
+            return Err(e);
+        }
+
+        writer
+            .flush()
+            .map_err(|e| format!("Failed to flush CSV: {}", e))?;
+
+        Ok(written)
+    }
+
+    /// Cached vector for `file_id` if it was stored under `fingerprint` and holds
+    /// exactly `dim` floats; anything else must be re-encoded.
+    pub fn get_file_vector(
+        &self,
+        file_id: i64,
+        fingerprint: u64,
+        dim: usize,
+    ) -> Result<Option<Vec<f32>>> {
         let mut stmt = self.conn.prepare_cached(
             "SELECT fingerprint, vector_blob FROM file_vectors WHERE file_id = ?1",
         )?;
@@ -313,7 +1788,7 @@ impl Database {
 
         if let Some((stored_fingerprint, blob)) = row {
             if stored_fingerprint == fingerprint {
-                if blob.len() % std::mem::size_of::<f32>() != 0 {
+                if blob.len() != dim * std::mem::size_of::<f32>() {
                     return Ok(None);
                 }
                 let floats = cast_slice::<u8, f32>(&blob).to_vec();
@@ -324,18 +1799,52 @@ impl Database {
         Ok(None)
     }
 
-    pub fn upsert_file_vector(&self, file_id: i64, fingerprint: u64, data: &[f32]) -> Result<()> {
-        let blob = cast_slice(data);
-        self.conn.execute(
-            "INSERT INTO file_vectors (file_id, fingerprint, vector_blob, updated_at)
-             VALUES (?1, ?2, ?3, ?4)
-             ON CONFLICT(file_id) DO UPDATE SET
-                 fingerprint=excluded.fingerprint,
-                 vector_blob=excluded.vector_blob,
-                 updated_at=excluded.updated_at",
-            params![file_id, fingerprint as i64, blob, Utc::now().to_rfc3339()],
-        )?;
-        Ok(())
+    /// Persist many vectors in a single transaction. Entries are
+    /// `(file_id, fingerprint, vector)`; returns the number written.
+    pub fn upsert_file_vectors(&mut self, entries: &[(i64, u64, Vec<f32>)]) -> Result<usize> {
+        if entries.is_empty() {
+            return Ok(0);
+        }
+
+        let mut session = self.start_vector_import()?;
+        for (file_id, fingerprint, data) in entries {
+            session.upsert(*file_id, *fingerprint, data)?;
+        }
+        session.commit()?;
+        Ok(entries.len())
+    }
+
+    /// Add `files` as read from another cache, keeping their IDs, so matches
+    /// stored here for those IDs resolve to the same files. Rows already
+    /// holding one of the IDs or paths are replaced. Returns the number written.
+    pub fn copy_files(&mut self, files: &[&FileRecord]) -> Result<usize> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT OR REPLACE INTO files
+                     (id, file_path, file_name, scan_date, display_name, scan_root)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for file in files {
+                stmt.execute(params![
+                    file.id,
+                    file.file_path,
+                    file.file_name,
+                    file.scan_date,
+                    file.display_name,
+                    file.scan_root
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(files.len())
+    }
+
+    /// Refresh the query planner's statistics with `ANALYZE` and
+    /// `PRAGMA optimize`. Run it after bulk inserts: with stale statistics
+    /// SQLite can pick a poor index for the match lookups on large caches.
+    pub fn optimize(&self) -> Result<()> {
+        self.conn.execute_batch("ANALYZE; PRAGMA optimize;")
     }
 
     pub fn cleanup_orphan_vectors(&self) -> Result<()> {
@@ -346,3 +1855,1147 @@ impl Database {
         Ok(())
     }
 }
+
+/// The `(id, file_path)` of each entry in `files` (as returned by
+/// `Database::file_locations`) whose file no longer exists. Runs on the
+/// current rayon pool, keeps the input order and calls `progress` with
+/// (paths checked, total).
+pub fn find_stale_files<F>(
+    files: Vec<(i64, String, Option<String>)>,
+    progress: F,
+) -> Vec<(i64, String)>
+where
+    F: Fn(usize, usize) + Sync,
+{
+    let total = files.len();
+    let checked = AtomicUsize::new(0);
+    progress(0, total);
+    files
+        .into_par_iter()
+        .filter_map(|(id, file_path, scan_root)| {
+            let exists = resolve_file_path(&file_path, scan_root.as_deref()).exists();
+            let done = checked.fetch_add(1, Ordering::Relaxed) + 1;
+            if done.is_multiple_of(STALE_CHECK_PROGRESS_STEP) || done == total {
+                progress(done, total);
+            }
+            (!exists).then_some((id, file_path))
+        })
+        .collect()
+}
+
+/// Absolute path of a cached file; relative paths from older caches hang off
+/// their scan root (or the current directory when none was recorded).
+pub fn resolve_file_path(file_path: &str, scan_root: Option<&str>) -> PathBuf {
+    let path = Path::new(file_path);
+    match scan_root {
+        _ if path.is_absolute() => path.to_path_buf(),
+        Some(root) => Path::new(root).join(path),
+        None => std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()),
+    }
+}
+
+/// SQL condition: `column` is the prefix bound to `?1` or a path under it,
+/// with either separator.
+fn under_prefix(column: &str) -> String {
+    format!(
+        "({c} = ?1 OR substr({c}, 1, length(?1) + 1) IN (?1 || '/', ?1 || '\\'))",
+        c = column
+    )
+}
+
+/// SQL expression: `column` with its leading `?1` swapped for `?2`.
+fn replace_prefix(column: &str) -> String {
+    format!("?2 || substr({}, length(?1) + 1)", column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn seeded_db() -> Database {
+        let mut db = Database::new(":memory:").expect("in-memory database");
+        {
+            let mut session = db.start_file_import().unwrap();
+            session
+                .upsert_file("/scans/HH001_a.tif", "HH001_a.tif")
+                .unwrap();
+            session
+                .upsert_file("/scans/HH001_b.tif", "HH001_b.tif")
+                .unwrap();
+            session
+                .upsert_file("/scans/HH002.tif", "HH002.tif")
+                .unwrap();
+            session.commit().unwrap();
+        }
+        let a = db.get_file_id("/scans/HH001_a.tif").unwrap();
+        let b = db.get_file_id("/scans/HH001_b.tif").unwrap();
+        let c = db.get_file_id("/scans/HH002.tif").unwrap();
+        db.insert_match("HH001", a, 0.95, 0.5, "cpu", None).unwrap();
+        db.insert_match("HH001", b, 0.60, 0.5, "cpu", None).unwrap();
+        db.insert_match("HH002", c, 1.0, 0.5, "cpu", None).unwrap();
+        db
+    }
+
+    #[test]
+    fn id_prefixes_collect_the_matches_of_every_id_grouped() {
+        let db = seeded_db();
+        let c = db.get_file_id("/scans/HH002.tif").unwrap();
+        db.insert_match("HH010", c, 0.4, 0.3, "cpu", None).unwrap();
+        db.insert_match("XHH001", c, 0.9, 0.3, "cpu", None).unwrap();
+        let found = |prefix: &str, min_similarity: f64| -> Vec<(String, String)> {
+            db.matches_for_id_prefix(prefix, min_similarity, 100)
+                .unwrap()
+                .into_iter()
+                .map(|stored| (stored.hh_id, stored.result.file_name))
+                .collect()
+        };
+        let pair = |id: &str, name: &str| (id.to_string(), name.to_string());
+
+        assert_eq!(
+            found("HH00", 0.5),
+            vec![
+                pair("HH001", "HH001_a.tif"),
+                pair("HH001", "HH001_b.tif"),
+                pair("HH002", "HH002.tif"),
+            ]
+        );
+        assert_eq!(found("HH0", 0.5).len(), 3);
+        assert_eq!(found("HH0", 0.3).last(), Some(&pair("HH010", "HH002.tif")));
+        assert!(found("hh00", 0.0).is_empty());
+        assert!(found("HH%", 0.0).is_empty());
+        assert_eq!(db.matches_for_id_prefix("HH", 0.0, 2).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn blocked_files_are_left_out_of_matching() {
+        let mut db = seeded_db();
+        let files = db.get_all_files().unwrap();
+        let blocked = files[0].file_path.clone();
+        assert_eq!(blocked, "/scans/HH001_a.tif");
+
+        assert_eq!(db.block_files(&[&blocked, &blocked]).unwrap(), 1);
+        assert_eq!(db.get_blocklist().unwrap(), std::slice::from_ref(&blocked));
+        // Its one stored match is kept but no longer read back
+        assert_eq!(db.get_match_count().unwrap(), 3);
+        let names = |db: &Database| -> Vec<String> {
+            db.search_single_id("HH001", 0.5, None, ResultOrder::FileName)
+                .unwrap()
+                .into_iter()
+                .map(|result| result.file_name)
+                .collect()
+        };
+        assert_eq!(names(&db), ["HH001_b.tif"]);
+        assert!(db.search_matches("HH001_a", 10).unwrap().is_empty());
+        let matchable = db.get_matchable_files().unwrap();
+        assert_eq!(matchable.len(), files.len() - 1);
+        assert!(matchable.iter().all(|file| file.file_path != blocked));
+        // Blocking leaves the cached file itself alone
+        assert_eq!(db.get_all_files().unwrap().len(), files.len());
+
+        assert!(db.unblock_file(&blocked).unwrap());
+        assert!(!db.unblock_file(&blocked).unwrap());
+        assert_eq!(db.get_matchable_files().unwrap().len(), files.len());
+        assert_eq!(names(&db), ["HH001_a.tif", "HH001_b.tif"]);
+    }
+
+    #[test]
+    fn the_latest_match_run_is_read_back() {
+        let db = Database::new(":memory:").expect("in-memory database");
+        assert_eq!(db.last_match_run().unwrap(), None);
+
+        db.record_match_run("gpu", 0.7, 1200, 950).unwrap();
+        db.record_match_run("cpu", 0.85, 40, 31).unwrap();
+
+        let run = db.last_match_run().unwrap().expect("a recorded run");
+        assert_eq!(run.engine, "cpu");
+        assert_eq!(run.threshold, 0.85);
+        assert_eq!((run.id_count, run.match_count), (40, 31));
+        assert!(DateTime::parse_from_rfc3339(&run.finished_at).is_ok());
+    }
+
+    #[test]
+    fn repeated_searches_move_to_the_top_of_the_history() {
+        let db = Database::new(":memory:").expect("in-memory database");
+        assert!(db.recent_searches(10).unwrap().is_empty());
+
+        db.record_search("HH001", 3).unwrap();
+        db.record_search("HH002", 0).unwrap();
+        db.record_search(" HH003 ", 7).unwrap();
+        db.record_search("HH001", 4).unwrap();
+
+        let history = db.recent_searches(10).unwrap();
+        let queries: Vec<(&str, usize)> = history
+            .iter()
+            .map(|entry| (entry.query.as_str(), entry.result_count))
+            .collect();
+        assert_eq!(queries, [("HH001", 4), ("HH003", 7), ("HH002", 0)]);
+        assert!(DateTime::parse_from_rfc3339(&history[0].searched_at).is_ok());
+        assert_eq!(db.recent_searches(2).unwrap().len(), 2);
+
+        for i in 0..SEARCH_HISTORY_LIMIT {
+            db.record_search(&format!("ID{}", i), i).unwrap();
+        }
+        let history = db.recent_searches(usize::MAX).unwrap();
+        assert_eq!(history.len(), SEARCH_HISTORY_LIMIT);
+        assert_eq!(history[0].query, format!("ID{}", SEARCH_HISTORY_LIMIT - 1));
+        assert!(history.iter().all(|entry| entry.query != "HH001"));
+    }
+
+    #[test]
+    fn optimize_leaves_lookups_unchanged() {
+        let mut db = Database::new(":memory:").expect("in-memory database");
+        {
+            let mut session = db.start_file_import().unwrap();
+            for i in 0..50 {
+                let name = format!("HH{:03}.tif", i);
+                session
+                    .upsert_file(&format!("/scans/{}", name), &name)
+                    .unwrap();
+            }
+            session.commit().unwrap();
+        }
+        for i in 0..50 {
+            let id = db.get_file_id(&format!("/scans/HH{:03}.tif", i)).unwrap();
+            db.insert_match(&format!("HH{:03}", i), id, 0.9, 0.5, "cpu", None)
+                .unwrap();
+        }
+        let before = db
+            .search_single_id("HH007", 0.5, None, ResultOrder::FileName)
+            .unwrap();
+
+        db.optimize().expect("optimize succeeds");
+
+        let after = db
+            .search_single_id("HH007", 0.5, None, ResultOrder::FileName)
+            .unwrap();
+        assert_eq!(after.len(), 1);
+        assert_eq!(after[0].file_name, before[0].file_name);
+        assert_eq!(db.get_match_count().unwrap(), 50);
+    }
+
+    #[test]
+    fn search_single_id_respects_similarity_band() {
+        let mut db = Database::new(":memory:").expect("in-memory database");
+        let scores = [0.55, 0.62, 0.70, 0.74, 0.80, 0.97, 1.0];
+        {
+            let mut session = db.start_file_import().unwrap();
+            for (i, _) in scores.iter().enumerate() {
+                let name = format!("HH001_{}.tif", i);
+                session
+                    .upsert_file(&format!("/scans/{}", name), &name)
+                    .unwrap();
+            }
+            session.commit().unwrap();
+        }
+        for (i, score) in scores.iter().enumerate() {
+            let id = db.get_file_id(&format!("/scans/HH001_{}.tif", i)).unwrap();
+            db.insert_match("HH001", id, *score, 0.5, "cpu", None)
+                .unwrap();
+        }
+
+        let band = db
+            .search_single_id("HH001", 0.6, Some(0.75), ResultOrder::FileName)
+            .unwrap();
+        let band_scores: Vec<f64> = band.iter().map(|r| r.similarity_score).collect();
+        assert_eq!(band_scores, vec![0.74, 0.70, 0.62]);
+
+        let open = db
+            .search_single_id("HH001", 0.6, None, ResultOrder::FileName)
+            .unwrap();
+        assert_eq!(open.len(), 6);
+        assert!((open[0].similarity_score - 1.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn paged_matches_cover_the_full_result_set() {
+        let mut db = Database::new(":memory:").expect("in-memory database");
+        // Repeated scores check that ties keep a stable order across pages
+        let scores = [0.9, 0.8, 0.8, 0.8, 0.7, 0.65, 0.6, 0.5];
+        {
+            let mut session = db.start_file_import().unwrap();
+            for i in 0..scores.len() {
+                let name = format!("HH001_{}.tif", i);
+                session
+                    .upsert_file(&format!("/scans/{}", name), &name)
+                    .unwrap();
+            }
+            session.commit().unwrap();
+        }
+        for (i, score) in scores.iter().enumerate() {
+            let id = db.get_file_id(&format!("/scans/HH001_{}.tif", i)).unwrap();
+            db.insert_match("HH001", id, *score, 0.5, "cpu", None)
+                .unwrap();
+        }
+
+        let all = db
+            .search_single_id("HH001", 0.5, None, ResultOrder::FileName)
+            .unwrap();
+        assert_eq!(db.count_matches_for_id("HH001", 0.5, None).unwrap(), 8);
+
+        let mut paged = Vec::new();
+        for offset in (0..all.len()).step_by(3) {
+            let page = db
+                .get_matches_for_id_paged("HH001", 0.5, None, ResultOrder::FileName, 3, offset)
+                .unwrap();
+            assert_eq!(page.len(), 3.min(all.len() - offset));
+            paged.extend(page);
+        }
+        let paths = |results: &[SearchResult]| -> Vec<String> {
+            results.iter().map(|r| r.file_path.clone()).collect()
+        };
+        assert_eq!(paths(&paged), paths(&all));
+
+        // Past the end is empty rather than an error
+        assert!(db
+            .get_matches_for_id_paged("HH001", 0.5, None, ResultOrder::FileName, 3, 8)
+            .unwrap()
+            .is_empty());
+
+        // Counts and pages follow the similarity band
+        assert_eq!(db.count_matches_for_id("HH001", 0.6, Some(0.8)).unwrap(), 6);
+        let band = db
+            .get_matches_for_id_paged("HH001", 0.6, Some(0.8), ResultOrder::FileName, 2, 4)
+            .unwrap();
+        let band_scores: Vec<f64> = band.iter().map(|r| r.similarity_score).collect();
+        assert_eq!(band_scores, vec![0.65, 0.6]);
+        assert_eq!(db.count_matches_for_id("HH404", 0.0, None).unwrap(), 0);
+    }
+
+    #[test]
+    fn tied_scores_follow_the_result_order() {
+        let mut db = Database::new(":memory:").expect("in-memory database");
+        // Inserted out of name order so file ids don't happen to sort by name
+        let names = ["HH001_c.tif", "HH001_a.tif", "HH001_b.tif"];
+        {
+            let mut session = db.start_file_import().unwrap();
+            for name in names {
+                session
+                    .upsert_file(&format!("/scans/{}", name), name)
+                    .unwrap();
+            }
+            session.commit().unwrap();
+        }
+        for (name, scan_date) in names.iter().zip(["2024-01-02", "2024-01-01", "2024-01-03"]) {
+            db.conn
+                .execute(
+                    "UPDATE files SET scan_date = ?1 WHERE file_name = ?2",
+                    params![scan_date, name],
+                )
+                .unwrap();
+            let id = db.get_file_id(&format!("/scans/{}", name)).unwrap();
+            db.insert_match("HH001", id, 0.8, 0.5, "cpu", None).unwrap();
+        }
+        let names_in = |order| -> Vec<String> {
+            db.search_single_id("HH001", 0.5, None, order)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.file_name)
+                .collect()
+        };
+
+        assert_eq!(
+            names_in(ResultOrder::FileName),
+            ["HH001_a.tif", "HH001_b.tif", "HH001_c.tif"]
+        );
+        assert_eq!(
+            names_in(ResultOrder::ScanDate),
+            ["HH001_b.tif", "HH001_c.tif", "HH001_a.tif"]
+        );
+        // The same order on every call, and page by page
+        assert_eq!(
+            names_in(ResultOrder::FileName),
+            names_in(ResultOrder::FileName)
+        );
+        let second_page = db
+            .get_matches_for_id_paged("HH001", 0.5, None, ResultOrder::ScanDate, 2, 2)
+            .unwrap();
+        assert_eq!(second_page[0].file_name, "HH001_a.tif");
+    }
+
+    #[test]
+    fn search_matches_filters_stored_matches_by_substring() {
+        let mut db = Database::new(":memory:").expect("in-memory database");
+        let stored = [
+            ("HH001", "HH001_front.tif", 0.9),
+            ("HH001", "misc_HH001.tif", 0.6),
+            ("HH0012", "HH0012.tif", 0.95),
+            ("XYZ789", "XYZ789_report.tif", 0.7),
+            ("A_B", "A_B.tif", 0.8),
+            ("AXB", "AXB.tif", 0.8),
+        ];
+        {
+            let mut session = db.start_file_import().unwrap();
+            for (_, name, _) in stored {
+                session
+                    .upsert_file(&format!("/scans/{}", name), name)
+                    .unwrap();
+            }
+            session.commit().unwrap();
+        }
+        for (hh_id, name, score) in stored {
+            let id = db.get_file_id(&format!("/scans/{}", name)).unwrap();
+            db.insert_match(hh_id, id, score, 0.5, "cpu", None).unwrap();
+        }
+        let found = |query: &str, limit| -> Vec<(String, String)> {
+            db.search_matches(query, limit)
+                .unwrap()
+                .into_iter()
+                .map(|m| (m.hh_id, m.result.file_name))
+                .collect()
+        };
+        let pair = |hh_id: &str, name: &str| (hh_id.to_string(), name.to_string());
+
+        // Partial IDs match either column, case-insensitively, best first
+        assert_eq!(
+            found("hh001", 10),
+            [
+                pair("HH0012", "HH0012.tif"),
+                pair("HH001", "HH001_front.tif"),
+                pair("HH001", "misc_HH001.tif"),
+            ]
+        );
+        assert_eq!(found("report", 10), [pair("XYZ789", "XYZ789_report.tif")]);
+        // `_` and `%` are literal, not wildcards
+        assert_eq!(found("A_B", 10), [pair("A_B", "A_B.tif")]);
+        assert!(found("%", 10).is_empty());
+        // An empty query lists the best matches up to the limit
+        assert_eq!(
+            found("", 2),
+            [
+                pair("HH0012", "HH0012.tif"),
+                pair("HH001", "HH001_front.tif")
+            ]
+        );
+    }
+
+    #[test]
+    fn score_histogram_counts_each_bucket() {
+        let mut db = Database::new(":memory:").expect("in-memory database");
+        let scores = [0.0, 0.04, 0.05, 0.7, 0.72, 0.749, 0.75, 0.99, 1.0];
+        {
+            let mut session = db.start_file_import().unwrap();
+            for i in 0..scores.len() {
+                let name = format!("HH001_{}.tif", i);
+                session
+                    .upsert_file(&format!("/scans/{}", name), &name)
+                    .unwrap();
+            }
+            session.commit().unwrap();
+        }
+        for (i, score) in scores.iter().enumerate() {
+            let id = db.get_file_id(&format!("/scans/HH001_{}.tif", i)).unwrap();
+            db.insert_match("HH001", id, *score, 0.0, "cpu", None)
+                .unwrap();
+        }
+
+        let histogram = db.score_histogram(20).unwrap();
+        assert_eq!(histogram.len(), 20);
+        let counts: Vec<usize> = histogram.iter().map(|&(_, _, count)| count).collect();
+        assert_eq!(counts.iter().sum::<usize>(), scores.len());
+        assert_eq!(counts[0], 2);
+        assert_eq!(counts[1], 1);
+        assert_eq!(counts[14], 3);
+        assert_eq!(counts[15], 1);
+        // 0.99 and the perfect 1.0 share the top bucket
+        assert_eq!(counts[19], 2);
+        let (low, high, _) = histogram[14];
+        assert!((low - 0.7).abs() < 1e-9 && (high - 0.75).abs() < 1e-9);
+        assert!((histogram[19].1 - 1.0).abs() < 1e-9);
+
+        assert!(db.score_histogram(0).unwrap().is_empty());
+        assert_eq!(
+            Database::new(":memory:")
+                .unwrap()
+                .score_histogram(4)
+                .unwrap(),
+            vec![
+                (0.0, 0.25, 0),
+                (0.25, 0.5, 0),
+                (0.5, 0.75, 0),
+                (0.75, 1.0, 0)
+            ]
+        );
+    }
+
+    /// `seeded_db` plus a vector per file and two reference IDs.
+    fn fully_seeded_db() -> Database {
+        let mut db = seeded_db();
+        let vectors: Vec<(i64, u64, Vec<f32>)> = db
+            .get_all_files()
+            .unwrap()
+            .iter()
+            .map(|file| (db.get_file_id(&file.file_path).unwrap(), 1, vec![0.5]))
+            .collect();
+        db.upsert_file_vectors(&vectors).unwrap();
+        let mut session = db.start_reference_import().unwrap();
+        session.insert("HH001").unwrap();
+        session.insert("HH002").unwrap();
+        session.commit().unwrap();
+        db
+    }
+
+    /// Rows left in (files, matches, vectors, reference IDs).
+    fn table_counts(db: &Database) -> (usize, usize, usize, usize) {
+        (
+            db.get_file_count().unwrap(),
+            db.get_match_count().unwrap(),
+            db.get_vector_count().unwrap(),
+            db.get_reference_id_count().unwrap(),
+        )
+    }
+
+    #[test]
+    fn clearing_only_empties_the_selected_tables() {
+        let mut db = fully_seeded_db();
+        assert_eq!(table_counts(&db), (3, 3, 3, 2));
+
+        db.clear_matches().unwrap();
+        assert_eq!(table_counts(&db), (3, 0, 3, 2));
+        db.clear_vectors().unwrap();
+        assert_eq!(table_counts(&db), (3, 0, 0, 2));
+        db.clear_reference_ids().unwrap();
+        assert_eq!(table_counts(&db), (3, 0, 0, 0));
+        // Clearing twice is harmless
+        db.clear_reference_ids().unwrap();
+        assert_eq!(table_counts(&db), (3, 0, 0, 0));
+
+        // Files take their matches and vectors with them, but not the IDs
+        let mut db = fully_seeded_db();
+        db.add_scan_root("/scans").unwrap();
+        db.clear_files().unwrap();
+        assert_eq!(table_counts(&db), (0, 0, 0, 2));
+        assert_eq!(db.get_scan_roots().unwrap()[0].last_scan, None);
+
+        let mut db = fully_seeded_db();
+        db.clear(ClearSelection {
+            matches: true,
+            reference_ids: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(table_counts(&db), (3, 0, 3, 0));
+    }
+
+    #[test]
+    fn a_failed_clear_changes_nothing() {
+        let mut db = fully_seeded_db();
+        db.conn
+            .execute_batch(
+                "CREATE TRIGGER keep_ids BEFORE DELETE ON reference_ids
+                 BEGIN SELECT RAISE(ABORT, 'locked'); END;",
+            )
+            .unwrap();
+        let everything = ClearSelection {
+            files: true,
+            reference_ids: true,
+            ..Default::default()
+        };
+        assert!(db.clear(everything).is_err());
+        assert_eq!(table_counts(&db), (3, 3, 3, 2));
+    }
+
+    #[test]
+    fn upsert_file_vectors_round_trip() {
+        let mut db = seeded_db();
+        let a = db.get_file_id("/scans/HH001_a.tif").unwrap();
+        let b = db.get_file_id("/scans/HH001_b.tif").unwrap();
+        let entries = vec![
+            (a, 11u64, vec![0.5f32, 0.25, 0.0]),
+            (b, 22u64, vec![1.0f32; 4]),
+        ];
+
+        assert_eq!(db.upsert_file_vectors(&entries).unwrap(), 2);
+        assert_eq!(
+            db.get_file_vector(a, 11, 3).unwrap(),
+            Some(entries[0].2.clone())
+        );
+        assert_eq!(
+            db.get_file_vector(b, 22, 4).unwrap(),
+            Some(entries[1].2.clone())
+        );
+        assert_eq!(db.get_file_vector(a, 99, 3).unwrap(), None);
+        // A blob of another dimension is never returned
+        assert_eq!(db.get_file_vector(b, 22, 3).unwrap(), None);
+
+        // Re-upserting replaces the stored vector and fingerprint
+        db.upsert_file_vectors(&[(a, 33, vec![0.1f32, 0.2])])
+            .unwrap();
+        assert_eq!(db.get_file_vector(a, 11, 2).unwrap(), None);
+        assert_eq!(
+            db.get_file_vector(a, 33, 2).unwrap(),
+            Some(vec![0.1f32, 0.2])
+        );
+    }
+
+    #[test]
+    fn export_matches_csv_round_trips() {
+        let db = seeded_db();
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        let mut progress = Vec::new();
+        let written = db
+            .export_matches_csv(&mut writer, 0.0, None, |rows| progress.push(rows))
+            .expect("export succeeds");
+        assert_eq!(written, 3);
+        assert_eq!(progress, vec![1, 2, 3]);
+
+        let bytes = writer.into_inner().expect("flushed writer");
+        let mut reader = csv::Reader::from_reader(bytes.as_slice());
+        assert_eq!(
+            reader.headers().unwrap(),
+            vec![
+                "hh_id",
+                "file_name",
+                "file_path",
+                "similarity_score",
+                "match_date",
+                "status"
+            ]
+        );
+        let rows: Vec<csv::StringRecord> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(&rows[0][0], "HH001");
+        assert_eq!(&rows[0][1], "HH001_a.tif");
+        assert_eq!(&rows[0][2], "/scans/HH001_a.tif");
+        assert!((rows[0][3].parse::<f64>().unwrap() - 0.95).abs() < 1e-9);
+        assert!(!rows[0][4].is_empty());
+        assert_eq!(&rows[2][0], "HH002");
+    }
+
+    #[test]
+    fn export_matches_csv_respects_min_similarity() {
+        let db = seeded_db();
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        let written = db
+            .export_matches_csv(&mut writer, 0.9, None, |_| {})
+            .expect("export succeeds");
+        assert_eq!(written, 2);
+    }
+
+    #[test]
+    fn export_matches_csv_filters_by_status() {
+        let db = seeded_db();
+        let a = db.get_file_id("/scans/HH001_a.tif").unwrap();
+        db.set_match_status("HH001", a, MatchStatus::Confirmed)
+            .unwrap();
+        let export = |status| {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            let written = db
+                .export_matches_csv(&mut writer, 0.0, status, |_| {})
+                .expect("export succeeds");
+            let bytes = writer.into_inner().expect("flushed writer");
+            (written, String::from_utf8(bytes).unwrap())
+        };
+
+        let (written, csv) = export(Some(MatchStatus::Confirmed));
+        assert_eq!(written, 1);
+        assert!(csv.contains("HH001_a.tif"));
+        assert!(csv.trim_end().ends_with(",confirmed"));
+        assert_eq!(export(Some(MatchStatus::Pending)).0, 2);
+        assert_eq!(export(Some(MatchStatus::Rejected)).0, 0);
+    }
+
+    #[test]
+    fn reviewed_matches_survive_rematching() {
+        let mut db = seeded_db();
+        let a = db.get_file_id("/scans/HH001_a.tif").unwrap();
+        let status_of = |db: &Database, name: &str| {
+            db.search_single_id("HH001", 0.5, None, ResultOrder::default())
+                .unwrap()
+                .into_iter()
+                .find(|result| result.file_name == name)
+                .map(|result| (result.status, result.similarity_score))
+        };
+
+        assert!(db
+            .set_match_status("HH001", a, MatchStatus::Confirmed)
+            .unwrap());
+        assert!(!db
+            .set_match_status("HH002", a, MatchStatus::Rejected)
+            .unwrap());
+        assert_eq!(
+            status_of(&db, "HH001_a.tif"),
+            Some((MatchStatus::Confirmed, 0.95))
+        );
+
+        // A rerun clears only pending matches, and re-inserting a reviewed
+        // one refreshes its score but keeps its status
+        let mut session = db.start_match_import().unwrap();
+        session.clear_for_ids(&["HH001".to_string()], 0.5).unwrap();
+        session
+            .insert_match("HH001", a, 0.9, 0.5, "gpu", None)
+            .unwrap();
+        session.commit().unwrap();
+        assert_eq!(
+            status_of(&db, "HH001_a.tif"),
+            Some((MatchStatus::Confirmed, 0.9))
+        );
+        assert_eq!(status_of(&db, "HH001_b.tif"), None);
+
+        // Set back to pending, the next rerun drops it like any other
+        db.set_match_status("HH001", a, MatchStatus::Pending)
+            .unwrap();
+        let mut session = db.start_match_import().unwrap();
+        session.clear_for_ids(&["HH001".to_string()], 0.5).unwrap();
+        session.commit().unwrap();
+        assert_eq!(status_of(&db, "HH001_a.tif"), None);
+    }
+
+    #[test]
+    fn kept_reviewed_matches_take_the_rerun_threshold() {
+        let mut db = seeded_db();
+        let a = db.get_file_id("/scans/HH001_a.tif").unwrap();
+        let b = db.get_file_id("/scans/HH001_b.tif").unwrap();
+        db.set_match_status("HH001", b, MatchStatus::Confirmed)
+            .unwrap();
+
+        // Rerun at 0.8: the confirmed 0.6 match is kept but not found again
+        let mut session = db.start_match_import().unwrap();
+        session.clear_for_ids(&["HH001".to_string()], 0.8).unwrap();
+        session
+            .insert_match("HH001", a, 0.95, 0.8, "cpu", None)
+            .unwrap();
+        session.commit().unwrap();
+
+        // A search at 0.6 recomputes instead of getting only the confirmed row
+        assert_eq!(db.count_matches_for_id("HH001", 0.6, None).unwrap(), 0);
+        assert_eq!(db.count_matches_for_id("HH001", 0.8, None).unwrap(), 1);
+    }
+
+    #[test]
+    fn batch_inserts_match_per_row_inserts() {
+        type Row = (String, i64, f64, f64, String, Option<String>, String);
+        let stored = |db: &Database| -> Vec<Row> {
+            db.conn
+                .prepare(
+                    "SELECT hh_id, file_id, similarity_score, threshold, engine, matched_via,
+                         status FROM matches ORDER BY hh_id, file_id",
+                )
+                .unwrap()
+                .query_map([], |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        row.get(4)?,
+                        row.get(5)?,
+                        row.get(6)?,
+                    ))
+                })
+                .unwrap()
+                .collect::<Result<_>>()
+                .unwrap()
+        };
+
+        let mut per_row = seeded_db();
+        let mut batch = seeded_db();
+        let a = per_row.get_file_id("/scans/HH001_a.tif").unwrap();
+        let c = per_row.get_file_id("/scans/HH002.tif").unwrap();
+        // One row conflicts with a reviewed match, one with a pending match
+        // and one is new
+        let rows = vec![
+            ("HH001".to_string(), a, 0.9, Some("file name".to_string())),
+            ("HH002".to_string(), c, 0.8, None),
+            ("HH003".to_string(), c, 0.7, Some("digits".to_string())),
+        ];
+        for db in [&per_row, &batch] {
+            db.set_match_status("HH001", a, MatchStatus::Confirmed)
+                .unwrap();
+        }
+
+        let mut session = per_row.start_match_import().unwrap();
+        for (hh_id, file_id, score, matched_via) in &rows {
+            session
+                .insert_match(hh_id, *file_id, *score, 0.6, "cpu", matched_via.as_deref())
+                .unwrap();
+        }
+        session.commit().unwrap();
+        let mut session = batch.start_match_import().unwrap();
+        assert_eq!(session.insert_matches(&rows, 0.6, "cpu").unwrap(), 3);
+        session.commit().unwrap();
+
+        let stored_rows = stored(&batch);
+        assert_eq!(stored_rows, stored(&per_row));
+        assert_eq!(stored_rows.len(), 4);
+        assert!(stored_rows.contains(&(
+            "HH001".to_string(),
+            a,
+            0.9,
+            0.6,
+            "cpu".to_string(),
+            Some("file name".to_string()),
+            "confirmed".to_string()
+        )));
+    }
+
+    #[test]
+    fn stale_file_ids_flags_only_missing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let real = dir.path().join("HH001.tif");
+        std::fs::write(&real, b"II*\0").unwrap();
+        std::fs::write(dir.path().join("HH002.tif"), b"II*\0").unwrap();
+        let root = dir.path().to_str().unwrap();
+        let real = real.to_str().unwrap();
+        let gone = dir.path().join("HH003.tif");
+        let gone = gone.to_str().unwrap();
+
+        let mut db = Database::new(":memory:").unwrap();
+        {
+            let mut session = db.start_file_import().unwrap();
+            session.upsert_file(real, "HH001.tif").unwrap();
+            session.upsert_file(gone, "HH003.tif").unwrap();
+            session
+                .upsert_file("/moved/HH004.tif", "HH004.tif")
+                .unwrap();
+            // A legacy relative path, resolved against its scan root
+            session
+                .upsert_scanned_file("HH002.tif", "HH002.tif", None, Some(root), None)
+                .unwrap();
+            session.commit().unwrap();
+        }
+
+        let reports = Mutex::new(Vec::new());
+        let stale = db
+            .stale_file_ids_with_progress(|checked, total| {
+                reports.lock().unwrap().push((checked, total))
+            })
+            .unwrap();
+        let stale_paths: Vec<&str> = stale.iter().map(|(_, path)| path.as_str()).collect();
+        assert_eq!(stale_paths, ["/moved/HH004.tif", gone]);
+        assert_eq!(stale[0].0, db.get_file_id("/moved/HH004.tif").unwrap());
+        assert_eq!(reports.lock().unwrap().last(), Some(&(4, 4)));
+    }
+
+    #[test]
+    fn sample_file_paths_is_bounded_by_n_and_the_cache() {
+        let db = seeded_db();
+
+        let sample = db.sample_file_paths(2).unwrap();
+        assert_eq!(sample.len(), 2);
+        assert!(sample.iter().all(|(path, _)| path.starts_with("/scans/")));
+
+        let mut all: Vec<String> = db
+            .sample_file_paths(10)
+            .unwrap()
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        all.sort();
+        assert_eq!(
+            all,
+            [
+                "/scans/HH001_a.tif",
+                "/scans/HH001_b.tif",
+                "/scans/HH002.tif"
+            ]
+        );
+    }
+
+    #[test]
+    fn remap_paths_moves_whole_components_only() {
+        let mut db = Database::new(":memory:").expect("in-memory database");
+        db.add_scan_root("/mnt/old").unwrap();
+        {
+            let mut session = db.start_file_import().unwrap();
+            for path in ["/mnt/old/2019/a.tif", "/mnt/old/b.tif", "/mnt/older/c.tif"] {
+                let name = path.rsplit('/').next().unwrap();
+                session
+                    .upsert_scanned_file(path, name, None, Some("/mnt/old"), None)
+                    .unwrap();
+            }
+            session.commit().unwrap();
+        }
+        let before = db.get_scan_generation().unwrap();
+
+        assert_eq!(db.count_paths_with_prefix("/mnt/old").unwrap(), 2);
+        assert_eq!(db.remap_paths("/mnt/old", "/mnt/new").unwrap(), 2);
+
+        assert!(db.get_file_id("/mnt/new/2019/a.tif").is_ok());
+        assert!(db.get_file_id("/mnt/new/b.tif").is_ok());
+        assert!(db.get_file_id("/mnt/older/c.tif").is_ok());
+        assert_eq!(db.count_paths_with_prefix("/mnt/old").unwrap(), 0);
+        let roots: Vec<String> = db
+            .get_scan_roots()
+            .unwrap()
+            .into_iter()
+            .map(|root| root.path)
+            .collect();
+        assert_eq!(roots, ["/mnt/new"]);
+        assert_ne!(db.get_scan_generation().unwrap(), before);
+    }
+
+    #[test]
+    fn remap_paths_changes_nothing_when_paths_clash() {
+        let mut db = seeded_db();
+        {
+            let mut session = db.start_file_import().unwrap();
+            session
+                .upsert_file("/archive/HH002.tif", "HH002.tif")
+                .unwrap();
+            session.commit().unwrap();
+        }
+
+        assert!(db.remap_paths("/scans", "/archive").is_err());
+        assert_eq!(db.count_paths_with_prefix("/scans").unwrap(), 3);
+    }
+
+    #[test]
+    fn duplicate_names_list_every_path() {
+        let mut db = Database::new(":memory:").unwrap();
+        {
+            let mut session = db.start_file_import().unwrap();
+            for path in ["/mnt/b/HH001.tif", "/mnt/a/HH001.tif", "/mnt/a/HH002.tif"] {
+                let name = path.rsplit('/').next().unwrap();
+                session.upsert_file(path, name).unwrap();
+            }
+            session.commit().unwrap();
+        }
+
+        assert_eq!(
+            db.find_duplicate_names().unwrap(),
+            vec![DuplicateName {
+                file_name: "HH001.tif".to_string(),
+                paths: vec![
+                    "/mnt/a/HH001.tif".to_string(),
+                    "/mnt/b/HH001.tif".to_string()
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn removing_a_root_keeps_other_roots_files() {
+        let mut db = Database::new(":memory:").unwrap();
+        {
+            let mut session = db.start_file_import().unwrap();
+            for (root, name) in [("/mnt/a", "HH001.tif"), ("/mnt/b", "HH002.tif")] {
+                let path = format!("{}/{}", root, name);
+                session
+                    .upsert_scanned_file(&path, name, Some(name), Some(root), None)
+                    .unwrap();
+                session
+                    .touch_scan_root(root, "2024-05-01T09:00:00+00:00")
+                    .unwrap();
+            }
+            session.commit().unwrap();
+        }
+        let b_file = db.get_file_id("/mnt/b/HH002.tif").unwrap();
+        db.insert_match("HH002", b_file, 0.9, 0.5, "cpu", None)
+            .unwrap();
+
+        assert_eq!(db.remove_scan_root("/mnt/a").unwrap(), 1);
+        let files = db.get_all_files().unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].file_path, "/mnt/b/HH002.tif");
+        assert_eq!(
+            db.search_single_id("HH002", 0.5, None, ResultOrder::FileName)
+                .unwrap()
+                .len(),
+            1
+        );
+        let roots = db.get_scan_roots().unwrap();
+        assert_eq!(roots.len(), 1);
+        assert_eq!(roots[0].path, "/mnt/b");
+        assert!(roots[0].last_scan.is_some());
+        assert_eq!(
+            roots[0].last_scan_started.as_deref(),
+            Some("2024-05-01T09:00:00+00:00")
+        );
+    }
+
+    #[test]
+    fn pruning_is_scoped_to_one_root() {
+        let mut db = Database::new(":memory:").unwrap();
+        {
+            let mut session = db.start_file_import().unwrap();
+            session
+                .upsert_scanned_file("/mnt/a/old.tif", "old.tif", None, Some("/mnt/a"), None)
+                .unwrap();
+            session
+                .upsert_scanned_file("/mnt/b/other.tif", "other.tif", None, Some("/mnt/b"), None)
+                .unwrap();
+            session.commit().unwrap();
+        }
+
+        let started = Utc::now().to_rfc3339();
+        let mut session = db.start_file_import().unwrap();
+        session
+            .upsert_scanned_file("/mnt/a/new.tif", "new.tif", None, Some("/mnt/a"), None)
+            .unwrap();
+        assert_eq!(session.prune_root("/mnt/a", &started).unwrap(), 1);
+        session.commit().unwrap();
+
+        let mut names: Vec<String> = db
+            .get_all_files()
+            .unwrap()
+            .into_iter()
+            .map(|file| file.file_name)
+            .collect();
+        names.sort();
+        assert_eq!(names, ["new.tif", "other.tif"]);
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let db = Database::new(":memory:").unwrap();
+        assert_eq!(db.get_setting("theme").unwrap(), None);
+        db.set_setting("theme", "light").unwrap();
+        db.set_setting("theme", "dark").unwrap();
+        assert_eq!(db.get_setting("theme").unwrap().as_deref(), Some("dark"));
+    }
+
+    #[test]
+    fn legacy_relative_paths_get_the_cache_folder_as_scan_root() {
+        let dir = std::env::temp_dir().join(format!("legacy_paths_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cache.db");
+        let _ = std::fs::remove_file(&path);
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute(
+                "CREATE TABLE files (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    file_path TEXT NOT NULL UNIQUE,
+                    file_name TEXT NOT NULL,
+                    scan_date TEXT NOT NULL
+                )",
+                [],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO files (file_path, file_name, scan_date) VALUES
+                 ('scans/HH001.tif', 'HH001.tif', 'then'),
+                 ('/abs/HH002.tif', 'HH002.tif', 'then')",
+                [],
+            )
+            .unwrap();
+        }
+
+        let db = Database::new(path.to_str().unwrap()).expect("migrates legacy cache");
+        let files = db.get_all_files().unwrap();
+        let root = dir.to_string_lossy().to_string();
+        assert_eq!(files[0].scan_root.as_deref(), Some(root.as_str()));
+        assert_eq!(files[1].scan_root, None);
+
+        drop(db);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn open_with_retry_waits_for_a_locked_database() {
+        let path = std::env::temp_dir().join(format!("locked_cache_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let path_str = path.to_str().unwrap().to_string();
+        drop(Database::new(&path_str).unwrap());
+
+        let writer = Connection::open(&path).unwrap();
+        writer.execute_batch("BEGIN EXCLUSIVE").unwrap();
+        let backoff = Duration::from_millis(20);
+        let err = Database::open_with_retry(&path_str, 1, backoff)
+            .err()
+            .unwrap();
+        assert!(Database::is_locked(&err), "{}", err);
+
+        // Released while the second open is still retrying
+        let release = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            writer.execute_batch("COMMIT").unwrap();
+        });
+        let db = Database::open_with_retry(&path_str, 8, backoff).unwrap();
+        assert_eq!(db.get_file_count().unwrap(), 0);
+        release.join().unwrap();
+
+        drop(db);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn legacy_matches_table_is_migrated() {
+        let path = std::env::temp_dir().join(format!("legacy_cache_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute(
+                "CREATE TABLE matches (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    hh_id TEXT NOT NULL,
+                    file_id INTEGER NOT NULL,
+                    similarity_score REAL NOT NULL,
+                    match_date TEXT NOT NULL
+                )",
+                [],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO matches (hh_id, file_id, similarity_score, match_date)
+                 VALUES ('HH001', 1, 0.9, 'then')",
+                [],
+            )
+            .unwrap();
+        }
+
+        let db = Database::new(path.to_str().unwrap()).expect("migrates legacy cache");
+        db.conn
+            .execute(
+                "INSERT INTO files (id, file_path, file_name, scan_date) VALUES (1, '/a.tif', 'a.tif', 'now')",
+                [],
+            )
+            .unwrap();
+        // Rows without a recorded threshold are never served from the cache
+        assert!(db
+            .search_single_id("HH001", 0.5, None, ResultOrder::FileName)
+            .unwrap()
+            .is_empty());
+        db.insert_match("HH001", 1, 0.9, 0.5, "gpu", None).unwrap();
+        let results = db
+            .search_single_id("HH001", 0.5, None, ResultOrder::FileName)
+            .unwrap();
+        assert_eq!(results[0].engine.as_deref(), Some("gpu"));
+
+        drop(db);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn foreign_tables_are_rejected_with_the_missing_columns() {
+        let path = std::env::temp_dir().join(format!("foreign_cache_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute(
+                "CREATE TABLE files (id INTEGER PRIMARY KEY, file_path TEXT, checksum TEXT)",
+                [],
+            )
+            .unwrap();
+        }
+
+        let err = Database::new(path.to_str().unwrap()).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "table 'files' missing columns 'file_name', 'scan_date'"
+        );
+        // Nothing was migrated into the foreign table
+        let conn = Connection::open(&path).unwrap();
+        let columns: Vec<String> = conn
+            .prepare("SELECT name FROM pragma_table_info('files')")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(columns, ["id", "file_path", "checksum"]);
+        // and none of the cache's own tables were added next to it
+        let tables: Vec<String> = conn
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table'")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(tables, ["files"]);
+
+        drop(conn);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -1,9 +1,10 @@
 use std::num::NonZeroU64;
 use std::panic::{catch_unwind, AssertUnwindSafe};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use bytemuck::{Pod, Zeroable};
 use futures::channel::oneshot;
@@ -13,17 +14,77 @@ use wgpu::util::DeviceExt;
 pub struct SimilarityComputer {
     device: Arc<wgpu::Device>,
     queue: Arc<wgpu::Queue>,
+    kernel: Kernel,
     pipeline: wgpu::ComputePipeline,
+    // 8x8 tiled pipeline for tiles too large to dispatch with a reduction kernel
+    fallback_pipeline: Option<wgpu::ComputePipeline>,
+    max_workgroups: u32,
     bind_group_layout: wgpu::BindGroupLayout,
     max_storage_bytes: u64,
-    _poller: DevicePoller,
+    max_buffer_bytes: u64,
+    device_lost: Arc<AtomicBool>,
+    map_timeout: Duration,
+    tile_pool: Arc<Mutex<Vec<TileBuffers>>>,
+    tile_pool_size: usize,
+    buffers_created: AtomicUsize,
+    poller: DevicePoller,
+}
+
+/// How long a tile may wait for its results to be mapped before it is
+/// reported as stalled.
+pub const DEFAULT_MAP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Interval of the default background poller.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How the background poller drives the device so mapped results reach
+/// waiting tiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PollStrategy {
+    /// Poll without blocking at a fixed interval. Shorter intervals deliver
+    /// results sooner but keep a core busier; safe with any number of tiles
+    /// in flight.
+    Interval(Duration),
+    /// Sleep until a tile is submitted, then block in `Maintain::Wait` until
+    /// the queue drains. Nothing runs while idle and results arrive as soon
+    /// as they are ready, but `Wait` covers every submission, so with several
+    /// tiles in flight the first would only be delivered with the last. Meant
+    /// for one tile in flight.
+    Blocking,
+}
+
+impl std::fmt::Display for PollStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PollStrategy::Interval(interval) => write!(f, "every {} ms", interval.as_millis()),
+            PollStrategy::Blocking => write!(f, "blocking"),
+        }
+    }
+}
+
+/// Idle tile buffer sets kept for reuse until `set_tile_pool_size` sizes the
+/// pool for the tiles a caller keeps in flight.
+const DEFAULT_TILE_POOL_SIZE: usize = 4;
+
+/// Output, staging and params buffers of one tile. Sets go back to the pool
+/// once their results have been read, so later tiles of the same or a smaller
+/// size skip the allocations.
+pub struct TileBuffers {
+    output: wgpu::Buffer,
+    staging: wgpu::Buffer,
+    params: wgpu::Buffer,
+    capacity: u64,
 }
 
 pub enum GpuTileHandle {
     Pending {
         device: Arc<wgpu::Device>,
-        staging: Arc<wgpu::Buffer>,
+        buffers: Box<TileBuffers>,
+        pool: Arc<Mutex<Vec<TileBuffers>>>,
+        pool_size: usize,
         output_bytes: u64,
+        timeout: Duration,
+        device_lost: Arc<AtomicBool>,
     },
     Immediate(Result<Vec<f32>, String>),
 }
@@ -34,50 +95,144 @@ impl GpuTileHandle {
             GpuTileHandle::Immediate(result) => result,
             GpuTileHandle::Pending {
                 device,
-                staging,
+                buffers,
+                pool,
+                pool_size,
                 output_bytes,
+                timeout,
+                device_lost,
             } => {
                 if output_bytes == 0 {
                     return Ok(Vec::new());
                 }
+                let staging = &buffers.staging;
                 let slice = staging.slice(..output_bytes);
-                let (sender, receiver) = oneshot::channel();
+                let (sender, mut receiver) = oneshot::channel();
                 slice.map_async(wgpu::MapMode::Read, move |res| {
                     let _ = sender.send(res);
                 });
-                match block_on(receiver) {
+
+                // Polled here as well as by the background poller, so the
+                // callback still fires if the poller is between intervals
+                let outcome = wait_for_map(&mut receiver, timeout, || {
+                    device.poll(wgpu::Maintain::Poll);
+                });
+
+                match outcome {
+                    Err(MapWaitError::TimedOut) => {
+                        staging.unmap();
+                        // A stalled device cannot be trusted for later tiles either,
+                        // so the engine recreates it or finishes on the CPU
+                        device_lost.store(true, Ordering::Relaxed);
+                        Err(format!(
+                            "GPU stalled: results not ready after {} ms",
+                            timeout.as_millis()
+                        ))
+                    }
                     Ok(Ok(())) => {
                         let view = slice.get_mapped_range();
                         let floats = bytemuck::cast_slice(&view).to_vec();
                         drop(view);
                         staging.unmap();
                         device.poll(wgpu::Maintain::Poll);
+                        release_tile_buffers(&pool, pool_size, *buffers);
                         Ok(floats)
                     }
                     Ok(Err(err)) => Err(format!("Failed to map GPU buffer: {:?}", err)),
-                    Err(_) => Err("GPU map receiver dropped before completion".to_string()),
+                    Err(MapWaitError::Dropped) => {
+                        Err("GPU map receiver dropped before completion".to_string())
+                    }
                 }
             }
         }
     }
 
-    fn immediate(result: Result<Vec<f32>, String>) -> Self {
+    pub(crate) fn immediate(result: Result<Vec<f32>, String>) -> Self {
         GpuTileHandle::Immediate(result)
     }
 }
 
+/// Why [`wait_for_map`] gave up on a map callback.
+#[derive(Debug, PartialEq)]
+enum MapWaitError {
+    TimedOut,
+    Dropped,
+}
+
+/// Calls `poll` and checks `receiver` until the map callback reports or
+/// `timeout` passes. `block_on` would wait forever on a hung driver and keep
+/// the matching thread from ever failing over.
+fn wait_for_map<T>(
+    receiver: &mut oneshot::Receiver<T>,
+    timeout: Duration,
+    mut poll: impl FnMut(),
+) -> Result<T, MapWaitError> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        poll();
+        match receiver.try_recv() {
+            Ok(Some(value)) => return Ok(value),
+            Ok(None) if Instant::now() >= deadline => return Err(MapWaitError::TimedOut),
+            Ok(None) => thread::sleep(Duration::from_millis(1)),
+            Err(_) => return Err(MapWaitError::Dropped),
+        }
+    }
+}
+
+fn request_adapter(instance: &wgpu::Instance) -> Result<wgpu::Adapter, String> {
+    block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::HighPerformance,
+        compatible_surface: None,
+        force_fallback_adapter: false,
+    }))
+    .ok_or_else(|| "No suitable GPU adapter found".to_string())
+}
+
+/// Name and backend of the adapter a [`SimilarityComputer`] would use, e.g.
+/// "NVIDIA GeForce RTX 3060 (Vulkan)", without creating a device.
+pub fn adapter_description() -> Result<String, String> {
+    let info = request_adapter(&wgpu::Instance::default())?.get_info();
+    Ok(format!("{} ({:?})", info.name, info.backend))
+}
+
+/// Returns a read tile's buffers to the pool. A pool over `pool_size` drops
+/// its smallest sets, so sets sized for the largest tiles are the ones kept.
+fn release_tile_buffers(pool: &Mutex<Vec<TileBuffers>>, pool_size: usize, buffers: TileBuffers) {
+    let Ok(mut pool) = pool.lock() else {
+        return;
+    };
+    pool.push(buffers);
+    while pool.len() > pool_size {
+        let Some(smallest) = (0..pool.len()).min_by_key(|&i| pool[i].capacity) else {
+            break;
+        };
+        pool.swap_remove(smallest);
+    }
+}
+
 impl SimilarityComputer {
-    pub fn new() -> Result<Self, String> {
+    /// Computer using `kernel` (the backend's default when `None`, and
+    /// [`FALLBACK_KERNEL`] when the adapter's limits cannot run it) to score
+    /// pairs with `similarity`.
+    pub fn with_options(kernel: Option<Kernel>, similarity: Similarity) -> Result<Self, String> {
         let instance = wgpu::Instance::default();
-        let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::HighPerformance,
-            compatible_surface: None,
-            force_fallback_adapter: false,
-        }))
-        .ok_or_else(|| "No suitable GPU adapter found".to_string())?;
+        let adapter = request_adapter(&instance)?;
 
         let limits = adapter.limits();
         let max_storage = limits.max_storage_buffer_binding_size as u64;
+        let max_buffer = limits.max_buffer_size;
+        let max_workgroups = limits.max_compute_workgroups_per_dimension;
+        let requested = kernel.unwrap_or_else(|| Kernel::default_for(adapter.get_info().backend));
+        let kernel = if requested.fits(&limits) {
+            requested
+        } else {
+            log::warn!(
+                "GPU kernel {} exceeds this adapter's limits; using {}",
+                requested,
+                FALLBACK_KERNEL
+            );
+            FALLBACK_KERNEL
+        };
         let (device, queue) = block_on(adapter.request_device(
             &wgpu::DeviceDescriptor {
                 label: Some("gpu-matcher-device"),
@@ -88,10 +243,25 @@ impl SimilarityComputer {
         ))
         .map_err(|e| format!("Failed to create GPU device: {}", e))?;
 
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("similarity-shader"),
-            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        // Any uncaptured error leaves in-flight results untrustworthy, so it is
+        // treated like a lost device (e.g. after a laptop suspend) and the engine
+        // recreates the device instead of panicking.
+        let device_lost = Arc::new(AtomicBool::new(false));
+        let lost_flag = Arc::clone(&device_lost);
+        device.set_device_lost_callback(move |reason, message| {
+            if !matches!(
+                reason,
+                wgpu::DeviceLostReason::Dropped | wgpu::DeviceLostReason::ReplacedCallback
+            ) {
+                log::warn!("GPU device lost ({:?}): {}", reason, message);
+                lost_flag.store(true, Ordering::Relaxed);
+            }
         });
+        let error_flag = Arc::clone(&device_lost);
+        device.on_uncaptured_error(Box::new(move |err| {
+            log::error!("Uncaptured GPU error: {}", err);
+            error_flag.store(true, Ordering::Relaxed);
+        }));
 
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("similarity-bind-group-layout"),
@@ -147,46 +317,202 @@ impl SimilarityComputer {
             push_constant_ranges: &[],
         });
 
-        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: Some("similarity-pipeline"),
-            layout: Some(&pipeline_layout),
-            module: &shader,
-            entry_point: "main",
-            compilation_options: wgpu::PipelineCompilationOptions::default(),
-        });
+        let build_pipeline = |kernel: Kernel| {
+            let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("similarity-shader"),
+                source: wgpu::ShaderSource::Wgsl(kernel.shader_source(similarity).into()),
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("similarity-pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: "main",
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            })
+        };
+        let pipeline = build_pipeline(kernel);
+        let fallback_pipeline = match kernel {
+            Kernel::Reduction { .. } => Some(build_pipeline(FALLBACK_KERNEL)),
+            Kernel::Tiled { .. } => None,
+        };
+        log::info!("GPU similarity kernel: {} ({})", kernel, similarity);
 
         let device = Arc::new(device);
         let queue = Arc::new(queue);
-        let poller = DevicePoller::start(Arc::clone(&device));
+        let poller = DevicePoller::start(
+            Arc::clone(&device),
+            PollStrategy::Interval(DEFAULT_POLL_INTERVAL),
+        );
 
         Ok(Self {
             device,
             queue,
+            kernel,
             pipeline,
+            fallback_pipeline,
+            max_workgroups,
             bind_group_layout,
             max_storage_bytes: max_storage,
-            _poller: poller,
+            max_buffer_bytes: max_buffer,
+            device_lost,
+            map_timeout: DEFAULT_MAP_TIMEOUT,
+            tile_pool: Arc::new(Mutex::new(Vec::new())),
+            tile_pool_size: DEFAULT_TILE_POOL_SIZE,
+            buffers_created: AtomicUsize::new(0),
+            poller,
         })
     }
 
+    /// Runs one tiny tile so shader compilation and driver setup happen before
+    /// the first real tile rather than during it.
+    pub fn warm_up(&self) -> Result<(), String> {
+        let vectors = [1.0f32];
+        let files = self.create_file_buffer(&vectors);
+        self.compute_with_file_buffer(&vectors, 1, &files, 0, 1, 1)
+            .map(|_| ())
+    }
+
+    /// GPU buffers allocated by this computer so far, for measuring reuse.
+    pub fn buffers_created(&self) -> usize {
+        self.buffers_created.load(Ordering::Relaxed)
+    }
+
+    pub fn kernel(&self) -> Kernel {
+        self.kernel
+    }
+
+    pub fn set_map_timeout(&mut self, timeout: Duration) {
+        self.map_timeout = timeout;
+    }
+
+    /// Idle tile buffer sets kept for reuse. One per tile in flight plus a
+    /// spare lets every tile of a full pipeline reuse its buffers.
+    pub fn set_tile_pool_size(&mut self, size: usize) {
+        self.tile_pool_size = size;
+    }
+
+    /// Replaces the background poller; the old one is stopped first.
+    pub fn set_poll_strategy(&mut self, strategy: PollStrategy) {
+        if strategy != self.poller.strategy {
+            self.poller = DevicePoller::start(Arc::clone(&self.device), strategy);
+        }
+    }
+
+    pub fn poll_strategy(&self) -> PollStrategy {
+        self.poller.strategy
+    }
+
     pub fn max_storage_bytes(&self) -> u64 {
         self.max_storage_bytes
     }
 
+    /// Largest buffer the adapter allows; wgpu reports no memory size, so this
+    /// is the best hint of how much the device can hold.
+    pub fn max_buffer_bytes(&self) -> u64 {
+        self.max_buffer_bytes
+    }
+
+    /// True once the device has been lost or reported an uncaptured error.
+    /// A lost computer must be recreated with [`SimilarityComputer::with_options`].
+    pub fn is_device_lost(&self) -> bool {
+        self.device_lost.load(Ordering::Relaxed)
+    }
+
     pub fn create_file_buffer(&self, vectors: &[f32]) -> Arc<wgpu::Buffer> {
-        Arc::new(
-            self.device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("gpu-file-buffer"),
-                    contents: bytemuck::cast_slice(vectors),
-                    usage: wgpu::BufferUsages::STORAGE,
-                }),
-        )
+        Arc::new(self.create_storage_buffer("gpu-file-buffer", vectors))
+    }
+
+    /// A zeroed file buffer for `len` vectors of `dim` floats, to be filled
+    /// slice by slice with [`SimilarityComputer::write_file_vectors`] so the
+    /// whole corpus never sits in host memory at once.
+    pub fn create_empty_file_buffer(&self, len: usize, dim: usize) -> Arc<wgpu::Buffer> {
+        self.buffers_created.fetch_add(1, Ordering::Relaxed);
+        Arc::new(self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("gpu-file-buffer"),
+            size: (len * dim * std::mem::size_of::<f32>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }))
+    }
+
+    /// Copy `vectors` into `buffer` from vector `file_offset` on. Waits for
+    /// the upload, so its staging copy is released before the next slice.
+    pub fn write_file_vectors(
+        &self,
+        buffer: &wgpu::Buffer,
+        file_offset: usize,
+        dim: usize,
+        vectors: &[f32],
+    ) -> Result<(), String> {
+        if self.is_device_lost() {
+            return Err("GPU device lost".to_string());
+        }
+        let stride_bytes = (dim * std::mem::size_of::<f32>()) as u64;
+        let offset_bytes = file_offset as u64 * stride_bytes;
+        let bytes: &[u8] = bytemuck::cast_slice(vectors);
+        if offset_bytes + bytes.len() as u64 > buffer.size() {
+            return Err("File vectors exceed the GPU file buffer".to_string());
+        }
+        self.queue.write_buffer(buffer, offset_bytes, bytes);
+        let submission = self.queue.submit(std::iter::empty());
+        self.device.poll(wgpu::Maintain::wait_for(submission));
+        Ok(())
+    }
+
+    /// Upload one query chunk. The buffer is meant to be shared by every tile
+    /// of that chunk, since the query vectors do not change across file chunks.
+    pub fn create_query_buffer(&self, vectors: &[f32]) -> Arc<wgpu::Buffer> {
+        Arc::new(self.create_storage_buffer("gpu-query-buffer", vectors))
+    }
+
+    fn create_storage_buffer(&self, label: &str, vectors: &[f32]) -> wgpu::Buffer {
+        self.buffers_created.fetch_add(1, Ordering::Relaxed);
+        self.device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytemuck::cast_slice(vectors),
+                usage: wgpu::BufferUsages::STORAGE,
+            })
+    }
+
+    /// Smallest pooled buffer set that holds `output_bytes`, or a new one.
+    fn take_tile_buffers(&self, output_bytes: u64) -> TileBuffers {
+        if let Ok(mut pool) = self.tile_pool.lock() {
+            let best = (0..pool.len())
+                .filter(|&i| pool[i].capacity >= output_bytes)
+                .min_by_key(|&i| pool[i].capacity);
+            if let Some(index) = best {
+                return pool.swap_remove(index);
+            }
+        }
+
+        self.buffers_created.fetch_add(3, Ordering::Relaxed);
+        TileBuffers {
+            output: self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("gpu-output-buffer"),
+                size: output_bytes,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            staging: self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("gpu-staging-buffer"),
+                size: output_bytes,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            params: self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("gpu-params-buffer"),
+                size: std::mem::size_of::<ShaderParams>() as u64,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            capacity: output_bytes,
+        }
     }
 
     pub fn dispatch_tile(
         &self,
-        query_vectors: &[f32],
+        query_buffer: &Arc<wgpu::Buffer>,
         query_len: usize,
         file_buffer: &Arc<wgpu::Buffer>,
         file_offset: usize,
@@ -197,9 +523,13 @@ impl SimilarityComputer {
             return Ok(GpuTileHandle::immediate(Ok(Vec::new())));
         }
 
+        if self.is_device_lost() {
+            return Err("GPU device lost".to_string());
+        }
+
         catch_unwind(AssertUnwindSafe(|| {
             self.dispatch_tile_inner(
-                query_vectors,
+                query_buffer,
                 query_len,
                 file_buffer,
                 file_offset,
@@ -210,7 +540,6 @@ impl SimilarityComputer {
         .map_err(|_| "GPU dispatch panicked".to_string())?
     }
 
-    #[allow(dead_code)]
     pub fn compute_with_file_buffer(
         &self,
         query_vectors: &[f32],
@@ -220,8 +549,9 @@ impl SimilarityComputer {
         file_len: usize,
         dim: usize,
     ) -> Result<Vec<f32>, String> {
+        let query_buffer = self.create_query_buffer(query_vectors);
         self.dispatch_tile(
-            query_vectors,
+            &query_buffer,
             query_len,
             file_buffer,
             file_offset,
@@ -231,9 +561,11 @@ impl SimilarityComputer {
         .wait()
     }
 
+    /// Bind groups are still built per tile: each tile binds a different slice
+    /// of the file buffer, so no two tiles share all their bindings.
     fn dispatch_tile_inner(
         &self,
-        query_vectors: &[f32],
+        query_buffer: &Arc<wgpu::Buffer>,
         query_len: usize,
         file_buffer: &Arc<wgpu::Buffer>,
         file_offset: usize,
@@ -252,9 +584,8 @@ impl SimilarityComputer {
             return Err("Requested file chunk exceeds GPU buffer size".to_string());
         }
 
-        let query_bytes = std::mem::size_of_val(query_vectors);
-        if query_bytes == 0 {
-            return Ok(GpuTileHandle::immediate(Ok(Vec::new())));
+        if query_len as u64 * stride_bytes > query_buffer.size() {
+            return Err("Requested query chunk exceeds GPU buffer size".to_string());
         }
 
         let output_floats = query_len * file_len;
@@ -269,27 +600,7 @@ impl SimilarityComputer {
             ));
         }
 
-        let query_buffer = self
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("gpu-query-buffer"),
-                contents: bytemuck::cast_slice(query_vectors),
-                usage: wgpu::BufferUsages::STORAGE,
-            });
-
-        let output_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("gpu-output-buffer"),
-            size: output_bytes,
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
-            mapped_at_creation: false,
-        });
-
-        let staging_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("gpu-staging-buffer"),
-            size: output_bytes,
-            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        let buffers = self.take_tile_buffers(output_bytes);
 
         let params = ShaderParams {
             query_len: query_len as u32,
@@ -297,14 +608,10 @@ impl SimilarityComputer {
             dim: dim as u32,
             _pad: 0,
         };
-
-        let params_buffer = self
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("gpu-params-buffer"),
-                contents: bytemuck::bytes_of(&params),
-                usage: wgpu::BufferUsages::UNIFORM,
-            });
+        // Queued writes land before the next submit, so a reused params
+        // buffer never changes under an earlier tile
+        self.queue
+            .write_buffer(&buffers.params, 0, bytemuck::bytes_of(&params));
 
         let queries_binding = query_buffer.as_entire_buffer_binding();
         let files_binding = wgpu::BufferBinding {
@@ -312,8 +619,13 @@ impl SimilarityComputer {
             offset: file_offset_bytes,
             size: Some(file_binding_size),
         };
-        let output_binding = output_buffer.as_entire_buffer_binding();
-        let params_binding = params_buffer.as_entire_buffer_binding();
+        // Pooled output buffers may be larger than this tile needs
+        let output_binding = wgpu::BufferBinding {
+            buffer: &buffers.output,
+            offset: 0,
+            size: NonZeroU64::new(output_bytes),
+        };
+        let params_binding = buffers.params.as_entire_buffer_binding();
 
         let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("similarity-bind-group"),
@@ -349,53 +661,96 @@ impl SimilarityComputer {
                 label: Some("similarity-pass"),
                 ..Default::default()
             });
-            pass.set_pipeline(&self.pipeline);
+            let mut kernel = self.kernel;
+            let mut pipeline = &self.pipeline;
+            if let Some(fallback) = &self.fallback_pipeline {
+                let (x_groups, y_groups) = kernel.workgroups(query_len, file_len);
+                if x_groups > self.max_workgroups || y_groups > self.max_workgroups {
+                    kernel = FALLBACK_KERNEL;
+                    pipeline = fallback;
+                }
+            }
+            pass.set_pipeline(pipeline);
             pass.set_bind_group(0, &bind_group, &[]);
-            let x_groups = (query_len as u32).div_ceil(WORKGROUP_X);
-            let y_groups = (file_len as u32).div_ceil(WORKGROUP_Y);
+            let (x_groups, y_groups) = kernel.workgroups(query_len, file_len);
             pass.dispatch_workgroups(x_groups.max(1), y_groups.max(1), 1);
         }
 
-        encoder.copy_buffer_to_buffer(&output_buffer, 0, &staging_buffer, 0, output_bytes);
+        encoder.copy_buffer_to_buffer(&buffers.output, 0, &buffers.staging, 0, output_bytes);
         self.queue.submit(std::iter::once(encoder.finish()));
         self.device.poll(wgpu::Maintain::Poll);
+        self.poller.submitted();
 
         Ok(GpuTileHandle::Pending {
             device: Arc::clone(&self.device),
-            staging: Arc::new(staging_buffer),
+            buffers: Box::new(buffers),
+            pool: Arc::clone(&self.tile_pool),
+            pool_size: self.tile_pool_size,
             output_bytes,
+            timeout: self.map_timeout,
+            device_lost: Arc::clone(&self.device_lost),
         })
     }
 }
 
 struct DevicePoller {
-    active: Arc<AtomicBool>,
+    strategy: PollStrategy,
+    // Wakes the thread after a submission; dropping it stops the thread
+    wake: Option<mpsc::Sender<()>>,
     handle: Option<thread::JoinHandle<()>>,
 }
 
 impl DevicePoller {
-    fn start(device: Arc<wgpu::Device>) -> Self {
-        let active = Arc::new(AtomicBool::new(true));
-        let flag = Arc::clone(&active);
+    fn start(device: Arc<wgpu::Device>, strategy: PollStrategy) -> Self {
+        let (wake, woken) = mpsc::channel::<()>();
         let handle = thread::Builder::new()
             .name("wgpu-poller".to_string())
             .spawn(move || {
-                while flag.load(Ordering::Relaxed) {
-                    device.poll(wgpu::Maintain::Poll);
-                    // Reduced from 1ms to 10ms to lower CPU overhead
-                    thread::sleep(Duration::from_millis(10));
+                loop {
+                    match strategy {
+                        PollStrategy::Interval(interval) => {
+                            device.poll(wgpu::Maintain::Poll);
+                            // Waiting on the channel rather than sleeping lets
+                            // Drop stop the thread without a full interval
+                            if let Err(RecvTimeoutError::Disconnected) =
+                                woken.recv_timeout(interval)
+                            {
+                                break;
+                            }
+                        }
+                        PollStrategy::Blocking => {
+                            if woken.recv().is_err() {
+                                break;
+                            }
+                            // One wait covers every submission made so far
+                            while woken.try_recv().is_ok() {}
+                            device.poll(wgpu::Maintain::Wait);
+                        }
+                    }
                 }
                 device.poll(wgpu::Maintain::Wait);
             })
             .ok();
 
-        Self { active, handle }
+        Self {
+            strategy,
+            wake: Some(wake),
+            handle,
+        }
+    }
+
+    fn submitted(&self) {
+        if self.strategy == PollStrategy::Blocking {
+            if let Some(wake) = &self.wake {
+                let _ = wake.send(());
+            }
+        }
     }
 }
 
 impl Drop for DevicePoller {
     fn drop(&mut self) {
-        self.active.store(false, Ordering::Relaxed);
+        self.wake.take();
         if let Some(handle) = self.handle.take() {
             let _ = handle.join();
         }
@@ -411,10 +766,152 @@ struct ShaderParams {
     _pad: u32,
 }
 
-const WORKGROUP_X: u32 = 8;
-const WORKGROUP_Y: u32 = 8;
+/// How the similarity shader spreads a tile over GPU threads. The sizes are
+/// substituted into the WGSL source, so any supported size can be tried with
+/// `TIFF_GPU_KERNEL` (e.g. `16x16` or `reduce64`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kernel {
+    /// One thread per query/file pair, in `x` by `y` workgroups.
+    Tiled { x: u32, y: u32 },
+    /// One workgroup per pair: `threads` lanes (a power of two) each sum part
+    /// of the dot product, then add the parts up in workgroup memory.
+    Reduction { threads: u32 },
+}
+
+/// The original 8x8 layout, within every adapter's limits.
+pub const FALLBACK_KERNEL: Kernel = Kernel::Tiled { x: 8, y: 8 };
+
+impl Kernel {
+    /// Reads `<x>x<y>` as a tiled kernel and `reduce<threads>` as a reduction.
+    pub fn parse(value: &str) -> Option<Kernel> {
+        let value = value.trim().to_ascii_lowercase();
+        let kernel = if let Some(threads) = value.strip_prefix("reduce") {
+            Kernel::Reduction {
+                threads: threads.parse().ok()?,
+            }
+        } else {
+            let (x, y) = value.split_once('x')?;
+            Kernel::Tiled {
+                x: x.parse().ok()?,
+                y: y.parse().ok()?,
+            }
+        };
+        kernel.is_valid().then_some(kernel)
+    }
+
+    /// Default kernel for `backend`. On llvmpipe (GL; 64 IDs against 4,096
+    /// files of 512 floats) the tiled 8x8, 16x16, 32x1 and 64x1 layouts were
+    /// within noise of each other, while 1x64 took about 5x as long, reduce32
+    /// 2.5x and reduce64 6x. Other backends have not been measured yet, so
+    /// they keep the 8x8 layout until they are.
+    pub fn default_for(_backend: wgpu::Backend) -> Kernel {
+        FALLBACK_KERNEL
+    }
+
+    fn is_valid(&self) -> bool {
+        match *self {
+            Kernel::Tiled { x, y } => x > 0 && y > 0,
+            Kernel::Reduction { threads } => threads.is_power_of_two(),
+        }
+    }
+
+    fn threads(&self) -> u32 {
+        match *self {
+            Kernel::Tiled { x, y } => x.saturating_mul(y),
+            Kernel::Reduction { threads } => threads,
+        }
+    }
+
+    fn fits(&self, limits: &wgpu::Limits) -> bool {
+        let (size_x, size_y) = match *self {
+            Kernel::Tiled { x, y } => (x, y),
+            Kernel::Reduction { threads } => (threads, 1),
+        };
+        let shared_bytes = match *self {
+            Kernel::Tiled { .. } => 0,
+            Kernel::Reduction { threads } => threads.saturating_mul(4),
+        };
+        self.is_valid()
+            && size_x <= limits.max_compute_workgroup_size_x
+            && size_y <= limits.max_compute_workgroup_size_y
+            && self.threads() <= limits.max_compute_invocations_per_workgroup
+            && shared_bytes <= limits.max_compute_workgroup_storage_size
+    }
+
+    /// Workgroups needed for a `query_len` by `file_len` tile.
+    fn workgroups(&self, query_len: usize, file_len: usize) -> (u32, u32) {
+        match *self {
+            Kernel::Tiled { x, y } => (
+                (query_len as u32).div_ceil(x),
+                (file_len as u32).div_ceil(y),
+            ),
+            Kernel::Reduction { .. } => (query_len as u32, file_len as u32),
+        }
+    }
+
+    fn shader_source(&self, similarity: Similarity) -> String {
+        let scoring = match similarity {
+            Similarity::Dot => DOT_SCORING,
+            Similarity::Cosine => COSINE_SCORING,
+        };
+        let body = match *self {
+            Kernel::Tiled { x, y } => TILED_KERNEL
+                .replace("__WORKGROUP_X__", &x.to_string())
+                .replace("__WORKGROUP_Y__", &y.to_string()),
+            Kernel::Reduction { threads } => {
+                REDUCTION_KERNEL.replace("__THREADS__", &threads.to_string())
+            }
+        };
+        format!("{}{}{}", SHADER_BINDINGS, scoring, body)
+    }
+}
+
+/// What the shader computes for each query/file pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Similarity {
+    /// Plain dot product. Only a cosine when both vectors are unit length, as
+    /// the vectorizer's are; the default and the cheaper of the two.
+    Dot,
+    /// `dot / (|q| |f|)`, a true cosine whatever the vectors' lengths (e.g.
+    /// weighted vectors). Costs two more multiply-adds per component.
+    Cosine,
+}
+
+impl Similarity {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim() {
+            "dot" => Some(Similarity::Dot),
+            "cosine" => Some(Similarity::Cosine),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Similarity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Similarity::Dot => write!(f, "dot"),
+            Similarity::Cosine => write!(f, "cosine"),
+        }
+    }
+}
 
-const SHADER: &str = r#"
+impl std::fmt::Display for Kernel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Kernel::Tiled { x, y } => write!(f, "{}x{}", x, y),
+            Kernel::Reduction { threads } => write!(f, "reduce{}", threads),
+        }
+    }
+}
+
+/// Both kernels score every query/file pair with the cosine similarity of their
+/// vectors, clamped to [0, 1] to share the CPU matcher's scale. The
+/// vectorizer's n-gram counts are never negative, so clamping only trims float
+/// error; unrelated (orthogonal) names score 0. The kernels accumulate
+/// `term`s into a `Partial` and turn the sum into a score, which the scoring
+/// snippets below define for each [`Similarity`].
+const SHADER_BINDINGS: &str = r#"
 struct Params {
     query_len: u32,
     file_len: u32,
@@ -433,9 +930,41 @@ var<storage, read_write> output: array<f32>;
 
 @group(0) @binding(3)
 var<uniform> params: Params;
+"#;
+
+/// Vectors are already L2-normalized, so the dot product is the cosine.
+const DOT_SCORING: &str = r#"
+alias Partial = f32;
+
+fn term(q: f32, f: f32) -> Partial {
+    return q * f;
+}
+
+fn score(sum: Partial) -> f32 {
+    return sum;
+}
+"#;
+
+/// Accumulates the dot product and both squared norms, dividing at the end.
+const COSINE_SCORING: &str = r#"
+alias Partial = vec3<f32>;
+
+fn term(q: f32, f: f32) -> Partial {
+    return vec3<f32>(q * f, q * q, f * f);
+}
 
-const WORKGROUP_X: u32 = 8u;
-const WORKGROUP_Y: u32 = 8u;
+fn score(sum: Partial) -> f32 {
+    let norms = sum.y * sum.z;
+    if (norms <= 0.0) {
+        return 0.0;
+    }
+    return sum.x / sqrt(norms);
+}
+"#;
+
+const TILED_KERNEL: &str = r#"
+const WORKGROUP_X: u32 = __WORKGROUP_X__u;
+const WORKGROUP_Y: u32 = __WORKGROUP_Y__u;
 
 @compute @workgroup_size(WORKGROUP_X, WORKGROUP_Y, 1)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
@@ -446,25 +975,120 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
         return;
     }
 
-    var sum: f32 = 0.0;
+    var sum = Partial();
     for (var i: u32 = 0u; i < params.dim; i = i + 1u) {
         let q_index = q * params.dim + i;
         let f_index = f * params.dim + i;
-        sum = sum + queries[q_index] * files[f_index];
+        sum = sum + term(queries[q_index], files[f_index]);
     }
 
     let out_index = q * params.file_len + f;
-    output[out_index] = sum;
+    output[out_index] = clamp(score(sum), 0.0, 1.0);
 }
 "#;
 
+// Dispatched as exactly query_len x file_len workgroups, so every lane reaches
+// the barriers
+const REDUCTION_KERNEL: &str = r#"
+const THREADS: u32 = __THREADS__u;
+
+var<workgroup> partial: array<Partial, THREADS>;
+
+@compute @workgroup_size(THREADS, 1, 1)
+fn main(
+    @builtin(workgroup_id) group: vec3<u32>,
+    @builtin(local_invocation_index) lane: u32,
+) {
+    let q = group.x;
+    let f = group.y;
+
+    var sum = Partial();
+    for (var i: u32 = lane; i < params.dim; i = i + THREADS) {
+        sum = sum + term(queries[q * params.dim + i], files[f * params.dim + i]);
+    }
+    partial[lane] = sum;
+    workgroupBarrier();
+
+    for (var stride: u32 = THREADS / 2u; stride > 0u; stride = stride / 2u) {
+        if (lane < stride) {
+            partial[lane] = partial[lane] + partial[lane + stride];
+        }
+        workgroupBarrier();
+    }
+
+    if (lane == 0u) {
+        output[q * params.file_len + f] = clamp(score(partial[0]), 0.0, 1.0);
+    }
+}
+"#;
+
+// Runs without a GPU: the map callback is a plain channel and names are
+// only parsed
+#[cfg(test)]
+mod host_tests {
+    use super::*;
+
+    #[test]
+    fn a_stuck_map_times_out_instead_of_hanging() {
+        let (_sender, mut receiver) = oneshot::channel::<()>();
+        let mut polls = 0;
+        let started = Instant::now();
+        let outcome = wait_for_map(&mut receiver, Duration::from_millis(50), || polls += 1);
+
+        assert_eq!(outcome, Err(MapWaitError::TimedOut));
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(polls > 1, "the device is polled while waiting");
+    }
+
+    #[test]
+    fn a_delivered_or_dropped_map_returns_at_once() {
+        let (sender, mut receiver) = oneshot::channel();
+        let mut sender = Some(sender);
+        // The callback fires during the second poll, as it would once the
+        // device got round to the copy
+        let mut polls = 0;
+        let outcome = wait_for_map(&mut receiver, Duration::from_secs(30), || {
+            polls += 1;
+            if polls == 2 {
+                let _ = sender.take().unwrap().send(7);
+            }
+        });
+        assert_eq!(outcome, Ok(7));
+
+        let (sender, mut receiver) = oneshot::channel::<()>();
+        drop(sender);
+        let outcome = wait_for_map(&mut receiver, Duration::from_secs(30), || {});
+        assert_eq!(outcome, Err(MapWaitError::Dropped));
+    }
+
+    #[test]
+    fn kernel_names_round_trip() {
+        for name in ["8x8", "16x16", "64x1", "reduce64"] {
+            assert_eq!(Kernel::parse(name).unwrap().to_string(), name);
+        }
+        assert_eq!(Kernel::parse("reduce48"), None);
+        assert_eq!(Kernel::parse("0x8"), None);
+        assert_eq!(Kernel::parse("fast"), None);
+    }
+
+    #[test]
+    fn similarity_names_round_trip() {
+        for name in ["dot", "cosine"] {
+            assert_eq!(Similarity::parse(name).unwrap().to_string(), name);
+        }
+        assert_eq!(Similarity::parse(" cosine "), Some(Similarity::Cosine));
+        assert_eq!(Similarity::parse("euclid"), None);
+    }
+}
+
 #[cfg(all(test, feature = "gpu-smoke"))]
 mod tests {
     use super::*;
 
     #[test]
     fn gpu_similarity_small_job() {
-        let Ok(computer) = SimilarityComputer::new() else {
+        let Ok(computer) = SimilarityComputer::with_options(None, Similarity::Dot) else {
             eprintln!("GPU unavailable on this host; skipping smoke test");
             return;
         };
@@ -478,4 +1102,189 @@ mod tests {
         assert_eq!(scores.len(), 1);
         assert!(scores[0] > 0.5);
     }
+
+    #[test]
+    fn poller_stops_when_the_computer_is_dropped() {
+        for strategy in [
+            PollStrategy::Interval(Duration::from_secs(60)),
+            PollStrategy::Blocking,
+        ] {
+            let Ok(mut computer) = SimilarityComputer::with_options(None, Similarity::Dot) else {
+                eprintln!("GPU unavailable on this host; skipping smoke test");
+                return;
+            };
+            computer.set_poll_strategy(strategy);
+
+            let file_buffer = computer.create_file_buffer(&[1.0, 0.0]);
+            let scores = computer
+                .compute_with_file_buffer(&[1.0, 0.0], 1, &file_buffer, 0, 1, 2)
+                .unwrap();
+            assert!((scores[0] - 1.0).abs() < 1e-6);
+            drop(file_buffer);
+
+            // The poller thread holds the last other reference to the device,
+            // so the device only goes away once the thread has exited; the
+            // long interval checks that Drop doesn't wait it out
+            let device = Arc::downgrade(&computer.device);
+            let started = Instant::now();
+            drop(computer);
+            assert!(
+                device.upgrade().is_none(),
+                "{} poller still running",
+                strategy
+            );
+            assert!(started.elapsed() < Duration::from_secs(10));
+        }
+    }
+
+    #[test]
+    fn gpu_scores_share_the_cpu_scale() {
+        let Ok(computer) = SimilarityComputer::with_options(None, Similarity::Dot) else {
+            eprintln!("GPU unavailable on this host; skipping smoke test");
+            return;
+        };
+
+        // Same, orthogonal and opposite to the query
+        let file_vectors: Vec<f32> = vec![1.0, 0.0, 0.0, 1.0, -1.0, 0.0];
+        let file_buffer = computer.create_file_buffer(&file_vectors);
+        let scores = computer
+            .compute_with_file_buffer(&[1.0, 0.0], 1, &file_buffer, 0, 3, 2)
+            .unwrap();
+        assert!(scores.iter().all(|score| (0.0..=1.0).contains(score)));
+        assert!((scores[0] - 1.0).abs() < 1e-6);
+        assert_eq!(scores[1], 0.0);
+        assert_eq!(scores[2], 0.0);
+    }
+
+    #[test]
+    fn reused_buffers_give_the_same_scores() {
+        let Ok(computer) = SimilarityComputer::with_options(None, Similarity::Dot) else {
+            eprintln!("GPU unavailable on this host; skipping smoke test");
+            return;
+        };
+
+        let dim = 4;
+        let vector = |seed: usize| -> Vec<f32> {
+            (0..dim)
+                .map(|i| ((seed * 7 + i * 3) % 11) as f32 / 22.0)
+                .collect()
+        };
+        let files: Vec<f32> = (0..16).flat_map(vector).collect();
+        let queries: Vec<f32> = (100..103).flat_map(vector).collect();
+        let expected = |q: usize, f: usize| -> f32 {
+            (0..dim)
+                .map(|i| queries[q * dim + i] * files[f * dim + i])
+                .sum::<f32>()
+                .clamp(0.0, 1.0)
+        };
+
+        let file_buffer = computer.create_file_buffer(&files);
+        let query_buffer = computer.create_query_buffer(&queries);
+        let before = computer.buffers_created();
+
+        // Two passes of four equal tiles, then a smaller tile that fits a pooled set
+        let tiles = [(0, 4), (4, 4), (8, 4), (12, 4)];
+        for (offset, len) in tiles.iter().chain(tiles.iter()).chain([&(6, 2)]) {
+            let scores = computer
+                .dispatch_tile(&query_buffer, 3, &file_buffer, *offset, *len, dim)
+                .unwrap()
+                .wait()
+                .unwrap();
+            assert_eq!(scores.len(), 3 * len);
+            for q in 0..3 {
+                for f in 0..*len {
+                    let score = scores[q * len + f];
+                    assert!((score - expected(q, offset + f)).abs() < 1e-5);
+                }
+            }
+        }
+
+        // One output/staging/params set served all nine tiles
+        assert_eq!(computer.buffers_created() - before, 3);
+    }
+
+    #[test]
+    fn kernels_agree_on_scores() {
+        // Sizes that are not multiples of any workgroup size
+        let (dim, query_len, file_len) = (37, 5, 23);
+        let vector = |seed: usize| -> Vec<f32> {
+            (0..dim)
+                .map(|i| ((seed * 13 + i * 5) % 17) as f32 / 100.0)
+                .collect()
+        };
+        let files: Vec<f32> = (0..file_len).flat_map(vector).collect();
+        let queries: Vec<f32> = (50..50 + query_len).flat_map(vector).collect();
+
+        let kernels = [
+            FALLBACK_KERNEL,
+            Kernel::Tiled { x: 16, y: 16 },
+            Kernel::Tiled { x: 64, y: 1 },
+            Kernel::Reduction { threads: 32 },
+            Kernel::Reduction { threads: 64 },
+        ];
+        for kernel in kernels {
+            let Ok(computer) = SimilarityComputer::with_options(Some(kernel), Similarity::Dot)
+            else {
+                eprintln!("GPU unavailable on this host; skipping smoke test");
+                return;
+            };
+            let file_buffer = computer.create_file_buffer(&files);
+            let scores = computer
+                .compute_with_file_buffer(&queries, query_len, &file_buffer, 0, file_len, dim)
+                .unwrap();
+            assert_eq!(scores.len(), query_len * file_len);
+            for q in 0..query_len {
+                for f in 0..file_len {
+                    let expected: f32 = (0..dim)
+                        .map(|i| queries[q * dim + i] * files[f * dim + i])
+                        .sum::<f32>()
+                        .clamp(0.0, 1.0);
+                    let score = scores[q * file_len + f];
+                    assert!(
+                        (score - expected).abs() < 1e-5,
+                        "{} scored {} instead of {}",
+                        computer.kernel(),
+                        score,
+                        expected
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cosine_scores_ignore_vector_length() {
+        // Same direction at twice the length, 45 degrees apart, and a zero vector
+        let files: Vec<f32> = vec![2.0, 0.0, 3.0, 3.0, 0.0, 0.0];
+        let queries: Vec<f32> = vec![4.0, 0.0];
+        for kernel in [FALLBACK_KERNEL, Kernel::Reduction { threads: 32 }] {
+            let Ok(cosine) = SimilarityComputer::with_options(Some(kernel), Similarity::Cosine)
+            else {
+                eprintln!("GPU unavailable on this host; skipping smoke test");
+                return;
+            };
+            let file_buffer = cosine.create_file_buffer(&files);
+            let scores = cosine
+                .compute_with_file_buffer(&queries, 1, &file_buffer, 0, 3, 2)
+                .unwrap();
+            assert!((scores[0] - 1.0).abs() < 1e-6, "{}: {:?}", kernel, scores);
+            assert!(
+                (scores[1] - 0.5f32.sqrt()).abs() < 1e-6,
+                "{}: {:?}",
+                kernel,
+                scores
+            );
+            assert_eq!(scores[2], 0.0);
+        }
+
+        // The dot product only clamps, so it saturates on long vectors
+        let Ok(dot) = SimilarityComputer::with_options(None, Similarity::Dot) else {
+            return;
+        };
+        let file_buffer = dot.create_file_buffer(&files);
+        let scores = dot
+            .compute_with_file_buffer(&queries, 1, &file_buffer, 0, 3, 2)
+            .unwrap();
+        assert_eq!(scores, vec![1.0, 1.0, 0.0]);
+    }
 }
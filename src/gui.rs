@@ -1,15 +1,31 @@
-use crate::database::{Database, SearchResult};
-use crate::match_engine::{self, MatchEngineKind, MatchProgressCallback};
 use crate::opener;
-use crate::reference_loader::{ReferenceLoadReport, ReferenceLoader};
-use crate::scanner::Scanner;
-use crate::searcher::Searcher;
+use crate::session_log::{self, LogLevel, SessionLog};
+use crate::thumbnail;
 use eframe::egui;
-use log::error;
+use log::{error, warn};
 use rfd::FileDialog;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::{Arc, Mutex, MutexGuard};
 use std::thread;
+use tiff_locator::controller::{
+    export_results_json, export_results_jsonl, export_results_xlsx, CacheCheck, Controller,
+    Diagnostics, ExportStats, MatchRequest, MatchScope, ScanSummary, SearchOutcome, SearchRequest,
+};
+use tiff_locator::database::{
+    ClearSelection, Database, DuplicateName, MatchRun, MatchStatus, ResultOrder, ScanRoot,
+    SearchHistoryEntry, SearchResult, StoredMatch,
+};
+use tiff_locator::match_engine::{MatchCountCallback, MatchEngineKind, MatchProgressCallback};
+use tiff_locator::matcher::{
+    parse_matched_via, CandidateKind, MatchExplanation, ScoreCombination, DEFAULT_PATH_COMPONENTS,
+    MAX_PATH_COMPONENTS,
+};
+use tiff_locator::reference_loader::{ReferenceLoadReport, ReferenceLoader};
+use tiff_locator::scanner::SIZE_BUCKET_LABELS;
+use tiff_locator::searcher::{SearchPhase, SearchProgressCallback};
 
 #[derive(Debug, Clone, PartialEq)]
 enum AppState {
@@ -18,6 +34,9 @@ enum AppState {
     LoadingReferenceIds,
     Matching,
     Searching,
+    Exporting,
+    ValidatingCache,
+    VerifyingCache,
 }
 
 // Messages sent from background threads to GUI
@@ -27,8 +46,8 @@ enum BackgroundMessage {
         total: usize,
     },
     ScanComplete {
-        discovered: usize,
-        db_total: usize,
+        summary: ScanSummary,
+        incremental: bool,
     },
     ScanError {
         error: String,
@@ -42,6 +61,9 @@ enum BackgroundMessage {
         report: ReferenceLoadReport,
         total: usize,
     },
+    ReferenceIdsCancelled {
+        processed_rows: usize,
+    },
     ReferenceIdsError {
         error: String,
     },
@@ -49,9 +71,15 @@ enum BackgroundMessage {
         processed: usize,
         total: usize,
     },
+    MatchingMatchCount {
+        found: usize,
+    },
     MatchingComplete {
         match_count: usize,
         engine: MatchEngineKind,
+        threshold: f64,
+        dry_run: bool,
+        output: Option<String>,
     },
     MatchingError {
         error: String,
@@ -59,25 +87,323 @@ enum BackgroundMessage {
     MatchingEngineNotice {
         message: String,
     },
+    SearchProgress {
+        phase: SearchPhase,
+        processed: usize,
+        total: usize,
+    },
     SearchComplete {
+        request: SearchRequest,
         results: Vec<SearchResult>,
+        total: usize,
+        from_cache: bool,
         cache_error: Option<String>,
     },
     SearchError {
         error: String,
     },
+    ExportProgress {
+        rows: usize,
+        total: usize,
+    },
+    ExportComplete {
+        rows: usize,
+        path: String,
+    },
+    ExportError {
+        error: String,
+    },
+    CacheValidated {
+        check: CacheCheck,
+    },
+    CacheValidationError {
+        error: String,
+    },
+    VerifyCacheProgress {
+        checked: usize,
+        total: usize,
+    },
+    VerifyCacheComplete {
+        stale: Vec<(i64, String)>,
+    },
+    VerifyCacheError {
+        error: String,
+    },
+    DiagnosticsGathered {
+        diagnostics: Result<Diagnostics, String>,
+    },
+    CacheOptimized {
+        result: Result<(), String>,
+    },
+    LocationsOpened {
+        result: Result<String, String>,
+    },
+    ThumbnailDecoded {
+        path: String,
+        image: Result<egui::ColorImage, String>,
+    },
+}
+
+/// Zoom factor applied when "Larger text" is enabled.
+const LARGER_TEXT_ZOOM: f32 = 1.25;
+
+/// Thresholds below this match most file names, so a warning is shown.
+const NOISY_THRESHOLD: f64 = 0.4;
+
+/// Buckets of the match score histogram, 5% wide.
+const HISTOGRAM_BUCKETS: usize = 20;
+
+/// Decoded thumbnails kept at most, so an "All" page of thousands of rows
+/// does not keep a texture for every row scrolled past.
+const THUMBNAIL_CACHE_LIMIT: usize = 300;
+
+/// Rows listed when browsing stored matches; narrow the filter to see others.
+const STORED_MATCH_LIMIT: usize = 200;
+
+/// Searches remembered by default so re-viewing them skips the database.
+const DEFAULT_RECENT_SEARCHES: usize = 16;
+const MAX_RECENT_SEARCHES: usize = 256;
+
+/// Past queries listed under the search box.
+const SEARCH_HISTORY_SHOWN: usize = 10;
+
+/// Cached paths checked by "Validate Cache".
+const CACHE_CHECK_SAMPLE: usize = 200;
+
+const THEME_SETTING: &str = "theme";
+const HIGH_CONTRAST_SETTING: &str = "high_contrast";
+const LARGER_TEXT_SETTING: &str = "larger_text";
+const THREADS_SETTING: &str = "worker_threads";
+const PAGE_SIZE_SETTING: &str = "results_per_page";
+const FOLLOW_LINKS_SETTING: &str = "follow_links";
+const READ_METADATA_SETTING: &str = "read_metadata";
+const RECENT_SEARCHES_SETTING: &str = "recent_searches";
+const PATH_COMPONENTS_SETTING: &str = "path_components";
+const SCORE_COMBINATION_SETTING: &str = "score_combination";
+const MIN_FILE_SIZE_SETTING: &str = "min_file_size";
+const MAX_FILE_SIZE_SETTING: &str = "max_file_size";
+
+/// Choices for results per page; 0 stands for "All".
+const PAGE_SIZES: [usize; 6] = [50, 100, 500, 1000, 2000, 0];
+const DEFAULT_PAGE_SIZE: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ThemePreference {
+    System,
+    Light,
+    Dark,
+}
+
+impl ThemePreference {
+    const ALL: [ThemePreference; 3] = [
+        ThemePreference::System,
+        ThemePreference::Light,
+        ThemePreference::Dark,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            ThemePreference::System => "System",
+            ThemePreference::Light => "Light",
+            ThemePreference::Dark => "Dark",
+        }
+    }
+
+    fn key(self) -> &'static str {
+        match self {
+            ThemePreference::System => "system",
+            ThemePreference::Light => "light",
+            ThemePreference::Dark => "dark",
+        }
+    }
+
+    fn from_key(key: &str) -> Self {
+        match key {
+            "light" => ThemePreference::Light,
+            "dark" => ThemePreference::Dark,
+            _ => ThemePreference::System,
+        }
+    }
+}
+
+// Which reference IDs "Match IDs" and the match count preview cover, or
+// every ID against only the files scanned since the last full run
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MatchSelection {
+    All,
+    Listed,
+    ImportedSince,
+    NewFiles,
+}
+
+// The "Clear Cache" confirmation: what to clear, and the rows each part held
+// when the dialog opened
+struct ClearDialog {
+    selection: ClearSelection,
+    files: usize,
+    matches: usize,
+    vectors: usize,
+    reference_ids: usize,
+}
+
+// A result whose name was clicked, with how its score came about; `None`
+// when there's no breakdown to show
+struct MatchDetail {
+    hh_id: String,
+    result: SearchResult,
+    explanation: Option<MatchExplanation>,
+}
+
+// The last few search outcomes, most recent first, so repeating a search
+// shows its results without a database round trip. Cleared whenever files
+// or matches change.
+struct RecentSearches {
+    capacity: usize,
+    entries: VecDeque<(SearchRequest, SearchOutcome)>,
+}
+
+impl RecentSearches {
+    fn new(capacity: usize) -> Self {
+        RecentSearches {
+            capacity,
+            entries: VecDeque::new(),
+        }
+    }
+
+    // The outcome of an identical earlier request, now the most recent
+    fn get(&mut self, request: &SearchRequest) -> Option<SearchOutcome> {
+        let index = self.entries.iter().position(|(seen, _)| seen == request)?;
+        let entry = self.entries.remove(index)?;
+        let outcome = entry.1.clone();
+        self.entries.push_front(entry);
+        Some(outcome)
+    }
+
+    // A completed search that replays an identical earlier request as it was
+    // first delivered, stored or freshly scored
+    fn replay(&mut self, request: SearchRequest) -> Option<BackgroundMessage> {
+        let outcome = self.get(&request)?;
+        Some(BackgroundMessage::SearchComplete {
+            request,
+            results: outcome.results,
+            total: outcome.total,
+            from_cache: outcome.from_cache,
+            cache_error: None,
+        })
+    }
+
+    fn insert(&mut self, request: SearchRequest, outcome: SearchOutcome) {
+        self.entries.retain(|(seen, _)| *seen != request);
+        self.entries.push_front((request, outcome));
+        self.entries.truncate(self.capacity);
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.entries.truncate(capacity);
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+// A search served from stored matches that is read one page at a time
+struct CachedSearch {
+    request: SearchRequest,
+    // Matches for the request, and those inside the display band
+    total: usize,
+    in_band: usize,
+    // Page, page size and display band of the rows in `search_results`
+    loaded: (usize, usize, f64, f64),
+}
+
+// Preview state of one result file, cached by file path
+enum Thumbnail {
+    Loading,
+    Ready(egui::TextureHandle),
+    Unavailable(String),
 }
 
 pub struct TiffLocatorApp {
     // Paths
-    folder_path: String,
+    scan_roots: Vec<ScanRoot>,
     csv_path: String,
-    cache_path: String,
+    // Headers and first rows of the selected ID file, shown before importing
+    id_preview: Option<(Vec<String>, Vec<Vec<String>>)>,
+
+    // File names cached under several paths; results with these names also
+    // show their folder
+    duplicate_names: Vec<DuplicateName>,
+    ambiguous_names: HashSet<String>,
+
+    // Runs the background steps against the cache database
+    controller: Controller,
+    // Worker threads for scanning, matching and searching; 0 uses every core
+    thread_limit: usize,
+    // Whether scans descend into symlinked folders, and whether they read
+    // page counts and sizes from each file's header
+    follow_links: bool,
+    read_metadata: bool,
+    // Whether the next scan only reads files modified since each folder's
+    // last scan; not remembered, since such scans never prune
+    since_last_scan: bool,
+    // Size bounds as typed, e.g. "1" or "500 MB"; blank leaves that end open
+    min_file_size: String,
+    max_file_size: String,
+    // Stored matches per 5% similarity bucket, for tuning the threshold
+    score_histogram: Vec<(f64, f64, usize)>,
+    // Shown in the bottom panel so the stored matches' engine is never in doubt
+    last_match_run: Option<MatchRun>,
+    // Filter and rows of the stored match browser, re-read when the filter
+    // or the stored matches change
+    stored_match_query: String,
+    stored_matches: Vec<StoredMatch>,
+    stored_matches_stale: bool,
+
+    // Prefixes typed into the remap tool, and the old prefix last previewed
+    // with the number of files it covers
+    remap_old_prefix: String,
+    remap_new_prefix: String,
+    remap_preview: Option<(String, usize)>,
+
+    // Cached files the last "Verify Cache" found missing, until pruned or
+    // dismissed
+    stale_files: Option<Vec<(i64, String)>>,
+
+    // Open while the user picks what "Clear Cache" removes
+    clear_dialog: Option<ClearDialog>,
+    // The "Why it matched" window, open for the last clicked result
+    match_detail: Option<MatchDetail>,
+
+    // Outcomes of the last `recent_search_limit` searches (0 keeps none)
+    recent_searches: RecentSearches,
+    recent_search_limit: usize,
 
     // Settings
     similarity_threshold: f64,
-    use_gpu_matcher: bool,
+    similarity_max: f64,
+    match_engine: MatchEngineKind,
     gpu_available: bool,
+    match_path_components: bool,
+    // Enclosing folders compared when `match_path_components` is on
+    path_components: usize,
+    case_sensitive: bool,
+    score_combination: ScoreCombination,
+    result_order: ResultOrder,
+    match_selection: MatchSelection,
+    match_ids_input: String,
+    // Local time, set to the start of the last import in this session
+    match_since_input: String,
+    // Each run's matches go to a new timestamped database here instead of
+    // the cache when set
+    match_output_dir: Option<PathBuf>,
+
+    // Appearance, persisted in the cache database
+    theme: ThemePreference,
+    high_contrast: bool,
+    larger_text: bool,
+    appearance_dirty: bool,
 
     // State
     state: AppState,
@@ -86,12 +412,42 @@ pub struct TiffLocatorApp {
 
     // Search
     search_input: String,
+    // List the stored matches of every ID starting with the input instead
+    id_prefix_search: bool,
+    // Prefix and stored matches of the last prefix search, grouped by ID
+    prefix_matches: Option<(String, Vec<StoredMatch>)>,
     search_results: Vec<SearchResult>,
+    // Request that produced `search_results`, for export summaries
+    last_search: Option<SearchRequest>,
+    // Exports only include matches with this review status, when set
+    export_status: Option<MatchStatus>,
+    // Set when `search_results` only holds the current page
+    cached_search: Option<CachedSearch>,
+    // Paths hidden with "Exclude This File", for this session only
+    excluded_paths: HashSet<String>,
+    // Paths on the cache's blocklist, kept across sessions
+    blocklist: Vec<String>,
+    // Latest Quick Search queries, most recent first, kept across sessions
+    search_history: Vec<SearchHistoryEntry>,
 
-    // Pagination for results
+    // Result previews, decoded lazily for visible rows of the current page
+    show_thumbnails: bool,
+    thumbnails: HashMap<String, Thumbnail>,
+
+    // Show one row per file name when the same scan is filed at several paths
+    collapse_duplicates: bool,
+    expanded_groups: HashSet<String>,
+
+    // Pagination for results; 0 rows per page shows them all on one page
     results_page: usize,
+    // Row of the current page picked with the arrow keys
+    selected_result_index: Option<usize>,
     results_per_page: usize,
 
+    // Display-only similarity band applied to search results and their export
+    filter_min_similarity: f64,
+    filter_max_similarity: f64,
+
     // Database
     db: Option<Arc<Mutex<Database>>>,
     file_count: usize,
@@ -103,6 +459,25 @@ pub struct TiffLocatorApp {
     // Reference ID count and import details
     reference_id_count: usize,
     last_reference_report: Option<ReferenceLoadReport>,
+    reference_cancel_flag: Option<Arc<AtomicBool>>,
+
+    // Engine notice raised during the current matching run, kept for the summary
+    matching_notice: Option<String>,
+    // Matches found so far in the current matching run
+    matches_found: usize,
+
+    // Session history of status/error messages, notices and logged warnings;
+    // the last messages written to it, so only changes are recorded
+    session_log: SessionLog,
+    logged_status: String,
+    logged_error: String,
+    // Rotating log file written by the logger, shown under About
+    log_file: Option<PathBuf>,
+    // Facts shown under About, gathered on a worker thread when first shown
+    diagnostics: Option<Result<Diagnostics, String>>,
+    diagnostics_pending: bool,
+    optimize_pending: bool,
+    open_locations_pending: bool,
 
     // Channel for background thread communication
     bg_receiver: Receiver<BackgroundMessage>,
@@ -114,11 +489,69 @@ impl Default for TiffLocatorApp {
         let (bg_sender, bg_receiver) = mpsc::channel();
         let cache_path = "cache.db".to_string();
 
+        let mut theme = ThemePreference::System;
+        let mut high_contrast = false;
+        let mut larger_text = false;
+        let mut saved_threads = None;
+        let mut recent_search_limit = DEFAULT_RECENT_SEARCHES;
+        let mut path_components = DEFAULT_PATH_COMPONENTS;
+        let mut score_combination = ScoreCombination::default();
+        let mut follow_links = false;
+        let mut read_metadata = false;
+        let mut min_file_size = String::new();
+        let mut max_file_size = String::new();
+        let mut results_per_page = DEFAULT_PAGE_SIZE;
+        let mut score_histogram = Vec::new();
+        let mut last_match_run = None;
+        let mut scan_roots = Vec::new();
+        let mut duplicate_names = Vec::new();
+        let mut blocklist = Vec::new();
+        let mut search_history = Vec::new();
+
         let (db, reference_id_count, file_count, status_message, error_message) =
             match Database::new(&cache_path) {
                 Ok(db) => {
                     let reference_id_count = db.get_reference_id_count().unwrap_or(0);
                     let file_count = db.get_all_files().map(|files| files.len()).unwrap_or(0);
+                    scan_roots = db.get_scan_roots().unwrap_or_default();
+                    duplicate_names = db.find_duplicate_names().unwrap_or_default();
+                    blocklist = db.get_blocklist().unwrap_or_default();
+                    search_history = db.recent_searches(SEARCH_HISTORY_SHOWN).unwrap_or_default();
+                    score_histogram = db.score_histogram(HISTOGRAM_BUCKETS).unwrap_or_default();
+                    last_match_run = db.last_match_run().ok().flatten();
+                    let setting = |key| db.get_setting(key).ok().flatten();
+                    if let Some(value) = setting(THEME_SETTING) {
+                        theme = ThemePreference::from_key(&value);
+                    }
+                    high_contrast = setting(HIGH_CONTRAST_SETTING).as_deref() == Some("true");
+                    larger_text = setting(LARGER_TEXT_SETTING).as_deref() == Some("true");
+                    saved_threads =
+                        setting(THREADS_SETTING).and_then(|value| value.parse::<usize>().ok());
+                    follow_links = setting(FOLLOW_LINKS_SETTING).as_deref() == Some("true");
+                    if let Some(limit) = setting(RECENT_SEARCHES_SETTING)
+                        .and_then(|value| value.parse::<usize>().ok())
+                    {
+                        recent_search_limit = limit.min(MAX_RECENT_SEARCHES);
+                    }
+                    if let Some(depth) = setting(PATH_COMPONENTS_SETTING)
+                        .and_then(|value| value.parse::<usize>().ok())
+                    {
+                        path_components = depth.clamp(1, MAX_PATH_COMPONENTS);
+                    }
+                    if let Some(combination) = setting(SCORE_COMBINATION_SETTING)
+                        .and_then(|value| ScoreCombination::from_label(&value))
+                    {
+                        score_combination = combination;
+                    }
+                    read_metadata = setting(READ_METADATA_SETTING).as_deref() == Some("true");
+                    min_file_size = setting(MIN_FILE_SIZE_SETTING).unwrap_or_default();
+                    max_file_size = setting(MAX_FILE_SIZE_SETTING).unwrap_or_default();
+                    if let Some(size) = setting(PAGE_SIZE_SETTING)
+                        .and_then(|value| value.parse::<usize>().ok())
+                        .filter(|size| PAGE_SIZES.contains(size))
+                    {
+                        results_per_page = size;
+                    }
                     (
                         Some(Arc::new(Mutex::new(db))),
                         reference_id_count,
@@ -136,186 +569,1211 @@ impl Default for TiffLocatorApp {
                 ),
             };
 
+        // TIFF_THREADS, applied by the controller, wins over the saved value
+        let mut controller = Controller::new(&cache_path);
+        if let Some(limit) = saved_threads.filter(|_| std::env::var_os("TIFF_THREADS").is_none()) {
+            if let Err(e) = controller.set_thread_limit(limit) {
+                warn!("Ignoring saved worker thread limit: {}", e);
+            }
+        }
+        controller.set_follow_links(follow_links);
+        controller.set_read_metadata(read_metadata);
+        controller.set_path_components(path_components);
+        controller.set_score_combination(score_combination);
+        // Single-ID searches are cheap to explain, unlike match runs
+        controller.set_explain_scores(true);
+        match size_range(&min_file_size, &max_file_size) {
+            Ok((min, max)) => controller.set_size_range(min, max),
+            Err(e) => warn!("Ignoring saved file size range: {}", e),
+        }
+
         Self {
-            folder_path: String::new(),
+            scan_roots,
+            ambiguous_names: ambiguous_names(&duplicate_names),
+            duplicate_names,
             csv_path: String::new(),
-            cache_path,
+            id_preview: None,
+            thread_limit: controller.thread_limit(),
+            follow_links,
+            read_metadata,
+            since_last_scan: false,
+            min_file_size,
+            max_file_size,
+            score_histogram,
+            last_match_run,
+            stored_match_query: String::new(),
+            stored_matches: Vec::new(),
+            stored_matches_stale: true,
+            remap_old_prefix: String::new(),
+            remap_new_prefix: String::new(),
+            remap_preview: None,
+            stale_files: None,
+            clear_dialog: None,
+            match_detail: None,
+            recent_searches: RecentSearches::new(recent_search_limit),
+            recent_search_limit,
+            controller,
             similarity_threshold: 0.7,
+            similarity_max: 1.0,
             state: AppState::Idle,
             progress: 0.0,
             progress_text: String::new(),
             search_input: String::new(),
+            id_prefix_search: false,
+            prefix_matches: None,
             search_results: Vec::new(),
+            last_search: None,
+            export_status: None,
+            excluded_paths: HashSet::new(),
+            blocklist,
+            search_history,
+            cached_search: None,
+            show_thumbnails: false,
+            thumbnails: HashMap::new(),
+            collapse_duplicates: false,
+            expanded_groups: HashSet::new(),
             results_page: 0,
-            results_per_page: 500,
+            selected_result_index: None,
+            results_per_page,
+            filter_min_similarity: 0.0,
+            filter_max_similarity: 1.0,
             db,
             file_count,
             status_message,
             error_message,
             reference_id_count,
             last_reference_report: None,
+            reference_cancel_flag: None,
+            matching_notice: None,
+            matches_found: 0,
+            session_log: SessionLog::default(),
+            logged_status: String::new(),
+            logged_error: String::new(),
+            log_file: None,
+            diagnostics: None,
+            diagnostics_pending: false,
+            optimize_pending: false,
+            open_locations_pending: false,
             bg_receiver,
             bg_sender,
-            use_gpu_matcher: false,
+            match_engine: MatchEngineKind::Cpu,
             gpu_available: true,
+            match_path_components: false,
+            path_components,
+            case_sensitive: false,
+            score_combination,
+            result_order: ResultOrder::default(),
+            match_selection: MatchSelection::All,
+            match_ids_input: String::new(),
+            match_since_input: String::new(),
+            match_output_dir: None,
+            theme,
+            high_contrast,
+            larger_text,
+            appearance_dirty: true,
         }
     }
 }
 
 impl TiffLocatorApp {
-    pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        Self::default()
+    pub fn new(
+        _cc: &eframe::CreationContext<'_>,
+        session_log: SessionLog,
+        log_file: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            session_log,
+            log_file,
+            ..Self::default()
+        }
     }
 
-    fn db_handle(&self) -> Result<Arc<Mutex<Database>>, String> {
-        self.db
-            .as_ref()
-            .cloned()
-            .ok_or_else(|| "Database is unavailable. Check cache.db permissions.".to_string())
+    /// Record status and error messages in the session log when they change,
+    /// since the single-line labels are overwritten by the next step; errors
+    /// also go to the log file. Returns whether anything was recorded.
+    fn log_message_changes(&mut self) -> bool {
+        let changed =
+            self.status_message != self.logged_status || self.error_message != self.logged_error;
+        if self.status_message != self.logged_status {
+            self.logged_status = self.status_message.clone();
+            if !self.logged_status.is_empty() {
+                self.session_log
+                    .push(LogLevel::Info, self.logged_status.clone());
+            }
+        }
+        if self.error_message != self.logged_error {
+            self.logged_error = self.error_message.clone();
+            if !self.logged_error.is_empty() {
+                error!(target: session_log::SESSION_TARGET, "{}", self.logged_error);
+                self.session_log
+                    .push(LogLevel::Error, self.logged_error.clone());
+            }
+        }
+        changed
     }
 
-    fn lock_db<'a>(db: &'a Arc<Mutex<Database>>) -> Result<MutexGuard<'a, Database>, String> {
-        db.lock()
-            .map_err(|e| format!("Database access error: {}", e))
+    fn session_log_panel(&mut self, ctx: &egui::Context) {
+        egui::TopBottomPanel::bottom("session_log").show(ctx, |ui| {
+            self.last_match_run_line(ui);
+            let entries = self.session_log.entries();
+            egui::CollapsingHeader::new(format!("📜 Session log ({})", entries.len()))
+                .id_source("session_log_header")
+                .show(ui, |ui| {
+                    if ui.small_button("Clear").clicked() {
+                        self.session_log.clear();
+                    }
+                    egui::ScrollArea::vertical()
+                        .max_height(160.0)
+                        .stick_to_bottom(true)
+                        .show(ui, |ui| {
+                            for entry in &entries {
+                                let text =
+                                    format!("{}  {}", entry.time.format("%H:%M:%S"), entry.message);
+                                match entry.level {
+                                    LogLevel::Info => ui.label(text),
+                                    LogLevel::Warning => {
+                                        ui.colored_label(egui::Color32::from_rgb(230, 160, 0), text)
+                                    }
+                                    LogLevel::Error => ui.colored_label(egui::Color32::RED, text),
+                                };
+                            }
+                        });
+                });
+            egui::CollapsingHeader::new("ℹ About / diagnostics")
+                .id_source("about_header")
+                .show(ui, |ui| self.about_section(ui));
+        });
     }
 
-    fn select_folder(&mut self) {
-        if let Some(path) = FileDialog::new().pick_folder() {
-            self.folder_path = path.to_string_lossy().to_string();
-            self.status_message = format!("Selected folder: {}", self.folder_path);
-            self.error_message.clear();
-        }
+    /// Engine, threshold and counts of the run that produced the stored
+    /// matches; the CPU and GPU matchers score differently.
+    fn last_match_run_line(&self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("⚡ Last match run:");
+            let Some(run) = &self.last_match_run else {
+                ui.weak("none yet");
+                return;
+            };
+            let finished = chrono::DateTime::parse_from_rfc3339(&run.finished_at)
+                .map(|date| {
+                    date.with_timezone(&chrono::Local)
+                        .format("%Y-%m-%d %H:%M")
+                        .to_string()
+                })
+                .unwrap_or_else(|_| run.finished_at.clone());
+            ui.strong(run.engine.to_uppercase());
+            ui.label(format!(
+                "at {:.0}% · {} IDs → {} matches · {}",
+                run.threshold * 100.0,
+                run.id_count,
+                run.match_count,
+                finished
+            ));
+        });
     }
 
-    fn select_csv(&mut self) {
-        if let Some(path) = FileDialog::new().add_filter("CSV", &["csv"]).pick_file() {
-            self.csv_path = path.to_string_lossy().to_string();
-            self.status_message = format!("Selected CSV: {}", self.csv_path);
-            self.error_message.clear();
+    /// Version, environment, cache counts and where the log file lives, for
+    /// bug reports.
+    fn about_section(&mut self, ui: &mut egui::Ui) {
+        if self.diagnostics.is_none() && !self.diagnostics_pending {
+            self.gather_diagnostics();
+        }
+        match &self.diagnostics {
+            None => {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("Gathering diagnostics...");
+                });
+            }
+            Some(Err(e)) => {
+                ui.colored_label(egui::Color32::RED, e);
+            }
+            Some(Ok(diagnostics)) => {
+                egui::Grid::new("diagnostics_grid")
+                    .num_columns(2)
+                    .spacing([10.0, 2.0])
+                    .show(ui, |ui| {
+                        let cache_size = diagnostics
+                            .cache_bytes
+                            .map(|bytes| format!(" ({})", format_size(bytes)))
+                            .unwrap_or_default();
+                        let gpu = match &diagnostics.gpu {
+                            Ok(adapter) => adapter.clone(),
+                            Err(reason) => format!("unavailable ({})", reason),
+                        };
+                        let rows = [
+                            ("Version", diagnostics.version.to_string()),
+                            ("Platform", diagnostics.platform.clone()),
+                            ("Cache", format!("{}{}", diagnostics.cache_path, cache_size)),
+                            ("Cached files", group_thousands(diagnostics.file_count)),
+                            (
+                                "Reference IDs",
+                                group_thousands(diagnostics.reference_id_count),
+                            ),
+                            ("Stored matches", group_thousands(diagnostics.match_count)),
+                            ("GPU", gpu),
+                        ];
+                        for (label, value) in rows {
+                            ui.label(label);
+                            ui.label(value);
+                            ui.end_row();
+                        }
+                    });
+            }
         }
+
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(
+                    matches!(self.diagnostics, Some(Ok(_))),
+                    egui::Button::new("📋 Copy diagnostics"),
+                )
+                .on_hover_text("Copy these details as text, e.g. for a bug report")
+                .clicked()
+            {
+                ui.ctx().copy_text(self.diagnostics_text());
+                self.status_message = "Copied diagnostics to the clipboard".to_string();
+            }
+            if ui
+                .add_enabled(!self.diagnostics_pending, egui::Button::new("🔄 Refresh"))
+                .clicked()
+            {
+                self.gather_diagnostics();
+            }
+            if ui
+                .add_enabled(!self.optimize_pending, egui::Button::new("⚙ Optimize"))
+                .on_hover_text(
+                    "Refresh the cache's query statistics; worth doing after large scans \
+                     or imports if searches feel slow",
+                )
+                .clicked()
+            {
+                self.optimize_cache();
+            }
+        });
+
+        let Some(log_file) = self.log_file.clone() else {
+            ui.label("Log file: unavailable (see the session log)");
+            return;
+        };
+        let log_file = log_file.to_string_lossy().to_string();
+        ui.horizontal(|ui| {
+            ui.label(format!("Log file: {}", log_file));
+            if ui.small_button("📋 Copy path").clicked() {
+                ui.ctx().copy_text(log_file.clone());
+                self.status_message = "Copied log file path".to_string();
+            }
+            if ui
+                .small_button("📂 Open folder")
+                .on_hover_text("Show the log file in the file manager")
+                .clicked()
+            {
+                if let Err(e) = opener::open_file_location(&log_file) {
+                    self.error_message = format!("Failed to open log folder: {}", e);
+                }
+            }
+        });
     }
 
-    fn load_reference_ids(&mut self) {
-        if self.csv_path.is_empty() {
-            self.error_message = "Please select a CSV file first".to_string();
+    /// Filter box and rows of the stored matches, read straight from the
+    /// cache without scoring anything.
+    fn stored_matches_browser(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Filter:");
+            if ui
+                .add(
+                    egui::TextEdit::singleline(&mut self.stored_match_query)
+                        .hint_text("part of an ID or file name"),
+                )
+                .changed()
+            {
+                self.stored_matches_stale = true;
+            }
+        });
+        self.load_stored_matches();
+
+        if self.stored_matches.is_empty() {
+            ui.label("No stored matches contain this text");
             return;
         }
+        if self.stored_matches.len() == STORED_MATCH_LIMIT {
+            ui.label(format!(
+                "Showing the best {} matches; type more of the ID or file name to narrow them",
+                STORED_MATCH_LIMIT
+            ));
+        }
 
-        if self.db.is_none() {
-            self.error_message = "Database is unavailable. Check cache.db permissions.".to_string();
-            return;
+        let mut action_outcome = None;
+        egui::ScrollArea::vertical()
+            .id_source("stored_matches_scroll")
+            .max_height(300.0)
+            .show(ui, |ui| {
+                egui::Grid::new("stored_matches_grid")
+                    .striped(true)
+                    .spacing([10.0, 4.0])
+                    .show(ui, |ui| {
+                        ui.label(egui::RichText::new("Household ID").strong());
+                        ui.label(egui::RichText::new("File Name").strong());
+                        ui.label(egui::RichText::new("Similarity").strong());
+                        ui.label(egui::RichText::new("Threshold").strong());
+                        ui.label(egui::RichText::new("Matched via").strong());
+                        ui.label(egui::RichText::new("Action").strong());
+                        ui.end_row();
+
+                        for stored in &self.stored_matches {
+                            if self.excluded_paths.contains(&stored.result.file_path) {
+                                continue;
+                            }
+                            ui.label(&stored.hh_id);
+                            let name = ui
+                                .label(&stored.result.file_name)
+                                .on_hover_text(&stored.result.file_path);
+                            if let Some(action) = result_menu(&name, &stored.result, &stored.hh_id)
+                            {
+                                action_outcome = Some(action);
+                            }
+                            if let Some(action) = result_cells(ui, &stored.result, &stored.hh_id) {
+                                action_outcome = Some(action);
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+
+        if let Some(action) = action_outcome {
+            self.apply_row_action(action);
         }
+    }
 
-        self.state = AppState::LoadingReferenceIds;
-        self.progress = 0.0;
-        self.progress_text = "Loading reference IDs...".to_string();
-        self.error_message.clear();
-        self.status_message.clear();
-        self.last_reference_report = None;
+    /// List the stored matches of every ID starting with `prefix`. Reads an
+    /// index range, so it runs on the UI thread like the stored match browser.
+    fn search_id_prefix(&mut self, prefix: String) {
+        match self.controller.search_id_prefix(
+            &prefix,
+            self.similarity_threshold,
+            STORED_MATCH_LIMIT,
+        ) {
+            Ok(matches) => {
+                let ids = matches.chunk_by(|a, b| a.hh_id == b.hh_id).count();
+                self.status_message = format!(
+                    "{} stored matches for {} IDs starting with '{}'",
+                    matches.len(),
+                    ids,
+                    prefix
+                );
+                self.error_message.clear();
+                self.prefix_matches = Some((prefix, matches));
+            }
+            Err(e) => self.error_message = e,
+        }
+    }
 
-        let csv_path = self.csv_path.clone();
-        let cache_path = self.cache_path.clone();
-        let sender = self.bg_sender.clone();
+    fn prefix_matches_view(&mut self, ui: &mut egui::Ui) {
+        let Some((prefix, matches)) = &self.prefix_matches else {
+            return;
+        };
+        let mut close = false;
+        let mut action_outcome = None;
+        ui.horizontal(|ui| {
+            ui.strong(format!("Stored matches for IDs starting with '{}'", prefix));
+            if ui.small_button("✖ Close").clicked() {
+                close = true;
+            }
+        });
+        if matches.is_empty() {
+            ui.label("No stored match belongs to an ID starting with this text");
+        } else if matches.len() == STORED_MATCH_LIMIT {
+            ui.label(format!(
+                "Showing the first {} matches; type more of the ID to narrow them",
+                STORED_MATCH_LIMIT
+            ));
+        }
 
-        thread::spawn(move || {
-            let loader = ReferenceLoader::new();
-            let mut db = match Database::new(&cache_path) {
-                Ok(db) => db,
-                Err(e) => {
-                    let _ = sender.send(BackgroundMessage::ReferenceIdsError {
-                        error: format!("Database access error while loading IDs: {}", e),
-                    });
-                    return;
+        egui::ScrollArea::vertical()
+            .id_source("prefix_matches_scroll")
+            .max_height(300.0)
+            .show(ui, |ui| {
+                for group in matches.chunk_by(|a, b| a.hh_id == b.hh_id) {
+                    let hh_id = &group[0].hh_id;
+                    egui::CollapsingHeader::new(format!("{} ({})", hh_id, group.len()))
+                        .id_source(("prefix_group", hh_id))
+                        .default_open(true)
+                        .show(ui, |ui| {
+                            egui::Grid::new(("prefix_grid", hh_id))
+                                .striped(true)
+                                .spacing([10.0, 4.0])
+                                .show(ui, |ui| {
+                                    ui.label(egui::RichText::new("File Name").strong());
+                                    ui.label(egui::RichText::new("Similarity").strong());
+                                    ui.label(egui::RichText::new("Threshold").strong());
+                                    ui.label(egui::RichText::new("Matched via").strong());
+                                    ui.label(egui::RichText::new("Action").strong());
+                                    ui.end_row();
+
+                                    for stored in group {
+                                        if self.excluded_paths.contains(&stored.result.file_path) {
+                                            continue;
+                                        }
+                                        let name = ui
+                                            .label(&stored.result.file_name)
+                                            .on_hover_text(&stored.result.file_path);
+                                        if let Some(action) =
+                                            result_menu(&name, &stored.result, hh_id)
+                                        {
+                                            action_outcome = Some(action);
+                                        }
+                                        if let Some(action) =
+                                            result_cells(ui, &stored.result, hh_id)
+                                        {
+                                            action_outcome = Some(action);
+                                        }
+                                        ui.end_row();
+                                    }
+                                });
+                        });
                 }
-            };
+            });
 
-            let progress_sender = sender.clone();
-            let progress_callback =
-                move |processed_rows: usize, bytes_read: u64, total_bytes: u64| {
-                    let _ = progress_sender.send(BackgroundMessage::ReferenceIdsProgress {
-                        processed_rows,
-                        bytes_read,
-                        total_bytes,
-                    });
-                };
+        if close {
+            self.prefix_matches = None;
+        }
+        if let Some(action) = action_outcome {
+            self.apply_row_action(action);
+        }
+    }
 
-            let load_result =
-                loader.load_from_csv_with_progress(&csv_path, &mut db, Some(progress_callback));
+    /// Put paths on the blocklist and take them out of the current results.
+    fn block_files(&mut self, paths: Vec<String>) {
+        match self.controller.block_files(&paths) {
+            Ok(added) => {
+                let blocked: HashSet<&String> = paths.iter().collect();
+                self.search_results
+                    .retain(|result| !blocked.contains(&result.file_path));
+                self.stored_matches
+                    .retain(|stored| !blocked.contains(&stored.result.file_path));
+                if let Some((_, matches)) = self.prefix_matches.as_mut() {
+                    matches.retain(|stored| !blocked.contains(&stored.result.file_path));
+                }
+                // Their stored matches are gone, so cached pages are re-read
+                if let Some(cached) = self.cached_search.as_mut() {
+                    cached.loaded.0 = usize::MAX;
+                }
+                self.recent_searches.clear();
+                self.refresh_blocklist();
+                self.status_message = format!("Blocked {} file(s)", added);
+                self.error_message.clear();
+            }
+            Err(e) => self.error_message = e,
+        }
+    }
 
-            match load_result {
-                Ok(report) => {
-                    let total = db
-                        .get_reference_id_count()
-                        .map_err(|e| format!("Failed to refresh reference ID count: {}", e));
-
-                    match total {
-                        Ok(total) => {
-                            let _ = sender
-                                .send(BackgroundMessage::ReferenceIdsLoaded { report, total });
-                        }
-                        Err(e) => {
-                            let _ = sender.send(BackgroundMessage::ReferenceIdsError { error: e });
+    // Shown rows of the match are updated in place; cached searches would
+    // still carry the old status
+    fn review_match(&mut self, hh_id: &str, file_path: &str, status: MatchStatus) {
+        match self.controller.set_match_status(hh_id, file_path, status) {
+            Ok(()) => {
+                let searched = self
+                    .last_search
+                    .as_ref()
+                    .is_some_and(|request| request.household_id() == hh_id);
+                if searched {
+                    for result in &mut self.search_results {
+                        if result.file_path == file_path {
+                            result.status = status;
                         }
                     }
                 }
-                Err(e) => {
-                    let _ = sender.send(BackgroundMessage::ReferenceIdsError { error: e });
+                let stored = self
+                    .stored_matches
+                    .iter_mut()
+                    .chain(self.prefix_matches.iter_mut().flat_map(|(_, m)| m));
+                for stored in stored {
+                    if stored.hh_id == hh_id && stored.result.file_path == file_path {
+                        stored.result.status = status;
+                    }
                 }
+                self.recent_searches.clear();
+                self.status_message = format!("Marked {} for {} as {}", file_path, hh_id, status);
+                self.error_message.clear();
             }
-        });
+            Err(e) => self.error_message = e,
+        }
     }
 
-    fn start_scanning(&mut self) {
-        if self.folder_path.is_empty() {
-            self.error_message = "Please select a folder first".to_string();
-            return;
+    fn unblock_file(&mut self, path: &str) {
+        match self.controller.unblock_file(path) {
+            Ok(_) => {
+                self.recent_searches.clear();
+                self.refresh_blocklist();
+                self.status_message = format!("Unblocked {}", path);
+            }
+            Err(e) => self.error_message = e,
         }
+    }
 
-        if self.db.is_none() {
-            self.error_message = "Database is unavailable. Check cache.db permissions.".to_string();
+    fn refresh_blocklist(&mut self) {
+        match self.controller.blocklist() {
+            Ok(blocklist) => self.blocklist = blocklist,
+            Err(e) => self.error_message = e,
+        }
+    }
+
+    fn export_blocklist(&mut self) {
+        let Some(path) = FileDialog::new()
+            .set_file_name("blocklist.txt")
+            .add_filter("Text", &["txt"])
+            .save_file()
+        else {
             return;
+        };
+        match self.controller.export_blocklist(&path.to_string_lossy()) {
+            Ok(count) => {
+                self.status_message = format!("Exported {} blocked paths", count);
+                self.error_message.clear();
+            }
+            Err(e) => self.error_message = e,
         }
+    }
 
-        self.state = AppState::Scanning;
-        self.progress = 0.0;
-        self.progress_text = "Scanning...".to_string();
-        self.error_message.clear();
-        self.status_message.clear();
+    fn import_blocklist(&mut self) {
+        let Some(path) = FileDialog::new().add_filter("Text", &["txt"]).pick_file() else {
+            return;
+        };
+        match self.controller.import_blocklist(&path.to_string_lossy()) {
+            Ok(added) => {
+                self.recent_searches.clear();
+                if let Some(cached) = self.cached_search.as_mut() {
+                    cached.loaded.0 = usize::MAX;
+                }
+                self.refresh_blocklist();
+                let blocked: HashSet<&String> = self.blocklist.iter().collect();
+                self.search_results
+                    .retain(|result| !blocked.contains(&result.file_path));
+                self.status_message = format!("Blocked {} more file(s)", added);
+                self.error_message.clear();
+            }
+            Err(e) => self.error_message = e,
+        }
+    }
 
-        let folder_path = self.folder_path.clone();
-        let cache_path = self.cache_path.clone();
-        let sender = self.bg_sender.clone();
+    /// Blocked paths with a remove button each, plus import and export of
+    /// the whole list.
+    fn blocklist_panel(&mut self, ui: &mut egui::Ui) {
+        ui.label(
+            "Blocked files never appear in searches or matching. Right-click a result \
+             and choose \"Block Permanently\" to add one.",
+        );
+        ui.horizontal(|ui| {
+            if ui.button("📥 Import list").clicked() {
+                self.import_blocklist();
+            }
+            if ui
+                .add_enabled(
+                    !self.blocklist.is_empty(),
+                    egui::Button::new("📤 Export list"),
+                )
+                .on_hover_text("One file path per line")
+                .clicked()
+            {
+                self.export_blocklist();
+            }
+        });
 
-        thread::spawn(move || {
-            let mut scanner = Scanner::new();
-            let progress_sender = sender.clone();
-            scanner.set_progress_callback(move |processed, total| {
-                let _ = progress_sender.send(BackgroundMessage::ScanProgress { processed, total });
+        let mut unblock = None;
+        egui::ScrollArea::vertical()
+            .id_source("blocklist_scroll")
+            .max_height(200.0)
+            .show(ui, |ui| {
+                for path in &self.blocklist {
+                    ui.horizontal(|ui| {
+                        if ui.small_button("✖").on_hover_text("Unblock").clicked() {
+                            unblock = Some(path.clone());
+                        }
+                        ui.label(path);
+                    });
+                }
             });
+        if let Some(path) = unblock {
+            self.unblock_file(&path);
+        }
+    }
+
+    fn apply_row_action(&mut self, action: RowAction) {
+        match action {
+            RowAction::Done(Ok(status)) => {
+                self.status_message = status;
+                self.error_message.clear();
+            }
+            RowAction::Done(Err(e)) => self.error_message = e,
+            RowAction::Block(path) => self.block_files(vec![path]),
+            RowAction::OpenLocations(paths) => self.open_locations(paths),
+            RowAction::Review {
+                hh_id,
+                file_path,
+                status,
+            } => self.review_match(&hh_id, &file_path, status),
+            RowAction::Exclude(path) => {
+                self.excluded_paths.insert(path.clone());
+                self.status_message = format!(
+                    "Excluded {}; {} file(s) hidden until exclusions are cleared",
+                    path,
+                    self.excluded_paths.len()
+                );
+                self.error_message.clear();
+            }
+        }
+    }
 
-            let mut db = match Database::new(&cache_path) {
-                Ok(db) => db,
+    /// Apply the theme and text size. Also re-applies an explicit Light/Dark
+    /// choice after eframe follows an OS theme change.
+    fn apply_appearance(&mut self, ctx: &egui::Context, system_theme: Option<eframe::Theme>) {
+        let dark = match self.theme {
+            ThemePreference::System => system_theme != Some(eframe::Theme::Light),
+            ThemePreference::Light => false,
+            ThemePreference::Dark => true,
+        };
+        let current = &ctx.style().visuals;
+        if !self.appearance_dirty
+            && current.dark_mode == dark
+            && current.override_text_color.is_some() == self.high_contrast
+        {
+            return;
+        }
+
+        let mut visuals = if dark {
+            egui::Visuals::dark()
+        } else {
+            egui::Visuals::light()
+        };
+        if self.high_contrast {
+            visuals.override_text_color = Some(if dark {
+                egui::Color32::WHITE
+            } else {
+                egui::Color32::BLACK
+            });
+        }
+        ctx.set_visuals(visuals);
+
+        if self.appearance_dirty {
+            ctx.set_zoom_factor(if self.larger_text {
+                LARGER_TEXT_ZOOM
+            } else {
+                1.0
+            });
+            self.appearance_dirty = false;
+        }
+    }
+
+    fn save_appearance(&mut self) {
+        self.appearance_dirty = true;
+        let db = match self.db_handle() {
+            Ok(db) => db,
+            Err(_) => return,
+        };
+        let result = Self::lock_db(&db).and_then(|db| {
+            db.set_setting(THEME_SETTING, self.theme.key())
+                .and_then(|_| {
+                    db.set_setting(HIGH_CONTRAST_SETTING, &self.high_contrast.to_string())
+                })
+                .and_then(|_| db.set_setting(LARGER_TEXT_SETTING, &self.larger_text.to_string()))
+                .map_err(|e| format!("Failed to save settings: {}", e))
+        });
+        if let Err(e) = result {
+            self.error_message = e;
+        }
+    }
+
+    fn apply_thread_limit(&mut self) {
+        if let Err(e) = self.controller.set_thread_limit(self.thread_limit) {
+            self.error_message = e;
+            self.thread_limit = self.controller.thread_limit();
+            return;
+        }
+        self.status_message = match self.thread_limit {
+            0 => "Using every core for scanning and matching".to_string(),
+            n => format!("Using {} worker threads for scanning and matching", n),
+        };
+        let result = self.db_handle().and_then(|db| {
+            Self::lock_db(&db)?
+                .set_setting(THREADS_SETTING, &self.thread_limit.to_string())
+                .map_err(|e| format!("Failed to save settings: {}", e))
+        });
+        if let Err(e) = result {
+            self.error_message = e;
+        }
+    }
+
+    fn apply_score_combination(&mut self) {
+        self.controller
+            .set_score_combination(self.score_combination);
+        let result = self.db_handle().and_then(|db| {
+            Self::lock_db(&db)?
+                .set_setting(SCORE_COMBINATION_SETTING, &self.score_combination.label())
+                .map_err(|e| format!("Failed to save settings: {}", e))
+        });
+        if let Err(e) = result {
+            self.error_message = e;
+        }
+    }
+
+    fn apply_path_components(&mut self) {
+        self.controller.set_path_components(self.path_components);
+        // Remembered outcomes were scored against a different set of folders
+        self.recent_searches.clear();
+        let result = self.db_handle().and_then(|db| {
+            Self::lock_db(&db)?
+                .set_setting(PATH_COMPONENTS_SETTING, &self.path_components.to_string())
+                .map_err(|e| format!("Failed to save settings: {}", e))
+        });
+        if let Err(e) = result {
+            self.error_message = e;
+        }
+    }
+
+    fn apply_recent_search_limit(&mut self) {
+        self.recent_searches.set_capacity(self.recent_search_limit);
+        let result = self.db_handle().and_then(|db| {
+            Self::lock_db(&db)?
+                .set_setting(
+                    RECENT_SEARCHES_SETTING,
+                    &self.recent_search_limit.to_string(),
+                )
+                .map_err(|e| format!("Failed to save settings: {}", e))
+        });
+        if let Err(e) = result {
+            self.error_message = e;
+        }
+    }
+
+    /// Hand the scan checkboxes to the controller and remember them.
+    fn apply_scan_options(&mut self) {
+        self.controller.set_follow_links(self.follow_links);
+        self.controller.set_read_metadata(self.read_metadata);
+        let result = self.db_handle().and_then(|db| {
+            let db = Self::lock_db(&db)?;
+            db.set_setting(FOLLOW_LINKS_SETTING, &self.follow_links.to_string())
+                .and_then(|_| {
+                    db.set_setting(READ_METADATA_SETTING, &self.read_metadata.to_string())
+                })
+                .map_err(|e| format!("Failed to save settings: {}", e))
+        });
+        if let Err(e) = result {
+            self.error_message = e;
+        }
+    }
+
+    /// Hand the typed size bounds to the controller and remember them.
+    fn apply_size_range(&mut self) {
+        let (min, max) = match size_range(&self.min_file_size, &self.max_file_size) {
+            Ok(range) => range,
+            Err(e) => {
+                self.error_message = e;
+                return;
+            }
+        };
+        self.controller.set_size_range(min, max);
+        let result = self.db_handle().and_then(|db| {
+            let db = Self::lock_db(&db)?;
+            db.set_setting(MIN_FILE_SIZE_SETTING, self.min_file_size.trim())
+                .and_then(|_| db.set_setting(MAX_FILE_SIZE_SETTING, self.max_file_size.trim()))
+                .map_err(|e| format!("Failed to save settings: {}", e))
+        });
+        match result {
+            Ok(()) => self.error_message.clear(),
+            Err(e) => self.error_message = e,
+        }
+    }
+
+    fn save_page_size(&mut self) {
+        let result = self.db_handle().and_then(|db| {
+            Self::lock_db(&db)?
+                .set_setting(PAGE_SIZE_SETTING, &self.results_per_page.to_string())
+                .map_err(|e| format!("Failed to save settings: {}", e))
+        });
+        if let Err(e) = result {
+            self.error_message = e;
+        }
+    }
+
+    fn db_handle(&self) -> Result<Arc<Mutex<Database>>, String> {
+        self.db
+            .as_ref()
+            .cloned()
+            .ok_or_else(|| "Database is unavailable. Check cache.db permissions.".to_string())
+    }
+
+    fn lock_db<'a>(db: &'a Arc<Mutex<Database>>) -> Result<MutexGuard<'a, Database>, String> {
+        db.lock()
+            .map_err(|e| format!("Database access error: {}", e))
+    }
+
+    fn pick_match_output_dir(&mut self) {
+        if let Some(path) = FileDialog::new().pick_folder() {
+            self.match_output_dir = Some(path);
+        }
+    }
+
+    fn add_folder(&mut self) {
+        if let Some(path) = FileDialog::new().pick_folder() {
+            match self.controller.add_scan_root(&path.to_string_lossy()) {
+                Ok(root) => {
+                    self.status_message = format!("Added scan folder: {}", root);
+                    self.error_message.clear();
+                    self.refresh_scan_roots();
+                }
+                Err(e) => self.error_message = e,
+            }
+        }
+    }
+
+    fn remove_folder(&mut self, root: &str) {
+        match self.controller.remove_scan_root(root) {
+            Ok(removed) => {
+                self.file_count = self.file_count.saturating_sub(removed);
+                self.search_results.clear();
+                self.cached_search = None;
+                self.status_message =
+                    format!("Removed {} ({} cached files dropped)", root, removed);
+                self.error_message.clear();
+                self.refresh_scan_roots();
+                self.recent_searches.clear();
+                self.refresh_duplicate_names();
+                self.refresh_stored_matches();
+            }
+            Err(e) => self.error_message = e,
+        }
+    }
+
+    /// Old/new prefix fields for moving cached paths to a relocated archive.
+    /// Applying needs a preview of the current old prefix first, so the user
+    /// sees how many files will change.
+    fn remap_tool(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new("remap_grid")
+            .num_columns(2)
+            .spacing([10.0, 4.0])
+            .show(ui, |ui| {
+                ui.label("Old folder:");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.remap_old_prefix)
+                        .hint_text("/mnt/old")
+                        .desired_width(320.0),
+                );
+                ui.end_row();
+                ui.label("New folder:");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.remap_new_prefix)
+                        .hint_text("/mnt/new")
+                        .desired_width(320.0),
+                );
+                ui.end_row();
+            });
+
+        let idle = self.state == AppState::Idle;
+        let preview = self
+            .remap_preview
+            .as_ref()
+            .filter(|(prefix, _)| *prefix == self.remap_old_prefix)
+            .map(|(_, count)| *count);
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(idle, egui::Button::new("🔍 Preview"))
+                .on_hover_text("Count the cached files under the old folder")
+                .clicked()
+            {
+                match self.controller.remap_preview(&self.remap_old_prefix) {
+                    Ok(count) => {
+                        self.remap_preview = Some((self.remap_old_prefix.clone(), count));
+                        self.error_message.clear();
+                    }
+                    Err(e) => self.error_message = e,
+                }
+            }
+            let can_apply = idle
+                && preview.is_some_and(|count| count > 0)
+                && !self.remap_new_prefix.trim().is_empty();
+            if ui
+                .add_enabled(can_apply, egui::Button::new("🔀 Remap paths"))
+                .clicked()
+            {
+                self.remap_paths();
+            }
+            match preview {
+                Some(count) => ui.label(format!(
+                    "{} cached files would change",
+                    group_thousands(count)
+                )),
+                None => ui.weak("Preview the old folder before remapping"),
+            };
+        });
+    }
+
+    fn remap_paths(&mut self) {
+        match self
+            .controller
+            .remap_paths(&self.remap_old_prefix, &self.remap_new_prefix)
+        {
+            Ok(changed) => {
+                self.search_results.clear();
+                self.cached_search = None;
+                self.thumbnails.clear();
+                self.remap_preview = None;
+                self.status_message = format!(
+                    "Remapped {} cached files from {} to {}",
+                    group_thousands(changed),
+                    self.remap_old_prefix.trim(),
+                    self.remap_new_prefix.trim()
+                );
+                self.error_message.clear();
+                self.refresh_scan_roots();
+                self.recent_searches.clear();
+                self.refresh_duplicate_names();
+                self.refresh_stored_matches();
+            }
+            Err(e) => self.error_message = e,
+        }
+    }
+
+    fn refresh_scan_roots(&mut self) {
+        match self.controller.scan_roots() {
+            Ok(roots) => self.scan_roots = roots,
+            Err(e) => self.error_message = e,
+        }
+    }
+
+    fn refresh_duplicate_names(&mut self) {
+        match self.controller.duplicate_names() {
+            Ok(duplicates) => {
+                self.ambiguous_names = ambiguous_names(&duplicates);
+                self.duplicate_names = duplicates;
+            }
+            Err(e) => self.error_message = e,
+        }
+    }
+
+    // Stored matches changed: reload the histogram and re-read the browser
+    // rows the next time they are shown
+    fn refresh_stored_matches(&mut self) {
+        self.stored_matches_stale = true;
+        self.prefix_matches = None;
+        match self.controller.score_histogram(HISTOGRAM_BUCKETS) {
+            Ok(histogram) => self.score_histogram = histogram,
+            Err(e) => self.error_message = e,
+        }
+    }
+
+    // A failure only costs the history entry, so it is logged, not shown
+    fn record_search(&mut self, query: &str, result_count: usize) {
+        if let Err(e) = self.controller.record_search(query, result_count) {
+            warn!("{}", e);
+            return;
+        }
+        match self.controller.search_history(SEARCH_HISTORY_SHOWN) {
+            Ok(history) => self.search_history = history,
+            Err(e) => warn!("{}", e),
+        }
+    }
+
+    /// Past queries as buttons that search again.
+    fn search_history_line(&mut self, ui: &mut egui::Ui) {
+        if self.search_history.is_empty() {
+            return;
+        }
+        let mut rerun = None;
+        ui.horizontal_wrapped(|ui| {
+            ui.label("🕘 Recent:");
+            let enabled = self.state == AppState::Idle && self.db.is_some();
+            for entry in &self.search_history {
+                let searched = chrono::DateTime::parse_from_rfc3339(&entry.searched_at)
+                    .map(|date| {
+                        date.with_timezone(&chrono::Local)
+                            .format("%Y-%m-%d %H:%M")
+                            .to_string()
+                    })
+                    .unwrap_or_else(|_| entry.searched_at.clone());
+                if ui
+                    .add_enabled(enabled, egui::Button::new(&entry.query).small())
+                    .on_hover_text(format!("{} matches · {}", entry.result_count, searched))
+                    .clicked()
+                {
+                    rerun = Some(entry.query.clone());
+                }
+            }
+        });
+        if let Some(query) = rerun {
+            self.search_input = query;
+            self.search_household_id();
+        }
+    }
+
+    fn refresh_last_match_run(&mut self) {
+        match self.controller.last_match_run() {
+            Ok(run) => self.last_match_run = run,
+            Err(e) => self.error_message = e,
+        }
+    }
+
+    fn load_stored_matches(&mut self) {
+        if !self.stored_matches_stale {
+            return;
+        }
+        // Don't retry a failed read every frame
+        self.stored_matches_stale = false;
+        match self
+            .controller
+            .search_stored_matches(&self.stored_match_query, STORED_MATCH_LIMIT)
+        {
+            Ok(matches) => self.stored_matches = matches,
+            Err(e) => {
+                self.stored_matches.clear();
+                self.error_message = e;
+            }
+        }
+    }
+
+    fn select_csv(&mut self) {
+        if let Some(path) = FileDialog::new()
+            .add_filter("CSV or Excel", &["csv", "xlsx"])
+            .add_filter("CSV", &["csv"])
+            .add_filter("Excel", &["xlsx"])
+            .pick_file()
+        {
+            self.csv_path = path.to_string_lossy().to_string();
+            self.status_message.clear();
+            self.error_message.clear();
+            self.id_preview = match ReferenceLoader::preview(&self.csv_path) {
+                Ok(preview) => {
+                    if ReferenceLoader::id_column(&preview.0).is_some() {
+                        self.status_message = format!(
+                            "Selected ID file: {}. Check the preview, then load it",
+                            self.csv_path
+                        );
+                    } else {
+                        self.error_message = format!(
+                            "{} has no 'hh_id' column; choose another file",
+                            self.csv_path
+                        );
+                    }
+                    Some(preview)
+                }
                 Err(e) => {
-                    let _ = sender.send(BackgroundMessage::ScanError {
-                        error: format!("Database access error while scanning: {}", e),
-                    });
-                    return;
+                    self.error_message = format!("Failed to preview {}: {}", self.csv_path, e);
+                    None
                 }
             };
+        }
+    }
 
-            let result = match scanner.scan_and_store(&folder_path, &mut db) {
-                Ok(report) => match db.get_file_count() {
-                    Ok(total_files) => Ok((report, total_files)),
-                    Err(e) => Err(format!("Failed to refresh cached file count: {}", e)),
-                },
-                Err(e) => Err(e),
+    /// Whether the selected file previewed cleanly and has an ID column.
+    fn id_file_ready(&self) -> bool {
+        self.id_preview
+            .as_ref()
+            .is_some_and(|(headers, _)| ReferenceLoader::id_column(headers).is_some())
+    }
+
+    fn load_reference_ids(&mut self) {
+        if self.csv_path.is_empty() {
+            self.error_message = "Please select a CSV or Excel file first".to_string();
+            return;
+        }
+
+        if self.db.is_none() {
+            self.error_message = "Database is unavailable. Check cache.db permissions.".to_string();
+            return;
+        }
+
+        self.state = AppState::LoadingReferenceIds;
+        self.progress = 0.0;
+        self.progress_text = "Loading reference IDs...".to_string();
+        // Lets "Imported since" pick out the IDs this import adds
+        self.match_since_input = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        self.error_message.clear();
+        self.status_message.clear();
+        self.last_reference_report = None;
+
+        let csv_path = self.csv_path.clone();
+        let controller = self.controller.clone();
+        let sender = self.bg_sender.clone();
+        let cancel_flag = Arc::new(AtomicBool::new(false));
+        self.reference_cancel_flag = Some(Arc::clone(&cancel_flag));
+
+        thread::spawn(move || {
+            let progress_sender = sender.clone();
+            let progress_callback =
+                move |processed_rows: usize, bytes_read: u64, total_bytes: u64| {
+                    let _ = progress_sender.send(BackgroundMessage::ReferenceIdsProgress {
+                        processed_rows,
+                        bytes_read,
+                        total_bytes,
+                    });
+                };
+
+            let load_result =
+                controller.import_ids(&csv_path, Some(cancel_flag), Some(progress_callback));
+
+            match load_result {
+                Ok(summary) if summary.report.cancelled => {
+                    let _ = sender.send(BackgroundMessage::ReferenceIdsCancelled {
+                        processed_rows: summary.report.processed,
+                    });
+                }
+                Ok(summary) => {
+                    let _ = sender.send(BackgroundMessage::ReferenceIdsLoaded {
+                        report: summary.report,
+                        total: summary.total,
+                    });
+                }
+                Err(e) => {
+                    let _ = sender.send(BackgroundMessage::ReferenceIdsError { error: e });
+                }
+            }
+        });
+    }
+
+    fn cancel_reference_load(&mut self) {
+        if let Some(flag) = &self.reference_cancel_flag {
+            flag.store(true, Ordering::Relaxed);
+            self.progress_text = "Cancelling reference ID import...".to_string();
+        }
+    }
+
+    // `incremental` only stores files that are new or changed since the last scan
+    fn start_scanning(&mut self, incremental: bool) {
+        if self.scan_roots.is_empty() {
+            self.error_message = "Please add a folder first".to_string();
+            return;
+        }
+
+        if self.db.is_none() {
+            self.error_message = "Database is unavailable. Check cache.db permissions.".to_string();
+            return;
+        }
+
+        self.state = AppState::Scanning;
+        self.progress = 0.0;
+        self.progress_text = "Scanning...".to_string();
+        self.error_message.clear();
+        self.status_message.clear();
+
+        let mut controller = self.controller.clone();
+        controller.set_since_last_scan(self.since_last_scan);
+        let sender = self.bg_sender.clone();
+
+        thread::spawn(move || {
+            let progress_sender = sender.clone();
+            let progress_callback = move |processed, total| {
+                let _ = progress_sender.send(BackgroundMessage::ScanProgress { processed, total });
             };
 
+            let result = if incremental {
+                controller.rescan_changed(Some(progress_callback))
+            } else {
+                controller.scan_all_roots(Some(progress_callback))
+            };
             match result {
-                Ok((report, total_files)) => {
+                Ok(summary) => {
                     let _ = sender.send(BackgroundMessage::ScanComplete {
-                        discovered: report.discovered,
-                        db_total: total_files,
+                        summary,
+                        incremental,
                     });
                 }
                 Err(e) => {
@@ -338,66 +1796,68 @@ impl TiffLocatorApp {
             return;
         }
 
+        if self.id_prefix_search && self.has_stored_matches() {
+            let prefix = search_id.to_string();
+            self.search_id_prefix(prefix);
+            return;
+        }
+        self.prefix_matches = None;
+
         self.state = AppState::Searching;
         self.progress = 0.0;
         self.progress_text = format!("Searching for '{}'...", search_id);
         self.error_message.clear();
         self.status_message.clear();
         self.results_page = 0; // Reset pagination
+        self.selected_result_index = None;
 
-        let search_id = search_id.to_string();
-        let threshold = self.similarity_threshold;
+        let request = SearchRequest {
+            hh_id: search_id.to_string(),
+            min_similarity: self.similarity_threshold,
+            max_similarity: self.similarity_max,
+            include_path_components: self.match_path_components,
+            case_sensitive: self.case_sensitive,
+            // "All" reads every stored match at once
+            page_size: (self.results_per_page > 0).then_some(self.results_per_page),
+            order: self.result_order,
+        };
         let sender = self.bg_sender.clone();
-        let cache_path = self.cache_path.clone();
+        if let Some(replayed) = self.recent_searches.replay(request.clone()) {
+            let _ = sender.send(replayed);
+            return;
+        }
+        let controller = self.controller.clone();
 
         thread::spawn(move || {
-            let searcher = Searcher::new();
-            let db = match Database::new(&cache_path) {
-                Ok(db) => db,
-                Err(e) => {
-                    let _ = sender.send(BackgroundMessage::SearchError {
-                        error: format!("Database access error while searching: {}", e),
+            let progress_sender = sender.clone();
+            let progress_callback: SearchProgressCallback =
+                Arc::new(Mutex::new(move |phase, processed, total| {
+                    let _ = progress_sender.send(BackgroundMessage::SearchProgress {
+                        phase,
+                        processed,
+                        total,
                     });
-                    return;
-                }
-            };
+                }));
 
-            let cached_results = match db.search_single_id(&search_id, threshold) {
-                Ok(results) => results,
-                Err(e) => {
-                    let _ = sender.send(BackgroundMessage::SearchError {
-                        error: format!("Failed to read cached matches: {}", e),
+            match controller.search(&request, Some(progress_callback)) {
+                Ok(outcome) => {
+                    let _ = sender.send(BackgroundMessage::SearchComplete {
+                        request,
+                        results: outcome.results,
+                        total: outcome.total,
+                        from_cache: outcome.from_cache,
+                        cache_error: outcome.cache_error,
                     });
-                    return;
                 }
-            };
-
-            if !cached_results.is_empty() {
-                let _ = sender.send(BackgroundMessage::SearchComplete {
-                    results: cached_results,
-                    cache_error: None,
-                });
-                return;
-            }
-
-            let results = match searcher.search_single_id(&search_id, &db, threshold) {
-                Ok(results) => results,
                 Err(e) => {
                     let _ = sender.send(BackgroundMessage::SearchError { error: e });
-                    return;
                 }
-            };
-
-            let cache_error = searcher.store_results(&search_id, &results, &db).err();
-
-            let _ = sender.send(BackgroundMessage::SearchComplete {
-                results,
-                cache_error,
-            });
+            }
         });
     }
 
-    fn start_matching(&mut self) {
+    // `dry_run` only counts the matches so the threshold can be tuned first
+    fn start_matching(&mut self, dry_run: bool) {
         if self.reference_id_count == 0 {
             self.error_message = "No reference IDs loaded. Please import a CSV first.".to_string();
             return;
@@ -413,76 +1873,80 @@ impl TiffLocatorApp {
             return;
         }
 
-        self.state = AppState::Matching;
-        self.progress = 0.0;
-        self.progress_text = "Matching household IDs...".to_string();
-        self.error_message.clear();
-        self.status_message.clear();
-
-        let sender = self.bg_sender.clone();
-        let cache_path = self.cache_path.clone();
-        let threshold = self.similarity_threshold;
-        let prefer_gpu = self.use_gpu_matcher && self.gpu_available;
-
-        thread::spawn(move || {
-            let mut db = match Database::new(&cache_path) {
-                Ok(db) => db,
-                Err(e) => {
-                    let _ = sender.send(BackgroundMessage::MatchingError {
-                        error: format!("Database access error while matching: {}", e),
-                    });
+        let scope = match self.match_selection {
+            MatchSelection::All | MatchSelection::NewFiles => MatchScope::All,
+            MatchSelection::Listed => {
+                let ids = MatchScope::parse_ids(&self.match_ids_input);
+                if ids.is_empty() {
+                    self.error_message = "Enter the household IDs to match".to_string();
                     return;
                 }
-            };
-
-            let hh_ids = match db.get_all_reference_ids() {
-                Ok(ids) => ids,
+                MatchScope::Ids(ids)
+            }
+            MatchSelection::ImportedSince => match MatchScope::parse_since(&self.match_since_input)
+            {
+                Ok(since) => MatchScope::ImportedSince(since),
                 Err(e) => {
-                    let _ = sender.send(BackgroundMessage::MatchingError {
-                        error: format!("Failed to read reference IDs: {}", e),
-                    });
+                    self.error_message = e;
                     return;
                 }
-            };
+            },
+        };
 
-            let desired_engine = if prefer_gpu {
-                MatchEngineKind::Gpu
-            } else {
-                MatchEngineKind::Cpu
-            };
+        if self.match_output_dir.is_some() && self.match_selection == MatchSelection::NewFiles {
+            self.error_message =
+                "Matching only new files adds to the cache's matches; clear \"Save matches to\" first"
+                    .to_string();
+            return;
+        }
+        let output = self
+            .match_output_dir
+            .as_ref()
+            .filter(|_| !dry_run)
+            .map(|dir| {
+                let name = format!(
+                    "matches_{}.db",
+                    chrono::Local::now().format("%Y%m%d_%H%M%S")
+                );
+                dir.join(name).to_string_lossy().into_owned()
+            });
 
-            let mut fallback_notice = None;
-            let mut engine = match match_engine::create_engine(desired_engine) {
-                Ok(engine) => engine,
-                Err(err) => {
-                    if desired_engine == MatchEngineKind::Gpu {
-                        fallback_notice = Some(format!(
-                            "GPU matcher unavailable ({}). Falling back to CPU matcher.",
-                            err
-                        ));
-                        match match_engine::create_engine(MatchEngineKind::Cpu) {
-                            Ok(engine) => engine,
-                            Err(cpu_err) => {
-                                let _ = sender.send(BackgroundMessage::MatchingError {
-                                    error: format!(
-                                        "Failed to initialize CPU matcher after GPU fallback: {}",
-                                        cpu_err
-                                    ),
-                                });
-                                return;
-                            }
-                        }
-                    } else {
-                        let _ = sender.send(BackgroundMessage::MatchingError { error: err });
-                        return;
-                    }
-                }
-            };
+        self.state = AppState::Matching;
+        self.progress = 0.0;
+        self.progress_text = if dry_run {
+            "Counting matches...".to_string()
+        } else {
+            "Matching household IDs...".to_string()
+        };
+        self.error_message.clear();
+        self.status_message.clear();
+        self.matching_notice = None;
+        self.matches_found = 0;
 
-            if let Some(message) = fallback_notice {
-                let _ = sender.send(BackgroundMessage::MatchingEngineNotice { message });
-            }
+        let sender = self.bg_sender.clone();
+        let controller = self.controller.clone();
+        // Folder-name and case-sensitive matching and combined scores are
+        // CPU-only, so they override the chosen matcher for this run
+        let fuzzy_only = self.match_path_components
+            || self.case_sensitive
+            || self.score_combination != ScoreCombination::Max;
+        let engine = match self.match_engine {
+            MatchEngineKind::Gpu if !self.gpu_available => MatchEngineKind::Cpu,
+            _ if fuzzy_only => MatchEngineKind::Cpu,
+            kind => kind,
+        };
+        let request = MatchRequest {
+            engine,
+            scope,
+            threshold: self.similarity_threshold,
+            include_path_components: self.match_path_components,
+            case_sensitive: self.case_sensitive,
+            dry_run,
+            new_files_only: self.match_selection == MatchSelection::NewFiles,
+            output,
+        };
 
+        thread::spawn(move || {
             let progress_sender = sender.clone();
             let progress_callback: MatchProgressCallback =
                 Arc::new(Mutex::new(move |processed, total| {
@@ -490,11 +1954,29 @@ impl TiffLocatorApp {
                         .send(BackgroundMessage::MatchingProgress { processed, total });
                 }));
 
-            match engine.match_and_store(&hh_ids, &mut db, threshold, Some(progress_callback)) {
-                Ok(count) => {
+            let count_sender = sender.clone();
+            let count_callback: MatchCountCallback = Arc::new(Mutex::new(move |found| {
+                let _ = count_sender.send(BackgroundMessage::MatchingMatchCount { found });
+            }));
+
+            let notice_sender = sender.clone();
+            let result = controller.run_match(
+                &request,
+                Some(progress_callback),
+                Some(count_callback),
+                |message| {
+                    let _ = notice_sender.send(BackgroundMessage::MatchingEngineNotice { message });
+                },
+            );
+
+            match result {
+                Ok(summary) => {
                     let _ = sender.send(BackgroundMessage::MatchingComplete {
-                        match_count: count,
-                        engine: engine.kind(),
+                        match_count: summary.match_count,
+                        engine: summary.engine,
+                        threshold: request.threshold,
+                        dry_run: request.dry_run,
+                        output: request.output,
                     });
                 }
                 Err(e) => {
@@ -504,17 +1986,87 @@ impl TiffLocatorApp {
         });
     }
 
-    fn export_to_csv(&mut self) {
-        if self.search_results.is_empty() {
+    fn has_stored_matches(&self) -> bool {
+        self.score_histogram.iter().any(|&(_, _, count)| count > 0)
+    }
+
+    fn has_search_results(&self) -> bool {
+        !self.search_results.is_empty() || self.cached_search.is_some()
+    }
+
+    fn cached_page_key(&self) -> (usize, usize, f64, f64) {
+        (
+            self.results_page,
+            self.results_per_page,
+            self.filter_min_similarity,
+            self.filter_max_similarity,
+        )
+    }
+
+    fn cached_page_stale(&self) -> bool {
+        self.cached_search
+            .as_ref()
+            .is_some_and(|cached| cached.loaded != self.cached_page_key())
+    }
+
+    // Read the current page of a cached search when the page, page size or
+    // display band has changed since it was loaded
+    fn load_cached_page(&mut self) {
+        if !self.cached_page_stale() {
+            return;
+        }
+        let key = self.cached_page_key();
+        let Some(cached) = self.cached_search.as_mut() else {
+            return;
+        };
+        // Don't retry a failed read every frame
+        cached.loaded = key;
+        match self.controller.stored_matches_page(
+            &cached.request.hh_id,
+            cached
+                .request
+                .min_similarity
+                .max(self.filter_min_similarity),
+            cached
+                .request
+                .max_similarity
+                .min(self.filter_max_similarity),
+            cached.request.order,
+            self.results_page,
+            rows_per_page(self.results_per_page, cached.total),
+        ) {
+            Ok((results, in_band)) => {
+                self.search_results = results;
+                cached.in_band = in_band;
+            }
+            Err(e) => {
+                self.search_results.clear();
+                self.error_message = e;
+            }
+        }
+    }
+
+    fn export_results(&mut self) {
+        if !self.has_search_results() {
             return;
         }
 
         if let Some(path) = FileDialog::new()
             .set_file_name("search_results.csv")
             .add_filter("CSV", &["csv"])
+            .add_filter("JSON", &["json"])
+            .add_filter("JSON Lines", &["jsonl"])
+            .add_filter("Excel", &["xlsx"])
             .save_file()
         {
-            match self.write_results_to_csv(&path.to_string_lossy()) {
+            if path
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("xlsx"))
+            {
+                self.export_results_xlsx(path.to_string_lossy().to_string());
+                return;
+            }
+            match self.write_results(&path.to_string_lossy()) {
                 Ok(_) => {
                     self.status_message = format!("Exported search results to {}", path.display());
                     self.error_message.clear();
@@ -527,24 +2079,129 @@ impl TiffLocatorApp {
         }
     }
 
-    fn write_results_to_csv(&self, path: &str) -> Result<(), String> {
-        let mut writer =
-            csv::Writer::from_path(path).map_err(|e| format!("Failed to create CSV: {}", e))?;
+    // Workbooks take a while for large result sets, so they are written on a
+    // background thread
+    fn export_results_xlsx(&mut self, path: String) {
+        let results = match self.results_in_band() {
+            Ok(results) => results,
+            Err(e) => {
+                self.error_message = format!("Export error: {}", e);
+                self.status_message.clear();
+                return;
+            }
+        };
+        let search_threshold = self
+            .last_search
+            .as_ref()
+            .map_or(0.0, |request| request.min_similarity);
+        let stats = ExportStats {
+            hh_id: self
+                .last_search
+                .as_ref()
+                .map(SearchRequest::household_id)
+                .unwrap_or_default(),
+            threshold: search_threshold.max(self.filter_min_similarity),
+            exported_at: chrono::Local::now(),
+        };
 
-        // Write headers
-        writer
-            .write_record(["file_name", "file_path", "similarity"])
-            .map_err(|e| format!("Failed to write headers: {}", e))?;
+        self.state = AppState::Exporting;
+        self.progress = 0.0;
+        self.progress_text = format!("Writing {} results to Excel...", results.len());
+        self.error_message.clear();
+        self.status_message.clear();
+
+        let sender = self.bg_sender.clone();
+        thread::spawn(move || {
+            let rows = results.len();
+            match export_results_xlsx(&path, &results, &stats) {
+                Ok(()) => {
+                    let _ = sender.send(BackgroundMessage::ExportComplete { rows, path });
+                }
+                Err(error) => {
+                    let _ = sender.send(BackgroundMessage::ExportError { error });
+                }
+            }
+        });
+    }
+
+    // Every result inside the active similarity band. A cached search only
+    // holds one page, so its matches are read again
+    fn results_in_band(&self) -> Result<Vec<SearchResult>, String> {
+        let all_results;
+        let results = match &self.cached_search {
+            Some(cached) => {
+                let request = SearchRequest {
+                    page_size: None,
+                    ..cached.request.clone()
+                };
+                all_results = self.controller.search(&request, None)?.results;
+                &all_results
+            }
+            None => &self.search_results,
+        };
+
+        Ok(results
+            .iter()
+            .filter(|result| {
+                Self::in_similarity_range(
+                    result,
+                    self.filter_min_similarity,
+                    self.filter_max_similarity,
+                ) && !self.excluded_paths.contains(&result.file_path)
+                    && self
+                        .export_status
+                        .is_none_or(|status| result.status == status)
+            })
+            .cloned()
+            .collect())
+    }
+
+    // The extension picks the format: `.json` is one array and `.jsonl` one
+    // object per line, both with every result in full; anything else is CSV
+    fn write_results(&self, path: &str) -> Result<(), String> {
+        let results = self.results_in_band()?;
+
+        let lower = path.to_lowercase();
+        if lower.ends_with(".jsonl") {
+            return export_results_jsonl(path, &results);
+        }
+        if lower.ends_with(".json") {
+            return export_results_json(path, &results);
+        }
+
+        let in_band: Vec<&SearchResult> = results.iter().collect();
 
-        // Write data
-        for result in &self.search_results {
+        let mut writer =
+            csv::Writer::from_path(path).map_err(|e| format!("Failed to create CSV: {}", e))?;
+        if self.collapse_duplicates {
+            // One row per file name, mirroring the collapsed table
             writer
-                .write_record([
-                    &result.file_name,
-                    &result.file_path,
-                    &format!("{:.2}%", result.similarity_score * 100.0),
-                ])
-                .map_err(|e| format!("Failed to write record: {}", e))?;
+                .write_record(["file_name", "file_path", "similarity", "locations"])
+                .map_err(|e| format!("Failed to write headers: {}", e))?;
+            for group in group_by_file_name(&in_band) {
+                let paths: Vec<&str> = group.iter().map(|r| r.file_path.as_str()).collect();
+                writer
+                    .write_record([
+                        &group[0].file_name,
+                        &paths.join("; "),
+                        &format!("{:.2}%", group[0].similarity_score * 100.0),
+                        &group.len().to_string(),
+                    ])
+                    .map_err(|e| format!("Failed to write record: {}", e))?;
+            }
+        } else {
+            writer
+                .write_record(["file_name", "file_path", "similarity"])
+                .map_err(|e| format!("Failed to write headers: {}", e))?;
+            for result in in_band {
+                writer
+                    .write_record([
+                        &result.file_name,
+                        &result.file_path,
+                        &format!("{:.2}%", result.similarity_score * 100.0),
+                    ])
+                    .map_err(|e| format!("Failed to write record: {}", e))?;
+            }
         }
 
         writer
@@ -554,7 +2211,440 @@ impl TiffLocatorApp {
         Ok(())
     }
 
-    fn clear_cache(&mut self) {
+    /// An upper bound of 100% is treated as open so float noise above 1.0
+    /// (possible with GPU dot products) never hides a result.
+    fn in_similarity_range(result: &SearchResult, min: f64, max: f64) -> bool {
+        result.similarity_score >= min && (max >= 1.0 || result.similarity_score <= max)
+    }
+
+    fn export_all_matches(&mut self) {
+        if self.db.is_none() {
+            self.error_message = "Database is unavailable. Check cache.db permissions.".to_string();
+            return;
+        }
+
+        let Some(path) = FileDialog::new()
+            .set_file_name("all_matches.csv")
+            .add_filter("CSV", &["csv"])
+            .add_filter("JSON", &["json"])
+            .save_file()
+        else {
+            return;
+        };
+
+        self.state = AppState::Exporting;
+        self.progress = 0.0;
+        self.progress_text = "Exporting all matches...".to_string();
+        self.error_message.clear();
+        self.status_message.clear();
+
+        let path = path.to_string_lossy().to_string();
+        let export_status = self.export_status;
+        let controller = self.controller.clone();
+        let sender = self.bg_sender.clone();
+
+        thread::spawn(move || {
+            let progress = |rows, total| {
+                let _ = sender.send(BackgroundMessage::ExportProgress { rows, total });
+            };
+            match controller.export(&path, export_status, progress) {
+                Ok(rows) => {
+                    let _ = sender.send(BackgroundMessage::ExportComplete { rows, path });
+                }
+                Err(error) => {
+                    let _ = sender.send(BackgroundMessage::ExportError { error });
+                }
+            }
+        });
+    }
+
+    /// Collect diagnostics on a worker thread; probing the GPU can stall a
+    /// frame or two.
+    fn gather_diagnostics(&mut self) {
+        self.diagnostics_pending = true;
+        let controller = self.controller.clone();
+        let sender = self.bg_sender.clone();
+        thread::spawn(move || {
+            let diagnostics = controller.diagnostics();
+            let _ = sender.send(BackgroundMessage::DiagnosticsGathered { diagnostics });
+        });
+    }
+
+    /// Open the folders on a worker thread; each launcher is watched for a
+    /// moment to catch the ones that exit with an error.
+    fn open_locations(&mut self, paths: Vec<String>) {
+        self.open_locations_pending = true;
+        self.status_message = format!("Opening {} folders...", paths.len());
+        let sender = self.bg_sender.clone();
+        thread::spawn(move || {
+            let result = open_page_locations(&paths);
+            let _ = sender.send(BackgroundMessage::LocationsOpened { result });
+        });
+    }
+
+    /// Run `ANALYZE` on a worker thread; it reads every index of the cache.
+    fn optimize_cache(&mut self) {
+        self.optimize_pending = true;
+        self.status_message = "Optimizing the cache...".to_string();
+        let controller = self.controller.clone();
+        let sender = self.bg_sender.clone();
+        thread::spawn(move || {
+            let result = controller.optimize();
+            let _ = sender.send(BackgroundMessage::CacheOptimized { result });
+        });
+    }
+
+    /// The diagnostics as pasted into a bug report, with the log file path.
+    fn diagnostics_text(&self) -> String {
+        let mut text = match &self.diagnostics {
+            Some(Ok(diagnostics)) => diagnostics.to_string(),
+            _ => String::new(),
+        };
+        let log_file = self
+            .log_file
+            .as_ref()
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or_else(|| "unavailable".to_string());
+        text.push_str(&format!("\nLog file: {}", log_file));
+        text
+    }
+
+    /// Check a sample of cached paths on a worker thread, since each check
+    /// can take a while on a network share.
+    fn validate_cache(&mut self) {
+        self.state = AppState::ValidatingCache;
+        self.progress = 0.0;
+        self.progress_text = format!("Checking {} sampled cached files...", CACHE_CHECK_SAMPLE);
+        self.error_message.clear();
+        self.status_message.clear();
+
+        let controller = self.controller.clone();
+        let sender = self.bg_sender.clone();
+        thread::spawn(
+            move || match controller.validate_cache(CACHE_CHECK_SAMPLE) {
+                Ok(check) => {
+                    let _ = sender.send(BackgroundMessage::CacheValidated { check });
+                }
+                Err(error) => {
+                    let _ = sender.send(BackgroundMessage::CacheValidationError { error });
+                }
+            },
+        );
+    }
+
+    /// Check every cached path on a worker thread; unlike `validate_cache`
+    /// this finds each missing file so they can be pruned.
+    fn verify_cache(&mut self) {
+        self.state = AppState::VerifyingCache;
+        self.progress = 0.0;
+        self.progress_text = "Checking cached files...".to_string();
+        self.error_message.clear();
+        self.status_message.clear();
+        self.stale_files = None;
+
+        let controller = self.controller.clone();
+        let sender = self.bg_sender.clone();
+        thread::spawn(move || {
+            let progress_sender = sender.clone();
+            let progress = move |checked, total| {
+                let _ =
+                    progress_sender.send(BackgroundMessage::VerifyCacheProgress { checked, total });
+            };
+            match controller.verify_cache(progress) {
+                Ok(stale) => {
+                    let _ = sender.send(BackgroundMessage::VerifyCacheComplete { stale });
+                }
+                Err(error) => {
+                    let _ = sender.send(BackgroundMessage::VerifyCacheError { error });
+                }
+            }
+        });
+    }
+
+    /// Missing files found by "Verify Cache", with a button to drop them
+    /// from the cache.
+    fn stale_files_panel(&mut self, ui: &mut egui::Ui) {
+        let Some(stale) = &self.stale_files else {
+            return;
+        };
+        let count = stale.len();
+        let idle = self.state == AppState::Idle;
+        let (mut prune, mut dismiss) = (false, false);
+        ui.add_space(5.0);
+        ui.group(|ui| {
+            ui.label(format!(
+                "⚠ {} cached files no longer exist:",
+                group_thousands(count)
+            ));
+            egui::ScrollArea::vertical()
+                .id_source("stale_files_scroll")
+                .max_height(150.0)
+                .show(ui, |ui| {
+                    for (_, path) in stale {
+                        ui.monospace(path);
+                    }
+                });
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(
+                        idle,
+                        egui::Button::new(format!(
+                            "🗑 Prune {} missing files",
+                            group_thousands(count)
+                        )),
+                    )
+                    .on_hover_text("Remove these files and their matches from the cache")
+                    .clicked()
+                {
+                    prune = true;
+                }
+                dismiss = ui.button("Dismiss").clicked();
+            });
+        });
+        if prune {
+            self.prune_stale_files();
+        } else if dismiss {
+            self.stale_files = None;
+        }
+    }
+
+    fn prune_stale_files(&mut self) {
+        let Some(stale) = self.stale_files.take() else {
+            return;
+        };
+        match self.controller.prune_files(&stale) {
+            Ok(removed) => {
+                self.file_count = self.file_count.saturating_sub(removed);
+                self.search_results.clear();
+                self.cached_search = None;
+                self.thumbnails.clear();
+                self.status_message = format!(
+                    "Pruned {} missing files from the cache",
+                    group_thousands(removed)
+                );
+                self.error_message.clear();
+                self.refresh_scan_roots();
+                self.recent_searches.clear();
+                self.refresh_duplicate_names();
+                self.refresh_stored_matches();
+            }
+            Err(e) => {
+                self.error_message = e;
+                self.stale_files = Some(stale);
+            }
+        }
+    }
+
+    /// Count what each part of the cache holds and ask what to clear; files
+    /// are preselected, as before there was a choice.
+    fn open_clear_dialog(&mut self) {
+        let dialog = self.db_handle().and_then(|db| {
+            let db = Self::lock_db(&db)?;
+            let count = |rows: Result<usize, rusqlite::Error>| {
+                rows.map_err(|e| format!("Failed to count cached rows: {}", e))
+            };
+            Ok(ClearDialog {
+                selection: ClearSelection {
+                    files: true,
+                    ..Default::default()
+                },
+                files: count(db.get_file_count())?,
+                matches: count(db.get_match_count())?,
+                vectors: count(db.get_vector_count())?,
+                reference_ids: count(db.get_reference_id_count())?,
+            })
+        });
+        match dialog {
+            Ok(dialog) => self.clear_dialog = Some(dialog),
+            Err(e) => self.error_message = e,
+        }
+    }
+
+    /// Modal: a dimmed backdrop swallows clicks meant for the rest of the
+    /// window, Enter confirms and Escape cancels.
+    fn show_match_detail(&mut self, result: SearchResult) {
+        let Some(request) = &self.last_search else {
+            return;
+        };
+        self.match_detail = Some(MatchDetail {
+            hh_id: request.household_id(),
+            explanation: self.controller.explain_match(request, &result),
+            result,
+        });
+    }
+
+    /// How the clicked result's score came about: the candidate that scored,
+    /// its raw fuzzy score and the length penalty that normalized it.
+    fn match_detail_window(&mut self, ctx: &egui::Context) {
+        let Some(detail) = &self.match_detail else {
+            return;
+        };
+        let result = &detail.result;
+        let mut open = true;
+        egui::Window::new("🔎 Why it matched")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("match_detail_grid")
+                    .num_columns(2)
+                    .spacing([12.0, 4.0])
+                    .show(ui, |ui| {
+                        ui.label("Household ID");
+                        ui.strong(&detail.hh_id);
+                        ui.end_row();
+                        ui.label("File");
+                        ui.label(&result.file_name).on_hover_text(&result.file_path);
+                        ui.end_row();
+                        ui.label("Similarity");
+                        ui.strong(format!("{:.1}%", result.similarity_score * 100.0));
+                        ui.end_row();
+                        ui.label("Run");
+                        ui.label(run_label(result));
+                        ui.end_row();
+                        let Some(explanation) = &detail.explanation else {
+                            ui.label("Matched via");
+                            ui.label(matched_via_label(result));
+                            ui.end_row();
+                            return;
+                        };
+                        ui.label("Candidate");
+                        ui.label(format!(
+                            "{} \"{}\"",
+                            explanation.candidate_kind, explanation.matched_candidate
+                        ));
+                        ui.end_row();
+                        ui.label("Raw score")
+                            .on_hover_text("Fuzzy score against the ID, out of the ID's own score");
+                        ui.label(format!(
+                            "{} of {} ({:.1}%)",
+                            explanation.raw_score,
+                            explanation.perfect_score,
+                            explanation.base_score() * 100.0
+                        ));
+                        ui.end_row();
+                        ui.label("Length ratio")
+                            .on_hover_text("Shorter over longer of the candidate and the ID");
+                        ui.label(format!("{:.2}", explanation.length_ratio));
+                        ui.end_row();
+                        ui.label("Normalized");
+                        ui.label(format!(
+                            "{:.1}% × {:.2} = {:.1}%",
+                            explanation.base_score() * 100.0,
+                            explanation.length_ratio,
+                            explanation.normalized * 100.0
+                        ));
+                        ui.end_row();
+                    });
+                match &detail.explanation {
+                    None if result
+                        .matched_via
+                        .as_deref()
+                        .and_then(parse_matched_via)
+                        .is_some_and(|(kind, _)| kind == CandidateKind::Vector) =>
+                    {
+                        ui.weak(
+                            "Vector scores compare the whole name's n-grams, so no single \
+                             candidate explains them",
+                        );
+                    }
+                    None => {
+                        ui.weak("The file no longer matches with the current settings");
+                    }
+                    Some(explanation)
+                        if (explanation.normalized - result.similarity_score).abs() > 1e-9 =>
+                    {
+                        ui.weak(
+                            "The similarity combines several candidates (see \"Score by\"); \
+                             this is the best one",
+                        );
+                    }
+                    Some(_) => {}
+                }
+            });
+        if !open {
+            self.match_detail = None;
+        }
+    }
+
+    fn clear_dialog_window(&mut self, ctx: &egui::Context) {
+        let Some(dialog) = &mut self.clear_dialog else {
+            return;
+        };
+        // Taken before the dialog is drawn so Enter can't also toggle a
+        // focused checkbox, and typing can't reach a field behind it
+        let (enter, escape) = ctx.input_mut(|input| {
+            (
+                input.consume_key(egui::Modifiers::NONE, egui::Key::Enter),
+                input.consume_key(egui::Modifiers::NONE, egui::Key::Escape),
+            )
+        });
+        ctx.memory_mut(|memory| memory.stop_text_input());
+
+        let screen = ctx.screen_rect();
+        egui::Area::new(egui::Id::new("clear_dialog_backdrop"))
+            .order(egui::Order::Middle)
+            .fixed_pos(screen.min)
+            .show(ctx, |ui| {
+                ui.painter()
+                    .rect_filled(screen, 0.0, egui::Color32::from_black_alpha(160));
+                ui.allocate_rect(screen, egui::Sense::click_and_drag());
+            });
+
+        let mut confirmed = enter && !dialog.selection.is_empty();
+        let mut cancelled = escape;
+        egui::Window::new("🗑 Clear Cache")
+            .collapsible(false)
+            .resizable(false)
+            .order(egui::Order::Foreground)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                let forced = dialog.selection.files;
+                ui.checkbox(
+                    &mut dialog.selection.files,
+                    format!("Files ({})", group_thousands(dialog.files)),
+                )
+                .on_hover_text("Scanned files; their matches and vectors go with them");
+                for (checked, label, count) in [
+                    (&mut dialog.selection.matches, "Matches", dialog.matches),
+                    (&mut dialog.selection.vectors, "Vectors", dialog.vectors),
+                ] {
+                    let mut shown = *checked || forced;
+                    ui.add_enabled(
+                        !forced,
+                        egui::Checkbox::new(
+                            &mut shown,
+                            format!("{} ({})", label, group_thousands(count)),
+                        ),
+                    );
+                    if !forced {
+                        *checked = shown;
+                    }
+                }
+                ui.checkbox(
+                    &mut dialog.selection.reference_ids,
+                    format!("Reference IDs ({})", group_thousands(dialog.reference_ids)),
+                );
+                ui.add_space(5.0);
+                ui.label(clear_summary(dialog));
+                ui.horizontal(|ui| {
+                    confirmed |= ui
+                        .add_enabled(!dialog.selection.is_empty(), egui::Button::new("🗑 Clear"))
+                        .on_hover_text("Enter")
+                        .clicked();
+                    cancelled |= ui.button("Cancel").on_hover_text("Escape").clicked();
+                });
+            });
+        if confirmed {
+            let selection = dialog.selection;
+            self.clear_dialog = None;
+            self.clear_cache(selection);
+        } else if cancelled {
+            self.clear_dialog = None;
+        }
+    }
+
+    fn clear_cache(&mut self, selection: ClearSelection) {
         let db = match self.db_handle() {
             Ok(db) => db,
             Err(err) => {
@@ -565,19 +2655,32 @@ impl TiffLocatorApp {
 
         let clear_result = {
             match Self::lock_db(&db) {
-                Ok(db_guard) => db_guard
-                    .clear_files()
+                Ok(mut db_guard) => db_guard
+                    .clear(selection)
                     .map_err(|e| format!("Failed to clear cache: {}", e)),
                 Err(err) => Err(err),
             }
         };
 
+        let selection = selection.effective();
         match clear_result {
             Ok(_) => {
-                self.file_count = 0;
+                if selection.files {
+                    self.file_count = 0;
+                    self.thumbnails.clear();
+                    self.stale_files = None;
+                    self.refresh_scan_roots();
+                    self.refresh_duplicate_names();
+                }
+                if selection.reference_ids {
+                    self.reference_id_count = 0;
+                }
                 self.search_results.clear();
-                self.status_message = "Cache cleared successfully".to_string();
+                self.cached_search = None;
+                self.recent_searches.clear();
+                self.status_message = format!("Cleared {}", cleared_parts(selection));
                 self.error_message.clear();
+                self.refresh_stored_matches();
             }
             Err(e) => {
                 self.error_message = e;
@@ -586,6 +2689,27 @@ impl TiffLocatorApp {
         }
     }
 
+    /// Decode thumbnails for `paths` on a worker thread. Each result is sent
+    /// back separately so rows fill in as they finish.
+    fn request_thumbnails(&mut self, paths: Vec<String>) {
+        for path in &paths {
+            self.thumbnails.insert(path.clone(), Thumbnail::Loading);
+        }
+
+        let sender = self.bg_sender.clone();
+        thread::spawn(move || {
+            for path in paths {
+                let image = thumbnail::decode_thumbnail(&path);
+                if sender
+                    .send(BackgroundMessage::ThumbnailDecoded { path, image })
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        });
+    }
+
     fn process_background_messages(&mut self, ctx: &egui::Context) {
         // Process all pending messages from background threads
         while let Ok(msg) = self.bg_receiver.try_recv() {
@@ -597,17 +2721,44 @@ impl TiffLocatorApp {
                     self.progress_text = format!("Scanning files... ({}/{})", processed, total);
                 }
                 BackgroundMessage::ScanComplete {
-                    discovered,
-                    db_total,
+                    summary,
+                    incremental,
                 } => {
                     self.state = AppState::Idle;
                     self.progress = 1.0;
-                    self.status_message = format!(
-                        "Scan complete: {} TIFF files found ({} cached total)",
-                        discovered, db_total
-                    );
-                    self.file_count = db_total;
-                    self.error_message.clear();
+                    self.status_message = if incremental {
+                        format!(
+                            "Rescan complete: {} added, {} changed, {} unchanged, {} missing files removed ({} cached total)",
+                            summary.added,
+                            summary.changed,
+                            summary.skipped,
+                            summary.pruned,
+                            summary.total_files
+                        )
+                    } else {
+                        format!(
+                            "Scan complete: {} TIFF files found, {} missing files removed ({} cached total)",
+                            summary.discovered, summary.pruned, summary.total_files
+                        )
+                    };
+                    if summary.discovered > 0 {
+                        self.status_message
+                            .push_str(&format!(" · {}", archive_summary(&summary)));
+                    }
+                    self.file_count = summary.total_files;
+                    self.error_message = if summary.empty_folders.is_empty() {
+                        String::new()
+                    } else {
+                        format!(
+                            "No TIFF files found under {} — check the folder, and that the \
+                             scans end in .tif or .tiff",
+                            summary.empty_folders.join(", ")
+                        )
+                    };
+                    self.refresh_scan_roots();
+                    self.recent_searches.clear();
+                    self.refresh_duplicate_names();
+                    self.refresh_stored_matches();
                 }
                 BackgroundMessage::ScanError { error } => {
                     self.state = AppState::Idle;
@@ -634,6 +2785,7 @@ impl TiffLocatorApp {
                 }
                 BackgroundMessage::ReferenceIdsLoaded { report, total } => {
                     self.state = AppState::Idle;
+                    self.reference_cancel_flag = None;
                     self.progress = 1.0;
                     self.reference_id_count = total;
                     self.last_reference_report = Some(report.clone());
@@ -660,8 +2812,19 @@ impl TiffLocatorApp {
                         );
                     }
                 }
+                BackgroundMessage::ReferenceIdsCancelled { processed_rows } => {
+                    self.state = AppState::Idle;
+                    self.reference_cancel_flag = None;
+                    self.progress = 0.0;
+                    self.status_message = format!(
+                        "Reference ID import cancelled after {} rows; no IDs were saved",
+                        processed_rows
+                    );
+                    self.error_message.clear();
+                }
                 BackgroundMessage::ReferenceIdsError { error } => {
                     self.state = AppState::Idle;
+                    self.reference_cancel_flag = None;
                     self.progress = 0.0;
                     self.error_message = format!("Failed to load reference IDs: {}", error);
                     self.status_message.clear();
@@ -672,22 +2835,57 @@ impl TiffLocatorApp {
                     }
                     self.progress_text = format!("Matching IDs... ({}/{})", processed, total);
                 }
+                BackgroundMessage::MatchingMatchCount { found } => {
+                    self.matches_found = found;
+                }
                 BackgroundMessage::MatchingComplete {
                     match_count,
                     engine,
+                    threshold,
+                    dry_run,
+                    output,
                 } => {
                     self.state = AppState::Idle;
                     self.progress = 1.0;
-                    self.status_message = format!(
-                        "Matching complete using {:?}: {} candidate matches stored",
-                        engine, match_count
-                    );
+                    self.status_message = if dry_run {
+                        format!(
+                            "Preview using {:?}: {} candidate matches at {:.0}% (nothing stored)",
+                            engine,
+                            match_count,
+                            threshold * 100.0
+                        )
+                    } else if let Some(output) = &output {
+                        format!(
+                            "Matching complete using {:?}: {} candidate matches stored in {}",
+                            engine, match_count, output
+                        )
+                    } else {
+                        format!(
+                            "Matching complete using {:?}: {} candidate matches stored",
+                            engine, match_count
+                        )
+                    };
+                    if let Some(notice) = self.matching_notice.take() {
+                        self.status_message = format!("{}. {}", self.status_message, notice);
+                    }
                     self.error_message.clear();
+                    // The cache's matches are unchanged when they went elsewhere
+                    if !dry_run && output.is_none() {
+                        self.recent_searches.clear();
+                        self.refresh_stored_matches();
+                        self.refresh_last_match_run();
+                    }
                 }
                 BackgroundMessage::MatchingEngineNotice { message } => {
-                    self.status_message = message;
+                    // Logged as a warning rather than as a status change
+                    self.session_log.push(LogLevel::Warning, message.clone());
+                    self.logged_status = message.clone();
+                    self.status_message = message.clone();
+                    self.matching_notice = Some(message);
                     self.gpu_available = false;
-                    self.use_gpu_matcher = false;
+                    if self.match_engine == MatchEngineKind::Gpu {
+                        self.match_engine = MatchEngineKind::Cpu;
+                    }
                 }
                 BackgroundMessage::MatchingError { error } => {
                     self.state = AppState::Idle;
@@ -695,18 +2893,48 @@ impl TiffLocatorApp {
                     self.error_message = format!("Matching error: {}", error);
                     self.status_message.clear();
                 }
+                BackgroundMessage::SearchProgress {
+                    phase,
+                    processed,
+                    total,
+                } => {
+                    if total > 0 {
+                        self.progress = (processed as f64 / total as f64).min(1.0);
+                    }
+                    self.progress_text = format!("{}... ({}/{})", phase.label(), processed, total);
+                }
                 BackgroundMessage::SearchComplete {
+                    request,
                     results,
+                    total,
+                    from_cache,
                     cache_error,
                 } => {
                     self.state = AppState::Idle;
                     self.progress = 1.0;
-                    self.search_results = results;
-                    self.status_message = format!(
-                        "Found {} matches for '{}'",
-                        self.search_results.len(),
-                        self.search_input.trim()
+                    self.last_search = Some(request.clone());
+                    let query = request.hh_id.clone();
+                    self.recent_searches.insert(
+                        request.clone(),
+                        SearchOutcome {
+                            results: results.clone(),
+                            total,
+                            from_cache,
+                            cache_error: None,
+                        },
                     );
+                    // The first page was read without the display band
+                    self.cached_search =
+                        (from_cache && total > results.len()).then_some(CachedSearch {
+                            request,
+                            total,
+                            in_band: total,
+                            loaded: (0, self.results_per_page, 0.0, 1.0),
+                        });
+                    self.search_results = results;
+                    self.status_message =
+                        format!("Found {} matches for '{}'", total, self.search_input.trim());
+                    self.record_search(&query, total);
                     if let Some(err) = cache_error {
                         self.error_message =
                             format!("Search completed but failed to save cache: {}", err);
@@ -714,6 +2942,11 @@ impl TiffLocatorApp {
                         self.error_message.clear();
                     }
                     self.results_page = 0; // Reset to first page
+                    self.selected_result_index = None;
+                    self.expanded_groups.clear();
+                    if !from_cache {
+                        self.refresh_stored_matches();
+                    }
                 }
                 BackgroundMessage::SearchError { error } => {
                     self.state = AppState::Idle;
@@ -721,6 +2954,107 @@ impl TiffLocatorApp {
                     self.error_message = format!("Search error: {}", error);
                     self.status_message.clear();
                 }
+                BackgroundMessage::ExportProgress { rows, total } => {
+                    if total > 0 {
+                        self.progress = (rows as f64 / total as f64).min(1.0);
+                    }
+                    self.progress_text = format!("Exporting matches... ({}/{})", rows, total);
+                }
+                BackgroundMessage::ExportComplete { rows, path } => {
+                    self.state = AppState::Idle;
+                    self.progress = 1.0;
+                    self.status_message = format!("Exported {} matches to {}", rows, path);
+                    self.error_message.clear();
+                }
+                BackgroundMessage::ExportError { error } => {
+                    self.state = AppState::Idle;
+                    self.progress = 0.0;
+                    self.error_message = format!("Export error: {}", error);
+                    self.status_message.clear();
+                }
+                BackgroundMessage::CacheValidated { check } => {
+                    self.state = AppState::Idle;
+                    self.progress = 1.0;
+                    let (status, error) = cache_check_messages(&check);
+                    self.status_message = status;
+                    self.error_message = error;
+                }
+                BackgroundMessage::CacheValidationError { error } => {
+                    self.state = AppState::Idle;
+                    self.progress = 0.0;
+                    self.error_message = format!("Cache validation error: {}", error);
+                    self.status_message.clear();
+                }
+                BackgroundMessage::VerifyCacheProgress { checked, total } => {
+                    if total > 0 {
+                        self.progress = (checked as f64 / total as f64).min(1.0);
+                    }
+                    self.progress_text = format!(
+                        "Checking cached files... ({}/{})",
+                        group_thousands(checked),
+                        group_thousands(total)
+                    );
+                }
+                BackgroundMessage::VerifyCacheComplete { stale } => {
+                    self.state = AppState::Idle;
+                    self.progress = 1.0;
+                    if stale.is_empty() {
+                        self.status_message = "All cached files still exist".to_string();
+                    } else {
+                        self.status_message =
+                            format!("{} cached files are missing", group_thousands(stale.len()));
+                        self.stale_files = Some(stale);
+                    }
+                }
+                BackgroundMessage::VerifyCacheError { error } => {
+                    self.state = AppState::Idle;
+                    self.progress = 0.0;
+                    self.error_message = format!("Cache verification error: {}", error);
+                    self.status_message.clear();
+                }
+                BackgroundMessage::DiagnosticsGathered { diagnostics } => {
+                    self.diagnostics_pending = false;
+                    self.diagnostics = Some(diagnostics);
+                }
+                BackgroundMessage::CacheOptimized { result } => {
+                    self.optimize_pending = false;
+                    match result {
+                        Ok(()) => self.status_message = "Cache optimized".to_string(),
+                        Err(e) => {
+                            self.status_message.clear();
+                            self.error_message = e;
+                        }
+                    }
+                }
+                BackgroundMessage::LocationsOpened { result } => {
+                    self.open_locations_pending = false;
+                    match result {
+                        Ok(status) => {
+                            self.status_message = status;
+                            self.error_message.clear();
+                        }
+                        Err(e) => {
+                            self.status_message.clear();
+                            self.error_message = e;
+                        }
+                    }
+                }
+                BackgroundMessage::ThumbnailDecoded { path, image } => {
+                    // Entries pruned while decoding are not brought back
+                    if let Some(entry) = self.thumbnails.get_mut(&path) {
+                        *entry = match image {
+                            Ok(image) => Thumbnail::Ready(ctx.load_texture(
+                                format!("thumbnail:{}", path),
+                                image,
+                                egui::TextureOptions::LINEAR,
+                            )),
+                            Err(e) => {
+                                warn!("No preview for {}: {}", path, e);
+                                Thumbnail::Unavailable(e)
+                            }
+                        };
+                    }
+                }
             }
             // Request repaint when we receive a message
             ctx.request_repaint();
@@ -728,52 +3062,923 @@ impl TiffLocatorApp {
     }
 }
 
+/// Draw one preview cell. Returns `None` when the cell is off screen, and
+/// otherwise whether the row still has no thumbnail, so the caller should
+/// request a decode.
+fn thumbnail_cell(ui: &mut egui::Ui, thumbnail: Option<&Thumbnail>) -> Option<bool> {
+    let side = thumbnail::THUMBNAIL_SIZE as f32;
+    let (rect, response) = ui.allocate_exact_size(egui::vec2(side, side), egui::Sense::hover());
+    if !ui.is_rect_visible(rect) {
+        return None;
+    }
+
+    match thumbnail {
+        Some(Thumbnail::Ready(texture)) => {
+            let size = texture.size_vec2();
+            let scale = (side / size.x).min(side / size.y).min(1.0);
+            let fitted = egui::Rect::from_center_size(rect.center(), size * scale);
+            egui::Image::new(texture).paint_at(ui, fitted);
+        }
+        Some(Thumbnail::Unavailable(reason)) => {
+            ui.painter().text(
+                rect.center(),
+                egui::Align2::CENTER_CENTER,
+                "⚠",
+                egui::FontId::proportional(20.0),
+                ui.visuals().weak_text_color(),
+            );
+            response.on_hover_text(format!("No preview: {}", reason));
+        }
+        Some(Thumbnail::Loading) => {
+            egui::Spinner::new().paint_at(ui, rect.shrink(side / 4.0));
+        }
+        None => return Some(true),
+    }
+    Some(false)
+}
+
+/// Group results that share a file name, in the order each name first appears.
+/// Results arrive best-first, so each group starts with its highest score.
+fn group_by_file_name<'a>(results: &[&'a SearchResult]) -> Vec<Vec<&'a SearchResult>> {
+    let mut group_of: HashMap<&str, usize> = HashMap::new();
+    let mut groups: Vec<Vec<&SearchResult>> = Vec::new();
+    for &result in results {
+        match group_of.get(result.file_name.as_str()) {
+            Some(&index) => groups[index].push(result),
+            None => {
+                group_of.insert(&result.file_name, groups.len());
+                groups.push(vec![result]);
+            }
+        }
+    }
+    groups
+}
+
+/// Preview rows of an ID file with the column that will be imported marked.
+fn id_preview_grid(ui: &mut egui::Ui, headers: &[String], rows: &[Vec<String>]) {
+    let id_column = ReferenceLoader::id_column(headers);
+    egui::Grid::new("id_preview_grid")
+        .striped(true)
+        .spacing([10.0, 4.0])
+        .show(ui, |ui| {
+            for (index, header) in headers.iter().enumerate() {
+                if Some(index) == id_column {
+                    ui.colored_label(
+                        egui::Color32::GREEN,
+                        egui::RichText::new(format!("{} ✔ ID", header)).strong(),
+                    );
+                } else {
+                    ui.label(egui::RichText::new(header).strong());
+                }
+            }
+            ui.end_row();
+
+            for row in rows {
+                for index in 0..headers.len() {
+                    let cell = row.get(index).map_or("", String::as_str);
+                    if Some(index) == id_column {
+                        ui.label(egui::RichText::new(cell).strong());
+                    } else {
+                        ui.label(cell);
+                    }
+                }
+                ui.end_row();
+            }
+        });
+}
+
+/// Bars of the stored matches per similarity bucket with the threshold marked,
+/// so the gap between true and false matches can be read off. Clicking or
+/// dragging on the chart moves the threshold; returns whether it changed.
+fn score_histogram_chart(
+    ui: &mut egui::Ui,
+    histogram: &[(f64, f64, usize)],
+    threshold: &mut f64,
+) -> bool {
+    const CHART_HEIGHT: f32 = 90.0;
+    const AXIS_HEIGHT: f32 = 14.0;
+    let width = ui.available_width().min(480.0);
+    let (rect, response) = ui.allocate_exact_size(
+        egui::vec2(width, CHART_HEIGHT + AXIS_HEIGHT),
+        egui::Sense::click_and_drag(),
+    );
+    let chart = egui::Rect::from_min_size(rect.min, egui::vec2(width, CHART_HEIGHT));
+    let mut changed = false;
+    if response.clicked() || response.dragged() {
+        if let Some(pos) = response.interact_pointer_pos() {
+            let fraction = ((pos.x - chart.left()) / chart.width()).clamp(0.0, 1.0);
+            // Snap to whole percent, like the slider's readout
+            let snapped = (fraction as f64 * 100.0).round() / 100.0;
+            changed = snapped != *threshold;
+            *threshold = snapped;
+        }
+    }
+    let response = response.on_hover_cursor(egui::CursorIcon::ResizeHorizontal);
+
+    let painter = ui.painter_at(rect);
+    let visuals = ui.visuals();
+    painter.rect_filled(chart, 2.0, visuals.extreme_bg_color);
+
+    let max = histogram
+        .iter()
+        .map(|&(_, _, count)| count)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let bar_width = chart.width() / histogram.len().max(1) as f32;
+    let hovered = response
+        .hover_pos()
+        .filter(|_| !response.dragged())
+        .map(|pos| ((pos.x - chart.left()) / bar_width) as usize)
+        .filter(|&index| index < histogram.len());
+    for (index, &(_, high, count)) in histogram.iter().enumerate() {
+        if count == 0 {
+            continue;
+        }
+        let left = chart.left() + index as f32 * bar_width;
+        let height = (chart.height() * count as f32 / max as f32).max(1.0);
+        let bar = egui::Rect::from_min_max(
+            egui::pos2(left + 1.0, chart.bottom() - height),
+            egui::pos2(left + bar_width - 1.0, chart.bottom()),
+        );
+        // Buckets reaching past the threshold hold matches a run would keep
+        let kept = high > *threshold;
+        let color = if hovered == Some(index) {
+            visuals.selection.bg_fill
+        } else if kept {
+            egui::Color32::from_rgb(70, 150, 230)
+        } else {
+            egui::Color32::GRAY
+        };
+        painter.rect_filled(bar, 0.0, color);
+    }
+
+    let marker = egui::Color32::from_rgb(230, 160, 0);
+    let threshold_x = chart.left() + *threshold as f32 * chart.width();
+    painter.vline(threshold_x, chart.y_range(), egui::Stroke::new(1.5, marker));
+    // Grip at the top of the marker, hinting that it can be dragged
+    painter.add(egui::Shape::convex_polygon(
+        vec![
+            egui::pos2(threshold_x - 5.0, chart.top()),
+            egui::pos2(threshold_x + 5.0, chart.top()),
+            egui::pos2(threshold_x, chart.top() + 7.0),
+        ],
+        marker,
+        egui::Stroke::NONE,
+    ));
+    for (fraction, align) in [
+        (0.0, egui::Align2::LEFT_TOP),
+        (0.5, egui::Align2::CENTER_TOP),
+        (1.0, egui::Align2::RIGHT_TOP),
+    ] {
+        painter.text(
+            egui::pos2(
+                chart.left() + fraction * chart.width(),
+                chart.bottom() + 1.0,
+            ),
+            align,
+            format!("{:.0}%", fraction * 100.0),
+            egui::FontId::proportional(11.0),
+            visuals.weak_text_color(),
+        );
+    }
+
+    if let Some(index) = hovered {
+        let (low, high, count) = histogram[index];
+        response.on_hover_text_at_pointer(format!(
+            "{}: {} matches",
+            bucket_label(low, high),
+            group_thousands(count)
+        ));
+    }
+    changed
+}
+
+/// Similarity range of a histogram bucket, e.g. `70–75%`.
+fn bucket_label(low: f64, high: f64) -> String {
+    format!("{:.0}–{:.0}%", low * 100.0, high * 100.0)
+}
+
+/// Rows on one results page: the chosen size, or every row (at least one, so
+/// page counts never divide by zero) when "All" (0) is chosen.
+fn rows_per_page(page_size: usize, total_rows: usize) -> usize {
+    match page_size {
+        0 => total_rows.max(1),
+        size => size,
+    }
+}
+
+/// Page that keeps `first_row` (the top row shown before the page size
+/// changed) in view, clamped to the last page.
+fn page_after_resize(first_row: usize, page_size: usize, total_rows: usize) -> usize {
+    let per_page = rows_per_page(page_size, total_rows);
+    let last_page = total_rows.div_ceil(per_page).max(1) - 1;
+    (first_row / per_page).min(last_page)
+}
+
+fn page_size_label(page_size: usize) -> String {
+    match page_size {
+        0 => "All".to_string(),
+        size => size.to_string(),
+    }
+}
+
+/// `1234567` as `1,234,567`.
+/// Byte count in the largest unit that keeps it at or above 1, e.g. "12.3 MB".
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} bytes", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Byte count typed as a number with an optional KB, MB or GB suffix (1024
+/// based, like `format_size`); blank means no limit.
+fn parse_size(text: &str) -> Result<Option<u64>, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    let upper = text.to_uppercase();
+    let (number, multiplier) = [
+        ("GB", 1u64 << 30),
+        ("MB", 1 << 20),
+        ("KB", 1 << 10),
+        ("B", 1),
+    ]
+    .iter()
+    .find_map(|(unit, multiplier)| {
+        upper
+            .strip_suffix(unit)
+            .map(|number| (number.trim(), *multiplier))
+    })
+    .unwrap_or((upper.as_str(), 1));
+    let value: f64 = number
+        .parse()
+        .ok()
+        .filter(|value: &f64| value.is_finite() && *value >= 0.0)
+        .ok_or_else(|| format!("Invalid file size: {}", text))?;
+    Ok(Some((value * multiplier as f64).round() as u64))
+}
+
+/// The parsed (min, max) size bounds, checking that min is not above max.
+fn size_range(min: &str, max: &str) -> Result<(Option<u64>, Option<u64>), String> {
+    let (min, max) = (parse_size(min)?, parse_size(max)?);
+    if let (Some(min), Some(max)) = (min, max) {
+        if min > max {
+            return Err(format!(
+                "Minimum file size {} is above the maximum {}",
+                format_size(min),
+                format_size(max)
+            ));
+        }
+    }
+    Ok((min, max))
+}
+
+fn group_thousands(value: usize) -> String {
+    let digits = value.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+fn ambiguous_names(duplicates: &[DuplicateName]) -> HashSet<String> {
+    duplicates
+        .iter()
+        .map(|duplicate| duplicate.file_name.clone())
+        .collect()
+}
+
+// "HH001.tif (/mnt/archive/2019)" for names that exist in several folders
+fn name_with_folder(result: &SearchResult) -> String {
+    match Path::new(&result.file_path).parent() {
+        Some(folder) => format!("{} ({})", result.file_name, folder.display()),
+        None => result.file_name.clone(),
+    }
+}
+
+/// A results key pressed this frame: the arrows move the selected row, Enter
+/// opens its location and Space the file itself.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ResultKey {
+    Up,
+    Down,
+    OpenLocation,
+    OpenFile,
+}
+
+impl ResultKey {
+    /// The key pressed, unless a text box or button has keyboard focus. Read
+    /// before any widget runs, so the Enter that submits the search box
+    /// doesn't also open a result.
+    fn pressed(ctx: &egui::Context) -> Option<Self> {
+        if ctx.wants_keyboard_input() {
+            return None;
+        }
+        ctx.input(|input| {
+            [
+                (egui::Key::ArrowUp, ResultKey::Up),
+                (egui::Key::ArrowDown, ResultKey::Down),
+                (egui::Key::Enter, ResultKey::OpenLocation),
+                (egui::Key::Space, ResultKey::OpenFile),
+            ]
+            .into_iter()
+            .find(|(key, _)| input.key_pressed(*key))
+            .map(|(_, result_key)| result_key)
+        })
+    }
+
+    /// Selected row after this key on a page of `rows` rows; the first row
+    /// when nothing was selected.
+    fn select(self, selected: Option<usize>, rows: usize) -> Option<usize> {
+        if rows == 0 {
+            return None;
+        }
+        match (self, selected) {
+            (ResultKey::Up | ResultKey::Down, None) => Some(0),
+            (ResultKey::Up, Some(index)) => Some(index.saturating_sub(1).min(rows - 1)),
+            (ResultKey::Down, Some(index)) => Some((index + 1).min(rows - 1)),
+            (_, selected) => selected.filter(|&index| index < rows),
+        }
+    }
+}
+
+/// What a result row's button or context menu asked for.
+enum RowAction {
+    /// Status (or error) message of an action that already ran.
+    Done(Result<String, String>),
+    /// Put this file path on the persistent blocklist.
+    Block(String),
+    /// Open the folders of these files, off the UI thread.
+    OpenLocations(Vec<String>),
+    /// Hide this file path from results for the rest of the session.
+    Exclude(String),
+    /// Set the review status of the stored match of this ID and file.
+    Review {
+        hh_id: String,
+        file_path: String,
+        status: MatchStatus,
+    },
+}
+
+/// Cell text of a result row, shaded by its review status: green once
+/// confirmed, struck through once rejected.
+fn status_text(text: String, status: MatchStatus) -> egui::RichText {
+    let text = egui::RichText::new(text);
+    match status {
+        MatchStatus::Pending => text,
+        MatchStatus::Confirmed => text.color(egui::Color32::from_rgb(60, 170, 60)),
+        MatchStatus::Rejected => text.weak().strikethrough(),
+    }
+}
+
+/// Colour of a similarity score on a red–yellow–green scale from `threshold`
+/// (the weakest score a run keeps) up to a perfect 1.0. Darker shades on
+/// light backgrounds keep the text readable.
+fn score_color(score: f64, threshold: f64, dark_mode: bool) -> egui::Color32 {
+    let (red, yellow, green) = if dark_mode {
+        (
+            egui::Color32::from_rgb(235, 90, 80),
+            egui::Color32::from_rgb(230, 200, 70),
+            egui::Color32::from_rgb(90, 205, 100),
+        )
+    } else {
+        (
+            egui::Color32::from_rgb(190, 40, 30),
+            egui::Color32::from_rgb(165, 125, 0),
+            egui::Color32::from_rgb(30, 135, 50),
+        )
+    };
+    let t = if threshold < 1.0 {
+        ((score - threshold) / (1.0 - threshold)).clamp(0.0, 1.0) as f32
+    } else {
+        1.0
+    };
+    if t < 0.5 {
+        red.lerp_to_gamma(yellow, t * 2.0)
+    } else {
+        yellow.lerp_to_gamma(green, (t - 0.5) * 2.0)
+    }
+}
+
+/// Draw the similarity, threshold and action cells of one result row.
+/// `hh_id` is the row's household ID; the review buttons are disabled when
+/// it's empty. Returns the outcome when an action was clicked.
+fn result_cells(ui: &mut egui::Ui, result: &SearchResult, hh_id: &str) -> Option<RowAction> {
+    let similarity = format!("{:.1}%", result.similarity_score * 100.0);
+    // Rejected rows stay struck through rather than coloured by score
+    if result.status == MatchStatus::Rejected {
+        ui.label(status_text(similarity, result.status));
+    } else {
+        let color = score_color(
+            result.similarity_score,
+            result.threshold.unwrap_or(0.0),
+            ui.visuals().dark_mode,
+        );
+        ui.colored_label(color, similarity);
+    }
+    ui.label(status_text(run_label(result), result.status));
+    ui.label(status_text(matched_via_label(result), result.status));
+
+    ui.horizontal(|ui| {
+        let mut action = ui
+            .button("📂 Open Location")
+            .on_hover_text("Right-click the file name for more actions")
+            .clicked()
+            .then(|| RowAction::Done(open_location(result)));
+        // Clicking the current status again sets the match back to pending
+        for (status, icon, hint) in [
+            (MatchStatus::Confirmed, "✔", "Confirm this match"),
+            (MatchStatus::Rejected, "✖", "Reject this match"),
+        ] {
+            let selected = result.status == status;
+            if ui
+                .add_enabled(
+                    !hh_id.is_empty(),
+                    egui::SelectableLabel::new(selected, icon),
+                )
+                .on_hover_text(hint)
+                .clicked()
+            {
+                action = Some(RowAction::Review {
+                    hh_id: hh_id.to_string(),
+                    file_path: result.file_path.clone(),
+                    status: if selected {
+                        MatchStatus::Pending
+                    } else {
+                        status
+                    },
+                });
+            }
+        }
+        action
+    })
+    .inner
+}
+
+/// Right-click menu on a result's file name: open, copy or exclude it.
+/// `hh_id` is the searched ID; Copy ID is disabled when it's empty.
+fn result_menu(response: &egui::Response, result: &SearchResult, hh_id: &str) -> Option<RowAction> {
+    let mut action = None;
+    response.context_menu(|ui| {
+        let file_path = opener::resolve_stored_path(&result.file_path, result.scan_root.as_deref());
+        if ui.button("🖼 Open File").clicked() {
+            action = Some(RowAction::Done(open_result_file(result)));
+        }
+        if ui.button("📂 Open Location").clicked() {
+            action = Some(RowAction::Done(open_location(result)));
+        }
+        ui.separator();
+        if ui.button("📋 Copy Path").clicked() {
+            ui.ctx().copy_text(file_path.clone());
+            action = Some(RowAction::Done(Ok(format!(
+                "Copied path of {}",
+                result.file_name
+            ))));
+        }
+        if ui
+            .add_enabled(!hh_id.is_empty(), egui::Button::new("📋 Copy ID"))
+            .clicked()
+        {
+            ui.ctx().copy_text(hh_id.to_string());
+            action = Some(RowAction::Done(Ok(format!("Copied ID {}", hh_id))));
+        }
+        if ui
+            .button("📋 Copy cd")
+            .on_hover_text("Copy a cd command for this folder")
+            .clicked()
+        {
+            action = Some(RowAction::Done(
+                opener::quoted_parent_command(&file_path).map(|command| {
+                    ui.ctx().copy_text(command);
+                    format!("Copied cd command for {}", result.file_name)
+                }),
+            ));
+        }
+        ui.separator();
+        if ui
+            .button("🚫 Exclude This File")
+            .on_hover_text("Hide this file from current and later results until cleared")
+            .clicked()
+        {
+            action = Some(RowAction::Exclude(result.file_path.clone()));
+        }
+        if ui
+            .button("⛔ Block Permanently")
+            .on_hover_text(
+                "Never show or match this file again, even after restarting; \
+                 undo it under \"Blocked files\"",
+            )
+            .clicked()
+        {
+            action = Some(RowAction::Block(result.file_path.clone()));
+        }
+        if action.is_some() {
+            ui.close_menu();
+        }
+    });
+    action
+}
+
+// Open a result in the default viewer, as a status or error message
+fn open_result_file(result: &SearchResult) -> Result<String, String> {
+    let file_path = opener::resolve_stored_path(&result.file_path, result.scan_root.as_deref());
+    match opener::open_file(&file_path) {
+        Ok(()) => Ok(format!("Opened {}", result.file_name)),
+        Err(e) => {
+            error!("Failed to open file: {}", e);
+            Err(e)
+        }
+    }
+}
+
+// Reveal a result in the file manager, as a status or error message
+fn open_location(result: &SearchResult) -> Result<String, String> {
+    let file_path = opener::resolve_stored_path(&result.file_path, result.scan_root.as_deref());
+    match opener::open_file_location(&file_path) {
+        Ok(_) => Ok(format!("Opened file location for {}", result.file_name)),
+        Err(e) => {
+            error!("Failed to open location: {}", e);
+            Err(format!("Failed to open location: {}", e))
+        }
+    }
+}
+
+/// Names of the cleared parts, e.g. "files, matches and vectors".
+fn cleared_parts(selection: ClearSelection) -> String {
+    let parts: Vec<&str> = [
+        (selection.files, "files"),
+        (selection.matches, "matches"),
+        (selection.vectors, "vectors"),
+        (selection.reference_ids, "reference IDs"),
+    ]
+    .into_iter()
+    .filter_map(|(chosen, name)| chosen.then_some(name))
+    .collect();
+    match parts.split_last() {
+        None => "nothing".to_string(),
+        Some((last, [])) => last.to_string(),
+        Some((last, rest)) => format!("{} and {}", rest.join(", "), last),
+    }
+}
+
+/// What the clear dialog's selection will delete, with row counts.
+fn clear_summary(dialog: &ClearDialog) -> String {
+    let selection = dialog.selection.effective();
+    let parts: Vec<String> = [
+        (selection.files, dialog.files, "files"),
+        (selection.matches, dialog.matches, "matches"),
+        (selection.vectors, dialog.vectors, "vectors"),
+        (
+            selection.reference_ids,
+            dialog.reference_ids,
+            "reference IDs",
+        ),
+    ]
+    .into_iter()
+    .filter(|(chosen, _, _)| *chosen)
+    .map(|(_, count, name)| format!("{} {}", group_thousands(count), name))
+    .collect();
+    match parts.split_last() {
+        None => "Nothing selected".to_string(),
+        Some((last, [])) => format!("Deletes {}. This can't be undone.", last),
+        Some((last, rest)) => format!(
+            "Deletes {} and {}. This can't be undone.",
+            rest.join(", "),
+            last
+        ),
+    }
+}
+
+/// Open the folders of a page of results, as a status or error message.
+fn open_page_locations(paths: &[String]) -> Result<String, String> {
+    let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+    let outcome = opener::open_locations(&paths)?;
+    if outcome.failed.is_empty() {
+        return Ok(format!("Opened {} folders", outcome.opened));
+    }
+    for failure in &outcome.failed {
+        error!("Failed to open location: {}", failure);
+    }
+    Err(format!(
+        "Opened {} of {} folders; failed: {}",
+        outcome.opened,
+        outcome.opened + outcome.failed.len(),
+        outcome.failed.join("; ")
+    ))
+}
+
+/// One line describing the scanned files, e.g. "9 .tif, 6 .tiff · 1.2 GB
+/// (under 1 MB: 3, 1-10 MB: 12)". Empty size buckets are left out.
+fn archive_summary(summary: &ScanSummary) -> String {
+    let mut extensions: Vec<(&String, &usize)> = summary.by_extension.iter().collect();
+    extensions.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    let extensions: Vec<String> = extensions
+        .into_iter()
+        .map(|(extension, count)| format!("{} .{}", group_thousands(*count), extension))
+        .collect();
+    let sizes: Vec<String> = summary
+        .by_size
+        .iter()
+        .zip(SIZE_BUCKET_LABELS)
+        .filter(|(count, _)| **count > 0)
+        .map(|(count, label)| format!("{}: {}", label, group_thousands(*count)))
+        .collect();
+    format!(
+        "{} · {} ({})",
+        extensions.join(", "),
+        format_size(summary.total_bytes),
+        sizes.join(", ")
+    )
+}
+
+/// Status and error lines for a cache check. Missing files are an error only
+/// when most of the sample is gone, since a few deletions are normal.
+fn cache_check_messages(check: &CacheCheck) -> (String, String) {
+    if check.sampled == 0 {
+        return (
+            "The cache holds no files to validate".to_string(),
+            String::new(),
+        );
+    }
+    let mut found = format!(
+        "{} of {} sampled cached files are missing ({:.0}%)",
+        check.missing,
+        check.sampled,
+        check.missing_fraction() * 100.0
+    );
+    if let Some(example) = check.missing_examples.first() {
+        found.push_str(&format!(", e.g. {}", example));
+    }
+    if check.foreign_separators > 0 {
+        found.push_str(&format!(
+            ". {} sampled paths use another platform's separators; the cache was probably \
+             built on a different operating system",
+            check.foreign_separators
+        ));
+    }
+    if check.suggests_rescan() {
+        (
+            String::new(),
+            format!(
+                "{}. The archive may have moved: remap its paths under \"Remap moved archive\" \
+                 or remove its old folder and scan the new location",
+                found
+            ),
+        )
+    } else {
+        (format!("Cache looks valid: {}", found), String::new())
+    }
+}
+
+/// Threshold and engine of the run that produced a result, e.g. "≥70% (gpu)".
+fn run_label(result: &SearchResult) -> String {
+    match (result.threshold, result.engine.as_deref()) {
+        (Some(threshold), Some(engine)) => format!("≥{:.0}% ({})", threshold * 100.0, engine),
+        (Some(threshold), None) => format!("≥{:.0}%", threshold * 100.0),
+        _ => "unknown".to_string(),
+    }
+}
+
+/// A result's file name cell: clicked to explain the match, right-clicked
+/// for its menu.
+fn row_name(ui: &mut egui::Ui, text: impl Into<egui::WidgetText>) -> egui::Response {
+    ui.add(egui::Label::new(text).sense(egui::Sense::click()))
+}
+
+/// Which candidate scored, e.g. `Stem "hh001"`.
+fn matched_via_label(result: &SearchResult) -> String {
+    let Some(matched_via) = result.matched_via.as_deref() else {
+        return "unknown".to_string();
+    };
+    match parse_matched_via(matched_via) {
+        // The vector stands for the whole name, already in the first column
+        Some((CandidateKind::Vector, _)) => CandidateKind::Vector.to_string(),
+        Some((kind, candidate)) => format!("{} \"{}\"", kind, candidate),
+        None => matched_via.to_string(),
+    }
+}
+
 impl eframe::App for TiffLocatorApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        self.apply_appearance(ctx, frame.info().system_theme);
+        // The clear dialog takes Enter for itself
+        let result_key = ResultKey::pressed(ctx).filter(|_| self.clear_dialog.is_none());
+
         // Process messages from background threads
         self.process_background_messages(ctx);
 
-        // Only request repaint if we're in an active state
-        if self.state != AppState::Idle {
+        // Only request repaint if we're in an active state or previews are decoding
+        let decoding = self
+            .thumbnails
+            .values()
+            .any(|thumbnail| matches!(thumbnail, Thumbnail::Loading));
+        if self.state != AppState::Idle || decoding {
             ctx.request_repaint_after(std::time::Duration::from_millis(100));
         }
 
+        self.session_log_panel(ctx);
+
         egui::CentralPanel::default().show(ctx, |ui| {
-            ui.heading("🔍 TiffLocator");
+            ui.horizontal(|ui| {
+                ui.heading("🔍 TiffLocator");
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    let mut changed = ui.checkbox(&mut self.larger_text, "Larger text").changed();
+                    changed |= ui
+                        .checkbox(&mut self.high_contrast, "High contrast")
+                        .changed();
+                    egui::ComboBox::from_id_source("theme")
+                        .selected_text(self.theme.label())
+                        .show_ui(ui, |ui| {
+                            for theme in ThemePreference::ALL {
+                                changed |= ui
+                                    .selectable_value(&mut self.theme, theme, theme.label())
+                                    .changed();
+                            }
+                        });
+                    ui.label("Theme:");
+                    if changed {
+                        self.save_appearance();
+                    }
+                });
+            });
+            ui.label(
+                egui::RichText::new(
+                    "Scan your folders, then either type an ID under Quick Search (no import \
+                     needed), or load a CSV / Excel list of reference IDs and click Match IDs \
+                     to match the whole list at once.",
+                )
+                .weak(),
+            );
             ui.add_space(10.0);
 
-            // Folder selection
+            // Scan folders
             ui.horizontal(|ui| {
-                if ui.button("📁 Select Folder").clicked() {
-                    self.select_folder();
+                if ui
+                    .add_enabled(
+                        self.state == AppState::Idle,
+                        egui::Button::new("📁 Add Folder"),
+                    )
+                    .clicked()
+                {
+                    self.add_folder();
+                }
+                if ui
+                    .add_enabled(
+                        self.state == AppState::Idle,
+                        egui::Checkbox::new(&mut self.follow_links, "Follow symlinks"),
+                    )
+                    .on_hover_text(
+                        "Scan folders reached through symbolic links; loops are detected \
+                         and each folder is scanned once",
+                    )
+                    .changed()
+                {
+                    self.apply_scan_options();
+                }
+                if ui
+                    .add_enabled(
+                        self.state == AppState::Idle,
+                        egui::Checkbox::new(&mut self.read_metadata, "Read page counts"),
+                    )
+                    .on_hover_text(
+                        "Record each TIFF's page count and image size while scanning; \
+                         slower, since every stored file is opened",
+                    )
+                    .changed()
+                {
+                    self.apply_scan_options();
                 }
-                ui.label(&self.folder_path);
                 if self.file_count > 0 {
                     ui.label(format!("({} TIFF files cached)", self.file_count));
                 }
             });
+            ui.horizontal(|ui| {
+                let idle = self.state == AppState::Idle;
+                ui.label("File size:");
+                let min = ui
+                    .add_enabled(
+                        idle,
+                        egui::TextEdit::singleline(&mut self.min_file_size)
+                            .hint_text("min, e.g. 1")
+                            .desired_width(90.0),
+                    )
+                    .on_hover_text("Skip smaller TIFFs; 1 leaves out zero-byte placeholders");
+                ui.label("to");
+                let max = ui
+                    .add_enabled(
+                        idle,
+                        egui::TextEdit::singleline(&mut self.max_file_size)
+                            .hint_text("max, e.g. 500 MB")
+                            .desired_width(90.0),
+                    )
+                    .on_hover_text("Skip larger TIFFs");
+                ui.weak("bytes, or KB / MB / GB; blank for no limit");
+                if min.lost_focus() || max.lost_focus() {
+                    self.apply_size_range();
+                }
+            });
+            let mut remove_root = None;
+            for root in &self.scan_roots {
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(self.state == AppState::Idle, egui::Button::new("✖"))
+                        .on_hover_text("Remove this folder and its cached files")
+                        .clicked()
+                    {
+                        remove_root = Some(root.path.clone());
+                    }
+                    ui.label(&root.path);
+                    let last_scan = root
+                        .last_scan
+                        .as_deref()
+                        .and_then(|date| chrono::DateTime::parse_from_rfc3339(date).ok())
+                        .map(|date| {
+                            date.with_timezone(&chrono::Local)
+                                .format("%Y-%m-%d %H:%M")
+                                .to_string()
+                        })
+                        .unwrap_or_else(|| "never".to_string());
+                    ui.weak(format!("last scanned: {}", last_scan));
+                });
+            }
+            if let Some(root) = remove_root {
+                self.remove_folder(&root);
+            }
+            if self.file_count > 0 {
+                egui::CollapsingHeader::new("🔀 Remap moved archive")
+                    .id_source("remap_paths")
+                    .show(ui, |ui| self.remap_tool(ui));
+            }
+            if !self.duplicate_names.is_empty() {
+                egui::CollapsingHeader::new(format!(
+                    "⚠ {} file names found in more than one folder",
+                    self.duplicate_names.len()
+                ))
+                .id_source("duplicate_names")
+                .show(ui, |ui| {
+                    egui::ScrollArea::vertical()
+                        .id_source("duplicate_names_scroll")
+                        .max_height(150.0)
+                        .show(ui, |ui| {
+                            for duplicate in &self.duplicate_names {
+                                ui.label(egui::RichText::new(&duplicate.file_name).strong());
+                                for path in &duplicate.paths {
+                                    ui.label(format!("    {}", path));
+                                }
+                            }
+                        });
+                });
+            }
 
             ui.add_space(5.0);
 
             // CSV selection and reference ID loading
+            ui.label(egui::RichText::new("Batch matching (optional)").strong())
+                .on_hover_text(
+                    "Only needed to match a whole list of IDs; Quick Search works without it",
+                );
             ui.horizontal(|ui| {
-                if ui.button("📄 Select CSV").clicked() {
+                if ui.button("📄 Select CSV / Excel").clicked() {
                     self.select_csv();
                 }
                 ui.label(&self.csv_path);
             });
 
+            if let Some((headers, rows)) = &self.id_preview {
+                egui::CollapsingHeader::new(format!("Preview (first {} rows)", rows.len()))
+                    .default_open(true)
+                    .show(ui, |ui| id_preview_grid(ui, headers, rows));
+            }
+
             ui.add_space(5.0);
 
             ui.horizontal(|ui| {
                 let can_load =
-                    self.state == AppState::Idle && !self.csv_path.is_empty() && self.db.is_some();
+                    self.state == AppState::Idle && self.id_file_ready() && self.db.is_some();
                 if ui
                     .add_enabled(can_load, egui::Button::new("📥 Load Reference IDs"))
                     .clicked()
                 {
                     self.load_reference_ids();
                 }
+                if self.state == AppState::LoadingReferenceIds && ui.button("⏹ Cancel").clicked()
+                {
+                    self.cancel_reference_load();
+                }
                 if self.reference_id_count > 0 {
                     ui.label(format!(
                         "({} reference IDs loaded)",
@@ -802,39 +4007,239 @@ impl eframe::App for TiffLocatorApp {
             // Similarity threshold slider
             ui.horizontal(|ui| {
                 ui.label("Similarity Threshold:");
-                ui.add(egui::Slider::new(&mut self.similarity_threshold, 0.5..=1.0).text(""));
+                if ui
+                    .add(egui::Slider::new(&mut self.similarity_threshold, 0.0..=1.0).text(""))
+                    .on_hover_text(
+                        "Lower values find files whose names barely resemble the ID \
+                         (e.g. heavy prefixes), at the cost of more false matches",
+                    )
+                    .changed()
+                    && self.similarity_threshold > self.similarity_max
+                {
+                    self.similarity_max = self.similarity_threshold;
+                }
                 ui.label(format!("{:.0}%", self.similarity_threshold * 100.0));
+                ui.label("Upper bound:");
+                if ui
+                    .add(egui::Slider::new(&mut self.similarity_max, 0.0..=1.0).text(""))
+                    .changed()
+                    && self.similarity_max < self.similarity_threshold
+                {
+                    self.similarity_threshold = self.similarity_max;
+                }
+                ui.label(format!("{:.0}%", self.similarity_max * 100.0));
             });
+            if self.has_stored_matches() {
+                egui::CollapsingHeader::new("📊 Match score distribution")
+                    .id_source("score_histogram")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        if score_histogram_chart(
+                            ui,
+                            &self.score_histogram,
+                            &mut self.similarity_threshold,
+                        ) && self.similarity_threshold > self.similarity_max
+                        {
+                            self.similarity_max = self.similarity_threshold;
+                        }
+                    })
+                    .header_response
+                    .on_hover_text("Click or drag on the chart to move the threshold");
+            }
+            if self.similarity_threshold < NOISY_THRESHOLD {
+                ui.colored_label(
+                    egui::Color32::from_rgb(230, 160, 0),
+                    format!(
+                        "⚠ Thresholds below {:.0}% match most file names; expect many noisy results",
+                        NOISY_THRESHOLD * 100.0
+                    ),
+                );
+            }
 
             ui.horizontal(|ui| {
-                let checkbox = egui::Checkbox::new(
-                    &mut self.use_gpu_matcher,
-                    "Use GPU matcher (experimental)",
-                );
-                let response = ui.add_enabled(self.gpu_available, checkbox);
+                ui.label("Matcher:");
+                let previous = self.match_engine;
+                egui::ComboBox::from_id_source("match_engine")
+                    .selected_text(self.match_engine.to_string())
+                    .show_ui(ui, |ui| {
+                        for kind in MatchEngineKind::ALL {
+                            let label = if kind == MatchEngineKind::Gpu {
+                                "GPU (experimental)".to_string()
+                            } else {
+                                kind.to_string()
+                            };
+                            let enabled = kind != MatchEngineKind::Gpu || self.gpu_available;
+                            if ui
+                                .add_enabled(
+                                    enabled,
+                                    egui::SelectableLabel::new(self.match_engine == kind, label),
+                                )
+                                .clicked()
+                            {
+                                self.match_engine = kind;
+                            }
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        "CPU (fuzzy) compares IDs with name fragments. GPU and CPU (cosine) \
+                         compare n-gram vectors and give the same scores, so CPU (cosine) \
+                         reproduces GPU results on machines without one",
+                    );
                 if !self.gpu_available {
                     ui.label(
                         egui::RichText::new("GPU support unavailable for this build").italics(),
                     );
-                } else if response.changed() && self.use_gpu_matcher {
+                } else if self.match_engine != previous && self.match_engine == MatchEngineKind::Gpu
+                {
                     self.status_message =
-                        "GPU matcher enabled. Results will match the CPU baseline.".to_string();
+                        "GPU matcher enabled. Its scores match the CPU (cosine) matcher.".to_string();
+                }
+            });
+
+            ui.horizontal(|ui| {
+                let cores = thread::available_parallelism().map_or(1, |n| n.get());
+                ui.label("Worker threads:");
+                let response = ui
+                    .add_enabled(
+                        self.state == AppState::Idle,
+                        egui::DragValue::new(&mut self.thread_limit).range(0..=cores),
+                    )
+                    .on_hover_text(
+                        "Limit the threads used to scan, match and search so other jobs \
+                         on a shared machine keep some cores",
+                    );
+                ui.label(format!("(0 = all {} cores)", cores));
+                if response.drag_stopped() || (response.changed() && !response.dragged()) {
+                    self.apply_thread_limit();
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Recent searches kept:");
+                let response = ui
+                    .add(
+                        egui::DragValue::new(&mut self.recent_search_limit)
+                            .range(0..=MAX_RECENT_SEARCHES),
+                    )
+                    .on_hover_text(
+                        "Repeating one of these searches shows its results instantly; \
+                         they are forgotten after a scan or match (0 = keep none)",
+                    );
+                if response.drag_stopped() || (response.changed() && !response.dragged()) {
+                    self.apply_recent_search_limit();
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.match_path_components, "Match folder names too")
+                    .on_hover_text(
+                        "Also compare IDs with enclosing folder names (slower, CPU only)",
+                    );
+                let response = ui
+                    .add_enabled(
+                        self.match_path_components,
+                        egui::DragValue::new(&mut self.path_components)
+                            .range(1..=MAX_PATH_COMPONENTS)
+                            .suffix(" folders up"),
+                    )
+                    .on_hover_text(
+                        "How many enclosing folders, nearest first, are compared; \
+                         each one adds work for every file",
+                    );
+                if response.drag_stopped() || (response.changed() && !response.dragged()) {
+                    self.apply_path_components();
+                }
+                ui.checkbox(&mut self.case_sensitive, "Case-sensitive IDs")
+                    .on_hover_text("Treat HH001a and HH001A as different households (CPU only)");
+                ui.label("Score by:");
+                let previous = self.score_combination;
+                egui::ComboBox::from_id_source("score_combination")
+                    .selected_text(self.score_combination.to_string())
+                    .show_ui(ui, |ui| {
+                        for combination in ScoreCombination::CHOICES {
+                            ui.selectable_value(
+                                &mut self.score_combination,
+                                combination,
+                                combination.to_string(),
+                            );
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        "How a file's name, stem, extracted ID and folder scores combine. \
+                         Weighted and agreement scoring favour files matched more than one \
+                         way, cutting single-candidate false positives (CPU only)",
+                    );
+                if self.score_combination != previous {
+                    self.apply_score_combination();
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Match:");
+                ui.radio_value(&mut self.match_selection, MatchSelection::All, "All IDs");
+                ui.radio_value(&mut self.match_selection, MatchSelection::Listed, "Listed IDs")
+                    .on_hover_text("Rematch only these IDs; other stored matches are kept");
+                ui.radio_value(
+                    &mut self.match_selection,
+                    MatchSelection::ImportedSince,
+                    "Imported since",
+                )
+                .on_hover_text("Match only IDs first imported at or after this time");
+                ui.radio_value(
+                    &mut self.match_selection,
+                    MatchSelection::NewFiles,
+                    "New files only",
+                )
+                .on_hover_text(
+                    "Match every ID against only the files scanned since the last full \
+                     match; matches of older files are kept",
+                );
+                if self.match_selection == MatchSelection::ImportedSince {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.match_since_input)
+                            .hint_text("YYYY-MM-DD HH:MM")
+                            .desired_width(150.0),
+                    );
                 }
             });
+            if self.match_selection == MatchSelection::Listed {
+                ui.add(
+                    egui::TextEdit::multiline(&mut self.match_ids_input)
+                        .hint_text("HH001, HH002 … (commas, spaces or one per line)")
+                        .desired_rows(2),
+                );
+            }
 
             ui.add_space(10.0);
 
             // Action buttons
             ui.horizontal(|ui| {
                 let can_scan = self.state == AppState::Idle
-                    && !self.folder_path.is_empty()
+                    && !self.scan_roots.is_empty()
                     && self.db.is_some();
                 if ui
-                    .add_enabled(can_scan, egui::Button::new("🔍 Scan Directory"))
+                    .add_enabled(can_scan, egui::Button::new("🔍 Scan Folders"))
+                    .clicked()
+                {
+                    self.start_scanning(false);
+                }
+                if ui
+                    .add_enabled(can_scan, egui::Button::new("🔄 Rescan Changed"))
+                    .on_hover_text("Only store files added or modified since the last scan")
                     .clicked()
                 {
-                    self.start_scanning();
+                    self.start_scanning(true);
                 }
+                ui.add_enabled(
+                    self.state == AppState::Idle,
+                    egui::Checkbox::new(&mut self.since_last_scan, "Since last scan only"),
+                )
+                .on_hover_text(
+                    "Only read files modified since each folder was last scanned; \
+                     files deleted since then stay cached",
+                );
 
                 let can_match = self.state == AppState::Idle
                     && self.reference_id_count > 0
@@ -844,17 +4249,96 @@ impl eframe::App for TiffLocatorApp {
                     .add_enabled(can_match, egui::Button::new("🔗 Match IDs"))
                     .clicked()
                 {
-                    self.start_matching();
+                    self.start_matching(false);
+                }
+                if ui
+                    .add_enabled(can_match, egui::Button::new("🔢 Preview match count"))
+                    .on_hover_text("Count the matches at the current threshold without storing them")
+                    .clicked()
+                {
+                    self.start_matching(true);
+                }
+                if ui
+                    .add_enabled(
+                        self.state == AppState::Idle,
+                        egui::Button::new("💾 Save matches to…"),
+                    )
+                    .on_hover_text(
+                        "Store each match run in a new timestamped database in a folder \
+                         instead of the cache",
+                    )
+                    .clicked()
+                {
+                    self.pick_match_output_dir();
+                }
+                if let Some(dir) = &self.match_output_dir {
+                    ui.label(format!("→ {}", dir.display()));
+                    if ui
+                        .add_enabled(self.state == AppState::Idle, egui::Button::new("✖"))
+                        .on_hover_text("Store matches in the cache again")
+                        .clicked()
+                    {
+                        self.match_output_dir = None;
+                    }
                 }
 
                 if ui
                     .add_enabled(
-                        !self.search_results.is_empty(),
+                        self.has_search_results(),
                         egui::Button::new("📤 Export Results"),
                     )
                     .clicked()
                 {
-                    self.export_to_csv();
+                    self.export_results();
+                }
+
+                if ui
+                    .add_enabled(
+                        self.state == AppState::Idle && self.db.is_some(),
+                        egui::Button::new("📦 Export All Matches"),
+                    )
+                    .clicked()
+                {
+                    self.export_all_matches();
+                }
+                egui::ComboBox::from_id_source("export_status")
+                    .selected_text(
+                        self.export_status
+                            .map_or("Any status".to_string(), |status| status.to_string()),
+                    )
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.export_status, None, "Any status");
+                        for status in MatchStatus::ALL {
+                            ui.selectable_value(
+                                &mut self.export_status,
+                                Some(status),
+                                status.to_string(),
+                            );
+                        }
+                    })
+                    .response
+                    .on_hover_text("Export only matches with this review status");
+
+                if ui
+                    .add_enabled(
+                        self.state == AppState::Idle && self.file_count > 0,
+                        egui::Button::new("🩺 Validate Cache"),
+                    )
+                    .on_hover_text("Check that a sample of the cached files still exist")
+                    .clicked()
+                {
+                    self.validate_cache();
+                }
+
+                if ui
+                    .add_enabled(
+                        self.state == AppState::Idle && self.file_count > 0,
+                        egui::Button::new("🧹 Verify Cache"),
+                    )
+                    .on_hover_text("Check every cached file and list the ones that are gone")
+                    .clicked()
+                {
+                    self.verify_cache();
                 }
 
                 if ui
@@ -864,38 +4348,93 @@ impl eframe::App for TiffLocatorApp {
                     )
                     .clicked()
                 {
-                    self.clear_cache();
+                    self.open_clear_dialog();
                 }
             });
+            self.stale_files_panel(ui);
 
             ui.add_space(10.0);
             ui.separator();
             ui.add_space(10.0);
 
-            // Search section
-            ui.heading("🔎 Search for Household ID");
+            // Search section: needs scanned files, never reference IDs
+            ui.heading("🔎 Quick Search (no import needed)");
+            if self.file_count == 0 {
+                ui.label("Scan a folder first; searching only needs the scanned files.");
+            }
             ui.add_space(5.0);
 
             ui.horizontal(|ui| {
                 ui.label("Household ID:");
-                ui.text_edit_singleline(&mut self.search_input);
+                let input = ui
+                    .text_edit_singleline(&mut self.search_input)
+                    .on_hover_text(
+                        "Terms after the ID narrow the file names: HH001 -draft leaves out \
+                         names containing \"draft\", HH001 +back keeps only names containing \"back\"",
+                    );
+                let entered =
+                    input.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter));
 
                 let can_search = self.state == AppState::Idle
                     && !self.search_input.trim().is_empty()
                     && self.db.is_some();
                 if ui
                     .add_enabled(can_search, egui::Button::new("🔍 Search"))
+                    .on_hover_text("Or press Enter in the ID box")
                     .clicked()
+                    || (entered && can_search)
                 {
                     self.search_household_id();
                 }
+                ui.add_enabled(
+                    self.has_stored_matches(),
+                    egui::Checkbox::new(&mut self.id_prefix_search, "ID prefix"),
+                )
+                .on_hover_text(
+                    "Treat the input as the start of household IDs (e.g. HH00) and list the \
+                     stored matches of every such ID, grouped by ID. Reads what Match IDs \
+                     stored; nothing is re-scored",
+                );
+                ui.label("Equal scores by:");
+                egui::ComboBox::from_id_source("result_order")
+                    .selected_text(self.result_order.to_string())
+                    .show_ui(ui, |ui| {
+                        for order in ResultOrder::ALL {
+                            ui.selectable_value(&mut self.result_order, order, order.to_string());
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        "Order of matches with the same score, so repeated searches and \
+                         their exports list files the same way. Applies from the next search",
+                    );
             });
+            self.search_history_line(ui);
+
+            self.prefix_matches_view(ui);
+
+            if self.has_stored_matches() {
+                egui::CollapsingHeader::new("🗂 Browse stored matches")
+                    .id_source("stored_matches")
+                    .show(ui, |ui| self.stored_matches_browser(ui));
+            }
+            egui::CollapsingHeader::new(format!("⛔ Blocked files ({})", self.blocklist.len()))
+                .id_source("blocklist")
+                .show(ui, |ui| self.blocklist_panel(ui));
 
             ui.add_space(10.0);
 
             // Progress bar
             if self.state != AppState::Idle {
-                ui.label(&self.progress_text);
+                ui.horizontal(|ui| {
+                    ui.label(&self.progress_text);
+                    if self.state == AppState::Matching {
+                        ui.label(format!(
+                            "· {} matches so far",
+                            group_thousands(self.matches_found)
+                        ));
+                    }
+                });
                 ui.add(egui::ProgressBar::new(self.progress as f32).show_percentage());
                 ui.add_space(5.0);
             }
@@ -913,15 +4452,132 @@ impl eframe::App for TiffLocatorApp {
             ui.add_space(10.0);
 
             // Search results table with pagination
-            if !self.search_results.is_empty() {
-                let total_results = self.search_results.len();
-                let start_idx = self.results_page * self.results_per_page;
-                let end_idx = (start_idx + self.results_per_page).min(total_results);
-                let total_pages = total_results.div_ceil(self.results_per_page);
+            if self.has_search_results() {
+                // Similarity band filter - display only, the full result set is kept
+                ui.horizontal(|ui| {
+                    ui.label("Show similarity from");
+                    let min_changed = ui
+                        .add(egui::Slider::new(
+                            &mut self.filter_min_similarity,
+                            0.0..=1.0,
+                        ))
+                        .changed();
+                    ui.label("to");
+                    let max_changed = ui
+                        .add(egui::Slider::new(
+                            &mut self.filter_max_similarity,
+                            0.0..=1.0,
+                        ))
+                        .changed();
+                    if min_changed && self.filter_min_similarity > self.filter_max_similarity {
+                        self.filter_max_similarity = self.filter_min_similarity;
+                    }
+                    if max_changed && self.filter_max_similarity < self.filter_min_similarity {
+                        self.filter_min_similarity = self.filter_max_similarity;
+                    }
+                    if min_changed || max_changed {
+                        self.results_page = 0;
+                    }
+                    if ui.button("Reset").clicked() {
+                        self.filter_min_similarity = 0.0;
+                        self.filter_max_similarity = 1.0;
+                        self.results_page = 0;
+                    }
+                    ui.separator();
+                    if ui
+                        .checkbox(&mut self.show_thumbnails, "Show thumbnails")
+                        .changed()
+                        && !self.show_thumbnails
+                    {
+                        self.thumbnails.clear();
+                    }
+                    if ui
+                        .checkbox(&mut self.collapse_duplicates, "Collapse duplicate names")
+                        .on_hover_text("Show one row per file name found at several paths")
+                        .changed()
+                    {
+                        self.results_page = 0;
+                    }
+                    if !self.excluded_paths.is_empty() {
+                        ui.separator();
+                        ui.label(format!("🚫 {} excluded", self.excluded_paths.len()));
+                        if ui
+                            .small_button("Clear")
+                            .on_hover_text("Show excluded files again")
+                            .clicked()
+                        {
+                            self.excluded_paths.clear();
+                        }
+                    }
+                });
+
+                // Cached searches read the band and page from the database, so
+                // their rows are already filtered and duplicates are only
+                // grouped within the page
+                self.load_cached_page();
+                let paged = self.cached_search.is_some();
+                let filtered: Vec<&SearchResult> = self
+                    .search_results
+                    .iter()
+                    .filter(|result| {
+                        paged
+                            || Self::in_similarity_range(
+                                result,
+                                self.filter_min_similarity,
+                                self.filter_max_similarity,
+                            )
+                    })
+                    .filter(|result| !self.excluded_paths.contains(&result.file_path))
+                    .collect();
+                let rows: Vec<Vec<&SearchResult>> = if self.collapse_duplicates {
+                    group_by_file_name(&filtered)
+                } else {
+                    filtered.iter().map(|result| vec![*result]).collect()
+                };
+                let (total_results, all_results, total_rows) = match &self.cached_search {
+                    Some(cached) => (cached.in_band, cached.total, cached.in_band),
+                    None => (filtered.len(), self.search_results.len(), rows.len()),
+                };
+                let per_page = rows_per_page(self.results_per_page, total_rows);
+                let total_pages = total_rows.div_ceil(per_page).max(1);
+                self.results_page = self.results_page.min(total_pages - 1);
+                let first_shown = self.results_page * per_page;
+                let (start_idx, end_idx, last_shown) = if paged {
+                    (0, rows.len(), first_shown + filtered.len())
+                } else {
+                    let end_idx = (first_shown + per_page).min(total_rows);
+                    (first_shown, end_idx, end_idx)
+                };
 
-                ui.heading(format!("Search Results ({} matches)", total_results));
+                let collapsed = if paged && rows.len() < filtered.len() {
+                    ", duplicates grouped per page".to_string()
+                } else if !paged && total_rows < total_results {
+                    format!(", {} file names", total_rows)
+                } else {
+                    String::new()
+                };
+                if total_results == all_results {
+                    ui.heading(format!(
+                        "Search Results ({} matches{})",
+                        total_results, collapsed
+                    ));
+                } else {
+                    ui.heading(format!(
+                        "Search Results ({} of {} matches in range{})",
+                        total_results, all_results, collapsed
+                    ));
+                }
 
                 // Pagination controls
+                let mut page_size_changed = false;
+                let mut open_all = false;
+                let page_paths: Vec<String> = rows[start_idx..end_idx]
+                    .iter()
+                    .flatten()
+                    .map(|result| {
+                        opener::resolve_stored_path(&result.file_path, result.scan_root.as_deref())
+                    })
+                    .collect();
                 ui.horizontal(|ui| {
                     ui.label(format!("Page {} of {}", self.results_page + 1, total_pages));
 
@@ -930,6 +4586,7 @@ impl eframe::App for TiffLocatorApp {
                         .clicked()
                     {
                         self.results_page = self.results_page.saturating_sub(1);
+                        self.selected_result_index = None;
                     }
 
                     if ui
@@ -940,60 +4597,530 @@ impl eframe::App for TiffLocatorApp {
                         .clicked()
                     {
                         self.results_page += 1;
+                        self.selected_result_index = None;
                     }
 
-                    ui.label(format!(
-                        "Showing {}-{} of {}",
-                        start_idx + 1,
-                        end_idx,
-                        total_results
-                    ));
+                    if total_rows > 0 {
+                        ui.label(format!(
+                            "Showing {}-{} of {}",
+                            first_shown + 1,
+                            last_shown,
+                            total_rows
+                        ));
+                    } else {
+                        ui.label("No results in the selected similarity range");
+                    }
+
+                    ui.separator();
+                    ui.label("Per page:");
+                    let previous = self.results_per_page;
+                    egui::ComboBox::from_id_source("results_per_page")
+                        .selected_text(page_size_label(self.results_per_page))
+                        .show_ui(ui, |ui| {
+                            for size in PAGE_SIZES {
+                                ui.selectable_value(
+                                    &mut self.results_per_page,
+                                    size,
+                                    page_size_label(size),
+                                );
+                            }
+                        });
+                    if self.results_per_page != previous {
+                        self.results_page =
+                            page_after_resize(first_shown, self.results_per_page, total_rows);
+                        page_size_changed = true;
+                    }
+
+                    ui.separator();
+                    open_all = ui
+                        .add_enabled(
+                            !page_paths.is_empty() && !self.open_locations_pending,
+                            egui::Button::new("📂 Open all on this page"),
+                        )
+                        .on_hover_text(format!(
+                            "Open the folder of every result on this page, each folder once \
+                             (at most {})",
+                            opener::MAX_OPEN_LOCATIONS
+                        ))
+                        .clicked();
+                    ui.weak(
+                        "↑/↓ select · Enter opens location · Space opens file · \
+                         click a name for why it matched",
+                    );
                 });
 
                 ui.add_space(5.0);
 
+                let mut wanted_thumbnails = Vec::new();
+                let mut visible_thumbnails = HashSet::new();
+                let mut toggled_group = None;
+                let mut action_outcome = None;
+                let mut clicked_row = None;
+
+                let page_rows = &rows[start_idx..end_idx];
+                self.selected_result_index = match result_key {
+                    Some(key) => key.select(self.selected_result_index, page_rows.len()),
+                    None => self
+                        .selected_result_index
+                        .filter(|&index| index < page_rows.len()),
+                };
+                let selected = self.selected_result_index.map(|index| page_rows[index][0]);
+                match (result_key, selected) {
+                    (Some(ResultKey::OpenLocation), Some(result)) => {
+                        action_outcome = Some(RowAction::Done(open_location(result)));
+                    }
+                    (Some(ResultKey::OpenFile), Some(result)) => {
+                        action_outcome = Some(RowAction::Done(open_result_file(result)));
+                    }
+                    _ => {}
+                }
+                let scroll_to_selected =
+                    matches!(result_key, Some(ResultKey::Up | ResultKey::Down));
+                // Grid row of the selection, past the header and the
+                // locations of expanded groups above it
+                let selected_row = self.selected_result_index.map(|index| {
+                    1 + page_rows[..index]
+                        .iter()
+                        .map(|group| {
+                            if group.len() > 1 && self.expanded_groups.contains(&group[0].file_name)
+                            {
+                                1 + group.len()
+                            } else {
+                                1
+                            }
+                        })
+                        .sum::<usize>()
+                });
+                let searched_id = self
+                    .last_search
+                    .as_ref()
+                    .map(SearchRequest::household_id)
+                    .unwrap_or_default();
                 egui::ScrollArea::vertical()
                     .max_height(400.0)
                     .show(ui, |ui| {
                         egui::Grid::new("results_grid")
-                            .striped(true)
+                            .with_row_color(move |row, style| {
+                                if Some(row) == selected_row {
+                                    Some(style.visuals.selection.bg_fill.gamma_multiply(0.5))
+                                } else {
+                                    (row % 2 == 1).then_some(style.visuals.faint_bg_color)
+                                }
+                            })
                             .spacing([10.0, 4.0])
                             .show(ui, |ui| {
                                 // Headers
+                                if self.show_thumbnails {
+                                    ui.label(egui::RichText::new("Preview").strong());
+                                }
                                 ui.label(egui::RichText::new("File Name").strong());
                                 ui.label(egui::RichText::new("Similarity").strong());
+                                ui.label(egui::RichText::new("Threshold").strong());
+                                ui.label(egui::RichText::new("Matched via").strong())
+                                    .on_hover_text(
+                                        "Which part of the file name (or folder) scored",
+                                    );
                                 ui.label(egui::RichText::new("Action").strong());
                                 ui.end_row();
 
                                 // Data rows - only render current page (NO CLONE!)
-                                for result in &self.search_results[start_idx..end_idx] {
-                                    ui.label(&result.file_name);
-                                    ui.label(format!("{:.1}%", result.similarity_score * 100.0));
-
-                                    let file_path = result.file_path.clone();
-                                    if ui.button("📂 Open Location").clicked() {
-                                        match opener::open_file_location(&file_path) {
-                                            Ok(_) => {
-                                                self.status_message = format!(
-                                                    "Opened file location for {}",
-                                                    result.file_name
-                                                );
-                                                self.error_message.clear();
-                                            }
-                                            Err(e) => {
-                                                error!("Failed to open location: {}", e);
-                                                self.error_message =
-                                                    format!("Failed to open location: {}", e);
-                                            }
+                                for (index, group) in page_rows.iter().enumerate() {
+                                    let result = group[0];
+                                    if self.show_thumbnails {
+                                        let cell = thumbnail_cell(
+                                            ui,
+                                            self.thumbnails.get(&result.file_path),
+                                        );
+                                        if cell.is_some() {
+                                            visible_thumbnails.insert(result.file_path.as_str());
+                                        }
+                                        if cell == Some(true) {
+                                            wanted_thumbnails.push(result.file_path.clone());
                                         }
                                     }
+
+                                    let expanded = group.len() > 1
+                                        && self.expanded_groups.contains(&result.file_name);
+                                    let name = if group.len() > 1 {
+                                        ui.horizontal(|ui| {
+                                            let name = row_name(ui, &result.file_name);
+                                            let arrow = if expanded { "▼" } else { "▶" };
+                                            if ui
+                                                .small_button(format!(
+                                                    "{} ({} locations)",
+                                                    arrow,
+                                                    group.len()
+                                                ))
+                                                .on_hover_text("Show every path for this name")
+                                                .clicked()
+                                            {
+                                                toggled_group = Some(result.file_name.clone());
+                                            }
+                                            name
+                                        })
+                                        .inner
+                                    } else if self.ambiguous_names.contains(&result.file_name) {
+                                        row_name(ui, name_with_folder(result))
+                                            .on_hover_text(&result.file_path)
+                                    } else {
+                                        row_name(ui, &result.file_name)
+                                    };
+                                    if name.clicked() {
+                                        clicked_row = Some((index, result.clone()));
+                                    }
+                                    if scroll_to_selected
+                                        && self.selected_result_index == Some(index)
+                                    {
+                                        name.scroll_to_me(None);
+                                    }
+                                    if let Some(action) = result_menu(&name, result, &searched_id)
+                                    {
+                                        action_outcome = Some(action);
+                                    }
+                                    if let Some(action) = result_cells(ui, result, &searched_id)
+                                    {
+                                        action_outcome = Some(action);
+                                    }
                                     ui.end_row();
+
+                                    if !expanded {
+                                        continue;
+                                    }
+                                    for location in group {
+                                        if self.show_thumbnails {
+                                            ui.label("");
+                                        }
+                                        let name =
+                                            row_name(ui, format!("    ↳ {}", location.file_path));
+                                        if name.clicked() {
+                                            clicked_row = Some((index, (*location).clone()));
+                                        }
+                                        if let Some(action) =
+                                            result_menu(&name, location, &searched_id)
+                                        {
+                                            action_outcome = Some(action);
+                                        }
+                                        if let Some(action) =
+                                            result_cells(ui, location, &searched_id)
+                                        {
+                                            action_outcome = Some(action);
+                                        }
+                                        ui.end_row();
+                                    }
                                 }
                             });
                     });
+
+                if open_all {
+                    action_outcome = Some(RowAction::OpenLocations(page_paths));
+                }
+                if let Some(name) = toggled_group {
+                    if !self.expanded_groups.remove(&name) {
+                        self.expanded_groups.insert(name);
+                    }
+                }
+
+                // Keep the cache to roughly one page so memory stays bounded
+                if self.thumbnails.len() > per_page {
+                    let page_paths: HashSet<&str> = rows[start_idx..end_idx]
+                        .iter()
+                        .map(|group| group[0].file_path.as_str())
+                        .collect();
+                    self.thumbnails
+                        .retain(|path, _| page_paths.contains(path.as_str()));
+                }
+                // A long page is bounded too, by what is on screen right now
+                if self.thumbnails.len() > THUMBNAIL_CACHE_LIMIT {
+                    self.thumbnails
+                        .retain(|path, _| visible_thumbnails.contains(path.as_str()));
+                }
+                if !wanted_thumbnails.is_empty() {
+                    self.request_thumbnails(wanted_thumbnails);
+                }
+                // After the rows are done with, since excluding changes them
+                if let Some(action) = action_outcome {
+                    self.apply_row_action(action);
+                }
+                if let Some((index, result)) = clicked_row {
+                    self.selected_result_index = Some(index);
+                    self.show_match_detail(result);
+                }
+                // Navigation moved to a page that isn't loaded yet
+                if self.cached_page_stale() {
+                    ui.ctx().request_repaint();
+                }
+                if page_size_changed {
+                    self.save_page_size();
+                }
             } else {
                 ui.label("Enter a household ID and click Search to find matching TIFF files.");
             }
         });
+
+        self.clear_dialog_window(ctx);
+        self.match_detail_window(ctx);
+
+        // After the panels, so messages set by this frame's clicks are caught
+        if self.log_message_changes() {
+            ctx.request_repaint();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(file_name: &str, file_path: &str, score: f64) -> SearchResult {
+        SearchResult {
+            file_name: file_name.to_string(),
+            file_path: file_path.to_string(),
+            similarity_score: score,
+            threshold: Some(0.5),
+            engine: Some("cpu".to_string()),
+            display_name: None,
+            scan_root: None,
+            matched_via: None,
+            status: MatchStatus::Pending,
+            explanation: None,
+        }
+    }
+
+    #[test]
+    fn duplicate_file_names_are_grouped_across_paths() {
+        let results = [
+            result("HH001.tif", "/a/HH001.tif", 0.9),
+            result("HH001_b.tif", "/a/HH001_b.tif", 0.8),
+            result("HH001.tif", "/b/HH001.tif", 0.9),
+            result("HH001.tif", "/c/2020/HH001.tif", 0.9),
+        ];
+        let refs: Vec<&SearchResult> = results.iter().collect();
+
+        let groups = group_by_file_name(&refs);
+        assert_eq!(groups.len(), 2);
+        let paths: Vec<&str> = groups[0].iter().map(|r| r.file_path.as_str()).collect();
+        assert_eq!(paths, ["/a/HH001.tif", "/b/HH001.tif", "/c/2020/HH001.tif"]);
+        assert_eq!(groups[1].len(), 1);
+        assert_eq!(groups[1][0].file_name, "HH001_b.tif");
+    }
+
+    #[test]
+    fn counts_are_grouped_in_thousands() {
+        assert_eq!(group_thousands(0), "0");
+        assert_eq!(group_thousands(999), "999");
+        assert_eq!(group_thousands(1234), "1,234");
+        assert_eq!(group_thousands(1234567), "1,234,567");
+    }
+
+    #[test]
+    fn archive_summary_lists_extensions_and_sizes() {
+        let summary = ScanSummary {
+            discovered: 15,
+            pruned: 0,
+            added: 15,
+            changed: 0,
+            skipped: 0,
+            total_files: 15,
+            by_extension: [("tiff".to_string(), 6), ("tif".to_string(), 9)].into(),
+            total_bytes: 3 * 1024 * 1024,
+            by_size: [14, 1, 0, 0],
+            empty_folders: Vec::new(),
+        };
+        assert_eq!(
+            archive_summary(&summary),
+            "9 .tif, 6 .tiff · 3.0 MB (under 1 MB: 14, 1-10 MB: 1)"
+        );
+    }
+
+    #[test]
+    fn sizes_use_the_largest_whole_unit() {
+        assert_eq!(format_size(512), "512 bytes");
+        assert_eq!(format_size(2048), "2.0 KB");
+        assert_eq!(format_size(5 * 1024 * 1024 + 300 * 1024), "5.3 MB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0 GB");
+    }
+
+    #[test]
+    fn clear_summary_lists_what_goes() {
+        let mut dialog = ClearDialog {
+            selection: ClearSelection {
+                files: true,
+                ..Default::default()
+            },
+            files: 1500,
+            matches: 20,
+            vectors: 1500,
+            reference_ids: 8,
+        };
+        assert_eq!(
+            clear_summary(&dialog),
+            "Deletes 1,500 files, 20 matches and 1,500 vectors. This can't be undone."
+        );
+        dialog.selection = ClearSelection {
+            reference_ids: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            clear_summary(&dialog),
+            "Deletes 8 reference IDs. This can't be undone."
+        );
+        dialog.selection = ClearSelection::default();
+        assert_eq!(clear_summary(&dialog), "Nothing selected");
+        assert_eq!(
+            cleared_parts(ClearSelection {
+                matches: true,
+                reference_ids: true,
+                ..Default::default()
+            }),
+            "matches and reference IDs"
+        );
+    }
+
+    fn request(hh_id: &str, min_similarity: f64) -> SearchRequest {
+        SearchRequest {
+            hh_id: hh_id.to_string(),
+            min_similarity,
+            max_similarity: 1.0,
+            include_path_components: false,
+            case_sensitive: false,
+            page_size: None,
+            order: ResultOrder::FileName,
+        }
+    }
+
+    fn outcome(total: usize) -> SearchOutcome {
+        SearchOutcome {
+            results: Vec::new(),
+            total,
+            from_cache: false,
+            cache_error: None,
+        }
+    }
+
+    #[test]
+    fn recent_searches_keep_the_most_recently_used() {
+        let mut recent = RecentSearches::new(2);
+        recent.insert(request("HH001", 0.7), outcome(1));
+        recent.insert(request("HH002", 0.7), outcome(2));
+        // Another threshold is another search
+        assert!(recent.get(&request("HH001", 0.8)).is_none());
+        // Using HH001 makes HH002 the one evicted next
+        assert_eq!(recent.get(&request("HH001", 0.7)).unwrap().total, 1);
+        recent.insert(request("HH003", 0.7), outcome(3));
+        assert!(recent.get(&request("HH002", 0.7)).is_none());
+        assert_eq!(recent.get(&request("HH003", 0.7)).unwrap().total, 3);
+        assert_eq!(recent.get(&request("HH001", 0.7)).unwrap().total, 1);
+
+        recent.set_capacity(0);
+        recent.insert(request("HH001", 0.7), outcome(1));
+        assert!(recent.get(&request("HH001", 0.7)).is_none());
+    }
+
+    #[test]
+    fn replayed_searches_keep_where_they_came_from() {
+        let mut recent = RecentSearches::new(2);
+        recent.insert(request("HH001", 0.7), outcome(1));
+        recent.insert(
+            request("HH002", 0.7),
+            SearchOutcome {
+                from_cache: true,
+                ..outcome(2)
+            },
+        );
+        let from_cache =
+            |recent: &mut RecentSearches, hh_id| match recent.replay(request(hh_id, 0.7)) {
+                Some(BackgroundMessage::SearchComplete { from_cache, .. }) => Some(from_cache),
+                _ => None,
+            };
+        // A freshly scored search was already band-filtered; it must not be
+        // paged like stored matches
+        assert_eq!(from_cache(&mut recent, "HH001"), Some(false));
+        assert_eq!(from_cache(&mut recent, "HH002"), Some(true));
+        assert_eq!(from_cache(&mut recent, "HH003"), None);
+    }
+
+    #[test]
+    fn typed_sizes_accept_units() {
+        assert_eq!(parse_size("  "), Ok(None));
+        assert_eq!(parse_size("1"), Ok(Some(1)));
+        assert_eq!(parse_size("2 kb"), Ok(Some(2048)));
+        assert_eq!(parse_size("1.5MB"), Ok(Some(1536 * 1024)));
+        assert_eq!(parse_size("1 GB"), Ok(Some(1 << 30)));
+        assert!(parse_size("-3").is_err());
+        assert!(parse_size("lots").is_err());
+        assert!(size_range("10 MB", "1 MB").is_err());
+        assert_eq!(size_range("1", ""), Ok((Some(1), None)));
+    }
+
+    #[test]
+    fn all_results_fit_on_one_page() {
+        assert_eq!(rows_per_page(100, 2500), 100);
+        assert_eq!(rows_per_page(0, 2500), 2500);
+        // An empty result set still has one (empty) page
+        assert_eq!(rows_per_page(0, 0), 1);
+        assert_eq!(0usize.div_ceil(rows_per_page(0, 0)).max(1), 1);
+        assert_eq!(2500usize.div_ceil(rows_per_page(0, 2500)), 1);
+        assert_eq!(page_size_label(0), "All");
+        assert_eq!(page_size_label(DEFAULT_PAGE_SIZE), "500");
+        assert!(PAGE_SIZES.contains(&DEFAULT_PAGE_SIZE));
+    }
+
+    #[test]
+    fn resizing_pages_keeps_the_top_row_in_view() {
+        // Row 1500 stays on screen whatever the new size
+        assert_eq!(page_after_resize(1500, 500, 2500), 3);
+        assert_eq!(page_after_resize(1500, 2000, 2500), 0);
+        assert_eq!(page_after_resize(1500, 50, 2500), 30);
+        assert_eq!(page_after_resize(1500, 0, 2500), 0);
+        // Never past the end, even for an empty result set
+        assert_eq!(page_after_resize(2400, 100, 1000), 9);
+        assert_eq!(page_after_resize(0, 100, 0), 0);
+    }
+
+    #[test]
+    fn scores_shade_from_red_at_the_threshold_to_green() {
+        let red = score_color(0.7, 0.7, true);
+        assert_eq!(red, egui::Color32::from_rgb(235, 90, 80));
+        assert_eq!(
+            score_color(0.85, 0.7, true),
+            egui::Color32::from_rgb(230, 200, 70)
+        );
+        assert_eq!(
+            score_color(1.0, 0.7, true),
+            egui::Color32::from_rgb(90, 205, 100)
+        );
+        // Below the threshold (e.g. a banded view) stays red
+        assert_eq!(score_color(0.5, 0.7, true), red);
+        assert_eq!(
+            score_color(1.0, 1.0, false),
+            egui::Color32::from_rgb(30, 135, 50)
+        );
+    }
+
+    #[test]
+    fn arrow_keys_stay_on_the_page() {
+        assert_eq!(ResultKey::Down.select(None, 3), Some(0));
+        assert_eq!(ResultKey::Up.select(None, 3), Some(0));
+        assert_eq!(ResultKey::Down.select(Some(1), 3), Some(2));
+        assert_eq!(ResultKey::Down.select(Some(2), 3), Some(2));
+        assert_eq!(ResultKey::Up.select(Some(0), 3), Some(0));
+        // A page that shrank under the selection
+        assert_eq!(ResultKey::Up.select(Some(7), 3), Some(2));
+        assert_eq!(ResultKey::OpenFile.select(Some(7), 3), None);
+        assert_eq!(ResultKey::OpenLocation.select(Some(1), 3), Some(1));
+        assert_eq!(ResultKey::Down.select(Some(0), 0), None);
+    }
+
+    #[test]
+    fn histogram_buckets_are_labelled_by_range() {
+        assert_eq!(bucket_label(0.0, 0.05), "0–5%");
+        assert_eq!(bucket_label(0.7, 0.75), "70–75%");
+        assert_eq!(bucket_label(0.95, 1.0), "95–100%");
+    }
+
+    #[test]
+    fn ambiguous_names_show_their_folder() {
+        let result = result("HH001.tif", "/c/2020/HH001.tif", 0.9);
+        assert_eq!(name_with_folder(&result), "HH001.tif (/c/2020)");
     }
 }
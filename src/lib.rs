@@ -0,0 +1,12 @@
+//! Scanning, ID import and fuzzy matching of TIFF files by household ID.
+//! The `tiff_locator` binary adds the egui front end and headless CLI on top.
+
+pub mod controller;
+pub mod database;
+mod gpu;
+pub mod match_engine;
+pub mod matcher;
+pub mod reference_loader;
+pub mod scanner;
+pub mod searcher;
+pub mod vectorizer;
@@ -1,21 +1,33 @@
-mod database;
-mod gpu;
+mod cli;
 mod gui;
-mod match_engine;
-mod matcher;
 mod opener;
-mod reference_loader;
-mod scanner;
-mod searcher;
-mod vectorizer;
+mod server;
+mod session_log;
+mod thumbnail;
 
 use eframe::NativeOptions;
 use gui::TiffLocatorApp;
+use session_log::SessionLog;
 
 fn main() -> Result<(), eframe::Error> {
-    let _ = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
-        .format_timestamp_millis()
-        .try_init();
+    let session_log = SessionLog::default();
+    let log_file = session_log::init_logger(session_log.clone());
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match cli::parse(&args) {
+        Ok(Some(invocation)) => {
+            if let Err(e) = cli::run(&invocation) {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    }
 
     let options = NativeOptions {
         viewport: eframe::egui::ViewportBuilder::default()
@@ -28,6 +40,6 @@ fn main() -> Result<(), eframe::Error> {
     eframe::run_native(
         "TiffLocator",
         options,
-        Box::new(|cc| Ok(Box::new(TiffLocatorApp::new(cc)))),
+        Box::new(|cc| Ok(Box::new(TiffLocatorApp::new(cc, session_log, log_file)))),
     )
 }
@@ -1,22 +1,61 @@
-use crate::database::Database;
-use crate::gpu::{GpuTileHandle, SimilarityComputer};
-use crate::matcher::{MatchResult, Matcher, ProgressCallback as MatcherProgressCallback};
-use crate::vectorizer::{Vectorizer, VECTOR_SIZE};
+use crate::database::{Database, FileRecord, ScanGeneration};
+use crate::gpu::{GpuTileHandle, Kernel, PollStrategy, Similarity, SimilarityComputer};
+use crate::matcher::{
+    CandidateKind, MatchCountCallback as MatcherMatchCountCallback, MatchResult, Matcher,
+    ProgressCallback as MatcherProgressCallback, ScoreCombination,
+};
+use crate::vectorizer::{VectorScheme, Vectorizer, VECTOR_SIZE};
 use log::info;
+use rayon::prelude::*;
 use std::collections::hash_map::DefaultHasher;
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use wgpu::Buffer;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MatchEngineKind {
     Cpu,
+    /// The GPU engine's n-gram cosine scoring run on the CPU, so GPU results
+    /// can be reproduced and checked on any machine.
+    CpuCosine,
     Gpu,
 }
 
+impl MatchEngineKind {
+    pub const ALL: [MatchEngineKind; 3] = [
+        MatchEngineKind::Cpu,
+        MatchEngineKind::CpuCosine,
+        MatchEngineKind::Gpu,
+    ];
+
+    /// Label stored alongside each match in the cache.
+    pub fn label(&self) -> &'static str {
+        match self {
+            MatchEngineKind::Cpu => "cpu",
+            MatchEngineKind::CpuCosine => "cpu-cosine",
+            MatchEngineKind::Gpu => "gpu",
+        }
+    }
+}
+
+impl std::fmt::Display for MatchEngineKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MatchEngineKind::Cpu => write!(f, "CPU (fuzzy)"),
+            MatchEngineKind::CpuCosine => write!(f, "CPU (cosine, as GPU)"),
+            MatchEngineKind::Gpu => write!(f, "GPU"),
+        }
+    }
+}
+
 pub type MatchProgressCallback = MatcherProgressCallback;
+pub type MatchCountCallback = MatcherMatchCountCallback;
 
+/// Every engine scores on the same [0, 1] scale, so one `min_similarity`
+/// means the same thing whichever engine runs.
 pub trait MatchEngine: Send {
     fn kind(&self) -> MatchEngineKind;
 
@@ -27,15 +66,62 @@ pub trait MatchEngine: Send {
         min_similarity: f64,
         progress_callback: Option<MatchProgressCallback>,
     ) -> Result<usize, String>;
+
+    /// Takes a user-facing notice raised during the last run, such as a
+    /// mid-run fallback from the GPU to the CPU matcher.
+    fn take_notice(&mut self) -> Option<String> {
+        None
+    }
+
+    /// Whether enclosing folder names count as match candidates. Engines that
+    /// only support file names ignore this.
+    fn set_include_path_components(&mut self, _enabled: bool) {}
+
+    /// How many enclosing folders path matching tries, nearest first.
+    fn set_path_components(&mut self, _depth: usize) {}
+
+    /// Whether IDs and file names are compared without lowercasing. Engines
+    /// that only support case-insensitive matching ignore this.
+    fn set_case_sensitive(&mut self, _enabled: bool) {}
+
+    /// How a file's candidate scores combine into its similarity. Engines
+    /// that only keep the best score ignore this.
+    fn set_score_combination(&mut self, _combination: ScoreCombination) {}
+
+    /// Score without clearing or inserting stored matches; `match_and_store`
+    /// then only returns the count it would have stored.
+    fn set_dry_run(&mut self, enabled: bool);
+
+    /// Only score files added after `since` (see
+    /// `Database::files_since_generation`) and merge their matches into the
+    /// stored ones: matches of older files are kept rather than cleared for
+    /// the IDs being matched. `None` matches every file.
+    fn set_new_files_since(&mut self, since: Option<ScanGeneration>);
+
+    /// Store the run's matches in `output`, e.g. a separate file kept for
+    /// auditing, instead of the working cache, which still provides the files
+    /// and vectors. `None` stores them in the working cache.
+    fn set_output(&mut self, output: Option<Database>);
+
+    /// Report the running number of matches found during `match_and_store`.
+    /// Reports come in batches (per chunk of IDs or per GPU tile), not per match.
+    fn set_match_count_handle(&mut self, handle: MatchCountCallback);
 }
 
 pub fn create_engine(kind: MatchEngineKind) -> Result<Box<dyn MatchEngine>, String> {
     match kind {
         MatchEngineKind::Cpu => Ok(Box::new(CpuMatchEngine::default())),
+        MatchEngineKind::CpuCosine => Ok(Box::new(CpuCosineEngine::default())),
         MatchEngineKind::Gpu => Ok(Box::new(GpuMatchEngine::new()?)),
     }
 }
 
+/// The GPU adapter the GPU engine would run on, for diagnostics; an error
+/// when none is usable.
+pub fn gpu_adapter() -> Result<String, String> {
+    crate::gpu::adapter_description()
+}
+
 fn make_logging_progress_callback(
     activity: &'static str,
     unit_label: &'static str,
@@ -79,17 +165,258 @@ fn make_logging_progress_callback(
     }))
 }
 
+/// Highest score a GPU match may keep unless its query equals one of the file's
+/// candidate strings. Hashed n-grams can collide, so a raw 1.0 alone is not proof.
+const NON_EXACT_MAX_SCORE: f64 = 0.999;
+
+/// Exact-string lookup used to settle scores the vectors cannot: equal strings
+/// snap to 1.0 and hash collisions stay below it. Each file keeps the kind
+/// of the first of its candidates equal to the string.
+#[derive(Default)]
+struct ExactNameIndex {
+    files_by_candidate: HashMap<String, HashMap<i64, CandidateKind>>,
+}
+
+impl ExactNameIndex {
+    fn build(files: &[(i64, String)]) -> Self {
+        let mut files_by_candidate: HashMap<String, HashMap<i64, CandidateKind>> = HashMap::new();
+        for (id, name) in files {
+            for (kind, candidate) in Matcher::filename_candidates(name, false) {
+                files_by_candidate
+                    .entry(candidate)
+                    .or_default()
+                    .entry(*id)
+                    .or_insert(kind);
+            }
+        }
+        Self { files_by_candidate }
+    }
+
+    fn exact_files(&self, hh_id: &str) -> Option<&HashMap<i64, CandidateKind>> {
+        self.files_by_candidate.get(&hh_id.trim().to_lowercase())
+    }
+
+    fn settle_score(raw: f64, exact: bool) -> f64 {
+        if exact {
+            1.0
+        } else {
+            raw.min(NON_EXACT_MAX_SCORE)
+        }
+    }
+
+    /// Matches at or above `min_similarity` from a row-major tile of vector
+    /// `scores`, one row of `files.len()` per ID, with exact names settled.
+    fn collect_matches(
+        &self,
+        hh_ids: &[String],
+        files: &[(i64, String)],
+        scores: &[f32],
+        min_similarity: f64,
+    ) -> Vec<MatchResult> {
+        let mut results = Vec::new();
+        let file_len = files.len();
+        for (qi, hh_id) in hh_ids.iter().enumerate() {
+            let exact_files = self.exact_files(hh_id);
+            for (fi, file) in files.iter().enumerate() {
+                let exact_kind = exact_files.and_then(|ids| ids.get(&file.0)).copied();
+                let score =
+                    Self::settle_score(scores[qi * file_len + fi] as f64, exact_kind.is_some());
+                if score >= min_similarity {
+                    let (candidate_kind, matched_candidate) = match exact_kind {
+                        Some(kind) => (kind, hh_id.trim().to_lowercase()),
+                        None => (CandidateKind::Vector, file.1.clone()),
+                    };
+                    results.push(MatchResult {
+                        hh_id: hh_id.clone(),
+                        file_id: file.0,
+                        similarity: score,
+                        matched_candidate,
+                        candidate_kind,
+                        explanation: None,
+                    });
+                }
+            }
+        }
+        results
+    }
+}
+
+/// Score of one ID and file vector pair as the GPU shader computes it with
+/// the default dot product: the vectors are L2-normalized, so the dot product
+/// is their cosine, clamped to [0, 1].
+fn cosine_score(query: &[f32], file: &[f32]) -> f32 {
+    query
+        .iter()
+        .zip(file)
+        .map(|(a, b)| a * b)
+        .sum::<f32>()
+        .clamp(0.0, 1.0)
+}
+
+/// Files a vector engine scores: those added since `new_files_since`, or
+/// every file not on the blocklist. Only the new-files set may be empty.
+fn vector_engine_files(
+    db: &Database,
+    new_files_since: Option<&ScanGeneration>,
+    engine: MatchEngineKind,
+) -> Result<Vec<FileRecord>, String> {
+    let files = match new_files_since {
+        Some(since) => db.files_since_generation(since),
+        None => db.get_matchable_files(),
+    }
+    .map_err(|e| format!("Failed to load files for the {} matcher: {}", engine, e))?;
+    if files.is_empty() && new_files_since.is_none() {
+        return Err("No files found in database. Please scan a directory first.".to_string());
+    }
+    Ok(files)
+}
+
+/// Database a run stores its matches in: `output` when set, after copying the
+/// matched files into it so its matches resolve without the working cache,
+/// otherwise the working cache `db` itself.
+pub(crate) fn match_output<'a>(
+    db: &'a mut Database,
+    output: Option<&'a mut Database>,
+    files: &[FileRecord],
+    matches: &[MatchResult],
+) -> Result<&'a mut Database, String> {
+    let Some(output) = output else {
+        return Ok(db);
+    };
+    let matched: HashSet<i64> = matches.iter().map(|result| result.file_id).collect();
+    let records: Vec<&FileRecord> = files
+        .iter()
+        .filter(|file| matched.contains(&file.id))
+        .collect();
+    output
+        .copy_files(&records)
+        .map_err(|e| format!("Failed to copy matched files to the output database: {}", e))?;
+    Ok(output)
+}
+
+/// `matches` as rows for `MatchImportSession::insert_matches`.
+pub(crate) fn match_rows(matches: &[MatchResult]) -> Vec<(String, i64, f64, Option<String>)> {
+    matches
+        .iter()
+        .map(|result| {
+            (
+                result.hh_id.clone(),
+                result.file_id,
+                result.similarity,
+                Some(result.matched_via()),
+            )
+        })
+        .collect()
+}
+
+/// Replace the stored matches a run covers with `matches`: those of `hh_ids`,
+/// or only those of `new_files` when just the new files were matched.
+fn store_matches(
+    db: &mut Database,
+    hh_ids: &[String],
+    new_files: Option<&[FileRecord]>,
+    matches: &[MatchResult],
+    min_similarity: f64,
+    engine: MatchEngineKind,
+) -> Result<(), String> {
+    let mut session = db
+        .start_match_import()
+        .map_err(|e| format!("Failed to start match transaction: {}", e))?;
+
+    match new_files {
+        Some(files) => {
+            let file_ids: Vec<i64> = files.iter().map(|file| file.id).collect();
+            session.clear_for_files(&file_ids, min_similarity)
+        }
+        None => session.clear_for_ids(hh_ids, min_similarity),
+    }
+    .map_err(|e| format!("Failed to clear previous matches: {}", e))?;
+
+    session
+        .insert_matches(&match_rows(matches), min_similarity, engine.label())
+        .map_err(|e| format!("Failed to store matches: {}", e))?;
+
+    session
+        .commit()
+        .map_err(|e| format!("Failed to commit matches: {}", e))
+}
+
+/// Above this many files the GPU file buffer is filled from the vector cache
+/// in slices instead of from vectors held in memory, unless
+/// `TIFF_GPU_STREAM_FILES` sets another count.
+const DEFAULT_STREAM_THRESHOLD: usize = 250_000;
+
+/// Files read, encoded and uploaded per slice when streaming: 16 MiB of
+/// vectors.
+const STREAM_SLICE_FILES: usize = 8192;
+
+/// Most tiles the adapter-derived default keeps in flight, however much
+/// memory the adapter reports; `TIFF_GPU_INFLIGHT` may set more.
+const MAX_DEFAULT_INFLIGHT: usize = 32;
+
+/// Share of `max_storage_bytes` that in-flight tiles may hold together unless
+/// `TIFF_GPU_INFLIGHT_FRACTION` says otherwise.
+const DEFAULT_INFLIGHT_FRACTION: f64 = 0.5;
+
+/// Tiles to keep in flight when each writes `tile_output_bytes` of scores: as
+/// many as fit in `fraction` of `max_storage_bytes`, counting each tile's
+/// output and staging buffers, clamped to `1..=ceiling`.
+fn inflight_limit_for(
+    max_storage_bytes: u64,
+    tile_output_bytes: u64,
+    fraction: f64,
+    ceiling: usize,
+) -> usize {
+    let budget = (max_storage_bytes as f64 * fraction) as u64;
+    let per_tile = tile_output_bytes.max(1).saturating_mul(2);
+    let fits = usize::try_from(budget / per_tile).unwrap_or(usize::MAX);
+    fits.clamp(1, ceiling.max(1))
+}
+
+/// In-flight ceiling used unless `TIFF_GPU_INFLIGHT` sets one: as many
+/// full-size tiles (`tile_output_bytes` each) as fit in `fraction` of the
+/// adapter's largest buffer, so a big discrete GPU keeps many more tiles
+/// busy than a small integrated one. Each chunk's `inflight_limit_for` can
+/// still allow fewer.
+fn default_inflight_ceiling(max_buffer_bytes: u64, tile_output_bytes: u64, fraction: f64) -> usize {
+    inflight_limit_for(
+        max_buffer_bytes,
+        tile_output_bytes,
+        fraction,
+        MAX_DEFAULT_INFLIGHT,
+    )
+}
+
+fn env_fraction(key: &str, default: f64) -> f64 {
+    match std::env::var(key) {
+        Err(_) => default,
+        Ok(value) => match value.parse::<f64>() {
+            Ok(fraction) if fraction > 0.0 && fraction <= 1.0 => fraction,
+            _ => {
+                log::warn!("Ignoring invalid {} '{}': expected 0-1", key, value);
+                default
+            }
+        },
+    }
+}
+
 fn env_chunk(key: &str, default: usize) -> usize {
+    env_count(key).unwrap_or(default)
+}
+
+/// Positive count set by `key`, if any.
+fn env_count(key: &str) -> Option<usize> {
     std::env::var(key)
         .ok()
         .and_then(|value| value.parse::<usize>().ok())
         .filter(|value| *value > 0)
-        .unwrap_or(default)
 }
 
 #[derive(Default)]
 struct CpuMatchEngine {
     matcher: Matcher,
+    dry_run: bool,
+    output: Option<Database>,
 }
 
 impl MatchEngine for CpuMatchEngine {
@@ -97,6 +424,38 @@ impl MatchEngine for CpuMatchEngine {
         MatchEngineKind::Cpu
     }
 
+    fn set_include_path_components(&mut self, enabled: bool) {
+        self.matcher.set_include_path_components(enabled);
+    }
+
+    fn set_path_components(&mut self, depth: usize) {
+        self.matcher.set_path_components(depth);
+    }
+
+    fn set_case_sensitive(&mut self, enabled: bool) {
+        self.matcher.set_case_sensitive(enabled);
+    }
+
+    fn set_score_combination(&mut self, combination: ScoreCombination) {
+        self.matcher.set_score_combination(combination);
+    }
+
+    fn set_dry_run(&mut self, enabled: bool) {
+        self.dry_run = enabled;
+    }
+
+    fn set_new_files_since(&mut self, since: Option<ScanGeneration>) {
+        self.matcher.set_new_files_since(since);
+    }
+
+    fn set_output(&mut self, output: Option<Database>) {
+        self.output = output;
+    }
+
+    fn set_match_count_handle(&mut self, handle: MatchCountCallback) {
+        self.matcher.set_match_count_handle(handle);
+    }
+
     fn match_and_store(
         &mut self,
         hh_ids: &[String],
@@ -134,7 +493,20 @@ impl MatchEngine for CpuMatchEngine {
             total_ids
         );
 
-        let result = self.matcher.match_and_store(hh_ids, db, min_similarity);
+        if self.dry_run {
+            let result = self.matcher.estimate_matches(hh_ids, db, min_similarity);
+            if let Ok(matches) = result {
+                info!(
+                    "CPU dry run finished: {} matches for {} household IDs (not stored)",
+                    matches, total_ids
+                );
+            }
+            return result;
+        }
+
+        let result = self
+            .matcher
+            .match_and_store(hh_ids, db, self.output.as_mut(), min_similarity);
 
         if let Ok(matches) = result {
             info!(
@@ -147,84 +519,352 @@ impl MatchEngine for CpuMatchEngine {
     }
 }
 
+/// IDs the CPU cosine engine scores per step; each step holds one row of
+/// scores per ID for every file.
+const COSINE_QUERY_CHUNK: usize = 8;
+
+/// Scores every pair the way the GPU engine does, on the CPU: the same
+/// vectors, dot product, clamping and exact-name settling, so its results
+/// equal the GPU's within float rounding. File names only, case-insensitive.
+#[derive(Default)]
+struct CpuCosineEngine {
+    vectorizer: Vectorizer,
+    dry_run: bool,
+    new_files_since: Option<ScanGeneration>,
+    match_count: Option<MatchCountCallback>,
+    output: Option<Database>,
+}
+
+impl CpuCosineEngine {
+    fn score(
+        &self,
+        hh_ids: &[String],
+        files: &[(i64, String)],
+        min_similarity: f64,
+        progress: Option<&MatchProgressCallback>,
+    ) -> Vec<MatchResult> {
+        let exact_names = ExactNameIndex::build(files);
+        let names: Vec<&str> = files.iter().map(|(_, name)| name.as_str()).collect();
+        let file_vectors = self.vectorizer.encode_batch(&names);
+        // Borrowed apart from `self`, whose output database can't be shared
+        let (vectorizer, match_count) = (&self.vectorizer, &self.match_count);
+        let total = hh_ids.len();
+        let processed = AtomicUsize::new(0);
+        let found = AtomicUsize::new(0);
+
+        hh_ids
+            .par_chunks(COSINE_QUERY_CHUNK)
+            .flat_map_iter(|chunk| {
+                let queries = vectorizer.encode_batch(chunk);
+                let mut scores = Vec::with_capacity(chunk.len() * files.len());
+                for query in queries.chunks(VECTOR_SIZE) {
+                    scores.extend(
+                        file_vectors
+                            .chunks(VECTOR_SIZE)
+                            .map(|file| cosine_score(query, file)),
+                    );
+                }
+                let matches = exact_names.collect_matches(chunk, files, &scores, min_similarity);
+
+                let completed = processed.fetch_add(chunk.len(), Ordering::Relaxed) + chunk.len();
+                if let Some(callback) = match_count {
+                    // Counted under the lock so reports never go backwards
+                    if !matches.is_empty() {
+                        if let Ok(mut cb) = callback.lock() {
+                            cb(found.fetch_add(matches.len(), Ordering::Relaxed) + matches.len());
+                        }
+                    }
+                }
+                if let Some(callback) = progress {
+                    if let Ok(mut cb) = callback.lock() {
+                        cb(completed.min(total), total);
+                    }
+                }
+                matches
+            })
+            .collect()
+    }
+}
+
+impl MatchEngine for CpuCosineEngine {
+    fn kind(&self) -> MatchEngineKind {
+        MatchEngineKind::CpuCosine
+    }
+
+    fn set_dry_run(&mut self, enabled: bool) {
+        self.dry_run = enabled;
+    }
+
+    fn set_new_files_since(&mut self, since: Option<ScanGeneration>) {
+        self.new_files_since = since;
+    }
+
+    fn set_output(&mut self, output: Option<Database>) {
+        self.output = output;
+    }
+
+    fn set_match_count_handle(&mut self, handle: MatchCountCallback) {
+        self.match_count = Some(handle);
+    }
+
+    fn match_and_store(
+        &mut self,
+        hh_ids: &[String],
+        db: &mut Database,
+        min_similarity: f64,
+        progress_callback: Option<MatchProgressCallback>,
+    ) -> Result<usize, String> {
+        let files = vector_engine_files(db, self.new_files_since.as_ref(), self.kind())?;
+        if files.is_empty() {
+            info!("CPU cosine matching skipped: no files were added since the last match run");
+            return Ok(0);
+        }
+        if hh_ids.is_empty() {
+            info!("CPU cosine matching completed immediately: no household IDs provided");
+            return Ok(0);
+        }
+
+        let progress = progress_callback.unwrap_or_else(|| {
+            make_logging_progress_callback("CPU cosine matching", "IDs", hh_ids.len())
+        });
+        if let Ok(mut cb) = progress.lock() {
+            cb(0, hh_ids.len());
+        }
+
+        let file_pairs: Vec<(i64, String)> = files
+            .iter()
+            .map(|record| (record.id, record.file_name.clone()))
+            .collect();
+        info!(
+            "CPU cosine match pass started: {} household IDs across {} files",
+            hh_ids.len(),
+            file_pairs.len()
+        );
+        let matches = self.score(hh_ids, &file_pairs, min_similarity, Some(&progress));
+
+        if self.dry_run {
+            info!(
+                "CPU cosine dry run complete: {} matches for {} household IDs (not stored)",
+                matches.len(),
+                hh_ids.len()
+            );
+            return Ok(matches.len());
+        }
+
+        let engine = self.kind();
+        let new_files = self.new_files_since.as_ref().map(|_| files.as_slice());
+        store_matches(
+            match_output(db, self.output.as_mut(), &files, &matches)?,
+            hh_ids,
+            new_files,
+            &matches,
+            min_similarity,
+            engine,
+        )?;
+        info!(
+            "CPU cosine match pass complete: {} matches stored for {} household IDs",
+            matches.len(),
+            hh_ids.len()
+        );
+        Ok(matches.len())
+    }
+}
+
+/// The file vectors on the GPU: consecutive segments of `segment_len` files,
+/// each small enough for one buffer. `count` and `fingerprint` identify the
+/// files they hold, so an unchanged corpus reuses them.
+#[derive(Clone)]
+struct FileBuffers {
+    segments: Vec<Arc<Buffer>>,
+    segment_len: usize,
+    count: usize,
+    fingerprint: u64,
+}
+
 struct GpuMatchEngine {
     vectorizer: Vectorizer,
     computer: SimilarityComputer,
     chunk_size: usize,
     file_chunk_size: usize,
+    // Ceiling for `inflight_limit_for`, at least 1; the poller is chosen for it
     inflight_limit: usize,
+    inflight_fraction: f64,
+    // Corpora larger than this are streamed instead of cached in `file_vectors`
+    stream_threshold: usize,
+    // Files per GPU file buffer: as many vectors as the adapter's largest
+    // buffer holds
+    segment_files: usize,
     file_vectors: HashMap<i64, Vec<f32>>,
-    file_gpu_buffer: Option<(Arc<Buffer>, usize, u64)>,
+    file_gpu_buffer: Option<FileBuffers>,
+    exact_names: ExactNameIndex,
+    notice: Option<String>,
+    dry_run: bool,
+    new_files_since: Option<ScanGeneration>,
+    match_count: Option<MatchCountCallback>,
+    output: Option<Database>,
 }
 
 impl GpuMatchEngine {
     fn new() -> Result<Self, String> {
         let chunk_size = env_chunk("TIFF_GPU_QUERY_CHUNK", 64);
         let file_chunk_size = env_chunk("TIFF_GPU_FILE_CHUNK", 256);
-        let inflight_limit = env_chunk("TIFF_GPU_INFLIGHT", 2);
+        let inflight_fraction =
+            env_fraction("TIFF_GPU_INFLIGHT_FRACTION", DEFAULT_INFLIGHT_FRACTION);
+        let mut computer = Self::create_computer()?;
+        let inflight_limit = match env_count("TIFF_GPU_INFLIGHT") {
+            Some(limit) => {
+                info!("GPU in-flight tiles: up to {} (TIFF_GPU_INFLIGHT)", limit);
+                limit
+            }
+            None => {
+                let tile_output_bytes = (chunk_size as u64)
+                    .saturating_mul(file_chunk_size as u64)
+                    .saturating_mul(std::mem::size_of::<f32>() as u64)
+                    .min(computer.max_storage_bytes());
+                let limit = default_inflight_ceiling(
+                    computer.max_buffer_bytes(),
+                    tile_output_bytes,
+                    inflight_fraction,
+                );
+                info!(
+                    "GPU in-flight tiles: up to {} ({} byte tiles within {:.0}% of the adapter's {} byte buffer limit)",
+                    limit,
+                    tile_output_bytes,
+                    inflight_fraction * 100.0,
+                    computer.max_buffer_bytes()
+                );
+                limit
+            }
+        };
+        computer.set_poll_strategy(Self::poll_strategy(inflight_limit));
+        computer.set_tile_pool_size(inflight_limit.saturating_add(1));
+        let bytes_per_vector = (VECTOR_SIZE * std::mem::size_of::<f32>()) as u64;
+        let segment_files = usize::try_from(computer.max_buffer_bytes() / bytes_per_vector)
+            .unwrap_or(usize::MAX)
+            .max(1);
         Ok(Self {
             vectorizer: Vectorizer::new(),
-            computer: SimilarityComputer::new()?,
+            computer,
             chunk_size,
             file_chunk_size,
-            inflight_limit: inflight_limit.max(1),
+            inflight_limit,
+            inflight_fraction,
+            stream_threshold: env_count("TIFF_GPU_STREAM_FILES")
+                .unwrap_or(DEFAULT_STREAM_THRESHOLD),
+            segment_files,
             file_vectors: HashMap::new(),
             file_gpu_buffer: None,
+            exact_names: ExactNameIndex::default(),
+            notice: None,
+            dry_run: false,
+            new_files_since: None,
+            match_count: None,
+            output: None,
         })
     }
 
-    fn encode_ids(&self, ids: &[String]) -> Vec<f32> {
-        let mut data = Vec::with_capacity(ids.len() * VECTOR_SIZE);
-        for id in ids {
-            data.extend(self.vectorizer.encode(id));
-        }
-        data
+    /// A warmed-up computer configured from the environment. Its poller is
+    /// left to the caller, since it depends on the in-flight limit.
+    fn create_computer() -> Result<SimilarityComputer, String> {
+        let default_ms = crate::gpu::DEFAULT_MAP_TIMEOUT.as_millis() as usize;
+        let timeout_ms = env_chunk("TIFF_GPU_MAP_TIMEOUT_MS", default_ms);
+        let kernel = std::env::var("TIFF_GPU_KERNEL").ok().and_then(|value| {
+            let kernel = Kernel::parse(&value);
+            if kernel.is_none() {
+                log::warn!("Ignoring invalid TIFF_GPU_KERNEL '{}'", value);
+            }
+            kernel
+        });
+        // The vectorizer normalizes its vectors, so the dot product is enough
+        // unless TIFF_GPU_SIMILARITY=cosine asks for the shader to normalize
+        let similarity = std::env::var("TIFF_GPU_SIMILARITY")
+            .ok()
+            .and_then(|value| {
+                let similarity = Similarity::parse(&value);
+                if similarity.is_none() {
+                    log::warn!("Ignoring invalid TIFF_GPU_SIMILARITY '{}'", value);
+                }
+                similarity
+            })
+            .unwrap_or(Similarity::Dot);
+        let mut computer = SimilarityComputer::with_options(kernel, similarity)?;
+        computer.set_map_timeout(Duration::from_millis(timeout_ms as u64));
+        computer
+            .warm_up()
+            .map_err(|e| format!("GPU warm-up failed: {}", e))?;
+        Ok(computer)
     }
 
-    fn collect_matches(
-        &self,
-        hh_ids: &[String],
-        files: &[(i64, String)],
-        scores: &[f32],
-        min_similarity: f64,
-    ) -> Vec<MatchResult> {
-        let mut results = Vec::new();
-        let file_len = files.len();
-        for (qi, hh_id) in hh_ids.iter().enumerate() {
-            for (fi, file) in files.iter().enumerate() {
-                let score = scores[qi * file_len + fi] as f64;
-                if score >= min_similarity {
-                    results.push(MatchResult {
-                        hh_id: hh_id.clone(),
-                        file_id: file.0,
-                        similarity: score,
-                    });
-                }
+    // TIFF_GPU_POLL_MS sets the poll interval; TIFF_GPU_POLLER=blocking trades
+    // it for a poller that waits on each submission, which only helps when a
+    // single tile is in flight
+    fn poll_strategy(inflight_limit: usize) -> PollStrategy {
+        let default_ms = crate::gpu::DEFAULT_POLL_INTERVAL.as_millis() as usize;
+        let interval = PollStrategy::Interval(Duration::from_millis(env_chunk(
+            "TIFF_GPU_POLL_MS",
+            default_ms,
+        ) as u64));
+        match std::env::var("TIFF_GPU_POLLER").ok().as_deref() {
+            None | Some("interval") => interval,
+            Some("blocking") if inflight_limit == 1 => PollStrategy::Blocking,
+            Some("blocking") => {
+                log::warn!(
+                    "TIFF_GPU_POLLER=blocking needs TIFF_GPU_INFLIGHT=1 ({} tiles in flight); polling {}",
+                    inflight_limit,
+                    interval
+                );
+                interval
+            }
+            Some(other) => {
+                log::warn!("Ignoring invalid TIFF_GPU_POLLER '{}'", other);
+                interval
             }
         }
-        results
     }
 
-    fn prepare_cache(&mut self, files: &[(i64, String)], db: &Database) -> Result<(), String> {
+    fn encode_ids(&self, ids: &[String]) -> Vec<f32> {
+        self.vectorizer.encode_batch(ids)
+    }
+
+    fn prepare_cache(&mut self, files: &[(i64, String)], db: &mut Database) -> Result<(), String> {
         let valid_ids: HashSet<i64> = files.iter().map(|(id, _)| *id).collect();
         self.file_vectors.retain(|id, _| valid_ids.contains(id));
 
+        let scheme = self.vectorizer.scheme();
+        let mut missing = Vec::new();
         for (id, name) in files {
             if self.file_vectors.contains_key(id) {
                 continue;
             }
-            let fingerprint = fingerprint_entry(*id, name);
+            let fingerprint = fingerprint_entry(*id, name, scheme);
             if let Some(cached) = db
-                .get_file_vector(*id, fingerprint)
+                .get_file_vector(*id, fingerprint, scheme.dim)
                 .map_err(|e| format!("Failed to read cached vector: {}", e))?
             {
                 self.file_vectors.insert(*id, cached);
                 continue;
             }
-            let encoded = self.vectorizer.encode(name);
-            db.upsert_file_vector(*id, fingerprint, &encoded)
-                .map_err(|e| format!("Failed to persist vector: {}", e))?;
-            self.file_vectors.insert(*id, encoded);
+            missing.push((*id, name.as_str(), fingerprint));
+        }
+
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        // Encoding is pure CPU work, so fan it out before a single batched write
+        let vectorizer = &self.vectorizer;
+        let encoded: Vec<(i64, u64, Vec<f32>)> = missing
+            .par_iter()
+            .map(|(id, name, fingerprint)| (*id, *fingerprint, vectorizer.encode(name)))
+            .collect();
+
+        db.upsert_file_vectors(&encoded)
+            .map_err(|e| format!("Failed to persist vectors: {}", e))?;
+
+        info!("Encoded and cached {} new file vectors", encoded.len());
+
+        for (id, _, vector) in encoded {
+            self.file_vectors.insert(id, vector);
         }
 
         Ok(())
@@ -250,10 +890,54 @@ impl GpuMatchEngine {
         data
     }
 
+    /// Vectors of one slice of files, read from the vector cache or encoded
+    /// (and cached) when missing, without keeping them in `file_vectors`.
+    fn stream_vectors(
+        &self,
+        files: &[(i64, String)],
+        db: &mut Database,
+    ) -> Result<Vec<f32>, String> {
+        let scheme = self.vectorizer.scheme();
+        let mut data = vec![0.0; files.len() * VECTOR_SIZE];
+        let mut missing = Vec::new();
+        for (index, (id, name)) in files.iter().enumerate() {
+            let fingerprint = fingerprint_entry(*id, name, scheme);
+            match db
+                .get_file_vector(*id, fingerprint, scheme.dim)
+                .map_err(|e| format!("Failed to read cached vector: {}", e))?
+            {
+                Some(cached) => {
+                    data[index * VECTOR_SIZE..(index + 1) * VECTOR_SIZE].copy_from_slice(&cached)
+                }
+                None => missing.push((index, *id, name.as_str(), fingerprint)),
+            }
+        }
+        if missing.is_empty() {
+            return Ok(data);
+        }
+
+        let vectorizer = &self.vectorizer;
+        let encoded: Vec<(i64, u64, Vec<f32>)> = missing
+            .par_iter()
+            .map(|(_, id, name, fingerprint)| (*id, *fingerprint, vectorizer.encode(name)))
+            .collect();
+        db.upsert_file_vectors(&encoded)
+            .map_err(|e| format!("Failed to persist vectors: {}", e))?;
+        for ((index, ..), (_, _, vector)) in missing.iter().zip(&encoded) {
+            data[index * VECTOR_SIZE..(index + 1) * VECTOR_SIZE].copy_from_slice(vector);
+        }
+        Ok(data)
+    }
+
+    /// Upload `files` to the GPU unless the same files already are. Small
+    /// corpora come from `file_vectors` (see `prepare_cache`); larger ones are
+    /// streamed from the vector cache in `STREAM_SLICE_FILES` slices, so host
+    /// memory holds one slice rather than every vector.
     fn ensure_gpu_buffer(
         &mut self,
         files: &[(i64, String)],
-    ) -> Result<(Arc<Buffer>, usize), String> {
+        db: &mut Database,
+    ) -> Result<FileBuffers, String> {
         // Create order-independent fingerprint by sorting files by ID
         let mut sorted_ids: Vec<(i64, &String)> =
             files.iter().map(|(id, name)| (*id, name)).collect();
@@ -267,16 +951,47 @@ impl GpuMatchEngine {
         }
         let fingerprint = hasher.finish();
 
-        if let Some((buffer, count, hash)) = &self.file_gpu_buffer {
-            if *count == files.len() && *hash == fingerprint {
-                return Ok((Arc::clone(buffer), *count));
+        if let Some(buffers) = &self.file_gpu_buffer {
+            if buffers.count == files.len() && buffers.fingerprint == fingerprint {
+                return Ok(buffers.clone());
             }
         }
+        // Free the old buffers before allocating their replacements
+        self.file_gpu_buffer = None;
+
+        let segment_len = self.segment_files;
+        let streaming = files.len() > self.stream_threshold;
+        let mut segments = Vec::new();
+        for segment in files.chunks(segment_len) {
+            let buffer = if streaming {
+                let buffer = self
+                    .computer
+                    .create_empty_file_buffer(segment.len(), VECTOR_SIZE);
+                for (index, slice) in segment.chunks(STREAM_SLICE_FILES).enumerate() {
+                    let vectors = self.stream_vectors(slice, db)?;
+                    self.computer.write_file_vectors(
+                        &buffer,
+                        index * STREAM_SLICE_FILES,
+                        VECTOR_SIZE,
+                        &vectors,
+                    )?;
+                }
+                buffer
+            } else {
+                let vectors = self.gather_cached_vectors(segment);
+                self.computer.create_file_buffer(&vectors)
+            };
+            segments.push(buffer);
+        }
 
-        let vectors = self.gather_cached_vectors(files);
-        let buffer = self.computer.create_file_buffer(&vectors);
-        self.file_gpu_buffer = Some((Arc::clone(&buffer), files.len(), fingerprint));
-        Ok((buffer, files.len()))
+        let buffers = FileBuffers {
+            segments,
+            segment_len,
+            count: files.len(),
+            fingerprint,
+        };
+        self.file_gpu_buffer = Some(buffers.clone());
+        Ok(buffers)
     }
 
     fn file_chunk_size_for(&self, query_count: usize) -> usize {
@@ -300,31 +1015,279 @@ impl GpuMatchEngine {
         base.min(adaptive as usize).max(1)
     }
 
+    /// Query chunk size, capped so a chunk's query vectors fit in one buffer.
+    fn query_chunk_size(&self) -> usize {
+        let bytes_per_query = (VECTOR_SIZE * std::mem::size_of::<f32>()) as u64;
+        let limit = (self.computer.max_storage_bytes() / bytes_per_query).max(1);
+        self.chunk_size.max(1).min(limit as usize)
+    }
+
     fn finish_next_tile(
         &self,
         pending: &mut VecDeque<PendingTile<'_>>,
         all_matches: &mut Vec<MatchResult>,
+        checkpoint: &mut MatchCheckpoint,
         min_similarity: f64,
         tracker: &mut ProgressTracker,
         progress: Option<&MatchProgressCallback>,
     ) -> Result<(), String> {
         if let Some(tile) = pending.pop_front() {
             let scores = tile.handle.wait()?;
-            let matches =
-                self.collect_matches(tile.hh_slice, tile.file_slice, &scores, min_similarity);
-            all_matches.extend(matches);
+            if tile.file_offset == 0 {
+                checkpoint.query_offset = tile.query_offset;
+                checkpoint.match_count = all_matches.len();
+            }
+            let matches = self.exact_names.collect_matches(
+                tile.hh_slice,
+                tile.file_slice,
+                &scores,
+                min_similarity,
+            );
+            if !matches.is_empty() {
+                all_matches.extend(matches);
+                self.report_match_count(all_matches.len());
+            }
             tracker.tile_complete(tile.hh_slice.len(), tile.file_slice.len(), progress);
         }
         Ok(())
     }
+
+    /// Dispatches every tile from `start` onwards. On failure, returns the
+    /// query offset of the first chunk that did not fully complete; matches
+    /// from that chunk are dropped so it can be redone from scratch.
+    #[allow(clippy::too_many_arguments)]
+    fn run_tiles(
+        &mut self,
+        hh_ids: &[String],
+        file_pairs: &[(i64, String)],
+        db: &mut Database,
+        start: usize,
+        all_matches: &mut Vec<MatchResult>,
+        min_similarity: f64,
+        tracker: &mut ProgressTracker,
+        progress: Option<&MatchProgressCallback>,
+    ) -> Result<(), (usize, String)> {
+        let file_buffers = self
+            .ensure_gpu_buffer(file_pairs, db)
+            .map_err(|e| (start, e))?;
+        let chunk_size = self.query_chunk_size();
+        let max_output_bytes = self.computer.max_storage_bytes();
+        let mut pending: VecDeque<PendingTile<'_>> = VecDeque::new();
+        let mut checkpoint = MatchCheckpoint {
+            query_offset: start,
+            match_count: all_matches.len(),
+        };
+
+        let result = (|| {
+            for (chunk_index, chunk) in hh_ids[start..].chunks(chunk_size).enumerate() {
+                if chunk.is_empty() {
+                    continue;
+                }
+                let query_offset = start + chunk_index * chunk_size;
+                // Shared by every file tile of this chunk
+                let query_buffer = self.computer.create_query_buffer(&self.encode_ids(chunk));
+                let chunk_file_size = self.file_chunk_size_for(chunk.len());
+                let tile_output_bytes = (chunk.len() as u64)
+                    .saturating_mul(chunk_file_size as u64)
+                    .saturating_mul(std::mem::size_of::<f32>() as u64)
+                    .min(max_output_bytes);
+                let inflight_limit = inflight_limit_for(
+                    max_output_bytes,
+                    tile_output_bytes,
+                    self.inflight_fraction,
+                    self.inflight_limit,
+                );
+
+                // Tiles never straddle two segments; offsets within a
+                // segment's buffer are relative to its first file
+                let segments = file_buffers
+                    .segments
+                    .iter()
+                    .zip(file_pairs.chunks(file_buffers.segment_len));
+                let tiles = segments.enumerate().flat_map(|(index, (buffer, segment))| {
+                    segment
+                        .chunks(chunk_file_size)
+                        .enumerate()
+                        .flat_map(move |(tile_index, file_chunk)| {
+                            split_tile(
+                                chunk.len(),
+                                tile_index * chunk_file_size,
+                                file_chunk.len(),
+                                max_output_bytes,
+                            )
+                        })
+                        .map(move |(local_offset, file_len)| {
+                            (
+                                buffer,
+                                index * file_buffers.segment_len,
+                                local_offset,
+                                file_len,
+                            )
+                        })
+                });
+                for (file_buffer, segment_start, local_offset, file_len) in tiles {
+                    let file_offset = segment_start + local_offset;
+                    let handle = self
+                        .computer
+                        .dispatch_tile(
+                            &query_buffer,
+                            chunk.len(),
+                            file_buffer,
+                            local_offset,
+                            file_len,
+                            VECTOR_SIZE,
+                        )
+                        .map_err(|e| (resume_offset(&pending, query_offset), e))?;
+
+                    tracker.register_tile(chunk.len(), file_len);
+                    pending.push_back(PendingTile {
+                        query_offset,
+                        file_offset,
+                        hh_slice: chunk,
+                        file_slice: &file_pairs[file_offset..file_offset + file_len],
+                        handle,
+                    });
+
+                    if pending.len() >= inflight_limit {
+                        let failed_at = resume_offset(&pending, query_offset);
+                        self.finish_next_tile(
+                            &mut pending,
+                            all_matches,
+                            &mut checkpoint,
+                            min_similarity,
+                            tracker,
+                            progress,
+                        )
+                        .map_err(|e| (failed_at, e))?;
+                    }
+                }
+            }
+
+            while !pending.is_empty() {
+                let failed_at = resume_offset(&pending, start);
+                self.finish_next_tile(
+                    &mut pending,
+                    all_matches,
+                    &mut checkpoint,
+                    min_similarity,
+                    tracker,
+                    progress,
+                )
+                .map_err(|e| (failed_at, e))?;
+            }
+            Ok(())
+        })();
+
+        result.map_err(|(failed_at, error)| {
+            checkpoint.roll_back(all_matches, failed_at);
+            (failed_at, error)
+        })
+    }
+
+    fn report_match_count(&self, found: usize) {
+        if let Some(callback) = &self.match_count {
+            if let Ok(mut cb) = callback.lock() {
+                cb(found);
+            }
+        }
+    }
+
+    /// Finishes `hh_ids[start..]` on the CPU after the GPU could not be recovered.
+    /// `found` is the number of GPU matches kept, so the running count continues.
+    #[allow(clippy::too_many_arguments)]
+    fn finish_on_cpu(
+        &self,
+        hh_ids: &[String],
+        files: &[FileRecord],
+        start: usize,
+        found: usize,
+        min_similarity: f64,
+        progress: Option<&MatchProgressCallback>,
+    ) -> Vec<MatchResult> {
+        let mut matcher = Matcher::new();
+        if let Some(callback) = &self.match_count {
+            let callback = Arc::clone(callback);
+            matcher.set_match_count_handle(Arc::new(Mutex::new(move |so_far: usize| {
+                if let Ok(mut cb) = callback.lock() {
+                    cb(found + so_far);
+                }
+            })));
+        }
+        if let Some(callback) = progress {
+            let callback = Arc::clone(callback);
+            let total = hh_ids.len();
+            matcher.set_progress_handle(Arc::new(Mutex::new(move |done: usize, _| {
+                if let Ok(mut cb) = callback.lock() {
+                    cb((start + done).min(total), total);
+                }
+            })));
+        }
+        matcher.match_ids(&hh_ids[start..], files, min_similarity)
+    }
+}
+
+/// Splits a tile of `query_len` IDs against files `file_offset..file_offset + file_len`
+/// into `(file_offset, file_len)` sub-tiles whose score output fits in
+/// `max_output_bytes`, halving the file range until it does. A single file is
+/// never split further.
+fn split_tile(
+    query_len: usize,
+    file_offset: usize,
+    file_len: usize,
+    max_output_bytes: u64,
+) -> Vec<(usize, usize)> {
+    let output_bytes = (query_len as u64)
+        .saturating_mul(file_len as u64)
+        .saturating_mul(std::mem::size_of::<f32>() as u64);
+    if output_bytes <= max_output_bytes || file_len <= 1 {
+        return vec![(file_offset, file_len)];
+    }
+
+    let half = file_len / 2;
+    let mut tiles = split_tile(query_len, file_offset, half, max_output_bytes);
+    tiles.extend(split_tile(
+        query_len,
+        file_offset + half,
+        file_len - half,
+        max_output_bytes,
+    ));
+    tiles
 }
 
 struct PendingTile<'a> {
+    query_offset: usize,
+    file_offset: usize,
     hh_slice: &'a [String],
     file_slice: &'a [(i64, String)],
     handle: GpuTileHandle,
 }
 
+/// Match count recorded when the first tile of a query chunk completed.
+struct MatchCheckpoint {
+    query_offset: usize,
+    match_count: usize,
+}
+
+impl MatchCheckpoint {
+    /// Drop the matches already found for the chunk at `failed_at`, which is
+    /// redone from scratch. Earlier chunks completed, so theirs are kept.
+    fn roll_back(&self, all_matches: &mut Vec<MatchResult>, failed_at: usize) {
+        if self.query_offset == failed_at {
+            all_matches.truncate(self.match_count);
+        }
+    }
+}
+
+/// Where a failed pass resumes: the oldest chunk with a tile still in
+/// flight, or `query_offset` when none is. Tiles of an earlier chunk can
+/// still be pending when dispatching a later one fails, e.g. once the device
+/// is lost, and resuming after them would skip the rest of that chunk.
+fn resume_offset(pending: &VecDeque<PendingTile<'_>>, query_offset: usize) -> usize {
+    pending
+        .front()
+        .map_or(query_offset, |tile| tile.query_offset)
+}
+
 struct ProgressTracker {
     total_queries: usize,
     total_work: usize,
@@ -365,6 +1328,12 @@ impl ProgressTracker {
         self.emit(progress);
     }
 
+    /// Resets completed work to the first `queries_done` IDs before a resumed pass.
+    fn rewind(&mut self, queries_done: usize) {
+        let total_files = self.total_work / self.total_queries.max(1);
+        self.completed_work = queries_done.saturating_mul(total_files);
+    }
+
     fn finish(&mut self, progress: Option<&MatchProgressCallback>) {
         self.completed_work = self.total_work;
         self.emit(progress);
@@ -426,10 +1395,13 @@ impl ProgressTracker {
     }
 }
 
-fn fingerprint_entry(id: i64, name: &str) -> u64 {
+/// Key of a cached file vector. Includes the encoding scheme, so changing the
+/// dimension, n-gram length or hash forces every vector to be re-encoded.
+fn fingerprint_entry(id: i64, name: &str, scheme: VectorScheme) -> u64 {
     let mut hasher = DefaultHasher::new();
     id.hash(&mut hasher);
     name.hash(&mut hasher);
+    scheme.hash(&mut hasher);
     hasher.finish()
 }
 
@@ -438,6 +1410,26 @@ impl MatchEngine for GpuMatchEngine {
         MatchEngineKind::Gpu
     }
 
+    fn take_notice(&mut self) -> Option<String> {
+        self.notice.take()
+    }
+
+    fn set_dry_run(&mut self, enabled: bool) {
+        self.dry_run = enabled;
+    }
+
+    fn set_new_files_since(&mut self, since: Option<ScanGeneration>) {
+        self.new_files_since = since;
+    }
+
+    fn set_output(&mut self, output: Option<Database>) {
+        self.output = output;
+    }
+
+    fn set_match_count_handle(&mut self, handle: MatchCountCallback) {
+        self.match_count = Some(handle);
+    }
+
     fn match_and_store(
         &mut self,
         hh_ids: &[String],
@@ -445,12 +1437,11 @@ impl MatchEngine for GpuMatchEngine {
         min_similarity: f64,
         progress_callback: Option<MatchProgressCallback>,
     ) -> Result<usize, String> {
-        let files = db
-            .get_all_files()
-            .map_err(|e| format!("Failed to load files for GPU matcher: {}", e))?;
-
+        // In new-files mode the file buffer only holds the new files
+        let files = vector_engine_files(db, self.new_files_since.as_ref(), self.kind())?;
         if files.is_empty() {
-            return Err("No files found in database. Please scan a directory first.".to_string());
+            info!("GPU matching skipped: no files were added since the last match run");
+            return Ok(0);
         }
 
         let total_queries = hh_ids.len();
@@ -487,24 +1478,39 @@ impl MatchEngine for GpuMatchEngine {
             .collect();
 
         info!(
-            "GPU match pass started: {} household IDs across {} files (query chunk: {}, file chunk: {}, in-flight tiles: {})",
+            "GPU match pass started: {} household IDs across {} files (query chunk: {}, file chunk: {}, in-flight tiles: up to {} within {:.0}% of {} bytes, kernel: {}, poller: {})",
             hh_ids.len(),
             file_pairs.len(),
             self.chunk_size.max(1),
             self.file_chunk_size.max(1),
-            self.inflight_limit
+            self.inflight_limit,
+            self.inflight_fraction * 100.0,
+            self.computer.max_storage_bytes(),
+            self.computer.kernel(),
+            self.computer.poll_strategy()
         );
 
         db.cleanup_orphan_vectors()
             .map_err(|e| format!("Failed to clean vector cache: {}", e))?;
 
-        self.prepare_cache(&file_pairs, db)?;
+        if file_pairs.len() > self.stream_threshold {
+            // Vectors are read slice by slice while uploading instead
+            self.file_vectors = HashMap::new();
+            info!(
+                "Streaming {} file vectors to the GPU in slices of {} (TIFF_GPU_STREAM_FILES={})",
+                file_pairs.len(),
+                STREAM_SLICE_FILES,
+                self.stream_threshold
+            );
+        } else {
+            self.prepare_cache(&file_pairs, db)?;
+        }
+        self.exact_names = ExactNameIndex::build(&file_pairs);
         let total_files = file_pairs.len().max(1);
-        let (file_buffer, _) = self.ensure_gpu_buffer(&file_pairs)?;
 
         let mut all_matches = Vec::new();
         let mut tracker = ProgressTracker::new(hh_ids.len(), total_files);
-        let mut pending: VecDeque<PendingTile<'_>> = VecDeque::new();
+        let buffers_before = self.computer.buffers_created();
 
         info!(
             "GPU matching started: processing {} household IDs across {} files",
@@ -512,76 +1518,98 @@ impl MatchEngine for GpuMatchEngine {
             file_pairs.len()
         );
 
-        for chunk in hh_ids.chunks(self.chunk_size.max(1)) {
-            if chunk.is_empty() {
-                continue;
+        let mut start = 0;
+        let mut recovered = false;
+        while let Err((failed_at, error)) = self.run_tiles(
+            hh_ids,
+            &file_pairs,
+            db,
+            start,
+            &mut all_matches,
+            min_similarity,
+            &mut tracker,
+            progress.as_ref(),
+        ) {
+            if !self.computer.is_device_lost() {
+                return Err(error);
             }
-            let chunk_vectors = self.encode_ids(chunk);
-            let chunk_file_size = self.file_chunk_size_for(chunk.len());
-
-            for (tile_index, file_chunk) in file_pairs.chunks(chunk_file_size).enumerate() {
-                if file_chunk.is_empty() {
-                    continue;
-                }
-                let file_offset = tile_index * chunk_file_size;
-                let handle = self.computer.dispatch_tile(
-                    &chunk_vectors,
-                    chunk.len(),
-                    &file_buffer,
-                    file_offset,
-                    file_chunk.len(),
-                    VECTOR_SIZE,
-                )?;
-
-                tracker.register_tile(chunk.len(), file_chunk.len());
-                pending.push_back(PendingTile {
-                    hh_slice: chunk,
-                    file_slice: file_chunk,
-                    handle,
-                });
+            start = failed_at;
+            tracker.rewind(start);
 
-                if pending.len() >= self.inflight_limit {
-                    self.finish_next_tile(
-                        &mut pending,
-                        &mut all_matches,
-                        min_similarity,
-                        &mut tracker,
-                        progress.as_ref(),
-                    )?;
+            // Recreate the device once; a second loss means the GPU is unreliable
+            if !recovered {
+                recovered = true;
+                log::warn!(
+                    "GPU device lost at ID {} of {} ({}). Recreating device.",
+                    start,
+                    total_queries,
+                    error
+                );
+                match Self::create_computer() {
+                    Ok(mut computer) => {
+                        computer.set_poll_strategy(Self::poll_strategy(self.inflight_limit));
+                        computer.set_tile_pool_size(self.inflight_limit.saturating_add(1));
+                        self.computer = computer;
+                        self.file_gpu_buffer = None;
+                        continue;
+                    }
+                    Err(e) => log::warn!("Failed to recreate GPU device: {}", e),
                 }
             }
-        }
 
-        while !pending.is_empty() {
-            self.finish_next_tile(
-                &mut pending,
-                &mut all_matches,
+            let remaining = total_queries - start;
+            log::warn!(
+                "Finishing the remaining {} household IDs on the CPU matcher",
+                remaining
+            );
+            self.notice = Some(format!(
+                "GPU device lost ({}). Finished the remaining {} IDs with the CPU matcher.",
+                error, remaining
+            ));
+            self.file_gpu_buffer = None;
+            all_matches.extend(self.finish_on_cpu(
+                hh_ids,
+                &files,
+                start,
+                all_matches.len(),
                 min_similarity,
-                &mut tracker,
                 progress.as_ref(),
-            )?;
+            ));
+            break;
         }
 
         tracker.finish(progress.as_ref());
+        // A tile used to allocate its own query, output, staging and params buffers
+        info!(
+            "GPU buffers: {} allocated for {} tiles ({} without reuse)",
+            self.computer
+                .buffers_created()
+                .saturating_sub(buffers_before),
+            tracker.total_tiles,
+            tracker.total_tiles * 4
+        );
 
-        let mut session = db
-            .start_match_import()
-            .map_err(|e| format!("Failed to start GPU match transaction: {}", e))?;
-
-        // Clear only matches for the hh_ids being processed (incremental update)
-        session
-            .clear_for_ids(hh_ids)
-            .map_err(|e| format!("Failed to clear previous matches: {}", e))?;
-
-        for result in &all_matches {
-            session
-                .insert_match(&result.hh_id, result.file_id, result.similarity)
-                .map_err(|e| format!("Failed to store GPU match: {}", e))?;
+        if self.dry_run {
+            info!(
+                "GPU dry run complete: {} matches for {} household IDs (not stored)",
+                all_matches.len(),
+                hh_ids.len()
+            );
+            return Ok(all_matches.len());
         }
 
-        session
-            .commit()
-            .map_err(|e| format!("Failed to commit GPU matches: {}", e))?;
+        // Clear only matches for the hh_ids being processed (incremental update),
+        // or just those of the new files when only they were matched
+        let engine = self.kind();
+        let new_files = self.new_files_since.as_ref().map(|_| files.as_slice());
+        store_matches(
+            match_output(db, self.output.as_mut(), &files, &all_matches)?,
+            hh_ids,
+            new_files,
+            &all_matches,
+            min_similarity,
+            engine,
+        )?;
 
         info!(
             "GPU match pass complete: {} matches persisted for {} household IDs",
@@ -592,3 +1620,377 @@ impl MatchEngine for GpuMatchEngine {
         Ok(all_matches.len())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files() -> Vec<(i64, String)> {
+        vec![
+            (1, "HH001.tif".to_string()),
+            (2, "HH_001_scan.TIF".to_string()),
+            (3, "HH002.tif".to_string()),
+        ]
+    }
+
+    #[test]
+    fn exact_names_match_stem_and_extracted_id() {
+        let index = ExactNameIndex::build(&files());
+        let exact = index.exact_files(" hh001 ").expect("stem of HH001.tif");
+        assert_eq!(exact.get(&1), Some(&CandidateKind::Stem));
+        assert!(!exact.contains_key(&3));
+
+        let extracted = index.exact_files("HH001scan").expect("extracted id");
+        assert_eq!(extracted.get(&2), Some(&CandidateKind::ExtractedId));
+        assert!(index.exact_files("HH003").is_none());
+    }
+
+    #[test]
+    fn device_loss_on_dispatch_resumes_at_the_oldest_chunk_in_flight() {
+        let ids: Vec<String> = (0..6).map(|i| format!("HH{:03}", i)).collect();
+        let files = files();
+        let tile = |query_offset: usize, file_offset: usize| PendingTile {
+            query_offset,
+            file_offset,
+            hh_slice: &ids[query_offset..query_offset + 2],
+            file_slice: &files[file_offset..file_offset + 1],
+            handle: GpuTileHandle::immediate(Ok(Vec::new())),
+        };
+        let found = |hh_id: &str| MatchResult {
+            hh_id: hh_id.to_string(),
+            file_id: 1,
+            similarity: 0.9,
+            matched_candidate: "hh001".to_string(),
+            candidate_kind: CandidateKind::Stem,
+            explanation: None,
+        };
+
+        // Chunk 0 (IDs 0-1) finished its first tile; its second tile and
+        // both of chunk 1's are in flight when dispatching chunk 2 fails
+        let pending: VecDeque<_> = [tile(0, 1), tile(2, 0), tile(2, 1)].into();
+        let checkpoint = MatchCheckpoint {
+            query_offset: 0,
+            match_count: 1,
+        };
+        let mut all_matches = vec![found("earlier"), found("HH000")];
+        let failed_at = resume_offset(&pending, 4);
+        assert_eq!(failed_at, 0);
+        checkpoint.roll_back(&mut all_matches, failed_at);
+        assert_eq!(all_matches.len(), 1, "chunk 0 is redone from scratch");
+
+        // Nothing in flight: the chunk being dispatched is redone
+        assert_eq!(resume_offset(&VecDeque::new(), 4), 4);
+        // Resuming after the checkpoint's chunk keeps its matches
+        let mut all_matches = vec![found("HH000")];
+        checkpoint.roll_back(&mut all_matches, 2);
+        assert_eq!(all_matches.len(), 1);
+    }
+
+    #[test]
+    fn scores_snap_to_one_only_for_exact_names() {
+        assert_eq!(ExactNameIndex::settle_score(0.9999, true), 1.0);
+        assert_eq!(ExactNameIndex::settle_score(0.42, true), 1.0);
+        assert_eq!(
+            ExactNameIndex::settle_score(1.0, false),
+            NON_EXACT_MAX_SCORE
+        );
+        assert_eq!(ExactNameIndex::settle_score(0.8, false), 0.8);
+    }
+
+    #[test]
+    fn oversized_tiles_are_halved_until_they_fit() {
+        // 64 IDs x 1000 files needs 256,000 bytes of scores; allow 25,600
+        let max_output_bytes = 64 * 100 * 4;
+        let tiles = split_tile(64, 2000, 1000, max_output_bytes);
+
+        assert!(tiles.len() > 1);
+        let mut next = 2000;
+        for &(offset, len) in &tiles {
+            assert_eq!(offset, next, "tiles cover the range in order");
+            assert!(len > 0);
+            assert!((64 * len * 4) as u64 <= max_output_bytes);
+            next = offset + len;
+        }
+        assert_eq!(next, 3000);
+
+        // A tile that already fits is left alone
+        assert_eq!(split_tile(64, 0, 100, max_output_bytes), vec![(0, 100)]);
+        // A single file is the smallest tile, even when it cannot fit
+        assert_eq!(split_tile(64, 7, 1, 4), vec![(7, 1)]);
+    }
+
+    #[test]
+    fn inflight_tiles_fit_the_memory_budget() {
+        const MIB: u64 = 1024 * 1024;
+        // 128 MiB binding, half of it for tiles of 4 MiB output + 4 MiB staging
+        assert_eq!(inflight_limit_for(128 * MIB, 4 * MIB, 0.5, 64), 8);
+        // The ceiling still applies on a big card
+        assert_eq!(inflight_limit_for(2048 * MIB, 4 * MIB, 0.5, 8), 8);
+        // Large tiles on a small card go one at a time, never zero
+        assert_eq!(inflight_limit_for(128 * MIB, 96 * MIB, 0.5, 8), 1);
+        assert_eq!(inflight_limit_for(0, 0, 0.5, 8), 1);
+        // Small tiles are bounded by the ceiling, not the budget
+        assert_eq!(inflight_limit_for(128 * MIB, 1024, 0.9, 3), 3);
+    }
+
+    #[test]
+    fn default_inflight_ceiling_follows_the_adapter() {
+        const MIB: u64 = 1024 * 1024;
+        // 64 IDs x 256 files of f32 scores per tile
+        let tile = 64 * 256 * 4;
+        // Half of 4 MiB holds 16 tiles with their staging buffers
+        assert_eq!(default_inflight_ceiling(4 * MIB, tile, 0.5), 16);
+        assert_eq!(default_inflight_ceiling(MIB, tile, 0.5), 4);
+        // Tiles bigger than the budget still go one at a time
+        assert_eq!(default_inflight_ceiling(MIB / 8, tile, 0.5), 1);
+        // Large adapters are capped
+        assert_eq!(
+            default_inflight_ceiling(16 * 1024 * MIB, tile, 0.5),
+            MAX_DEFAULT_INFLIGHT
+        );
+    }
+
+    #[test]
+    fn cpu_cosine_scores_are_the_vector_dot_products() {
+        let engine = CpuCosineEngine::default();
+        let files = files();
+        let ids = ["HH001".to_string(), "HH002".to_string(), "XY9".to_string()];
+        let exact_names = ExactNameIndex::build(&files);
+        let matches = engine.score(&ids, &files, 0.0, None);
+        assert_eq!(matches.len(), ids.len() * files.len());
+
+        for result in &matches {
+            let name = &files
+                .iter()
+                .find(|(id, _)| *id == result.file_id)
+                .unwrap()
+                .1;
+            let exact = exact_names
+                .exact_files(&result.hh_id)
+                .is_some_and(|exact| exact.contains_key(&result.file_id));
+            if exact {
+                assert_eq!(result.similarity, 1.0, "{} vs {}", result.hh_id, name);
+                continue;
+            }
+            let expected = cosine_score(
+                &engine.vectorizer.encode(&result.hh_id),
+                &engine.vectorizer.encode(name),
+            );
+            let expected = ExactNameIndex::settle_score(expected as f64, false);
+            assert!(
+                (result.similarity - expected).abs() < 1e-6,
+                "{} vs {}: {} != {}",
+                result.hh_id,
+                name,
+                result.similarity,
+                expected
+            );
+        }
+    }
+
+    // Needs a GPU, like the smoke tests in `gpu`
+    #[cfg(feature = "gpu-smoke")]
+    #[test]
+    fn cpu_cosine_scores_agree_with_the_gpu() {
+        let Ok(mut gpu) = GpuMatchEngine::new() else {
+            eprintln!("GPU unavailable on this host; skipping smoke test");
+            return;
+        };
+        let names = [
+            "HH001.tif",
+            "HH_001_scan.TIF",
+            "hh002-back.tiff",
+            "HH003 copy.tif",
+            "unrelated.tif",
+        ];
+        let ids: Vec<String> = ["HH001", "HH002", "HH003", "ZZ9"]
+            .iter()
+            .map(|id| id.to_string())
+            .collect();
+        let stored = |engine: &mut dyn MatchEngine| {
+            let mut db = Database::new(":memory:").expect("in-memory database");
+            let mut session = db.start_file_import().unwrap();
+            for name in names {
+                session
+                    .upsert_file(&format!("/scans/{}", name), name)
+                    .unwrap();
+            }
+            session.commit().unwrap();
+            engine.match_and_store(&ids, &mut db, 0.0, None).unwrap();
+            let mut scores = HashMap::new();
+            db.get_all_matches(0.0, None, |record| {
+                scores.insert((record.hh_id, record.file_name), record.similarity_score);
+                true
+            })
+            .unwrap();
+            scores
+        };
+
+        let on_gpu = stored(&mut gpu);
+        let on_cpu = stored(&mut CpuCosineEngine::default());
+        assert_eq!(on_gpu.len(), on_cpu.len());
+        for (pair, gpu_score) in &on_gpu {
+            let cpu_score = on_cpu[pair];
+            assert!(
+                (gpu_score - cpu_score).abs() < 1e-4,
+                "{:?}: GPU {} vs CPU {}",
+                pair,
+                gpu_score,
+                cpu_score
+            );
+        }
+    }
+
+    #[cfg(feature = "gpu-smoke")]
+    #[test]
+    fn every_inflight_limit_drains_all_tiles() {
+        let Ok(mut engine) = GpuMatchEngine::new() else {
+            eprintln!("GPU unavailable on this host; skipping smoke test");
+            return;
+        };
+        let mut db = Database::new(":memory:").expect("in-memory database");
+        {
+            let mut session = db.start_file_import().unwrap();
+            for i in 0..40 {
+                let name = format!("HH{:03}.tif", i);
+                session
+                    .upsert_file(&format!("/scans/{}", name), &name)
+                    .unwrap();
+            }
+            session.commit().unwrap();
+        }
+        let ids: Vec<String> = (0..40).map(|i| format!("HH{:03}", i)).collect();
+        // One file per tile, so each run dispatches many more tiles than fit
+        engine.file_chunk_size = 1;
+        engine.chunk_size = 8;
+        engine.set_dry_run(true);
+
+        let mut counts = Vec::new();
+        for limit in [1, 2, 7, 64] {
+            engine.inflight_limit = limit;
+            counts.push(engine.match_and_store(&ids, &mut db, 0.9, None).unwrap());
+        }
+        assert!(counts[0] >= ids.len());
+        assert!(
+            counts.iter().all(|count| *count == counts[0]),
+            "{:?}",
+            counts
+        );
+    }
+
+    #[cfg(feature = "gpu-smoke")]
+    #[test]
+    fn streamed_file_buffers_score_like_cached_ones() {
+        let Ok(mut engine) = GpuMatchEngine::new() else {
+            eprintln!("GPU unavailable on this host; skipping smoke test");
+            return;
+        };
+        let mut db = Database::new(":memory:").expect("in-memory database");
+        {
+            let mut session = db.start_file_import().unwrap();
+            for i in 0..30 {
+                let name = format!("HH{:03}.tif", i);
+                session
+                    .upsert_file(&format!("/scans/{}", name), &name)
+                    .unwrap();
+            }
+            session.commit().unwrap();
+        }
+        let ids: Vec<String> = (0..30).step_by(3).map(|i| format!("HH{:03}", i)).collect();
+        engine.set_dry_run(true);
+        let cached = engine.match_and_store(&ids, &mut db, 0.5, None).unwrap();
+        assert!(cached >= ids.len());
+        assert_eq!(engine.file_gpu_buffer.as_ref().unwrap().segments.len(), 1);
+
+        // Segments of 7 files, each filled in slices read from the vector cache
+        engine.stream_threshold = 1;
+        engine.segment_files = 7;
+        engine.file_gpu_buffer = None;
+        let streamed = engine.match_and_store(&ids, &mut db, 0.5, None).unwrap();
+        assert_eq!(streamed, cached);
+        assert!(engine.file_vectors.is_empty());
+        assert_eq!(engine.file_gpu_buffer.as_ref().unwrap().segments.len(), 5);
+
+        // An unchanged corpus keeps its buffers
+        let before = engine.computer.buffers_created();
+        engine.match_and_store(&ids, &mut db, 0.5, None).unwrap();
+        let segments = engine.computer.buffers_created() - before;
+        assert!(segments < 5, "{} buffers created", segments);
+    }
+
+    #[cfg(feature = "gpu-smoke")]
+    #[test]
+    fn gpu_new_files_mode_keeps_older_matches() {
+        let Ok(mut engine) = GpuMatchEngine::new() else {
+            eprintln!("GPU unavailable on this host; skipping smoke test");
+            return;
+        };
+        let mut db = Database::new(":memory:").expect("in-memory database");
+        let add = |db: &mut Database, name: &str| {
+            let mut session = db.start_file_import().unwrap();
+            session
+                .upsert_file(&format!("/scans/{}", name), name)
+                .unwrap();
+            session.commit().unwrap();
+        };
+        add(&mut db, "HH001.tif");
+        let ids = ["HH001".to_string()];
+        assert_eq!(engine.match_and_store(&ids, &mut db, 0.8, None), Ok(1));
+
+        let since = db.get_scan_generation().unwrap();
+        add(&mut db, "HH001_b.tif");
+        engine.set_new_files_since(Some(since));
+        assert_eq!(engine.match_and_store(&ids, &mut db, 0.5, None), Ok(1));
+        assert_eq!(db.get_match_count().unwrap(), 2);
+    }
+
+    #[test]
+    fn scheme_change_invalidates_cached_vectors() {
+        let mut db = Database::new(":memory:").expect("in-memory database");
+        {
+            let mut session = db.start_file_import().unwrap();
+            session
+                .upsert_file("/scans/HH001.tif", "HH001.tif")
+                .unwrap();
+            session.commit().unwrap();
+        }
+        let id = db.get_file_id("/scans/HH001.tif").unwrap();
+
+        let vectorizer = Vectorizer::new();
+        let current = vectorizer.scheme();
+        let vector = vectorizer.encode("HH001.tif");
+        let fingerprint = fingerprint_entry(id, "HH001.tif", current);
+        db.upsert_file_vectors(&[(id, fingerprint, vector.clone())])
+            .unwrap();
+        assert_eq!(
+            db.get_file_vector(id, fingerprint, current.dim).unwrap(),
+            Some(vector)
+        );
+
+        // Any change to the encoding yields a new fingerprint, so the stored
+        // vector is treated as missing and re-encoded
+        let rehashed = VectorScheme {
+            hash: current.hash + 1,
+            ..current
+        };
+        let regrammed = VectorScheme {
+            ngram_len: current.ngram_len + 1,
+            ..current
+        };
+        for scheme in [rehashed, regrammed] {
+            let stale = fingerprint_entry(id, "HH001.tif", scheme);
+            assert_ne!(stale, fingerprint);
+            assert_eq!(db.get_file_vector(id, stale, scheme.dim).unwrap(), None);
+        }
+
+        // A resized vector never comes back, even under a matching fingerprint
+        let resized = VectorScheme {
+            dim: current.dim * 2,
+            ..current
+        };
+        let stale = fingerprint_entry(id, "HH001.tif", resized);
+        db.upsert_file_vectors(&[(id, stale, vec![0.0; current.dim])])
+            .unwrap();
+        assert_eq!(db.get_file_vector(id, stale, resized.dim).unwrap(), None);
+    }
+}
@@ -1,56 +1,371 @@
-use crate::database::{Database, FileRecord};
+use crate::database::{Database, FileRecord, ScanGeneration};
+use crate::match_engine::{match_output, match_rows, MatchEngineKind};
 use fuzzy_matcher::skim::SkimMatcherV2;
 use fuzzy_matcher::FuzzyMatcher;
 use log::info;
 use rayon::prelude::*;
+use std::path::{Component, Path};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 
 pub type ProgressCallback = Arc<Mutex<dyn FnMut(usize, usize) + Send>>;
+/// Receives the running number of matches found so far in a pass.
+pub type MatchCountCallback = Arc<Mutex<dyn FnMut(usize) + Send>>;
+
+/// How many enclosing folders (nearest first) are tried when path matching is
+/// enabled, unless set otherwise. Each one is another fuzzy comparison and
+/// another stored string per file, so keep this small.
+pub const DEFAULT_PATH_COMPONENTS: usize = 3;
+
+/// Most enclosing folders path matching can be set to try.
+pub const MAX_PATH_COMPONENTS: usize = 16;
+
+/// Shortest digit run in a file name kept as a numeric candidate, so version
+/// suffixes like `v2` do not match every short ID.
+const MIN_DIGIT_RUN: usize = 3;
+
+/// Weight of the best candidate's score under `ScoreCombination::Weighted`;
+/// the runner-up gets the rest.
+const TOP_CANDIDATE_WEIGHT: f64 = 0.7;
+
+/// How a file's candidate scores are combined into its similarity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScoreCombination {
+    /// The best candidate's score.
+    #[default]
+    Max,
+    /// A weighted sum of the best two candidate scores, so a file matched by
+    /// a single candidate scores lower than one where two agree.
+    Weighted,
+    /// The best candidate's score, kept only when at least this many
+    /// candidates reach the threshold.
+    Agreement(usize),
+}
+
+impl ScoreCombination {
+    /// Choices offered in the GUI.
+    pub const CHOICES: [ScoreCombination; 4] = [
+        ScoreCombination::Max,
+        ScoreCombination::Weighted,
+        ScoreCombination::Agreement(2),
+        ScoreCombination::Agreement(3),
+    ];
+
+    /// Stable name used in settings and on the command line.
+    pub fn label(self) -> String {
+        match self {
+            ScoreCombination::Max => "max".to_string(),
+            ScoreCombination::Weighted => "weighted".to_string(),
+            ScoreCombination::Agreement(count) => format!("agree:{}", count),
+        }
+    }
+
+    pub fn from_label(label: &str) -> Option<Self> {
+        match label {
+            "max" => Some(ScoreCombination::Max),
+            "weighted" => Some(ScoreCombination::Weighted),
+            _ => label
+                .strip_prefix("agree:")?
+                .parse::<usize>()
+                .ok()
+                .filter(|count| *count >= 1)
+                .map(ScoreCombination::Agreement),
+        }
+    }
+
+    /// Combined similarity of `scores` (one per candidate, best first), or
+    /// `None` when the file falls below the threshold. The best candidate is
+    /// credited with the match.
+    fn combine(self, scores: &[f64], min_similarity: f64) -> Option<f64> {
+        let best = *scores.first()?;
+        let combined = match self {
+            ScoreCombination::Max => best,
+            ScoreCombination::Weighted => {
+                let runner_up = scores.get(1).copied().unwrap_or(0.0);
+                TOP_CANDIDATE_WEIGHT * best + (1.0 - TOP_CANDIDATE_WEIGHT) * runner_up
+            }
+            ScoreCombination::Agreement(count) => {
+                let agreeing = scores
+                    .iter()
+                    .filter(|score| **score >= min_similarity)
+                    .count();
+                if agreeing < count {
+                    return None;
+                }
+                best
+            }
+        };
+        (combined > 0.0 && combined >= min_similarity).then_some(combined)
+    }
+}
+
+impl std::fmt::Display for ScoreCombination {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScoreCombination::Max => write!(f, "Best candidate"),
+            ScoreCombination::Weighted => write!(f, "Weighted best two"),
+            ScoreCombination::Agreement(count) => write!(f, "{} candidates agree", count),
+        }
+    }
+}
+
+/// Which of a file's candidate strings produced a match's score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CandidateKind {
+    /// The whole file name, e.g. `HH001_scan.tif`.
+    FileName,
+    /// The file name without its TIFF suffix.
+    Stem,
+    /// The name with its suffix and separators removed, e.g. `hh001scan`.
+    ExtractedId,
+    /// An enclosing folder name, when path matching is enabled.
+    Folder,
+    /// A run of digits in the file name, tried for numeric IDs.
+    Digits,
+    /// The file name's n-gram vector (GPU matching) rather than one string.
+    Vector,
+}
+
+impl CandidateKind {
+    pub const ALL: [CandidateKind; 6] = [
+        CandidateKind::FileName,
+        CandidateKind::Stem,
+        CandidateKind::ExtractedId,
+        CandidateKind::Folder,
+        CandidateKind::Digits,
+        CandidateKind::Vector,
+    ];
+
+    /// Stable name used in `matches.matched_via`.
+    pub fn label(self) -> &'static str {
+        match self {
+            CandidateKind::FileName => "file_name",
+            CandidateKind::Stem => "stem",
+            CandidateKind::ExtractedId => "extracted_id",
+            CandidateKind::Folder => "folder",
+            CandidateKind::Digits => "digits",
+            CandidateKind::Vector => "vector",
+        }
+    }
+
+    pub fn from_label(label: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.label() == label)
+    }
+}
+
+impl std::fmt::Display for CandidateKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CandidateKind::FileName => write!(f, "File name"),
+            CandidateKind::Stem => write!(f, "Stem"),
+            CandidateKind::ExtractedId => write!(f, "Extracted ID"),
+            CandidateKind::Folder => write!(f, "Folder"),
+            CandidateKind::Digits => write!(f, "Digits"),
+            CandidateKind::Vector => write!(f, "Vector"),
+        }
+    }
+}
+
+/// `matches.matched_via` value for a candidate, e.g. `stem:hh001_scan`.
+pub fn format_matched_via(kind: CandidateKind, candidate: &str) -> String {
+    format!("{}:{}", kind.label(), candidate)
+}
+
+/// Kind and candidate of a stored `matched_via` value; `None` when it wasn't
+/// written by [`format_matched_via`].
+pub fn parse_matched_via(value: &str) -> Option<(CandidateKind, &str)> {
+    let (label, candidate) = value.split_once(':')?;
+    Some((CandidateKind::from_label(label)?, candidate))
+}
+
+/// How a candidate's fuzzy score became its similarity: the skim score is
+/// divided by the query's score against itself, then scaled by how close
+/// the two lengths are.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchExplanation {
+    /// The candidate string as compared, so usually lowercased.
+    pub matched_candidate: String,
+    pub candidate_kind: CandidateKind,
+    /// Skim fuzzy score of the candidate against the query.
+    pub raw_score: i64,
+    /// Skim score of the query against itself, the most `raw_score` can be.
+    pub perfect_score: i64,
+    /// Shorter over longer of the candidate and query lengths, in characters.
+    pub length_ratio: f64,
+    /// The candidate's similarity, `raw_score / perfect_score * length_ratio`
+    /// capped at 1.
+    pub normalized: f64,
+}
+
+impl MatchExplanation {
+    pub(crate) fn new(
+        kind: CandidateKind,
+        candidate: &str,
+        query: &str,
+        raw_score: i64,
+        perfect_score: i64,
+    ) -> Self {
+        let (_, length_ratio) =
+            Matcher::score_parts(raw_score, candidate, query, perfect_score).unwrap_or_default();
+        MatchExplanation {
+            matched_candidate: candidate.to_string(),
+            candidate_kind: kind,
+            raw_score,
+            perfect_score,
+            length_ratio,
+            normalized: Matcher::normalize_score(raw_score, candidate, query, perfect_score),
+        }
+    }
+
+    /// `raw_score` as a share of `perfect_score`, before the length penalty.
+    pub fn base_score(&self) -> f64 {
+        if self.perfect_score <= 0 {
+            return 0.0;
+        }
+        (self.raw_score.max(0) as f64 / self.perfect_score as f64).min(1.0)
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct MatchResult {
     pub hh_id: String,
     pub file_id: i64,
     pub similarity: f64,
+    /// The candidate string (as compared, so usually lowercased) that
+    /// produced `similarity`.
+    pub matched_candidate: String,
+    pub candidate_kind: CandidateKind,
+    /// How the candidate scored; only filled in when the matcher was asked
+    /// to explain (see `Matcher::set_explain`).
+    pub explanation: Option<MatchExplanation>,
+}
+
+impl MatchResult {
+    /// Provenance as stored in `matches.matched_via`.
+    pub fn matched_via(&self) -> String {
+        format_matched_via(self.candidate_kind, &self.matched_candidate)
+    }
 }
 
 #[derive(Clone)]
 struct FileMatchContext {
     record: FileRecord,
-    candidates: Vec<String>,
+    candidates: Vec<(CandidateKind, String)>,
+    // Only compared against purely numeric IDs
+    digit_candidates: Vec<String>,
 }
 
 impl FileMatchContext {
-    fn from_record(record: &FileRecord) -> Self {
-        let mut candidates = Vec::with_capacity(3);
-        candidates.push(record.file_name.to_lowercase());
-        if let Some(stem) = Matcher::strip_tiff_suffix(&record.file_name) {
-            candidates.push(stem.to_lowercase());
+    // `path_components` is 0 when folder names aren't matched
+    fn from_record(record: &FileRecord, path_components: usize, case_sensitive: bool) -> Self {
+        let mut candidates = Matcher::filename_candidates(&record.file_name, case_sensitive);
+        if path_components > 0 {
+            candidates.extend(
+                Matcher::path_candidates(&record.file_path, case_sensitive, path_components)
+                    .into_iter()
+                    .map(|folder| (CandidateKind::Folder, folder)),
+            );
         }
-        let extracted = Matcher::extract_id_from_filename(&record.file_name);
-        if !extracted.is_empty() {
-            candidates.push(extracted.to_lowercase());
-        }
-
         FileMatchContext {
             record: record.clone(),
             candidates,
+            digit_candidates: Matcher::digit_candidates(&record.file_name),
         }
     }
 }
 
+/// Match contexts of the files last matched, with the settings they were
+/// built under.
+struct FileContexts {
+    path_components: usize,
+    case_sensitive: bool,
+    contexts: Vec<FileMatchContext>,
+}
+
 pub struct Matcher {
     progress_callback: Option<ProgressCallback>,
+    match_count_callback: Option<MatchCountCallback>,
+    include_path_components: bool,
+    path_components: usize,
+    case_sensitive: bool,
+    score_combination: ScoreCombination,
+    new_files_since: Option<ScanGeneration>,
+    explain: bool,
+    file_contexts: Mutex<Option<Arc<FileContexts>>>,
 }
 
 impl Matcher {
     pub fn new() -> Self {
         Matcher {
             progress_callback: None,
+            match_count_callback: None,
+            include_path_components: false,
+            path_components: DEFAULT_PATH_COMPONENTS,
+            case_sensitive: false,
+            score_combination: ScoreCombination::Max,
+            new_files_since: None,
+            explain: false,
+            file_contexts: Mutex::new(None),
         }
     }
 
+    /// Attach a [`MatchExplanation`] to every result. Off by default, as bulk
+    /// runs only store the score and candidate.
+    pub fn set_explain(&mut self, enabled: bool) {
+        self.explain = enabled;
+    }
+
+    /// Only match files added after `since`, keeping the stored matches of
+    /// every other file instead of clearing the IDs' matches. `None` (the
+    /// default) matches every file.
+    pub fn set_new_files_since(&mut self, since: Option<ScanGeneration>) {
+        self.new_files_since = since;
+    }
+
+    /// Compare IDs and file names as written, so `HH001a` and `HH001A` are
+    /// different households. Off by default.
+    pub fn set_case_sensitive(&mut self, enabled: bool) {
+        self.case_sensitive = enabled;
+    }
+
+    /// How a file's candidate scores combine into its similarity.
+    /// `ScoreCombination::Max` by default.
+    pub fn set_score_combination(&mut self, combination: ScoreCombination) {
+        self.score_combination = combination;
+    }
+
+    /// The string actually compared: `value` as is in case-sensitive mode,
+    /// lowercased otherwise.
+    pub(crate) fn fold_case(value: &str, case_sensitive: bool) -> String {
+        if case_sensitive {
+            value.to_string()
+        } else {
+            value.to_lowercase()
+        }
+    }
+
+    /// Fuzzy scorer for the given mode. Skim's default smart case would still
+    /// ignore case for all-lowercase IDs.
+    pub(crate) fn fuzzy_matcher(case_sensitive: bool) -> SkimMatcherV2 {
+        if case_sensitive {
+            SkimMatcherV2::default().respect_case()
+        } else {
+            SkimMatcherV2::default()
+        }
+    }
+
+    /// Also match IDs against the names of the enclosing folders
+    /// (e.g. `/archive/HH001/scan.tif`). Off by default.
+    pub fn set_include_path_components(&mut self, enabled: bool) {
+        self.include_path_components = enabled;
+    }
+
+    /// How many enclosing folders, nearest first, path matching tries; kept
+    /// within `1..=MAX_PATH_COMPONENTS`. `DEFAULT_PATH_COMPONENTS` by default.
+    pub fn set_path_components(&mut self, depth: usize) {
+        self.path_components = depth.clamp(1, MAX_PATH_COMPONENTS);
+    }
+
     pub fn set_progress_handle(&mut self, handle: ProgressCallback) {
         self.progress_callback = Some(handle);
     }
@@ -59,6 +374,80 @@ impl Matcher {
         self.progress_callback = None;
     }
 
+    /// Report the running match count after each chunk of IDs that found any.
+    pub fn set_match_count_handle(&mut self, handle: MatchCountCallback) {
+        self.match_count_callback = Some(handle);
+    }
+
+    /// Strings a household ID is compared against for one file: the full name,
+    /// the name without its TIFF suffix, and the extracted ID. Lowercased
+    /// unless `case_sensitive`.
+    pub(crate) fn filename_candidates(
+        file_name: &str,
+        case_sensitive: bool,
+    ) -> Vec<(CandidateKind, String)> {
+        let mut candidates = Vec::with_capacity(3);
+        candidates.push((
+            CandidateKind::FileName,
+            Matcher::fold_case(file_name, case_sensitive),
+        ));
+        if let Some(stem) = Matcher::strip_tiff_suffix(file_name) {
+            candidates.push((
+                CandidateKind::Stem,
+                Matcher::fold_case(stem, case_sensitive),
+            ));
+        }
+        let extracted = Matcher::extract_id_from_filename(file_name);
+        if !extracted.is_empty() {
+            candidates.push((
+                CandidateKind::ExtractedId,
+                Matcher::fold_case(&extracted, case_sensitive),
+            ));
+        }
+        candidates
+    }
+
+    /// Names of up to `depth` folders containing `file_path`, nearest first.
+    /// Lowercased unless `case_sensitive`.
+    pub(crate) fn path_candidates(
+        file_path: &str,
+        case_sensitive: bool,
+        depth: usize,
+    ) -> Vec<String> {
+        let Some(parent) = Path::new(file_path).parent() else {
+            return Vec::new();
+        };
+        parent
+            .components()
+            .rev()
+            .filter_map(|component| match component {
+                Component::Normal(name) => {
+                    Some(Matcher::fold_case(&name.to_string_lossy(), case_sensitive))
+                }
+                _ => None,
+            })
+            .take(depth)
+            .collect()
+    }
+
+    /// Runs of at least `MIN_DIGIT_RUN` digits in the file name, e.g. `000123`
+    /// from `scanHH000123v2.tif`, plus each run without its zero padding.
+    fn digit_candidates(file_name: &str) -> Vec<String> {
+        let stem = Matcher::strip_tiff_suffix(file_name).unwrap_or(file_name);
+        let mut candidates = Vec::new();
+        for run in stem
+            .split(|c: char| !c.is_ascii_digit())
+            .filter(|run| run.len() >= MIN_DIGIT_RUN)
+        {
+            candidates.push(run.to_string());
+            let unpadded = run.trim_start_matches('0');
+            if !unpadded.is_empty() && unpadded.len() != run.len() {
+                candidates.push(unpadded.to_string());
+            }
+        }
+        candidates
+    }
+
     /// Extract potential ID from filename by removing common prefixes/suffixes and extensions
     fn extract_id_from_filename(filename: &str) -> String {
         // Remove extension
@@ -81,15 +470,14 @@ impl Matcher {
     ) -> Vec<MatchResult> {
         let total = hh_ids.len();
         let processed = Arc::new(AtomicUsize::new(0));
+        let found = AtomicUsize::new(0);
         let progress_callback = self.progress_callback.clone();
+        let match_count_callback = self.match_count_callback.clone();
         let log_progress = progress_callback.is_none() && total > 0;
         let log_step = if total > 0 { (total / 20).max(1) } else { 1 };
 
-        let file_contexts: Vec<FileMatchContext> = files
-            .par_iter()
-            .map(FileMatchContext::from_record)
-            .collect();
-
+        let file_contexts = self.file_contexts(files);
+        let file_contexts = &file_contexts.contexts;
         if file_contexts.is_empty() {
             return Vec::new();
         }
@@ -98,17 +486,35 @@ impl Matcher {
         let results: Vec<MatchResult> = hh_ids
             .par_chunks(32)
             .flat_map_iter(|chunk| {
-                let matcher = SkimMatcherV2::default();
+                let matcher = Self::fuzzy_matcher(self.case_sensitive);
                 let mut chunk_results = Vec::new();
 
                 for hh_id in chunk {
-                    let matches_for_id =
-                        Self::match_single_id(&matcher, hh_id, &file_contexts, min_similarity);
+                    let matches_for_id = Self::match_single_id(
+                        &matcher,
+                        hh_id,
+                        file_contexts,
+                        min_similarity,
+                        self.case_sensitive,
+                        self.score_combination,
+                        self.explain,
+                    );
                     chunk_results.extend(matches_for_id);
                 }
 
                 let completed = processed.fetch_add(chunk.len(), Ordering::Relaxed) + chunk.len();
 
+                if let Some(ref callback) = match_count_callback {
+                    // Counted under the lock so reports never go backwards
+                    if !chunk_results.is_empty() {
+                        if let Ok(mut cb) = callback.lock() {
+                            let so_far = found.fetch_add(chunk_results.len(), Ordering::Relaxed)
+                                + chunk_results.len();
+                            cb(so_far);
+                        }
+                    }
+                }
+
                 if let Some(ref callback) = progress_callback {
                     if let Ok(mut cb) = callback.lock() {
                         cb(completed.min(total), total);
@@ -135,21 +541,87 @@ impl Matcher {
         results
     }
 
-    /// Match IDs and store results in database
-    pub fn match_and_store(
-        &self,
-        hh_ids: &[String],
-        db: &mut Database,
-        min_similarity: f64,
-    ) -> Result<usize, String> {
-        // Get all files from database
+    /// Match contexts of `files`, built once and reused by later runs over the
+    /// same files with the same candidate settings.
+    fn file_contexts(&self, files: &[FileRecord]) -> Arc<FileContexts> {
+        let path_components = if self.include_path_components {
+            self.path_components
+        } else {
+            0
+        };
+        // A poisoned cache is only rebuilt
+        let mut slot = self
+            .file_contexts
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(cached) = slot.as_ref() {
+            if cached.path_components == path_components
+                && cached.case_sensitive == self.case_sensitive
+                && cached.contexts.len() == files.len()
+                && cached
+                    .contexts
+                    .iter()
+                    .zip(files)
+                    .all(|(context, record)| context.record == *record)
+            {
+                return Arc::clone(cached);
+            }
+        }
+
+        let built = Arc::new(FileContexts {
+            path_components,
+            case_sensitive: self.case_sensitive,
+            contexts: files
+                .par_iter()
+                .map(|record| {
+                    FileMatchContext::from_record(record, path_components, self.case_sensitive)
+                })
+                .collect(),
+        });
+        *slot = Some(Arc::clone(&built));
+        built
+    }
+
+    fn files_to_match(&self, db: &Database) -> Result<Vec<FileRecord>, String> {
+        if let Some(since) = &self.new_files_since {
+            // No new files is a valid, empty run
+            return db
+                .files_since_generation(since)
+                .map_err(|e| format!("Failed to get new files from database: {}", e));
+        }
+
         let files = db
-            .get_all_files()
+            .get_matchable_files()
             .map_err(|e| format!("Failed to get files from database: {}", e))?;
 
         if files.is_empty() {
             return Err("No files found in database. Please scan a directory first.".to_string());
         }
+        Ok(files)
+    }
+
+    /// Score IDs like `match_and_store` and return how many matches it would
+    /// store, leaving the `matches` table untouched.
+    pub fn estimate_matches(
+        &self,
+        hh_ids: &[String],
+        db: &Database,
+        min_similarity: f64,
+    ) -> Result<usize, String> {
+        let files = self.files_to_match(db)?;
+        Ok(self.match_ids(hh_ids, &files, min_similarity).len())
+    }
+
+    /// Match IDs and store results in database: `db`, or `output` when given
+    /// (see `MatchEngine::set_output`)
+    pub fn match_and_store(
+        &self,
+        hh_ids: &[String],
+        db: &mut Database,
+        output: Option<&mut Database>,
+        min_similarity: f64,
+    ) -> Result<usize, String> {
+        let files = self.files_to_match(db)?;
 
         info!(
             "CPU match pass started: {} household IDs across {} files",
@@ -161,24 +633,27 @@ impl Matcher {
         let matches = self.match_ids(hh_ids, &files, min_similarity);
         let count = matches.len();
 
-        let mut session = db
+        let mut session = match_output(db, output, &files, &matches)?
             .start_match_import()
             .map_err(|e| format!("Failed to start match transaction: {}", e))?;
 
-        // Clear only matches for the hh_ids being processed (incremental update)
-        session
-            .clear_for_ids(hh_ids)
-            .map_err(|e| format!("Failed to clear previous matches: {}", e))?;
-
-        for match_result in matches {
-            session
-                .insert_match(
-                    &match_result.hh_id,
-                    match_result.file_id,
-                    match_result.similarity,
-                )
-                .map_err(|e| format!("Failed to store match: {}", e))?;
+        // Clear only matches for the hh_ids being processed (incremental update),
+        // or just those of the new files when only they were matched
+        if self.new_files_since.is_some() {
+            let file_ids: Vec<i64> = files.iter().map(|file| file.id).collect();
+            session.clear_for_files(&file_ids, min_similarity)
+        } else {
+            session.clear_for_ids(hh_ids, min_similarity)
         }
+        .map_err(|e| format!("Failed to clear previous matches: {}", e))?;
+
+        session
+            .insert_matches(
+                &match_rows(&matches),
+                min_similarity,
+                MatchEngineKind::Cpu.label(),
+            )
+            .map_err(|e| format!("Failed to store matches: {}", e))?;
 
         session
             .commit()
@@ -203,19 +678,61 @@ impl Matcher {
     }
 
     fn normalize_score(score: i64, candidate: &str, query: &str, perfect_score: i64) -> f64 {
+        Self::score_parts(score, candidate, query, perfect_score)
+            .map_or(0.0, |(base, len_ratio)| (base * len_ratio).min(1.0))
+    }
+
+    // Base score and length ratio that `normalize_score` multiplies; `None`
+    // when either is zero
+    fn score_parts(
+        score: i64,
+        candidate: &str,
+        query: &str,
+        perfect_score: i64,
+    ) -> Option<(f64, f64)> {
         if score <= 0 || perfect_score <= 0 {
-            return 0.0;
+            return None;
         }
 
         let base = (score as f64 / perfect_score as f64).min(1.0);
         let candidate_len = candidate.chars().count();
         let query_len = query.chars().count();
         if candidate_len == 0 || query_len == 0 {
-            return 0.0;
+            return None;
         }
         let len_ratio =
             (candidate_len.min(query_len) as f64) / (candidate_len.max(query_len) as f64);
-        (base * len_ratio).min(1.0)
+        Some((base, len_ratio))
+    }
+
+    /// Why `record` matches `hh_id` at `min_similarity` under the current
+    /// settings: the candidate that scored, found the way a match run finds
+    /// it. Under a combination other than `Max` this is the best candidate,
+    /// whose own score can differ from the combined similarity. `None` when
+    /// the file doesn't match.
+    pub fn explain_match(
+        &self,
+        hh_id: &str,
+        record: &FileRecord,
+        min_similarity: f64,
+    ) -> Option<MatchExplanation> {
+        let path_components = if self.include_path_components {
+            self.path_components
+        } else {
+            0
+        };
+        let context = FileMatchContext::from_record(record, path_components, self.case_sensitive);
+        Self::match_single_id(
+            &Self::fuzzy_matcher(self.case_sensitive),
+            hh_id,
+            &[context],
+            min_similarity,
+            self.case_sensitive,
+            self.score_combination,
+            true,
+        )
+        .pop()
+        .and_then(|result| result.explanation)
     }
 
     fn match_single_id(
@@ -223,6 +740,9 @@ impl Matcher {
         hh_id: &str,
         files: &[FileMatchContext],
         min_similarity: f64,
+        case_sensitive: bool,
+        combination: ScoreCombination,
+        explain: bool,
     ) -> Vec<MatchResult> {
         let mut results = Vec::new();
         let trimmed = hh_id.trim();
@@ -230,30 +750,71 @@ impl Matcher {
             return results;
         }
 
-        let needle = trimmed.to_lowercase();
+        let needle = Self::fold_case(trimmed, case_sensitive);
         let perfect_score = Self::perfect_score(matcher, &needle);
+        let numeric_query = needle.chars().all(|c| c.is_ascii_digit());
 
         for context in files {
             let mut best = 0.0;
-            for candidate in &context.candidates {
+            let mut best_candidate = None;
+            let digit_candidates: &[String] = if numeric_query {
+                &context.digit_candidates
+            } else {
+                &[]
+            };
+            let candidates = context
+                .candidates
+                .iter()
+                .map(|(kind, candidate)| (*kind, candidate))
+                .chain(
+                    digit_candidates
+                        .iter()
+                        .map(|digits| (CandidateKind::Digits, digits)),
+                );
+            let mut scored = Vec::new();
+            for (kind, candidate) in candidates {
                 let score_forward = matcher.fuzzy_match(candidate, &needle).unwrap_or(0);
                 let score_reverse = matcher.fuzzy_match(&needle, candidate).unwrap_or(0);
                 let raw_score = score_forward.max(score_reverse);
                 let normalized =
                     Self::normalize_score(raw_score, candidate, &needle, perfect_score);
+                if combination != ScoreCombination::Max {
+                    // Every candidate counts, so no early exit
+                    scored.push((normalized, kind, candidate, raw_score));
+                    continue;
+                }
                 if normalized > best {
                     best = normalized;
+                    best_candidate = Some((kind, candidate, raw_score));
                 }
                 if best >= min_similarity {
                     break;
                 }
             }
+            if combination != ScoreCombination::Max {
+                // Stable, so ties keep candidate order as in max mode
+                scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+                let scores: Vec<f64> = scored.iter().map(|(score, ..)| *score).collect();
+                if let Some(combined) = combination.combine(&scores, min_similarity) {
+                    best = combined;
+                    best_candidate = scored
+                        .first()
+                        .map(|(_, kind, candidate, raw_score)| (*kind, *candidate, *raw_score));
+                }
+            }
 
-            if best >= min_similarity {
+            if let Some((kind, candidate, raw_score)) =
+                best_candidate.filter(|_| best >= min_similarity)
+            {
                 results.push(MatchResult {
                     hh_id: hh_id.to_string(),
                     file_id: context.record.id,
                     similarity: best,
+                    matched_candidate: candidate.clone(),
+                    candidate_kind: kind,
+                    explanation: explain.then(|| {
+                        MatchExplanation::new(kind, candidate, &needle, raw_score, perfect_score)
+                    }),
                 });
             }
         }
@@ -274,3 +835,287 @@ impl Default for Matcher {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: i64, path: &str) -> FileRecord {
+        FileRecord {
+            id,
+            file_path: path.to_string(),
+            file_name: Path::new(path)
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .to_string(),
+            scan_date: String::new(),
+            display_name: None,
+            scan_root: None,
+        }
+    }
+
+    #[test]
+    fn file_contexts_are_reused_until_files_or_settings_change() {
+        let mut files = vec![record(1, "/scans/HH001.tif"), record(2, "/scans/HH002.tif")];
+        let mut matcher = Matcher::new();
+        let first = matcher.file_contexts(&files);
+        assert!(Arc::ptr_eq(&first, &matcher.file_contexts(&files)));
+
+        matcher.set_case_sensitive(true);
+        let case_sensitive = matcher.file_contexts(&files);
+        assert!(!Arc::ptr_eq(&first, &case_sensitive));
+
+        files[1] = record(3, "/scans/HH003.tif");
+        let changed = matcher.file_contexts(&files);
+        assert!(!Arc::ptr_eq(&case_sensitive, &changed));
+        assert_eq!(changed.contexts[1].record.file_name, "HH003.tif");
+    }
+
+    #[test]
+    fn path_candidates_are_nearest_folders_first() {
+        assert_eq!(
+            Matcher::path_candidates("/mnt/archive/2019/HH001/Box 4/scan.tif", false, 3),
+            vec!["box 4", "hh001", "2019"]
+        );
+        assert_eq!(
+            Matcher::path_candidates("/mnt/archive/2019/HH001/Box 4/scan.tif", false, 1),
+            vec!["box 4"]
+        );
+        assert!(Matcher::path_candidates("scan.tif", false, 3).is_empty());
+    }
+
+    #[test]
+    fn digit_runs_are_extracted_from_names() {
+        assert_eq!(
+            Matcher::digit_candidates("scanHH000123v2.tif"),
+            vec!["000123", "123"]
+        );
+        assert_eq!(
+            Matcher::digit_candidates("batch_2019_4471.TIFF"),
+            vec!["2019", "4471"]
+        );
+        assert!(Matcher::digit_candidates("HH_v2.tif").is_empty());
+    }
+
+    #[test]
+    fn numeric_ids_match_embedded_digits() {
+        let files = vec![
+            record(1, "/scans/scanHH000123v2.tif"),
+            record(2, "/scans/scanHH000999v2.tif"),
+        ];
+        let matcher = Matcher::new();
+
+        let numeric = matcher.match_ids(&["000123".to_string()], &files, 0.95);
+        assert_eq!(numeric.len(), 1);
+        assert_eq!(numeric[0].file_id, 1);
+
+        let unpadded = matcher.match_ids(&["123".to_string()], &files, 0.95);
+        assert_eq!(unpadded.len(), 1);
+        assert_eq!(unpadded[0].file_id, 1);
+    }
+
+    #[test]
+    fn matches_record_the_candidate_that_scored() {
+        let files = vec![
+            record(1, "/scans/HH001.tif"),
+            record(2, "/scans/scanHH000123v2.tif"),
+            record(3, "/archive/HH002/scan.tif"),
+        ];
+        let mut matcher = Matcher::new();
+        matcher.set_include_path_components(true);
+        let provenance = |id: &str| {
+            let matches = matcher.match_ids(&[id.to_string()], &files, 0.95);
+            assert_eq!(matches.len(), 1, "{}", id);
+            matches[0].matched_via()
+        };
+
+        assert_eq!(provenance("HH001"), "stem:hh001");
+        assert_eq!(provenance("123"), "digits:123");
+        assert_eq!(provenance("HH002"), "folder:hh002");
+        assert_eq!(
+            parse_matched_via("extracted_id:hh001scan"),
+            Some((CandidateKind::ExtractedId, "hh001scan"))
+        );
+        assert_eq!(parse_matched_via("hh001"), None);
+    }
+
+    #[test]
+    fn alphabetic_ids_ignore_digit_candidates() {
+        let files = vec![record(1, "/scans/ABC000123.tif")];
+        let matches = Matcher::new().match_ids(&["ABC123".to_string()], &files, 0.95);
+        assert!(matches.is_empty());
+    }
+
+    #[test]
+    fn folder_ids_match_only_when_enabled() {
+        let files = vec![
+            record(1, "/archive/HH001/scan.tif"),
+            record(2, "/archive/misc/HH002.tif"),
+        ];
+        let ids = vec!["HH001".to_string()];
+        let mut matcher = Matcher::new();
+
+        assert!(matcher.match_ids(&ids, &files, 0.9).is_empty());
+
+        matcher.set_include_path_components(true);
+        let matches = matcher.match_ids(&ids, &files, 0.9);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].file_id, 1);
+    }
+
+    #[test]
+    fn deeper_folder_ids_need_a_larger_depth() {
+        let files = vec![record(1, "/archive/HH00123/2019/box 4/batch/scan001.tif")];
+        let ids = vec!["HH00123".to_string()];
+        let mut matcher = Matcher::new();
+        matcher.set_include_path_components(true);
+
+        // Four folders up, one past the default
+        assert!(matcher.match_ids(&ids, &files, 0.9).is_empty());
+        matcher.set_path_components(4);
+        let matches = matcher.match_ids(&ids, &files, 0.9);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].matched_via(), "folder:hh00123");
+    }
+
+    #[test]
+    fn case_variant_ids_stay_apart_when_case_sensitive() {
+        let files = vec![
+            record(1, "/scans/HH001a.tif"),
+            record(2, "/scans/HH001A.tif"),
+        ];
+        let ids = vec!["HH001a".to_string(), "HH001A".to_string()];
+        let mut matcher = Matcher::new();
+
+        assert_eq!(matcher.match_ids(&ids, &files, 0.9).len(), 4);
+
+        matcher.set_case_sensitive(true);
+        let mut pairs: Vec<(String, i64)> = matcher
+            .match_ids(&ids, &files, 0.9)
+            .into_iter()
+            .map(|m| (m.hh_id, m.file_id))
+            .collect();
+        pairs.sort();
+        assert_eq!(
+            pairs,
+            vec![("HH001A".to_string(), 2), ("HH001a".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn single_candidate_matches_need_agreement_outside_max_mode() {
+        // The stem and extracted ID both match the first file; only a folder
+        // name matches the second
+        let files = vec![
+            record(1, "/scans/HH001.tif"),
+            record(2, "/archive/HH001/scan.tif"),
+        ];
+        let ids = vec!["HH001".to_string()];
+        let mut matcher = Matcher::new();
+        matcher.set_include_path_components(true);
+        let matched_files = |matcher: &Matcher| {
+            let mut file_ids: Vec<i64> = matcher
+                .match_ids(&ids, &files, 0.9)
+                .into_iter()
+                .map(|m| m.file_id)
+                .collect();
+            file_ids.sort();
+            file_ids
+        };
+
+        assert_eq!(matched_files(&matcher), vec![1, 2]);
+
+        matcher.set_score_combination(ScoreCombination::Weighted);
+        assert_eq!(matched_files(&matcher), vec![1]);
+        let weighted = matcher.match_ids(&ids, &files[1..], 0.5);
+        assert!((weighted[0].similarity - TOP_CANDIDATE_WEIGHT).abs() < 1e-9);
+        assert_eq!(weighted[0].matched_via(), "folder:hh001");
+
+        matcher.set_score_combination(ScoreCombination::Agreement(2));
+        assert_eq!(matched_files(&matcher), vec![1]);
+        matcher.set_score_combination(ScoreCombination::Agreement(3));
+        assert!(matched_files(&matcher).is_empty());
+    }
+
+    #[test]
+    fn explanations_break_down_the_winning_candidate() {
+        let files = vec![
+            record(1, "/scans/HH001.tif"),
+            record(2, "/scans/scanHH000123v2.tif"),
+        ];
+        let mut matcher = Matcher::new();
+        let ids = vec!["HH001".to_string(), "123".to_string()];
+        assert!(matcher
+            .match_ids(&ids, &files, 0.5)
+            .iter()
+            .all(|m| m.explanation.is_none()));
+
+        matcher.set_explain(true);
+        let explained = |id: &str, min_similarity: f64| {
+            let matches = matcher.match_ids(&[id.to_string()], &files, min_similarity);
+            assert_eq!(matches.len(), 1, "{}", id);
+            let explanation = matches[0].explanation.clone().expect("explained");
+            assert_eq!(explanation.normalized, matches[0].similarity);
+            explanation
+        };
+        let exact = explained("HH001", 0.95);
+        assert_eq!(exact.candidate_kind, CandidateKind::Stem);
+        assert_eq!(exact.matched_candidate, "hh001");
+        assert_eq!(exact.raw_score, exact.perfect_score);
+        assert_eq!((exact.length_ratio, exact.normalized), (1.0, 1.0));
+
+        // Only the digit run (without its leading zeros) fits a numeric ID
+        let digits = explained("123", 0.4);
+        assert_eq!(digits.candidate_kind, CandidateKind::Digits);
+        assert_eq!(digits.matched_candidate, "123");
+        assert_eq!(digits.length_ratio, 1.0);
+        assert!((digits.base_score() * digits.length_ratio - digits.normalized).abs() < 1e-9);
+
+        // Combined scores are explained by their best candidate
+        let files = [record(3, "/scans/HH001_scan.tif")];
+        matcher.set_score_combination(ScoreCombination::Weighted);
+        let best = matcher
+            .explain_match("HH001", &files[0], 0.3)
+            .expect("matches");
+        assert_eq!(best.candidate_kind, CandidateKind::ExtractedId);
+        assert_eq!(best.matched_candidate, "hh001scan");
+        assert!(matcher.explain_match("HH999", &files[0], 0.9).is_none());
+    }
+
+    #[test]
+    fn score_combinations_round_trip_through_labels() {
+        for combination in ScoreCombination::CHOICES {
+            assert_eq!(
+                ScoreCombination::from_label(&combination.label()),
+                Some(combination)
+            );
+        }
+        assert_eq!(
+            ScoreCombination::from_label("agree:5"),
+            Some(ScoreCombination::Agreement(5))
+        );
+        assert_eq!(ScoreCombination::from_label("agree:0"), None);
+        assert_eq!(ScoreCombination::from_label("mean"), None);
+    }
+
+    #[test]
+    fn running_match_count_reaches_the_total() {
+        let files: Vec<FileRecord> = (0..100)
+            .map(|i| record(i, &format!("/scans/HH{:03}.tif", i)))
+            .collect();
+        let ids: Vec<String> = (0..100).map(|i| format!("HH{:03}", i)).collect();
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&reports);
+        let mut matcher = Matcher::new();
+        matcher.set_match_count_handle(Arc::new(Mutex::new(move |found| {
+            sink.lock().unwrap().push(found);
+        })));
+
+        let matches = matcher.match_ids(&ids, &files, 0.95);
+        let reports = reports.lock().unwrap();
+        assert!(reports.len() > 1, "one report per chunk of IDs");
+        assert!(reports.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(reports.last(), Some(&matches.len()));
+    }
+}
@@ -1,5 +1,48 @@
 use std::path::Path;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::process::Child;
 use std::process::Command;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::time::{Duration, Instant};
+
+/// How long a launched file manager is watched for an immediate failure exit.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const LAUNCH_GRACE_PERIOD: Duration = Duration::from_millis(250);
+
+/// Longest path Explorer's `/select,` can highlight: `MAX_PATH` less the
+/// terminating NUL. Longer ones open Documents instead.
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+const EXPLORER_MAX_SELECT_PATH: usize = 259;
+
+/// Environment variable that forces a specific Linux file manager command.
+/// The value is split on whitespace and the file path is appended, e.g.
+/// `TIFF_FILE_MANAGER="nautilus --select"`.
+#[cfg(target_os = "linux")]
+pub const FILE_MANAGER_ENV: &str = "TIFF_FILE_MANAGER";
+
+/// Absolute path for a file from the cache. Older caches stored paths relative
+/// to the folder the scan ran from; those are resolved against `scan_root`
+/// (or the current directory when none was recorded).
+pub fn resolve_stored_path(file_path: &str, scan_root: Option<&str>) -> String {
+    let path = Path::new(file_path);
+    if path.is_absolute() {
+        return file_path.to_string();
+    }
+    let resolved = match scan_root {
+        Some(root) => Path::new(root).join(path),
+        None => std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()),
+    };
+    resolved.to_string_lossy().to_string()
+}
+
+/// Opens the file itself in the system's default viewer, without waiting for
+/// the viewer to exit.
+pub fn open_file(file_path: &str) -> Result<(), String> {
+    if !Path::new(file_path).exists() {
+        return Err(format!("File does not exist: {}", file_path));
+    }
+    open::that_detached(file_path).map_err(|e| format!("Failed to open file: {}", e))
+}
 
 /// Opens the file location in the system's default file explorer
 /// Cross-platform support for Windows, macOS, and Linux
@@ -17,15 +60,35 @@ pub fn open_file_location(file_path: &str) -> Result<(), String> {
 
     #[cfg(target_os = "windows")]
     {
-        // On Windows, use explorer.exe with /select flag to highlight the file
-        let result = Command::new("explorer")
-            .args(["/select,", file_path])
-            .spawn();
+        use std::os::windows::process::CommandExt;
 
-        match result {
-            Ok(_) => Ok(()),
-            Err(e) => Err(format!("Failed to open file location: {}", e)),
+        // On Windows, use explorer.exe with /select flag to highlight the file.
+        // Explorer silently falls back to "Documents" when it cannot parse the
+        // path, so pass a quoted, backslash-only path as a single raw argument.
+        // It exits with 1 whether or not the file was selected, so a launched
+        // Explorer can't be told apart from a failed one; share paths it is
+        // known to mishandle skip `/select,` and open their folder instead.
+        let unc = is_unc_path(file_path);
+        if !unc || !select_too_long(file_path) {
+            match Command::new("explorer")
+                .raw_arg(explorer_select_arg(file_path))
+                .spawn()
+            {
+                Ok(_) => return Ok(()),
+                Err(e) if unc => {
+                    log::warn!("explorer /select failed for {}: {}", file_path, e);
+                }
+                Err(e) => return Err(format!("Failed to open file location: {}", e)),
+            }
         }
+
+        let folder = explorer_folder_arg(file_path)
+            .ok_or_else(|| format!("Could not get parent directory for: {}", file_path))?;
+        Command::new("explorer")
+            .raw_arg(folder)
+            .spawn()
+            .map(|_| ())
+            .map_err(|e| format!("Failed to open network folder: {}", e))
     }
 
     #[cfg(target_os = "macos")]
@@ -34,49 +97,294 @@ pub fn open_file_location(file_path: &str) -> Result<(), String> {
         let result = Command::new("open").args(["-R", file_path]).spawn();
 
         match result {
-            Ok(_) => Ok(()),
+            Ok(child) => confirm_launch(child, "open"),
             Err(e) => Err(format!("Failed to open file location: {}", e)),
         }
     }
 
     #[cfg(target_os = "linux")]
     {
-        // On Linux, try different file managers
-        // First try xdg-open on the directory
-        let dir_str = _dir.to_string_lossy();
+        // Prefer launchers that can highlight the file itself; only fall back to
+        // opening the parent directory when none of them are installed.
+        let forced = std::env::var(FILE_MANAGER_ENV).ok();
+        let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some();
+        let candidates = linux::candidates(path, forced.as_deref(), wayland, linux::in_path);
 
-        // Try xdg-open first (most common)
-        if Command::new("xdg-open").arg(&*dir_str).spawn().is_ok() {
-            return Ok(());
+        if candidates.is_empty() {
+            return Err("Could not find a suitable file manager on Linux".to_string());
         }
 
-        // Try nautilus (GNOME)
-        if Command::new("nautilus").arg(&*dir_str).spawn().is_ok() {
-            return Ok(());
+        let mut last_error = None;
+        for candidate in &candidates {
+            match Command::new(&candidate.program)
+                .args(&candidate.args)
+                .spawn()
+            {
+                Ok(child) => match confirm_launch(child, &candidate.program) {
+                    Ok(()) => return Ok(()),
+                    Err(e) => last_error = Some(e),
+                },
+                Err(e) => last_error = Some(format!("{}: {}", candidate.program, e)),
+            }
         }
 
-        // Try dolphin (KDE)
-        if Command::new("dolphin").arg(&*dir_str).spawn().is_ok() {
-            return Ok(());
+        Err(format!(
+            "Failed to open file location: {}",
+            last_error.unwrap_or_else(|| "no file manager launched".to_string())
+        ))
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+    {
+        // Fallback for other platforms - just open the directory
+        open::that(_dir).map_err(|e| format!("Failed to open directory: {}", e))
+    }
+}
+
+/// Most folders `open_locations` opens at once, so a long result page can't
+/// flood the desktop with file manager windows.
+pub const MAX_OPEN_LOCATIONS: usize = 10;
+
+/// Outcome of `open_locations`: how many folders opened, and an error message
+/// for each one that did not.
+#[derive(Debug, Default)]
+pub struct OpenedLocations {
+    pub opened: usize,
+    pub failed: Vec<String>,
+}
+
+/// Reveal several files, opening each containing folder once (with the first
+/// of its files selected). Refuses without opening anything when the files
+/// span more than `MAX_OPEN_LOCATIONS` folders.
+pub fn open_locations(paths: &[&str]) -> Result<OpenedLocations, String> {
+    let files = one_file_per_folder(paths);
+    if files.len() > MAX_OPEN_LOCATIONS {
+        return Err(format!(
+            "Not opening {} folders at once; at most {} are opened together",
+            files.len(),
+            MAX_OPEN_LOCATIONS
+        ));
+    }
+    let mut outcome = OpenedLocations::default();
+    for file in files {
+        match open_file_location(file) {
+            Ok(()) => outcome.opened += 1,
+            Err(e) => outcome.failed.push(e),
         }
+    }
+    Ok(outcome)
+}
+
+/// The first of `paths` in each distinct parent folder, in order.
+fn one_file_per_folder<'a>(paths: &[&'a str]) -> Vec<&'a str> {
+    let mut folders = std::collections::HashSet::new();
+    paths
+        .iter()
+        .copied()
+        .filter(|&path| folders.insert(Path::new(path).parent()))
+        .collect()
+}
 
-        // Try thunar (XFCE)
-        if Command::new("thunar").arg(&*dir_str).spawn().is_ok() {
-            return Ok(());
+/// Build a `cd '<dir>'` shell command for the directory containing `file_path`,
+/// single-quoted so spaces and quotes in the path survive a POSIX shell.
+pub fn quoted_parent_command(file_path: &str) -> Result<String, String> {
+    let dir = Path::new(file_path)
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .ok_or_else(|| format!("Could not get parent directory for: {}", file_path))?;
+    Ok(format!("cd {}", shell_quote(&dir.to_string_lossy())))
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Watch a freshly spawned launcher briefly and report a non-zero exit as an
+/// error. Launchers still running after the grace period are assumed healthy.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn confirm_launch(mut child: Child, program: &str) -> Result<(), String> {
+    let deadline = Instant::now() + LAUNCH_GRACE_PERIOD;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return Ok(()),
+            Ok(Some(status)) => {
+                return Err(format!("{} exited immediately with {}", program, status));
+            }
+            Ok(None) if Instant::now() >= deadline => return Ok(()),
+            Ok(None) => std::thread::sleep(Duration::from_millis(25)),
+            Err(e) => return Err(format!("Failed to check {} status: {}", program, e)),
         }
+    }
+}
 
-        // Try nemo (Cinnamon)
-        if Command::new("nemo").arg(&*dir_str).spawn().is_ok() {
-            return Ok(());
+/// Convert a path into the form Explorer expects: backslash separators only and
+/// no `\\?\` verbatim prefix (which `canonicalize` produces but Explorer rejects).
+/// UNC shares written with forward slashes (`//server/share`) become `\\server\share`,
+/// and repeated separators after the share prefix are collapsed.
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn normalize_windows_path(path: &str) -> String {
+    let native = path.replace('/', "\\");
+    let (prefix, rest) = if let Some(rest) = native.strip_prefix("\\\\?\\UNC\\") {
+        ("\\\\", rest)
+    } else if let Some(rest) = native.strip_prefix("\\\\?\\") {
+        ("", rest)
+    } else if let Some(rest) = native.strip_prefix("\\\\") {
+        ("\\\\", rest)
+    } else {
+        ("", native.as_str())
+    };
+
+    let mut normalized = String::with_capacity(native.len());
+    normalized.push_str(prefix);
+    for ch in rest.trim_start_matches('\\').chars() {
+        if ch == '\\' && normalized.ends_with('\\') && normalized.len() > prefix.len() {
+            continue;
         }
+        normalized.push(ch);
+    }
+    normalized
+}
 
-        Err("Could not find a suitable file manager on Linux".to_string())
+/// True for network share paths (`\\server\share\...`, including the
+/// forward-slash and `\\?\UNC\` spellings). `\\.\` device paths are excluded.
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn is_unc_path(path: &str) -> bool {
+    let normalized = normalize_windows_path(path);
+    normalized.starts_with("\\\\") && !normalized.starts_with("\\\\.\\")
+}
+
+/// Quoted parent folder of `path`, opened directly when `/select,` fails.
+/// Returns `None` when there is no folder to open (e.g. a bare `\\server`).
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn explorer_folder_arg(path: &str) -> Option<String> {
+    let normalized = normalize_windows_path(path);
+    let (parent, _) = normalized.trim_end_matches('\\').rsplit_once('\\')?;
+    let is_bare_server = parent.starts_with("\\\\") && !parent[2..].contains('\\');
+    if parent.is_empty() || parent == "\\" || is_bare_server {
+        return None;
     }
+    Some(format!("\"{}\"", parent))
+}
 
-    #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+/// True when `path` is too long for `/select,` to highlight.
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn select_too_long(path: &str) -> bool {
+    normalize_windows_path(path).chars().count() > EXPLORER_MAX_SELECT_PATH
+}
+
+/// Build the single `/select,"<path>"` argument passed verbatim to Explorer.
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn explorer_select_arg(path: &str) -> String {
+    format!("/select,\"{}\"", normalize_windows_path(path))
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::path::Path;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct LaunchCommand {
+        pub program: String,
+        pub args: Vec<String>,
+    }
+
+    impl LaunchCommand {
+        fn new(program: &str, args: Vec<String>) -> Self {
+            Self {
+                program: program.to_string(),
+                args,
+            }
+        }
+    }
+
+    /// Build the ordered list of commands to try for revealing `file`.
+    ///
+    /// A forced command (from `TIFF_FILE_MANAGER`) is used exclusively. Otherwise
+    /// launchers that select the file come first: the FileManager1 DBus interface
+    /// (preferred on Wayland, where it works across compositors), then managers
+    /// with a select flag. Directory-only launchers are the last resort.
+    pub fn candidates<F>(
+        file: &Path,
+        forced: Option<&str>,
+        wayland: bool,
+        is_available: F,
+    ) -> Vec<LaunchCommand>
+    where
+        F: Fn(&str) -> bool,
     {
-        // Fallback for other platforms - just open the directory
-        open::that(_dir).map_err(|e| format!("Failed to open directory: {}", e))
+        let file_str = file.to_string_lossy().to_string();
+
+        if let Some(forced) = forced {
+            let mut parts = forced.split_whitespace();
+            if let Some(program) = parts.next() {
+                let mut args: Vec<String> = parts.map(str::to_string).collect();
+                args.push(file_str);
+                return vec![LaunchCommand::new(program, args)];
+            }
+        }
+
+        let dbus = LaunchCommand::new(
+            "dbus-send",
+            vec![
+                "--session".to_string(),
+                "--print-reply".to_string(),
+                "--dest=org.freedesktop.FileManager1".to_string(),
+                "--type=method_call".to_string(),
+                "/org/freedesktop/FileManager1".to_string(),
+                "org.freedesktop.FileManager1.ShowItems".to_string(),
+                format!("array:string:{}", file_uri(file)),
+                "string:".to_string(),
+            ],
+        );
+
+        let mut selecting = vec![
+            LaunchCommand::new("nautilus", vec!["--select".to_string(), file_str.clone()]),
+            LaunchCommand::new("dolphin", vec!["--select".to_string(), file_str.clone()]),
+            LaunchCommand::new("nemo", vec![file_str.clone()]),
+        ];
+        if wayland {
+            selecting.insert(0, dbus);
+        } else {
+            selecting.push(dbus);
+        }
+
+        let mut plan: Vec<LaunchCommand> = selecting
+            .into_iter()
+            .filter(|cmd| is_available(&cmd.program))
+            .collect();
+
+        if plan.is_empty() {
+            let dir = file
+                .parent()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_default();
+            plan = ["xdg-open", "thunar"]
+                .into_iter()
+                .filter(|program| is_available(program))
+                .map(|program| LaunchCommand::new(program, vec![dir.clone()]))
+                .collect();
+        }
+
+        plan
+    }
+
+    pub fn in_path(program: &str) -> bool {
+        std::env::var_os("PATH")
+            .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+            .unwrap_or(false)
+    }
+
+    fn file_uri(path: &Path) -> String {
+        let mut uri = String::from("file://");
+        for byte in path.to_string_lossy().bytes() {
+            match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                    uri.push(byte as char)
+                }
+                _ => uri.push_str(&format!("%{:02X}", byte)),
+            }
+        }
+        uri
     }
 }
 
@@ -88,5 +396,215 @@ mod tests {
     fn test_nonexistent_file() {
         let result = open_file_location("/nonexistent/path/file.tif");
         assert!(result.is_err());
+        assert!(open_file("/nonexistent/path/file.tif").is_err());
+    }
+
+    #[test]
+    fn several_files_open_each_folder_once() {
+        let paths = [
+            "/data/2019/HH001.tif",
+            "/data/2020/HH001.tif",
+            "/data/2019/HH001_b.tif",
+        ];
+        assert_eq!(
+            one_file_per_folder(&paths),
+            ["/data/2019/HH001.tif", "/data/2020/HH001.tif"]
+        );
+
+        let outcome = open_locations(&paths).unwrap();
+        assert_eq!(outcome.opened, 0);
+        assert_eq!(outcome.failed.len(), 2);
+
+        let many: Vec<String> = (0..=MAX_OPEN_LOCATIONS)
+            .map(|i| format!("/data/batch{}/HH001.tif", i))
+            .collect();
+        let many: Vec<&str> = many.iter().map(String::as_str).collect();
+        assert!(open_locations(&many).is_err());
+    }
+
+    #[test]
+    fn relative_paths_resolve_against_the_scan_root() {
+        assert_eq!(
+            resolve_stored_path("/data/HH001.tif", Some("/elsewhere")),
+            "/data/HH001.tif"
+        );
+        assert_eq!(
+            resolve_stored_path("scans/HH001.tif", Some("/home/ops")),
+            "/home/ops/scans/HH001.tif"
+        );
+        assert!(Path::new(&resolve_stored_path("scans/HH001.tif", None)).is_absolute());
+    }
+
+    #[test]
+    fn parent_command_quotes_spaces() {
+        assert_eq!(
+            quoted_parent_command("/data/batch 7/HH001.tif").unwrap(),
+            "cd '/data/batch 7'"
+        );
+    }
+
+    #[test]
+    fn parent_command_escapes_single_quotes() {
+        assert_eq!(
+            quoted_parent_command("/data/o'brien's scans/HH001.tif").unwrap(),
+            "cd '/data/o'\\''brien'\\''s scans'"
+        );
+    }
+
+    #[test]
+    fn parent_command_requires_a_directory() {
+        assert!(quoted_parent_command("HH001.tif").is_err());
+    }
+
+    #[test]
+    fn windows_paths_use_backslashes() {
+        assert_eq!(
+            normalize_windows_path("C:/scans/batch 1/HH001.tif"),
+            "C:\\scans\\batch 1\\HH001.tif"
+        );
+        assert_eq!(
+            normalize_windows_path("C:\\scans/mixed\\HH001.tif"),
+            "C:\\scans\\mixed\\HH001.tif"
+        );
+    }
+
+    #[test]
+    fn windows_unc_paths_are_preserved() {
+        assert_eq!(
+            normalize_windows_path("//fileserver/archive/HH001.tif"),
+            "\\\\fileserver\\archive\\HH001.tif"
+        );
+        assert_eq!(
+            normalize_windows_path("\\\\fileserver\\archive\\HH001.tif"),
+            "\\\\fileserver\\archive\\HH001.tif"
+        );
+    }
+
+    #[test]
+    fn windows_verbatim_prefixes_are_stripped() {
+        assert_eq!(
+            normalize_windows_path("\\\\?\\C:\\scans\\HH001.tif"),
+            "C:\\scans\\HH001.tif"
+        );
+        assert_eq!(
+            normalize_windows_path("\\\\?\\UNC\\fileserver\\archive\\HH001.tif"),
+            "\\\\fileserver\\archive\\HH001.tif"
+        );
+    }
+
+    #[test]
+    fn windows_unc_separators_are_collapsed() {
+        assert_eq!(
+            normalize_windows_path("\\\\fileserver\\\\archive//2019\\HH001.tif"),
+            "\\\\fileserver\\archive\\2019\\HH001.tif"
+        );
+        assert_eq!(
+            normalize_windows_path("///fileserver/archive/HH001.tif"),
+            "\\\\fileserver\\archive\\HH001.tif"
+        );
+    }
+
+    #[test]
+    fn unc_paths_are_detected() {
+        assert!(is_unc_path("\\\\fileserver\\archive\\HH001.tif"));
+        assert!(is_unc_path("//fileserver/archive/HH001.tif"));
+        assert!(is_unc_path("\\\\?\\UNC\\fileserver\\archive\\HH001.tif"));
+        assert!(!is_unc_path("C:\\scans\\HH001.tif"));
+        assert!(!is_unc_path("\\\\?\\C:\\scans\\HH001.tif"));
+        assert!(!is_unc_path("\\\\.\\PhysicalDrive0"));
+    }
+
+    #[test]
+    fn unc_folder_fallback_targets_the_share_folder() {
+        assert_eq!(
+            explorer_folder_arg("//fileserver/archive/batch 1/HH001.tif").as_deref(),
+            Some("\"\\\\fileserver\\archive\\batch 1\"")
+        );
+        assert_eq!(
+            explorer_folder_arg("\\\\fileserver\\archive\\HH001.tif").as_deref(),
+            Some("\"\\\\fileserver\\archive\"")
+        );
+        assert_eq!(explorer_folder_arg("\\\\fileserver\\HH001.tif"), None);
+    }
+
+    #[test]
+    fn long_share_paths_skip_select() {
+        let folder = format!("//fileserver/archive/{}", "a".repeat(220));
+        assert!(!select_too_long(&format!("{}/HH001.tif", folder)));
+        assert!(select_too_long(&format!(
+            "{}/{}.tif",
+            folder,
+            "b".repeat(40)
+        )));
+    }
+
+    #[test]
+    fn explorer_argument_is_quoted() {
+        assert_eq!(
+            explorer_select_arg("C:/My Scans/HH001.tif"),
+            "/select,\"C:\\My Scans\\HH001.tif\""
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn immediate_failure_is_reported() {
+        let child = Command::new("false").spawn().expect("spawn false");
+        assert!(confirm_launch(child, "false").is_err());
+
+        let child = Command::new("true").spawn().expect("spawn true");
+        assert!(confirm_launch(child, "true").is_ok());
+    }
+
+    #[cfg(target_os = "linux")]
+    mod linux_selection {
+        use super::super::linux::candidates;
+        use std::path::Path;
+
+        const FILE: &str = "/data/scans/HH 001.tif";
+
+        fn programs(plan: &[super::super::linux::LaunchCommand]) -> Vec<&str> {
+            plan.iter().map(|c| c.program.as_str()).collect()
+        }
+
+        #[test]
+        fn forced_command_is_used_exclusively() {
+            let plan = candidates(Path::new(FILE), Some("pcmanfm --select"), false, |_| true);
+            assert_eq!(plan.len(), 1);
+            assert_eq!(plan[0].program, "pcmanfm");
+            assert_eq!(plan[0].args, vec!["--select", FILE]);
+        }
+
+        #[test]
+        fn wayland_prefers_dbus_show_items() {
+            let plan = candidates(Path::new(FILE), None, true, |_| true);
+            assert_eq!(
+                programs(&plan),
+                vec!["dbus-send", "nautilus", "dolphin", "nemo"]
+            );
+            assert!(plan[0]
+                .args
+                .contains(&"array:string:file:///data/scans/HH%20001.tif".to_string()));
+        }
+
+        #[test]
+        fn x11_prefers_native_select_managers() {
+            let plan = candidates(Path::new(FILE), None, false, |p| p != "nautilus");
+            assert_eq!(programs(&plan), vec!["dolphin", "nemo", "dbus-send"]);
+            assert_eq!(plan[0].args, vec!["--select", FILE]);
+        }
+
+        #[test]
+        fn falls_back_to_directory_openers() {
+            let plan = candidates(Path::new(FILE), None, true, |p| p == "xdg-open");
+            assert_eq!(programs(&plan), vec!["xdg-open"]);
+            assert_eq!(plan[0].args, vec!["/data/scans"]);
+        }
+
+        #[test]
+        fn nothing_available_yields_empty_plan() {
+            let plan = candidates(Path::new(FILE), None, false, |_| false);
+            assert!(plan.is_empty());
+        }
     }
 }
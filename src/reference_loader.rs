@@ -1,8 +1,13 @@
-use crate::database::Database;
+use crate::database::{Database, ReferenceImportSession};
+use calamine::{open_workbook, Data, Reader, Xlsx};
 use csv::ReaderBuilder;
 use log::info;
+use std::fmt::Display;
 use std::fs;
 use std::fs::File;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub struct ReferenceLoadReport {
@@ -10,13 +15,104 @@ pub struct ReferenceLoadReport {
     pub inserted: usize,
     pub skipped: usize,
     pub errors: Vec<String>,
+    pub cancelled: bool,
 }
 
-pub struct ReferenceLoader;
+/// Data rows shown by [`ReferenceLoader::preview`].
+pub const PREVIEW_ROWS: usize = 5;
+
+pub struct ReferenceLoader {
+    cancel_flag: Option<Arc<AtomicBool>>,
+}
 
 impl ReferenceLoader {
     pub fn new() -> Self {
-        ReferenceLoader
+        ReferenceLoader { cancel_flag: None }
+    }
+
+    /// Register a flag that aborts the import when set. A cancelled import
+    /// rolls back every row inserted so far.
+    pub fn set_cancel_flag(&mut self, flag: Arc<AtomicBool>) {
+        self.cancel_flag = Some(flag);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancel_flag
+            .as_ref()
+            .is_some_and(|flag| flag.load(Ordering::Relaxed))
+    }
+
+    /// Load household IDs from `path`, reading `.xlsx` workbooks with
+    /// [`Self::load_from_xlsx_with_progress`] and anything else as CSV.
+    pub fn load_with_progress<F>(
+        &self,
+        path: &str,
+        db: &mut Database,
+        progress_callback: Option<F>,
+    ) -> Result<ReferenceLoadReport, String>
+    where
+        F: FnMut(usize, u64, u64),
+    {
+        if Self::is_xlsx(path) {
+            self.load_from_xlsx_with_progress(path, db, progress_callback)
+        } else {
+            self.load_from_csv_with_progress(path, db, progress_callback)
+        }
+    }
+
+    pub fn is_xlsx(path: &str) -> bool {
+        Path::new(path)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("xlsx"))
+    }
+
+    /// Column holding the household IDs: the first header named `hh_id`,
+    /// ignoring case and surrounding spaces.
+    pub fn id_column<S: AsRef<str>>(headers: &[S]) -> Option<usize> {
+        headers
+            .iter()
+            .position(|h| h.as_ref().trim().eq_ignore_ascii_case("hh_id"))
+    }
+
+    /// Headers and the first [`PREVIEW_ROWS`] rows of a CSV or `.xlsx` file,
+    /// read without touching the database, so the ID column can be checked
+    /// before importing.
+    pub fn preview(path: &str) -> Result<(Vec<String>, Vec<Vec<String>>), String> {
+        if Self::is_xlsx(path) {
+            let mut workbook: Xlsx<_> =
+                open_workbook(path).map_err(|e| format!("Failed to open Excel file: {}", e))?;
+            let sheet = workbook
+                .worksheet_range_at(0)
+                .ok_or_else(|| "Excel file does not contain any sheets".to_string())?
+                .map_err(|e| format!("Failed to read the first sheet: {}", e))?;
+            let mut rows = sheet
+                .rows()
+                .map(|row| row.iter().map(|cell| cell.to_string()).collect());
+            let headers = rows.next().unwrap_or_default();
+            return Ok((headers, rows.take(PREVIEW_ROWS).collect()));
+        }
+
+        let file = File::open(path).map_err(|e| format!("Failed to open CSV file: {}", e))?;
+        let mut reader = ReaderBuilder::new()
+            .has_headers(true)
+            .flexible(true)
+            .from_reader(file);
+        let headers = reader
+            .headers()
+            .map_err(|e| format!("Failed to read CSV headers: {}", e))?
+            .iter()
+            .map(str::to_string)
+            .collect();
+        let rows = reader
+            .records()
+            .take(PREVIEW_ROWS)
+            .map(|record| {
+                record
+                    .map(|r| r.iter().map(str::to_string).collect())
+                    .map_err(|e| format!("Failed to read CSV row: {}", e))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok((headers, rows))
     }
 
     /// Load household IDs from CSV file into the database
@@ -49,22 +145,17 @@ impl ReferenceLoader {
             .headers()
             .map_err(|e| format!("Failed to read CSV headers: {}", e))?;
 
-        let hh_id_index = headers
-            .iter()
-            .position(|h| h.trim().eq_ignore_ascii_case("hh_id"))
+        let headers: Vec<&str> = headers.iter().collect();
+        let hh_id_index = Self::id_column(&headers)
             .ok_or_else(|| "CSV file must contain a 'hh_id' column".to_string())?;
 
-        let mut processed = 0;
-        let mut inserted = 0;
-        let mut skipped = 0;
-        let mut errors = Vec::new();
-
+        let mut tally = ImportTally::default();
         let mut record = csv::StringRecord::new();
         let mut user_callback = progress_callback;
         let mut logger = None;
 
         if user_callback.is_none() {
-            logger = Some(CsvLogger::new(csv_path, total_bytes));
+            logger = Some(ImportLogger::new("CSV", csv_path, total_bytes, "bytes"));
         }
 
         if let Some(cb) = user_callback.as_mut() {
@@ -78,55 +169,37 @@ impl ReferenceLoader {
             .start_reference_import()
             .map_err(|e| format!("Failed to start reference ID transaction: {}", e))?;
 
-        let mut last_logged_percent = 0usize;
-
         loop {
+            if self.is_cancelled() {
+                drop(import_session);
+                info!(
+                    "CSV import cancelled after {} rows; rolled back pending inserts",
+                    tally.processed
+                );
+                return Ok(tally.into_report(true));
+            }
+
             match reader.read_record(&mut record) {
                 Ok(true) => {
-                    processed += 1;
-                    let display_line = line_index + 2;
-
-                    if let Some(raw_hh_id) = record.get(hh_id_index) {
-                        let hh_id = raw_hh_id.trim();
-                        if hh_id.is_empty() {
-                            skipped += 1;
-                            errors.push(format!("Line {}: Empty hh_id value", display_line));
-                        } else {
-                            match import_session.insert(hh_id) {
-                                Ok(true) => inserted += 1,
-                                Ok(false) => skipped += 1,
-                                Err(e) => {
-                                    skipped += 1;
-                                    errors.push(format!("Line {}: {}", display_line, e));
-                                }
-                            }
-                        }
-                    } else {
-                        skipped += 1;
-                        errors.push(format!("Line {}: Missing hh_id column", display_line));
-                    }
-
+                    tally.add(&mut import_session, line_index + 2, record.get(hh_id_index));
                     line_index += 1;
                 }
                 Ok(false) => break,
                 Err(e) => {
-                    processed += 1;
-                    let display_line = line_index + 2;
-                    skipped += 1;
-                    errors.push(format!("Line {}: {}", display_line, e));
+                    tally.row_error(line_index + 2, e);
                     line_index += 1;
                 }
             }
 
             let bytes_read = reader.position().byte();
             if let Some(cb) = user_callback.as_mut() {
-                cb(processed, bytes_read, total_bytes);
+                cb(tally.processed, bytes_read, total_bytes);
             } else if let Some(ref mut log) = logger {
-                log.report(processed, bytes_read, total_bytes);
+                log.report(tally.processed, bytes_read, total_bytes);
             }
         }
 
-        if processed == 0 {
+        if tally.processed == 0 {
             drop(import_session);
             return Err("CSV file did not contain any records".to_string());
         }
@@ -136,41 +209,202 @@ impl ReferenceLoader {
             .map_err(|e| format!("Failed to commit reference IDs: {}", e))?;
 
         if let Some(ref mut log) = logger {
-            log.report(processed, total_bytes, total_bytes);
+            log.report(tally.processed, total_bytes, total_bytes);
         }
 
         info!(
             "CSV import complete: processed {} rows (inserted {}, skipped {})",
-            processed, inserted, skipped
+            tally.processed, tally.inserted, tally.skipped
+        );
+
+        Ok(tally.into_report(false))
+    }
+
+    /// Load household IDs from the first sheet of an Excel workbook. The first
+    /// row must hold an "hh_id" header. Progress is reported in rows: the
+    /// callback receives (rows processed, rows processed, total rows).
+    pub fn load_from_xlsx_with_progress<F>(
+        &self,
+        xlsx_path: &str,
+        db: &mut Database,
+        progress_callback: Option<F>,
+    ) -> Result<ReferenceLoadReport, String>
+    where
+        F: FnMut(usize, u64, u64),
+    {
+        let mut workbook: Xlsx<_> =
+            open_workbook(xlsx_path).map_err(|e| format!("Failed to open Excel file: {}", e))?;
+        let sheet = workbook
+            .worksheet_range_at(0)
+            .ok_or_else(|| "Excel file does not contain any sheets".to_string())?
+            .map_err(|e| format!("Failed to read the first sheet: {}", e))?;
+
+        let mut rows = sheet.rows();
+        let headers = rows
+            .next()
+            .ok_or_else(|| "Excel file did not contain any records".to_string())?;
+        let headers: Vec<String> = headers.iter().map(|cell| cell.to_string()).collect();
+        let hh_id_index = Self::id_column(&headers)
+            .ok_or_else(|| "Excel sheet must contain a 'hh_id' column".to_string())?;
+
+        // Line numbers match the row numbers Excel shows
+        let header_line = sheet.start().map_or(1, |(row, _)| row as usize + 1);
+        let total_rows = sheet.height().saturating_sub(1) as u64;
+
+        info!(
+            "Starting Excel import from '{}' ({} rows)",
+            xlsx_path, total_rows
+        );
+
+        let mut tally = ImportTally::default();
+        let mut user_callback = progress_callback;
+        let mut logger = None;
+
+        if user_callback.is_none() {
+            logger = Some(ImportLogger::new(
+                "Excel",
+                xlsx_path,
+                total_rows.max(1),
+                "rows",
+            ));
+        }
+
+        if let Some(cb) = user_callback.as_mut() {
+            cb(0, 0, total_rows);
+        } else if let Some(ref mut log) = logger {
+            log.report(0, 0, total_rows);
+        }
+
+        let mut import_session = db
+            .start_reference_import()
+            .map_err(|e| format!("Failed to start reference ID transaction: {}", e))?;
+
+        for (index, row) in rows.enumerate() {
+            if self.is_cancelled() {
+                drop(import_session);
+                info!(
+                    "Excel import cancelled after {} rows; rolled back pending inserts",
+                    tally.processed
+                );
+                return Ok(tally.into_report(true));
+            }
+
+            let line = header_line + index + 1;
+            match row.get(hh_id_index) {
+                Some(Data::Error(e)) => tally.row_error(line, format!("Cell error {}", e)),
+                Some(cell) => tally.add(&mut import_session, line, Some(&cell.to_string())),
+                None => tally.add(&mut import_session, line, None),
+            }
+
+            let done = tally.processed as u64;
+            if let Some(cb) = user_callback.as_mut() {
+                cb(tally.processed, done, total_rows);
+            } else if let Some(ref mut log) = logger {
+                log.report(tally.processed, done, total_rows);
+            }
+        }
+
+        if tally.processed == 0 {
+            drop(import_session);
+            return Err("Excel file did not contain any records".to_string());
+        }
+
+        import_session
+            .commit()
+            .map_err(|e| format!("Failed to commit reference IDs: {}", e))?;
+
+        info!(
+            "Excel import complete: processed {} rows (inserted {}, skipped {})",
+            tally.processed, tally.inserted, tally.skipped
         );
 
-        Ok(ReferenceLoadReport {
-            processed,
-            inserted,
-            skipped,
-            errors,
-        })
+        Ok(tally.into_report(false))
+    }
+}
+
+/// Row counts and errors of one import, shared by the CSV and Excel readers so
+/// both validate `hh_id` values the same way.
+#[derive(Default)]
+struct ImportTally {
+    processed: usize,
+    inserted: usize,
+    skipped: usize,
+    errors: Vec<String>,
+}
+
+impl ImportTally {
+    /// Insert the `hh_id` value read on `line`; `None` means the row has no
+    /// such column.
+    fn add(&mut self, session: &mut ReferenceImportSession<'_>, line: usize, raw: Option<&str>) {
+        self.processed += 1;
+        let Some(raw_hh_id) = raw else {
+            self.skip(line, "Missing hh_id column");
+            return;
+        };
+
+        let hh_id = raw_hh_id.trim();
+        if hh_id.is_empty() {
+            self.skip(line, "Empty hh_id value");
+            return;
+        }
+        match session.insert(hh_id) {
+            Ok(true) => self.inserted += 1,
+            Ok(false) => self.skipped += 1,
+            Err(e) => self.skip(line, e),
+        }
+    }
+
+    /// Count a row that could not be read at all.
+    fn row_error(&mut self, line: usize, error: impl Display) {
+        self.processed += 1;
+        self.skip(line, error);
+    }
+
+    fn skip(&mut self, line: usize, reason: impl Display) {
+        self.skipped += 1;
+        self.errors.push(format!("Line {}: {}", line, reason));
+    }
+
+    fn into_report(self, cancelled: bool) -> ReferenceLoadReport {
+        ReferenceLoadReport {
+            processed: self.processed,
+            // A cancelled import is rolled back
+            inserted: if cancelled { 0 } else { self.inserted },
+            skipped: self.skipped,
+            errors: self.errors,
+            cancelled,
+        }
+    }
+}
+
+impl Default for ReferenceLoader {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-struct CsvLogger {
+struct ImportLogger {
+    format: &'static str,
     path: String,
     total_hint: u64,
+    unit: &'static str,
     last_percent: Option<usize>,
 }
 
-impl CsvLogger {
-    fn new(path: &str, total_hint: u64) -> Self {
+impl ImportLogger {
+    fn new(format: &'static str, path: &str, total_hint: u64, unit: &'static str) -> Self {
         Self {
+            format,
             path: path.to_string(),
             total_hint,
+            unit,
             last_percent: None,
         }
     }
 
-    fn report(&mut self, rows: usize, bytes_read: u64, reported_total: u64) {
+    fn report(&mut self, rows: usize, done: u64, reported_total: u64) {
         let total_for_percent = reported_total.max(self.total_hint).max(1);
-        let percent = ((bytes_read as f64 / total_for_percent as f64) * 100.0)
+        let percent = ((done as f64 / total_for_percent as f64) * 100.0)
             .round()
             .clamp(0.0, 100.0) as usize;
         let should_log = match self.last_percent {
@@ -180,10 +414,113 @@ impl CsvLogger {
 
         if should_log {
             info!(
-                "CSV import progress ({}): {}% ({} rows processed, {} / {} bytes)",
-                self.path, percent, rows, bytes_read, total_for_percent
+                "{} import progress ({}): {}% ({} rows processed, {} / {} {})",
+                self.format, self.path, percent, rows, done, total_for_percent, self.unit
             );
             self.last_percent = Some(percent);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn sample_csv() -> String {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("sample_ids.csv")
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[test]
+    fn cancelled_import_rolls_back() {
+        let mut db = Database::new(":memory:").expect("in-memory database");
+        let mut loader = ReferenceLoader::new();
+        let flag = Arc::new(AtomicBool::new(false));
+        loader.set_cancel_flag(Arc::clone(&flag));
+
+        let cancel = Arc::clone(&flag);
+        let report = loader
+            .load_from_csv_with_progress(
+                &sample_csv(),
+                &mut db,
+                Some(move |rows: usize, _: u64, _: u64| {
+                    if rows >= 2 {
+                        cancel.store(true, Ordering::Relaxed);
+                    }
+                }),
+            )
+            .expect("cancelled import still reports");
+
+        assert!(report.cancelled);
+        assert_eq!(report.inserted, 0);
+        assert_eq!(db.get_reference_id_count().unwrap(), 0);
+    }
+
+    #[test]
+    fn uncancelled_import_commits() {
+        let mut db = Database::new(":memory:").expect("in-memory database");
+        let loader = ReferenceLoader::new();
+        let report = loader
+            .load_from_csv_with_progress(&sample_csv(), &mut db, None::<fn(usize, u64, u64)>)
+            .expect("import succeeds");
+
+        assert!(!report.cancelled);
+        assert!(report.inserted > 0);
+        assert_eq!(db.get_reference_id_count().unwrap(), report.inserted);
+    }
+
+    fn sample_xlsx() -> String {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test_data/sample_ids.xlsx")
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[test]
+    fn preview_reads_the_first_rows_without_importing() {
+        let (headers, rows) = ReferenceLoader::preview(&sample_csv()).unwrap();
+        assert_eq!(headers, vec!["hh_id"]);
+        assert_eq!(rows.len(), PREVIEW_ROWS);
+        assert_eq!(rows[0], vec!["HH001"]);
+        assert_eq!(ReferenceLoader::id_column(&headers), Some(0));
+
+        let (headers, rows) = ReferenceLoader::preview(&sample_xlsx()).unwrap();
+        assert_eq!(headers, vec!["Household", "hh_id"]);
+        assert_eq!(ReferenceLoader::id_column(&headers), Some(1));
+        assert_eq!(rows[2], vec!["Cruz", "1001"]);
+        assert_eq!(rows[3], vec!["Garcia", ""]);
+
+        assert_eq!(ReferenceLoader::id_column(&["name", " HH_ID "]), Some(1));
+        assert_eq!(ReferenceLoader::id_column(&["id"]), None);
+    }
+
+    #[test]
+    fn excel_ids_are_read_from_the_hh_id_column() {
+        let xlsx = sample_xlsx();
+        let mut db = Database::new(":memory:").expect("in-memory database");
+        let mut last_progress = None;
+        let report = ReferenceLoader::new()
+            .load_with_progress(
+                &xlsx,
+                &mut db,
+                Some(|rows: usize, done: u64, total: u64| {
+                    last_progress = Some((rows, done, total))
+                }),
+            )
+            .expect("import succeeds");
+
+        // HH001, HH002 and the numeric 1001; row 5 is blank and row 6 repeats HH001
+        assert_eq!(report.processed, 5);
+        assert_eq!(report.inserted, 3);
+        assert_eq!(report.skipped, 2);
+        assert_eq!(report.errors, vec!["Line 5: Empty hh_id value"]);
+        assert_eq!(last_progress, Some((5, 5, 5)));
+        assert_eq!(
+            db.get_all_reference_ids().unwrap(),
+            vec!["1001", "HH001", "HH002"]
+        );
+    }
+}
@@ -1,11 +1,16 @@
-use crate::database::Database;
+use crate::database::{Database, FileStamp, TiffMetadata};
+use chrono::Utc;
 use log::{info, warn};
 use rayon::iter::ParallelBridge;
 use rayon::prelude::*;
-use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
-use walkdir::WalkDir;
+use std::time::{SystemTime, UNIX_EPOCH};
+use walkdir::{DirEntry, WalkDir};
 
 type ProgressCallback = Arc<Mutex<dyn FnMut(usize, usize) + Send>>;
 
@@ -13,24 +18,161 @@ type ProgressCallback = Arc<Mutex<dyn FnMut(usize, usize) + Send>>;
 pub struct TiffFile {
     pub path: PathBuf,
     pub name: String,
+    /// `None` when the file's metadata could not be read.
+    pub stamp: Option<FileStamp>,
 }
 
 pub struct Scanner {
     progress_callback: Option<ProgressCallback>,
+    follow_links: bool,
+    read_metadata: bool,
+    // Inclusive byte bounds on the TIFFs collected (see `set_size_range`)
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    // Only TIFFs modified at or after this are collected (see `set_modified_after`)
+    modified_after: Option<SystemTime>,
 }
 
+/// Upper bounds of the first size buckets in `ScanReport::by_size` (under
+/// 1 MB, 1-10 MB, 10-100 MB); the last bucket holds everything larger.
+pub const SIZE_BUCKET_LIMITS: [u64; 3] = [1 << 20, 10 << 20, 100 << 20];
+
+/// Display names of the `ScanReport::by_size` buckets.
+pub const SIZE_BUCKET_LABELS: [&str; SIZE_BUCKET_LIMITS.len() + 1] =
+    ["under 1 MB", "1-10 MB", "10-100 MB", "100 MB and up"];
+
 #[derive(Debug, Clone)]
 pub struct ScanReport {
     pub discovered: usize,
+    /// Files previously cached under the same root that no longer exist.
+    pub pruned: usize,
+    /// Files not cached before this scan.
+    pub added: usize,
+    /// Cached files that were stored again. A full scan counts every
+    /// previously cached file here.
+    pub changed: usize,
+    /// Cached files left untouched because their size and modification time
+    /// match. Always 0 for a full scan.
+    pub skipped: usize,
+    /// Files found per lowercase extension, e.g. "tif" and "tiff".
+    pub by_extension: HashMap<String, usize>,
+    /// Combined size of the files found; files whose size could not be
+    /// read are left out.
+    pub total_bytes: u64,
+    /// Files found per size bucket (see [`SIZE_BUCKET_LIMITS`]).
+    pub by_size: [usize; SIZE_BUCKET_LIMITS.len() + 1],
+    /// The folder holds no TIFF files at all, not even ones the size or
+    /// modification-time filters left out: most likely the wrong folder.
+    pub empty: bool,
+}
+
+/// Extension, byte and size-bucket tallies of the files a scan found.
+#[derive(Default)]
+struct FileTally {
+    by_extension: HashMap<String, usize>,
+    total_bytes: u64,
+    by_size: [usize; SIZE_BUCKET_LIMITS.len() + 1],
+}
+
+impl FileTally {
+    fn add(mut self, file: &TiffFile) -> Self {
+        let extension = file
+            .path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        *self.by_extension.entry(extension).or_insert(0) += 1;
+        if let Some(stamp) = file.stamp {
+            let size = stamp.size.max(0) as u64;
+            self.total_bytes += size;
+            let bucket = SIZE_BUCKET_LIMITS
+                .iter()
+                .position(|&limit| size < limit)
+                .unwrap_or(SIZE_BUCKET_LIMITS.len());
+            self.by_size[bucket] += 1;
+        }
+        self
+    }
+
+    fn merge(mut self, other: Self) -> Self {
+        for (extension, count) in other.by_extension {
+            *self.by_extension.entry(extension).or_insert(0) += count;
+        }
+        self.total_bytes += other.total_bytes;
+        for (total, count) in self.by_size.iter_mut().zip(other.by_size) {
+            *total += count;
+        }
+        self
+    }
 }
 
 impl Scanner {
     pub fn new() -> Self {
         Scanner {
             progress_callback: None,
+            follow_links: false,
+            read_metadata: false,
+            min_size: None,
+            max_size: None,
+            modified_after: None,
         }
     }
 
+    /// Only collect TIFFs modified at or after `after`, e.g. the start of the
+    /// previous scan. Such a scan only sees part of the folder, so it never
+    /// prunes cached files. Files whose modification time the platform can't
+    /// report are collected, with a warning.
+    pub fn set_modified_after(&mut self, after: Option<SystemTime>) {
+        self.modified_after = after;
+    }
+
+    /// Only collect TIFFs of at least `min` and at most `max` bytes, e.g. to
+    /// leave out zero-byte placeholders or huge bundles; `None` leaves that
+    /// end open. Skipped files still count toward the walked total, and ones
+    /// cached by an earlier scan are pruned like missing files.
+    pub fn set_size_range(&mut self, min: Option<u64>, max: Option<u64>) {
+        self.min_size = min;
+        self.max_size = max;
+    }
+
+    /// Read each stored file's TIFF header for its page count and size (see
+    /// `Database::get_file_metadata`). Off by default because it opens every
+    /// file; files skipped by an incremental scan keep what was read before.
+    pub fn set_read_metadata(&mut self, read: bool) {
+        self.read_metadata = read;
+    }
+
+    /// Page count and first-page dimensions from the TIFF header at `path`,
+    /// without decoding any pixels.
+    pub fn read_tiff_metadata(path: &Path) -> Result<TiffMetadata, String> {
+        let file = File::open(path).map_err(|e| format!("Failed to open: {}", e))?;
+        let mut decoder = tiff::decoder::Decoder::new(BufReader::new(file))
+            .map_err(|e| format!("Not a readable TIFF: {}", e))?;
+        let (width, height) = decoder
+            .dimensions()
+            .map_err(|e| format!("Failed to read dimensions: {}", e))?;
+        let mut page_count = 1;
+        while decoder.more_images() {
+            decoder
+                .next_image()
+                .map_err(|e| format!("Failed to read page {}: {}", page_count + 1, e))?;
+            page_count += 1;
+        }
+        Ok(TiffMetadata {
+            page_count,
+            width,
+            height,
+        })
+    }
+
+    /// Descend into symlinked folders and pick up symlinked files. Off by
+    /// default, since a link back up the tree would otherwise be walked
+    /// forever; when on, each folder is visited once however many links
+    /// lead to it.
+    pub fn set_follow_links(&mut self, follow: bool) {
+        self.follow_links = follow;
+    }
+
     pub fn set_progress_callback<F>(&mut self, callback: F)
     where
         F: FnMut(usize, usize) + Send + 'static,
@@ -38,19 +180,47 @@ impl Scanner {
         self.progress_callback = Some(Arc::new(Mutex::new(callback)));
     }
 
-    /// Scan directory for TIFF files
-    pub fn scan_directory(&self, dir_path: &str) -> Result<Vec<TiffFile>, String> {
+    /// Absolute form of a folder to scan, so stored paths stay valid when the
+    /// working directory changes. `.` and `..` are resolved by name; symlinks
+    /// and UNC prefixes are left as typed.
+    pub fn scan_root(dir_path: &str) -> Result<PathBuf, String> {
         let path = Path::new(dir_path);
-
         if !path.exists() {
             return Err(format!("Directory does not exist: {}", dir_path));
         }
+        let absolute = std::path::absolute(path)
+            .map_err(|e| format!("Failed to resolve {}: {}", dir_path, e))?;
+        let mut root = PathBuf::new();
+        for component in absolute.components() {
+            match component {
+                Component::CurDir => {}
+                // `..` above the root stays at the root, as the OS does
+                Component::ParentDir => {
+                    if matches!(root.components().next_back(), Some(Component::Normal(_))) {
+                        root.pop();
+                    }
+                }
+                other => root.push(other),
+            }
+        }
+        Ok(root)
+    }
+
+    /// Scan directory for TIFF files. Returned paths are absolute.
+    pub fn scan_directory(&self, dir_path: &str) -> Result<Vec<TiffFile>, String> {
+        self.find_tiffs(dir_path).map(|(files, _)| files)
+    }
+
+    /// The TIFF files `scan_directory` returns, and how many more the size and
+    /// modification-time filters left out.
+    fn find_tiffs(&self, dir_path: &str) -> Result<(Vec<TiffFile>, usize), String> {
+        let root = Self::scan_root(dir_path)?;
+        let path = root.as_path();
 
         info!("Starting filesystem walk at {}", path.display());
 
-        let total = WalkDir::new(path)
-            .follow_links(true)
-            .into_iter()
+        let total = self
+            .walk(path)
             .filter_map(|entry| match entry {
                 Ok(e) => {
                     if e.file_type().is_file() {
@@ -63,6 +233,9 @@ impl Scanner {
             })
             .count();
         let processed = Arc::new(AtomicUsize::new(0));
+        let out_of_range = AtomicUsize::new(0);
+        let unmodified = AtomicUsize::new(0);
+        let mtime_warned = AtomicBool::new(false);
         let mut progress = self.progress_callback.clone();
 
         if total > 0 && progress.is_none() {
@@ -76,13 +249,12 @@ impl Scanner {
         }
 
         // Second pass: filter TIFF files in parallel
-        let tiff_files: Vec<TiffFile> = WalkDir::new(path)
-            .follow_links(true)
-            .into_iter()
+        let tiff_files: Vec<TiffFile> = self
+            .walk(path)
             .filter_map(|entry| match entry {
                 Ok(e) => {
                     if e.file_type().is_file() {
-                        Some(e.into_path())
+                        Some(e)
                     } else {
                         None
                     }
@@ -94,7 +266,7 @@ impl Scanner {
             })
             .par_bridge()
             .filter_map(|entry| {
-                let path = entry.as_path();
+                let path = entry.path();
 
                 if let Some(ext) = path.extension() {
                     let ext_str = ext.to_string_lossy().to_lowercase();
@@ -107,9 +279,19 @@ impl Scanner {
 
                         Self::report_progress(&progress, &processed, total);
 
+                        if !self.size_in_range(&entry) {
+                            out_of_range.fetch_add(1, Ordering::Relaxed);
+                            return None;
+                        }
+                        if !self.modified_since(&entry, &mtime_warned) {
+                            unmodified.fetch_add(1, Ordering::Relaxed);
+                            return None;
+                        }
+
                         return Some(TiffFile {
                             path: path.to_path_buf(),
                             name,
+                            stamp: Self::file_stamp(&entry),
                         });
                     }
                 }
@@ -126,41 +308,250 @@ impl Scanner {
             tiff_files.len(),
             total
         );
+        let out_of_range = out_of_range.into_inner();
+        if out_of_range > 0 {
+            info!(
+                "Skipped {} TIFF files outside the size range in {}",
+                out_of_range, dir_path
+            );
+        }
+        let unmodified = unmodified.into_inner();
+        if unmodified > 0 {
+            info!(
+                "Skipped {} TIFF files not modified since the cutoff in {}",
+                unmodified, dir_path
+            );
+        }
+
+        Ok((tiff_files, out_of_range + unmodified))
+    }
 
-        Ok(tiff_files)
+    /// Whether `entry` passes the size range; always true when no range is
+    /// set. Files whose size can't be read are logged and left out.
+    fn size_in_range(&self, entry: &DirEntry) -> bool {
+        if self.min_size.is_none() && self.max_size.is_none() {
+            return true;
+        }
+        let size = match entry.metadata() {
+            Ok(metadata) => metadata.len(),
+            Err(e) => {
+                warn!(
+                    "Skipping {}: could not read its size: {}",
+                    entry.path().display(),
+                    e
+                );
+                return false;
+            }
+        };
+        self.min_size.is_none_or(|min| size >= min) && self.max_size.is_none_or(|max| size <= max)
+    }
+
+    /// Whether `entry` was modified at or after the cutoff; always true when
+    /// none is set. Without a modification time the file is kept, and the
+    /// first such file of a scan logs a warning.
+    fn modified_since(&self, entry: &DirEntry, warned: &AtomicBool) -> bool {
+        let Some(after) = self.modified_after else {
+            return true;
+        };
+        let modified = entry
+            .metadata()
+            .map_err(|e| e.to_string())
+            .and_then(|metadata| metadata.modified().map_err(|e| e.to_string()));
+        match modified {
+            Ok(modified) => modified >= after,
+            Err(e) => {
+                if !warned.swap(true, Ordering::Relaxed) {
+                    warn!(
+                        "Modification time unavailable for {} ({}); including such files",
+                        entry.path().display(),
+                        e
+                    );
+                }
+                true
+            }
+        }
+    }
+
+    /// Entries under `root`. When following links, a folder whose canonical
+    /// path was already walked is skipped, which stops symlink loops and
+    /// double counting of folders reachable through several links.
+    fn walk(&self, root: &Path) -> impl Iterator<Item = walkdir::Result<DirEntry>> {
+        let follow_links = self.follow_links;
+        let mut visited = HashSet::new();
+        WalkDir::new(root)
+            .follow_links(follow_links)
+            .into_iter()
+            .filter_entry(move |entry| {
+                if !follow_links || !entry.file_type().is_dir() {
+                    return true;
+                }
+                let Ok(dir) = entry.path().canonicalize() else {
+                    return true;
+                };
+                let first_visit = visited.insert(dir);
+                if !first_visit {
+                    warn!(
+                        "Skipping {}: folder already scanned through another link",
+                        entry.path().display()
+                    );
+                }
+                first_visit
+            })
     }
 
-    /// Scan directory and store results in database
+    /// Scan directory and store results in database. The folder is registered as
+    /// a scan root, and files cached under it that were not found again are
+    /// pruned; other roots are untouched.
     pub fn scan_and_store(&self, dir_path: &str, db: &mut Database) -> Result<ScanReport, String> {
-        let tiff_files = self.scan_directory(dir_path)?;
+        self.store(dir_path, db, false)
+    }
+
+    /// Like `scan_and_store`, but only stores files that are new or whose size
+    /// or modification time differs from the cached copy. The folder is still
+    /// walked to find new and removed files.
+    pub fn scan_incremental(
+        &self,
+        dir_path: &str,
+        db: &mut Database,
+    ) -> Result<ScanReport, String> {
+        self.store(dir_path, db, true)
+    }
+
+    fn store(
+        &self,
+        dir_path: &str,
+        db: &mut Database,
+        incremental: bool,
+    ) -> Result<ScanReport, String> {
+        let scan_started = Utc::now().to_rfc3339();
+        let (tiff_files, filtered) = self.find_tiffs(dir_path)?;
         let count = tiff_files.len();
+        let root = Self::scan_root(dir_path)?;
+        let root_str = root.to_string_lossy().to_string();
+
+        let cached = db
+            .get_file_stamps(&root_str)
+            .map_err(|e| format!("Failed to read cached files: {}", e))?;
+
+        let tally = tiff_files
+            .par_iter()
+            .fold(FileTally::default, FileTally::add)
+            .reduce(FileTally::default, FileTally::merge);
 
         let mut session = db
             .start_file_import()
             .map_err(|e| format!("Failed to start file import transaction: {}", e))?;
 
         // Store files in database
+        let (mut added, mut changed, mut skipped) = (0, 0, 0);
+        let mut stored = Vec::with_capacity(tiff_files.len());
         for file in &tiff_files {
             let path_str = file.path.to_string_lossy().to_string();
+            match cached.get(&path_str) {
+                None => added += 1,
+                Some(Some(stamp)) if incremental && Some(*stamp) == file.stamp => {
+                    skipped += 1;
+                    continue;
+                }
+                Some(_) => changed += 1,
+            }
+            stored.push((file, path_str));
+        }
+        // Header reads are I/O bound, so they run in parallel before the writes
+        let metadata: Vec<Option<TiffMetadata>> = if self.read_metadata {
+            stored
+                .par_iter()
+                .map(|(file, _)| match Self::read_tiff_metadata(&file.path) {
+                    Ok(metadata) => Some(metadata),
+                    Err(e) => {
+                        warn!("Skipping metadata of {}: {}", file.path.display(), e);
+                        None
+                    }
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        for (index, (file, path_str)) in stored.iter().enumerate() {
+            let display_name = file
+                .path
+                .strip_prefix(&root)
+                .map(|relative| relative.to_string_lossy().to_string())
+                .ok();
             session
-                .upsert_file(&path_str, &file.name)
+                .upsert_scanned_file(
+                    path_str,
+                    &file.name,
+                    display_name.as_deref(),
+                    Some(&root_str),
+                    file.stamp,
+                )
                 .map_err(|e| format!("Database error storing {}: {}", file.name, e))?;
+            if self.read_metadata {
+                session
+                    .set_file_metadata(path_str, metadata[index])
+                    .map_err(|e| format!("Database error storing {}: {}", file.name, e))?;
+            }
         }
 
+        // Skipped files keep their old scan date, so prune by path instead.
+        // A scan limited by modification time only saw part of the folder.
+        let pruned = if self.modified_after.is_some() {
+            0
+        } else if incremental {
+            let found: HashSet<String> = tiff_files
+                .iter()
+                .map(|file| file.path.to_string_lossy().to_string())
+                .collect();
+            let mut pruned = 0;
+            for path in cached.keys().filter(|path| !found.contains(*path)) {
+                pruned += session
+                    .remove_file(path)
+                    .map_err(|e| format!("Failed to prune {}: {}", path, e))?;
+            }
+            pruned
+        } else {
+            session
+                .prune_root(&root_str, &scan_started)
+                .map_err(|e| format!("Failed to prune missing files: {}", e))?
+        };
+        session
+            .touch_scan_root(&root_str, &scan_started)
+            .map_err(|e| format!("Failed to record scan root: {}", e))?;
+
         session
             .commit()
             .map_err(|e| format!("Failed to commit file import: {}", e))?;
 
         info!(
-            "Persisted {} TIFF files from {} into cache database.",
-            count, dir_path
+            "Persisted {} TIFF files from {} into cache database ({} added, {} changed, {} unchanged, {} missing files pruned).",
+            count, dir_path, added, changed, skipped, pruned
         );
 
-        Ok(ScanReport { discovered: count })
+        Ok(ScanReport {
+            discovered: count,
+            pruned,
+            added,
+            changed,
+            skipped,
+            by_extension: tally.by_extension,
+            total_bytes: tally.total_bytes,
+            by_size: tally.by_size,
+            empty: count == 0 && filtered == 0,
+        })
     }
 }
 
 impl Scanner {
+    fn file_stamp(entry: &DirEntry) -> Option<FileStamp> {
+        let metadata = entry.metadata().ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(FileStamp {
+            size: metadata.len() as i64,
+            modified: modified.as_nanos() as i64,
+        })
+    }
+
     fn report_progress(
         callback: &Option<ProgressCallback>,
         processed: &Arc<AtomicUsize>,
@@ -254,6 +645,149 @@ mod tests {
         assert!(scanner.progress_callback.is_none());
     }
 
+    #[test]
+    #[cfg(unix)]
+    fn symlink_loops_do_not_hang_the_scan() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("2019");
+        std::fs::create_dir(&nested).unwrap();
+        std::fs::write(nested.join("HH001.tif"), b"II*\0").unwrap();
+        // A link back to the top and a second route into the same folder
+        std::os::unix::fs::symlink(dir.path(), nested.join("loop")).unwrap();
+        std::os::unix::fs::symlink(&nested, dir.path().join("latest")).unwrap();
+        let folder = dir.path().to_str().unwrap();
+
+        let mut scanner = Scanner::new();
+        let files = scanner.scan_directory(folder).unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].path.ends_with("2019/HH001.tif"));
+
+        scanner.set_follow_links(true);
+        let files = scanner.scan_directory(folder).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name, "HH001.tif");
+    }
+
+    #[test]
+    fn size_range_skips_files_outside_the_bounds() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("HH001.tif"), b"").unwrap();
+        std::fs::write(dir.path().join("HH002.tif"), [0u8; 10]).unwrap();
+        std::fs::write(dir.path().join("HH003.tif"), [0u8; 100]).unwrap();
+        let folder = dir.path().to_str().unwrap();
+
+        let mut scanner = Scanner::new();
+        let walked = Arc::new(Mutex::new(0));
+        let walked_total = Arc::clone(&walked);
+        scanner.set_progress_callback(move |_, total| *walked_total.lock().unwrap() = total);
+        scanner.set_size_range(Some(1), Some(50));
+        let files = scanner.scan_directory(folder).unwrap();
+        let names: Vec<&str> = files.iter().map(|file| file.name.as_str()).collect();
+        assert_eq!(names, ["HH002.tif"]);
+        assert_eq!(*walked.lock().unwrap(), 3);
+
+        scanner.set_size_range(Some(10), None);
+        let mut names: Vec<String> = scanner
+            .scan_directory(folder)
+            .unwrap()
+            .into_iter()
+            .map(|file| file.name)
+            .collect();
+        names.sort();
+        assert_eq!(names, ["HH002.tif", "HH003.tif"]);
+    }
+
+    #[test]
+    fn modified_after_skips_older_files_without_pruning_them() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = dir.path().join("HH001.tif");
+        std::fs::write(&old, b"II*\0").unwrap();
+        let cutoff = SystemTime::now() - std::time::Duration::from_secs(3600);
+        std::fs::File::options()
+            .write(true)
+            .open(&old)
+            .unwrap()
+            .set_modified(cutoff - std::time::Duration::from_secs(86_400))
+            .unwrap();
+        std::fs::write(dir.path().join("HH002.tif"), b"II*\0").unwrap();
+        let folder = dir.path().to_str().unwrap();
+
+        let mut db = Database::new(":memory:").unwrap();
+        let mut scanner = Scanner::new();
+        scanner.scan_and_store(folder, &mut db).unwrap();
+
+        scanner.set_modified_after(Some(cutoff));
+        let files = scanner.scan_directory(folder).unwrap();
+        let names: Vec<&str> = files.iter().map(|file| file.name.as_str()).collect();
+        assert_eq!(names, ["HH002.tif"]);
+
+        let report = scanner.scan_and_store(folder, &mut db).unwrap();
+        assert_eq!((report.discovered, report.pruned), (1, 0));
+        assert_eq!(db.get_file_count().unwrap(), 2);
+    }
+
+    #[test]
+    fn metadata_pass_records_page_counts() {
+        use tiff::encoder::{colortype, TiffEncoder};
+
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let file = std::fs::File::create(dir.path().join("HH001_bundle.tif")).unwrap();
+            let mut encoder = TiffEncoder::new(file).unwrap();
+            for _ in 0..3 {
+                encoder
+                    .write_image::<colortype::Gray8>(40, 20, &[128; 40 * 20])
+                    .unwrap();
+            }
+        }
+        // Not a TIFF inside, so its metadata is skipped with a warning
+        std::fs::write(dir.path().join("HH002.tif"), b"not a tiff").unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let root = Scanner::scan_root(folder).unwrap();
+        let path_of = |name: &str| root.join(name).to_string_lossy().to_string();
+
+        let mut db = Database::new(":memory:").unwrap();
+        let mut scanner = Scanner::new();
+        scanner.scan_and_store(folder, &mut db).unwrap();
+        assert_eq!(
+            db.get_file_metadata(&path_of("HH001_bundle.tif")).unwrap(),
+            None
+        );
+
+        scanner.set_read_metadata(true);
+        let report = scanner.scan_and_store(folder, &mut db).unwrap();
+        assert_eq!(report.discovered, 2);
+        assert_eq!(
+            db.get_file_metadata(&path_of("HH001_bundle.tif")).unwrap(),
+            Some(TiffMetadata {
+                page_count: 3,
+                width: 40,
+                height: 20
+            })
+        );
+        assert_eq!(db.get_file_metadata(&path_of("HH002.tif")).unwrap(), None);
+    }
+
+    #[test]
+    fn empty_folders_are_told_apart_from_filtered_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("notes.txt"), b"not a scan").unwrap();
+        let folder = dir.path().to_str().unwrap();
+        let mut db = Database::new(":memory:").unwrap();
+
+        let mut scanner = Scanner::new();
+        let report = scanner.scan_and_store(folder, &mut db).unwrap();
+        assert_eq!(report.discovered, 0);
+        assert!(report.empty);
+
+        // A TIFF outside the size range is still a TIFF in the right folder
+        std::fs::write(dir.path().join("HH001.tif"), b"II*\0").unwrap();
+        scanner.set_size_range(Some(1024), None);
+        let report = scanner.scan_and_store(folder, &mut db).unwrap();
+        assert_eq!(report.discovered, 0);
+        assert!(!report.empty);
+    }
+
     #[test]
     fn test_scan_finds_test_data_files() {
         let scanner = Scanner::new();
@@ -264,4 +798,90 @@ mod tests {
             .expect("scanner should succeed on test data");
         assert_eq!(files.len(), 15);
     }
+
+    #[test]
+    fn scan_report_breaks_files_down_by_extension_and_size() {
+        let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let data_dir = manifest_dir.join("test_data").join("tiff_files");
+        let mut db = Database::new(":memory:").unwrap();
+        let report = Scanner::new()
+            .scan_and_store(data_dir.to_str().unwrap(), &mut db)
+            .unwrap();
+
+        let expected: HashMap<String, usize> =
+            [("tif".to_string(), 9), ("tiff".to_string(), 6)].into();
+        assert_eq!(report.by_extension, expected);
+        // The fixtures are empty placeholders
+        assert_eq!(report.total_bytes, 0);
+        assert_eq!(report.by_size, [15, 0, 0, 0]);
+    }
+
+    #[test]
+    fn relative_folders_are_stored_as_absolute_paths() {
+        let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let parent = manifest_dir.parent().expect("manifest has a parent");
+        let relative = PathBuf::from("..")
+            .join(manifest_dir.strip_prefix(parent).unwrap())
+            .join("test_data")
+            .join("tiff_files");
+        // Tests run from the manifest directory, so this points at the fixtures
+        let mut db = Database::new(":memory:").unwrap();
+        Scanner::new()
+            .scan_and_store(relative.to_str().unwrap(), &mut db)
+            .unwrap();
+
+        let files = db.get_all_files().unwrap();
+        assert_eq!(files.len(), 15);
+        for file in &files {
+            assert!(
+                Path::new(&file.file_path).is_absolute(),
+                "{}",
+                file.file_path
+            );
+            assert_eq!(file.display_name.as_deref(), Some(file.file_name.as_str()));
+            assert!(file.scan_root.as_deref().unwrap().ends_with("tiff_files"));
+        }
+        // The `..` is resolved, so the same folder gets the same root either way
+        assert_eq!(
+            Scanner::scan_root(relative.to_str().unwrap()).unwrap(),
+            manifest_dir.join("test_data").join("tiff_files")
+        );
+    }
+
+    #[test]
+    fn incremental_scan_only_stores_changed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["HH001.tif", "HH002.tif", "HH003.tif"] {
+            std::fs::write(dir.path().join(name), b"II*\0").unwrap();
+        }
+        let folder = dir.path().to_str().unwrap();
+        let mut db = Database::new(":memory:").unwrap();
+        let scanner = Scanner::new();
+        scanner.scan_and_store(folder, &mut db).unwrap();
+
+        let report = scanner.scan_incremental(folder, &mut db).unwrap();
+        assert_eq!((report.added, report.changed, report.skipped), (0, 0, 3));
+
+        // A size change is detected regardless of the file system's mtime resolution
+        std::fs::write(dir.path().join("HH002.tif"), b"II*\0 rescanned").unwrap();
+        std::fs::remove_file(dir.path().join("HH003.tif")).unwrap();
+        std::fs::write(dir.path().join("HH004.tif"), b"II*\0").unwrap();
+
+        let report = scanner.scan_incremental(folder, &mut db).unwrap();
+        assert_eq!(report.discovered, 3);
+        assert_eq!((report.added, report.changed, report.skipped), (1, 1, 1));
+        assert_eq!(report.pruned, 1);
+        assert_eq!(report.total_bytes, 4 + 14 + 4);
+
+        let root = Scanner::scan_root(folder).unwrap();
+        let stamps = db.get_file_stamps(&root.to_string_lossy()).unwrap();
+        let size_of = |name: &str| {
+            stamps[&root.join(name).to_string_lossy().to_string()]
+                .unwrap()
+                .size
+        };
+        assert_eq!(size_of("HH002.tif"), 14);
+        assert_eq!(size_of("HH004.tif"), 4);
+        assert_eq!(stamps.len(), 3);
+    }
 }
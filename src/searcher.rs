@@ -1,67 +1,373 @@
-use crate::database::{Database, SearchResult};
+use crate::database::{Database, MatchStatus, ResultOrder, ScanGeneration, SearchResult};
+use crate::matcher::{
+    format_matched_via, CandidateKind, MatchExplanation, Matcher, DEFAULT_PATH_COMPONENTS,
+    MAX_PATH_COMPONENTS,
+};
 use fuzzy_matcher::skim::SkimMatcherV2;
 use fuzzy_matcher::FuzzyMatcher;
-use log::debug;
+use log::{debug, info};
 use rayon::prelude::*;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Index slot that can be shared between `Searcher`s on different threads so
+/// the preprocessed candidates survive the per-search `Searcher` instances.
+pub type SharedSearchIndex = Arc<Mutex<Option<Arc<SearchIndex>>>>;
+
+/// Engine label recorded for matches cached from interactive searches.
+pub const SEARCH_ENGINE_LABEL: &str = "search";
+
+/// Stage of a search, reported in order to a `SearchProgressCallback`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SearchPhase {
+    /// Dropping files whose names can't reach the threshold; counts files.
+    Prefiltering,
+    /// Fuzzy-scoring the files that are left; counts candidates.
+    Scoring,
+}
+
+impl SearchPhase {
+    pub fn label(self) -> &'static str {
+        match self {
+            SearchPhase::Prefiltering => "Prefiltering files",
+            SearchPhase::Scoring => "Scoring candidates",
+        }
+    }
+}
+
+/// Receives (phase, done, total) while a search runs.
+pub type SearchProgressCallback = Arc<Mutex<dyn FnMut(SearchPhase, usize, usize) + Send>>;
+
+/// Search box input: the household ID, then optional terms that narrow the
+/// results by file name. `-draft` drops files whose name contains "draft" and
+/// `+back` keeps only names containing "back"; once a term has started, plain
+/// words are required too. Everything before the first term is the ID, so
+/// IDs with spaces in them (`HH 001`) and bare IDs search exactly as written.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchQuery {
+    pub id: String,
+    pub required: Vec<String>,
+    pub excluded: Vec<String>,
+}
+
+impl SearchQuery {
+    pub fn parse(text: &str) -> Self {
+        // The first token is always part of the ID, even with a leading dash
+        let is_term = |token: &str| token.len() > 1 && token.starts_with(['-', '+']);
+        let Some(first_term) = text.split_whitespace().skip(1).find(|token| is_term(token)) else {
+            return SearchQuery {
+                id: text.to_string(),
+                ..SearchQuery::default()
+            };
+        };
+        // `split_whitespace` hands out slices of `text`
+        let offset = first_term.as_ptr() as usize - text.as_ptr() as usize;
+        let mut query = SearchQuery {
+            id: text[..offset].trim().to_string(),
+            ..SearchQuery::default()
+        };
+        for token in text[offset..].split_whitespace() {
+            match (token.strip_prefix('-'), token.strip_prefix('+')) {
+                (Some(term), _) if !term.is_empty() => query.excluded.push(term.to_string()),
+                (_, Some(term)) if !term.is_empty() => query.required.push(term.to_string()),
+                _ => query.required.push(token.to_string()),
+            }
+        }
+        query
+    }
+
+    /// Whether any term beyond the ID was given.
+    pub fn has_terms(&self) -> bool {
+        !self.required.is_empty() || !self.excluded.is_empty()
+    }
+
+    // `name_key` is a file name already folded for `case_sensitive`
+    fn keeps(&self, name_key: &str, case_sensitive: bool) -> bool {
+        let contains = |term: &String| name_key.contains(&Matcher::fold_case(term, case_sensitive));
+        self.required.iter().all(contains) && !self.excluded.iter().any(contains)
+    }
+}
+
+/// Filename candidates for every cached file, tagged with the scan generation,
+/// case mode and folder depth they were built from. Candidates are lowercased
+/// unless the index is case-sensitive. Folder names are only kept when path
+/// matching is on, so the usual index holds no extra strings per file.
+pub struct SearchIndex {
+    generation: ScanGeneration,
+    case_sensitive: bool,
+    // Enclosing folders kept per file; 0 without path matching
+    path_components: usize,
+    entries: Vec<IndexedFile>,
+}
+
+// A file in the score band, with the candidate that put it there and its raw
+// fuzzy score
+type Scored<'a> = (&'a IndexedFile, f64, CandidateKind, &'a str, i64);
+
+struct IndexedFile {
+    file_name: String,
+    file_path: String,
+    name_key: String,
+    stem_key: Option<String>,
+    // Enclosing folder names, nearest first; empty unless path matching is on
+    dir_keys: Vec<String>,
+    scan_date: String,
+    display_name: Option<String>,
+    scan_root: Option<String>,
+}
+
+impl SearchIndex {
+    fn build(
+        db: &Database,
+        generation: ScanGeneration,
+        case_sensitive: bool,
+        path_components: usize,
+    ) -> Result<Self, String> {
+        let files = db
+            .get_all_files()
+            .map_err(|e| format!("Failed to get files from database: {}", e))?;
+
+        let entries = files
+            .into_par_iter()
+            .map(|file| {
+                let name_key = Matcher::fold_case(&file.file_name, case_sensitive);
+                let stem_key = Searcher::strip_tiff_suffix(&file.file_name)
+                    .map(|stem| Matcher::fold_case(stem, case_sensitive));
+                let dir_keys =
+                    Matcher::path_candidates(&file.file_path, case_sensitive, path_components);
+                IndexedFile {
+                    file_name: file.file_name,
+                    file_path: file.file_path,
+                    name_key,
+                    stem_key,
+                    dir_keys,
+                    scan_date: file.scan_date,
+                    display_name: file.display_name,
+                    scan_root: file.scan_root,
+                }
+            })
+            .collect();
+
+        Ok(SearchIndex {
+            generation,
+            case_sensitive,
+            path_components,
+            entries,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
 
 pub struct Searcher {
     matcher: SkimMatcherV2,
+    index: SharedSearchIndex,
+    include_path_components: bool,
+    path_components: usize,
+    case_sensitive: bool,
+    result_order: ResultOrder,
+    explain: bool,
 }
 
 impl Searcher {
     pub fn new() -> Self {
+        Self::with_shared_index(SharedSearchIndex::default())
+    }
+
+    /// Create a searcher that reads and refreshes the given shared index.
+    pub fn with_shared_index(index: SharedSearchIndex) -> Self {
         Searcher {
-            matcher: SkimMatcherV2::default(),
+            matcher: Matcher::fuzzy_matcher(false),
+            index,
+            include_path_components: false,
+            path_components: DEFAULT_PATH_COMPONENTS,
+            case_sensitive: false,
+            result_order: ResultOrder::default(),
+            explain: false,
         }
     }
 
+    /// Attach a [`MatchExplanation`] to every result. Off by default.
+    pub fn set_explain(&mut self, enabled: bool) {
+        self.explain = enabled;
+    }
+
+    /// Compare the query and file names as written instead of lowercasing
+    /// both. Off by default. Switching modes rebuilds the shared index.
+    pub fn set_case_sensitive(&mut self, enabled: bool) {
+        self.case_sensitive = enabled;
+        self.matcher = Matcher::fuzzy_matcher(enabled);
+    }
+
+    /// Also score the enclosing folder names of each file, not just the
+    /// file name. Off by default. Switching it rebuilds the shared index.
+    pub fn set_include_path_components(&mut self, enabled: bool) {
+        self.include_path_components = enabled;
+    }
+
+    /// How many enclosing folders, nearest first, path matching scores; kept
+    /// within `1..=MAX_PATH_COMPONENTS`. `DEFAULT_PATH_COMPONENTS` by default.
+    pub fn set_path_components(&mut self, depth: usize) {
+        self.path_components = depth.clamp(1, MAX_PATH_COMPONENTS);
+    }
+
+    // Folders the index needs to keep per file
+    fn indexed_path_components(&self) -> usize {
+        if self.include_path_components {
+            self.path_components
+        } else {
+            0
+        }
+    }
+
+    /// How results with equal scores are ordered. File name by default.
+    pub fn set_result_order(&mut self, order: ResultOrder) {
+        self.result_order = order;
+    }
+
+    /// Make sure the cached index matches the current file set, rebuilding it
+    /// only when the scan generation has changed.
+    pub fn refresh_index(&self, db: &Database) -> Result<Arc<SearchIndex>, String> {
+        self.current_index(db)
+    }
+
+    fn current_index(&self, db: &Database) -> Result<Arc<SearchIndex>, String> {
+        let generation = db
+            .get_scan_generation()
+            .map_err(|e| format!("Failed to read scan generation: {}", e))?;
+
+        let mut slot = self
+            .index
+            .lock()
+            .map_err(|e| format!("Search index lock poisoned: {}", e))?;
+
+        let path_components = self.indexed_path_components();
+        if let Some(index) = slot.as_ref() {
+            if index.generation == generation
+                && index.case_sensitive == self.case_sensitive
+                && index.path_components == path_components
+            {
+                return Ok(Arc::clone(index));
+            }
+        }
+
+        let index = Arc::new(SearchIndex::build(
+            db,
+            generation,
+            self.case_sensitive,
+            path_components,
+        )?);
+        info!("Built search index for {} files", index.len());
+        *slot = Some(Arc::clone(&index));
+        Ok(index)
+    }
+
     /// Search for a single household ID against all TIFF files in the database
-    /// Returns results sorted by similarity score (highest first)
+    /// Returns results sorted by similarity score (highest first), ties ordered
+    /// by the `ResultOrder`
+    /// Only scores within `[min_similarity, max_similarity]` are kept; `max_similarity`
+    /// defaults to 1.0.
+    /// The optional progress callback first sees the prefilter over every
+    /// file, then the scoring of the candidates it kept, so the second phase's
+    /// total is the candidate count rather than the file count.
+    /// `hh_id` is parsed as a [`SearchQuery`]; its terms filter the scored
+    /// results by file name.
     pub fn search_single_id(
         &self,
         hh_id: &str,
         db: &Database,
         min_similarity: f64,
+        max_similarity: Option<f64>,
+        progress_callback: Option<SearchProgressCallback>,
     ) -> Result<Vec<SearchResult>, String> {
-        // Get all files from database
-        let files = db
-            .get_all_files()
-            .map_err(|e| format!("Failed to get files from database: {}", e))?;
+        let index = self.current_index(db)?;
+        let max_similarity = max_similarity.unwrap_or(1.0);
+        let in_band = |score: f64| score >= min_similarity && score <= max_similarity;
+
+        // Read per search, so blocking a file doesn't rebuild the index
+        let blocked: HashSet<String> = db
+            .get_blocklist()
+            .map_err(|e| format!("Failed to read the blocklist: {}", e))?
+            .into_iter()
+            .collect();
+
+        let query = SearchQuery::parse(hh_id);
+        let needle = Matcher::fold_case(&query.id, self.case_sensitive);
+        let total = index.entries.len();
+        Self::report_phase(&progress_callback, SearchPhase::Prefiltering, 0, total);
+        let candidates = self.prefilter(&index, &needle, min_similarity, &blocked);
+        Self::report_phase(&progress_callback, SearchPhase::Prefiltering, total, total);
 
-        if files.is_empty() {
+        let total = candidates.len();
+        Self::report_phase(&progress_callback, SearchPhase::Scoring, 0, total);
+        if candidates.is_empty() {
             return Ok(Vec::new());
         }
+        debug!(
+            "Prefilter kept {} of {} files for '{}'",
+            total,
+            index.entries.len(),
+            hh_id
+        );
 
-        let needle = hh_id.to_lowercase();
+        let processed = AtomicUsize::new(0);
         let perfect_score = Self::perfect_score(&self.matcher, &needle);
-        let mut results: Vec<SearchResult> = files
-            .par_iter()
+        let mut matches: Vec<Scored> = candidates
+            .into_par_iter()
             .filter_map(|file| {
-                let file_name_lower = file.file_name.to_lowercase();
+                Self::report_progress(&progress_callback, &processed, total);
 
-                if let Some(score) = self.matcher.fuzzy_match(&file_name_lower, &needle) {
+                if let Some(score) = self.matcher.fuzzy_match(&file.name_key, &needle) {
                     let normalized_score =
-                        Self::normalize_score(score, &file_name_lower, &needle, perfect_score);
-                    if normalized_score >= min_similarity {
-                        return Some(SearchResult {
-                            file_name: file.file_name.clone(),
-                            file_path: file.file_path.clone(),
-                            similarity_score: normalized_score,
-                        });
+                        Self::normalize_score(score, &file.name_key, &needle, perfect_score);
+                    if in_band(normalized_score) {
+                        return Some((
+                            file,
+                            normalized_score,
+                            CandidateKind::FileName,
+                            file.name_key.as_str(),
+                            score,
+                        ));
                     }
                 }
 
-                if let Some(stem) = Self::strip_tiff_suffix(&file.file_name) {
-                    let stem_lower = stem.to_lowercase();
-                    if let Some(score) = self.matcher.fuzzy_match(&stem_lower, &needle) {
+                if let Some(stem_key) = &file.stem_key {
+                    if let Some(score) = self.matcher.fuzzy_match(stem_key, &needle) {
                         let normalized_score =
-                            Self::normalize_score(score, &stem_lower, &needle, perfect_score);
-                        if normalized_score >= min_similarity {
-                            return Some(SearchResult {
-                                file_name: file.file_name.clone(),
-                                file_path: file.file_path.clone(),
-                                similarity_score: normalized_score,
-                            });
+                            Self::normalize_score(score, stem_key, &needle, perfect_score);
+                        if in_band(normalized_score) {
+                            return Some((
+                                file,
+                                normalized_score,
+                                CandidateKind::Stem,
+                                stem_key.as_str(),
+                                score,
+                            ));
+                        }
+                    }
+                }
+
+                if self.include_path_components {
+                    for dir_key in &file.dir_keys {
+                        if let Some(score) = self.matcher.fuzzy_match(dir_key, &needle) {
+                            let normalized_score =
+                                Self::normalize_score(score, dir_key, &needle, perfect_score);
+                            if in_band(normalized_score) {
+                                return Some((
+                                    file,
+                                    normalized_score,
+                                    CandidateKind::Folder,
+                                    dir_key.as_str(),
+                                    score,
+                                ));
+                            }
                         }
                     }
                 }
@@ -69,37 +375,142 @@ impl Searcher {
                 None
             })
             .collect();
+        if query.has_terms() {
+            matches.retain(|(file, ..)| query.keeps(&file.name_key, self.case_sensitive));
+        }
 
-        // Sort by similarity score (highest first)
-        results.sort_by(|a, b| {
-            b.similarity_score
-                .partial_cmp(&a.similarity_score)
+        // Sort by similarity score (highest first), then by the tie-break so
+        // equal scores come out the same on every run
+        matches.sort_by(|(a, a_score, ..), (b, b_score, ..)| {
+            b_score
+                .partial_cmp(a_score)
                 .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| match self.result_order {
+                    ResultOrder::FileName => std::cmp::Ordering::Equal,
+                    ResultOrder::ScanDate => b.scan_date.cmp(&a.scan_date),
+                })
+                .then_with(|| a.file_name.cmp(&b.file_name))
+                .then_with(|| a.file_path.cmp(&b.file_path))
         });
 
-        Ok(results)
+        let to_result =
+            |(file, similarity_score, kind, candidate, raw_score): Scored| SearchResult {
+                file_name: file.file_name.clone(),
+                file_path: file.file_path.clone(),
+                similarity_score,
+                threshold: Some(min_similarity),
+                engine: Some(SEARCH_ENGINE_LABEL.to_string()),
+                display_name: file.display_name.clone(),
+                scan_root: file.scan_root.clone(),
+                matched_via: Some(format_matched_via(kind, candidate)),
+                status: MatchStatus::Pending,
+                explanation: self.explain.then(|| {
+                    MatchExplanation::new(kind, candidate, &needle, raw_score, perfect_score)
+                }),
+            };
+        Ok(matches.into_iter().map(to_result).collect())
     }
 
     /// Store search results in the database (optional - for caching)
+    /// `min_similarity` is recorded so later searches with a lower threshold
+    /// recompute instead of reusing this narrower set. Written in one
+    /// transaction, since low thresholds can return most of the cache.
     pub fn store_results(
         &self,
         hh_id: &str,
+        min_similarity: f64,
         results: &[SearchResult],
-        db: &Database,
+        db: &mut Database,
     ) -> Result<(), String> {
-        db.clear_matches_for_id(hh_id)
+        let mut session = db
+            .start_match_import()
+            .map_err(|e| format!("Failed to start match transaction: {}", e))?;
+        session
+            .clear_for_ids(&[hh_id.to_string()], min_similarity)
             .map_err(|e| format!("Failed to clear previous matches: {}", e))?;
 
+        let mut rows = Vec::with_capacity(results.len());
         for result in results {
-            let file_id = db
-                .get_file_id(&result.file_path)
+            let file_id = session
+                .file_id(&result.file_path)
                 .map_err(|e| format!("Failed to fetch file id for {}: {}", result.file_path, e))?;
+            rows.push((
+                hh_id.to_string(),
+                file_id,
+                result.similarity_score,
+                result.matched_via.clone(),
+            ));
+        }
+        session
+            .insert_matches(&rows, min_similarity, SEARCH_ENGINE_LABEL)
+            .map_err(|e| format!("Failed to persist matches for {}: {}", hh_id, e))?;
+
+        session
+            .commit()
+            .map_err(|e| format!("Failed to commit matches: {}", e))
+    }
+
+    // Unblocked files with a name (or, with path matching, a folder) whose
+    // length could still reach `min_similarity`. A normalized score never
+    // exceeds the length ratio of candidate and query, so this drops nothing
+    // scoring would have kept and is much cheaper than fuzzy matching every file.
+    fn prefilter<'a>(
+        &self,
+        index: &'a SearchIndex,
+        needle: &str,
+        min_similarity: f64,
+        blocked: &HashSet<String>,
+    ) -> Vec<&'a IndexedFile> {
+        let needle_len = needle.chars().count();
+        let reachable = |key: &str| {
+            let key_len = key.chars().count();
+            let ratio = if key_len == 0 || needle_len == 0 {
+                0.0
+            } else {
+                key_len.min(needle_len) as f64 / key_len.max(needle_len) as f64
+            };
+            ratio >= min_similarity
+        };
+        index
+            .entries
+            .par_iter()
+            .filter(|file| !blocked.contains(&file.file_path))
+            .filter(|file| {
+                reachable(&file.name_key)
+                    || file.stem_key.as_deref().is_some_and(reachable)
+                    || (self.include_path_components
+                        && file.dir_keys.iter().any(|key| reachable(key)))
+            })
+            .collect()
+    }
+
+    fn report_phase(
+        callback: &Option<SearchProgressCallback>,
+        phase: SearchPhase,
+        done: usize,
+        total: usize,
+    ) {
+        if let Some(cb_handle) = callback {
+            if let Ok(mut cb) = cb_handle.lock() {
+                cb(phase, done, total);
+            }
+        }
+    }
 
-            db.insert_match(hh_id, file_id, result.similarity_score)
-                .map_err(|e| format!("Failed to persist match for {}: {}", hh_id, e))?;
+    fn report_progress(
+        callback: &Option<SearchProgressCallback>,
+        processed: &AtomicUsize,
+        total: usize,
+    ) {
+        if callback.is_none() {
+            return;
         }
 
-        Ok(())
+        let current = processed.fetch_add(1, Ordering::Relaxed) + 1;
+        let step = (total / 100).max(1);
+        if current.is_multiple_of(step) || current == total {
+            Self::report_phase(callback, SearchPhase::Scoring, current.min(total), total);
+        }
     }
 
     fn normalize_score(score: i64, candidate: &str, query: &str, perfect_score: i64) -> f64 {
@@ -140,10 +551,438 @@ impl Searcher {
     }
 }
 
+impl Default for Searcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn db_with_files(count: usize) -> Database {
+        let mut db = Database::new(":memory:").expect("in-memory database");
+        {
+            let mut session = db.start_file_import().unwrap();
+            for i in 0..count {
+                let name = format!("HH{:03}_scan.tif", i);
+                session
+                    .upsert_file(&format!("/scans/{}", name), &name)
+                    .unwrap();
+            }
+            session.commit().unwrap();
+        }
+        db
+    }
+
+    #[test]
+    fn index_is_reused_until_files_change() {
+        let mut db = db_with_files(10);
+        let searcher = Searcher::new();
+        let first = searcher.refresh_index(&db).unwrap();
+        let second = searcher.refresh_index(&db).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+
+        {
+            let mut session = db.start_file_import().unwrap();
+            session.upsert_file("/scans/new.tif", "new.tif").unwrap();
+            session.commit().unwrap();
+        }
+        let third = searcher.refresh_index(&db).unwrap();
+        assert!(!Arc::ptr_eq(&second, &third));
+        assert_eq!(third.len(), 11);
+    }
+
+    #[test]
+    fn shared_index_survives_searcher_instances() {
+        let db = db_with_files(10);
+        let shared = SharedSearchIndex::default();
+        let built = Searcher::with_shared_index(Arc::clone(&shared))
+            .refresh_index(&db)
+            .unwrap();
+        let reused = Searcher::with_shared_index(shared)
+            .refresh_index(&db)
+            .unwrap();
+        assert!(Arc::ptr_eq(&built, &reused));
+    }
+
+    #[test]
+    fn cached_index_gives_the_same_results_as_a_fresh_one() {
+        let db = db_with_files(2000);
+        let queries = ["HH001", "HH042", "HH999", "HH1500"];
+        let rounds = 5;
+
+        let mut uncached = Vec::new();
+        for _ in 0..rounds {
+            for query in queries {
+                uncached.push(
+                    Searcher::new()
+                        .search_single_id(query, &db, 0.5, None, None)
+                        .unwrap(),
+                );
+            }
+        }
+
+        let searcher = Searcher::new();
+        let built = searcher.refresh_index(&db).unwrap();
+        let mut cached = Vec::new();
+        for _ in 0..rounds {
+            for query in queries {
+                cached.push(
+                    searcher
+                        .search_single_id(query, &db, 0.5, None, None)
+                        .unwrap(),
+                );
+            }
+        }
+        // Every query reused the one index instead of building its own
+        assert!(Arc::ptr_eq(&built, &searcher.refresh_index(&db).unwrap()));
+
+        assert_eq!(uncached.len(), cached.len());
+        for (a, b) in uncached.iter().zip(&cached) {
+            let a: Vec<&str> = a.iter().map(|r| r.file_path.as_str()).collect();
+            let b: Vec<&str> = b.iter().map(|r| r.file_path.as_str()).collect();
+            assert_eq!(a, b);
+        }
+    }
+
+    #[test]
+    fn tied_scores_are_ordered_by_file_name() {
+        let mut db = db_with_files(0);
+        {
+            let mut session = db.start_file_import().unwrap();
+            for path in [
+                "/scans/HH001_c.tif",
+                "/z/HH001_a.tif",
+                "/scans/HH001_b.tif",
+                "/a/HH001_a.tif",
+            ] {
+                let name = path.rsplit('/').next().unwrap();
+                session.upsert_file(path, name).unwrap();
+            }
+            session.commit().unwrap();
+        }
+
+        let results = Searcher::new()
+            .search_single_id("HH001", &db, 0.5, None, None)
+            .unwrap();
+        assert!(results
+            .iter()
+            .all(|r| r.similarity_score == results[0].similarity_score));
+        let paths: Vec<&str> = results.iter().map(|r| r.file_path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "/a/HH001_a.tif",
+                "/z/HH001_a.tif",
+                "/scans/HH001_b.tif",
+                "/scans/HH001_c.tif"
+            ]
+        );
+    }
+
+    #[test]
+    fn search_band_excludes_scores_above_max() {
+        let mut db = db_with_files(20);
+        {
+            let mut session = db.start_file_import().unwrap();
+            session
+                .upsert_file("/scans/HH001.tif", "HH001.tif")
+                .unwrap();
+            session.commit().unwrap();
+        }
+        let searcher = Searcher::new();
+
+        let all = searcher
+            .search_single_id("HH001", &db, 0.6, None, None)
+            .unwrap();
+        assert!(all.iter().any(|r| r.file_name == "HH001.tif"));
+
+        let band = searcher
+            .search_single_id("HH001", &db, 0.6, Some(0.9), None)
+            .unwrap();
+        assert!(band.iter().all(|r| r.file_name != "HH001.tif"));
+        assert!(band
+            .iter()
+            .all(|r| r.similarity_score >= 0.6 && r.similarity_score <= 0.9));
+    }
+
+    #[test]
+    fn folder_names_match_only_when_enabled() {
+        let mut db = db_with_files(3);
+        {
+            let mut session = db.start_file_import().unwrap();
+            session
+                .upsert_file("/archive/HH777/scan.tif", "scan.tif")
+                .unwrap();
+            session
+                .upsert_file("/archive/HH888/2019/box/batch/scan.tif", "scan.tif")
+                .unwrap();
+            session.commit().unwrap();
+        }
+        let mut searcher = Searcher::new();
+        let paths = |searcher: &Searcher, hh_id: &str| -> Vec<String> {
+            searcher
+                .search_single_id(hh_id, &db, 0.8, None, None)
+                .unwrap()
+                .into_iter()
+                .map(|result| result.file_path)
+                .collect()
+        };
+
+        assert!(paths(&searcher, "HH777").is_empty());
+
+        searcher.set_include_path_components(true);
+        assert_eq!(paths(&searcher, "HH777"), ["/archive/HH777/scan.tif"]);
+        // Four folders up is past the default depth
+        assert!(paths(&searcher, "HH888").is_empty());
+        searcher.set_path_components(4);
+        assert_eq!(
+            paths(&searcher, "HH888"),
+            ["/archive/HH888/2019/box/batch/scan.tif"]
+        );
+    }
+
+    #[test]
+    fn terms_after_the_id_filter_file_names() {
+        let mut db = Database::new(":memory:").unwrap();
+        {
+            let mut session = db.start_file_import().unwrap();
+            for name in ["HH001_front.tif", "HH001_back.tif", "HH001_back_DRAFT.tif"] {
+                session
+                    .upsert_file(&format!("/scans/{}", name), name)
+                    .unwrap();
+            }
+            session.commit().unwrap();
+        }
+        let mut searcher = Searcher::new();
+        let names = |searcher: &Searcher, query: &str| -> Vec<String> {
+            searcher
+                .search_single_id(query, &db, 0.2, None, None)
+                .unwrap()
+                .into_iter()
+                .map(|result| result.file_name)
+                .collect()
+        };
+
+        assert_eq!(names(&searcher, "HH001").len(), 3);
+        assert_eq!(
+            names(&searcher, "HH001 -draft"),
+            ["HH001_back.tif", "HH001_front.tif"]
+        );
+        assert_eq!(names(&searcher, "HH001 +back -draft"), ["HH001_back.tif"]);
+        assert_eq!(names(&searcher, "HH001 -draft back"), ["HH001_back.tif"]);
+        searcher.set_case_sensitive(true);
+        assert_eq!(names(&searcher, "HH001 -draft").len(), 3);
+
+        // A lone token is the ID exactly as typed, even with a leading dash
+        assert_eq!(SearchQuery::parse("-HH001").id, "-HH001");
+        assert!(!SearchQuery::parse("HH001").has_terms());
+        assert_eq!(
+            SearchQuery::parse(" HH001  +back -draft "),
+            SearchQuery {
+                id: "HH001".to_string(),
+                required: vec!["back".to_string()],
+                excluded: vec!["draft".to_string()],
+            }
+        );
+        // Words before the first term belong to the ID
+        assert_eq!(
+            SearchQuery::parse("HH 001"),
+            SearchQuery {
+                id: "HH 001".to_string(),
+                ..SearchQuery::default()
+            }
+        );
+        assert_eq!(
+            SearchQuery::parse("HH  001 -draft"),
+            SearchQuery {
+                id: "HH  001".to_string(),
+                required: Vec::new(),
+                excluded: vec!["draft".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn explained_results_show_how_they_scored() {
+        let db = db_with_files(3);
+        let mut searcher = Searcher::new();
+        let results = searcher
+            .search_single_id("HH001", &db, 0.5, None, None)
+            .unwrap();
+        assert!(results.iter().all(|result| result.explanation.is_none()));
+
+        searcher.set_explain(true);
+        let results = searcher
+            .search_single_id("HH001", &db, 0.5, None, None)
+            .unwrap();
+        assert!(!results.is_empty());
+        for result in &results {
+            let explanation = result.explanation.as_ref().expect("explained");
+            assert_eq!(explanation.normalized, result.similarity_score);
+            assert_eq!(
+                result.matched_via.as_deref(),
+                Some(
+                    format_matched_via(explanation.candidate_kind, &explanation.matched_candidate)
+                        .as_str()
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn case_variant_ids_stay_apart_when_case_sensitive() {
+        let mut db = Database::new(":memory:").unwrap();
+        {
+            let mut session = db.start_file_import().unwrap();
+            for name in ["HH001a.tif", "HH001A.tif"] {
+                session
+                    .upsert_file(&format!("/scans/{}", name), name)
+                    .unwrap();
+            }
+            session.commit().unwrap();
+        }
+        let mut searcher = Searcher::new();
+        let names = |searcher: &Searcher, hh_id: &str| -> Vec<String> {
+            searcher
+                .search_single_id(hh_id, &db, 0.9, None, None)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.file_name)
+                .collect()
+        };
+
+        assert_eq!(names(&searcher, "HH001a").len(), 2);
+
+        searcher.set_case_sensitive(true);
+        assert_eq!(names(&searcher, "HH001a"), ["HH001a.tif"]);
+        assert_eq!(names(&searcher, "HH001A"), ["HH001A.tif"]);
+        assert!(names(&searcher, "hh001a").is_empty());
+    }
+
+    #[test]
+    fn lowering_threshold_recomputes_instead_of_using_cache() {
+        let mut db = db_with_files(5);
+        {
+            let mut session = db.start_file_import().unwrap();
+            session
+                .upsert_file("/scans/HH001.tif", "HH001.tif")
+                .unwrap();
+            session.commit().unwrap();
+        }
+        let searcher = Searcher::new();
+
+        let strict = searcher
+            .search_single_id("HH001", &db, 0.8, None, None)
+            .unwrap();
+        assert_eq!(strict.len(), 1);
+        searcher
+            .store_results("HH001", 0.8, &strict, &mut db)
+            .unwrap();
+
+        // Same or stricter threshold: the cached run is complete and is reused
+        let cached = db
+            .search_single_id("HH001", 0.9, None, ResultOrder::FileName)
+            .unwrap();
+        assert_eq!(cached.len(), 1);
+        assert_eq!(cached[0].threshold, Some(0.8));
+        assert_eq!(cached[0].engine.as_deref(), Some(SEARCH_ENGINE_LABEL));
+
+        // Looser threshold: the narrower cached set must not shadow a recompute
+        assert!(db
+            .search_single_id("HH001", 0.3, None, ResultOrder::FileName)
+            .unwrap()
+            .is_empty());
+        let loose = searcher
+            .search_single_id("HH001", &db, 0.3, None, None)
+            .unwrap();
+        assert!(loose.len() > strict.len());
+    }
+
+    type ProgressLog = Arc<Mutex<Vec<(SearchPhase, usize, usize)>>>;
+
+    fn record_progress() -> (SearchProgressCallback, ProgressLog) {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&calls);
+        let callback: SearchProgressCallback = Arc::new(Mutex::new(move |phase, done, total| {
+            sink.lock().unwrap().push((phase, done, total));
+        }));
+        (callback, calls)
+    }
+
+    #[test]
+    fn search_progress_reaches_total() {
+        let db = db_with_files(250);
+        let (callback, calls) = record_progress();
+
+        let results = Searcher::new()
+            .search_single_id("HH001", &db, 0.3, None, Some(callback))
+            .expect("search succeeds");
+        assert!(!results.is_empty());
+
+        // Every name is long enough at 0.3, so all 250 files are scored
+        let calls = calls.lock().unwrap();
+        let scoring: Vec<_> = calls
+            .iter()
+            .filter(|(phase, ..)| *phase == SearchPhase::Scoring)
+            .collect();
+        assert_eq!(scoring.first(), Some(&&(SearchPhase::Scoring, 0, 250)));
+        assert!(scoring.iter().all(|&&(_, _, total)| total == 250));
+        assert_eq!(scoring.iter().map(|&&(_, done, _)| done).max(), Some(250));
+    }
+
+    #[test]
+    fn progress_phases_come_in_order_with_candidate_totals() {
+        let mut db = db_with_files(250);
+        {
+            let mut session = db.start_file_import().unwrap();
+            session
+                .upsert_file("/scans/HH001.tif", "HH001.tif")
+                .unwrap();
+            session.commit().unwrap();
+        }
+        let (callback, calls) = record_progress();
+
+        // "hh001_scan" is too long to reach 0.6 against "hh001"; "hh001" isn't
+        let results = Searcher::new()
+            .search_single_id("HH001", &db, 0.6, None, Some(callback))
+            .expect("search succeeds");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].file_name, "HH001.tif");
+
+        let calls = calls.lock().unwrap();
+        let phases: Vec<SearchPhase> = calls.iter().map(|&(phase, ..)| phase).collect();
+        assert!(phases.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(calls.first(), Some(&(SearchPhase::Prefiltering, 0, 251)));
+        assert!(calls.contains(&(SearchPhase::Prefiltering, 251, 251)));
+        assert_eq!(calls.last(), Some(&(SearchPhase::Scoring, 1, 1)));
+        assert!(calls
+            .iter()
+            .filter(|(phase, ..)| *phase == SearchPhase::Scoring)
+            .all(|&(_, _, total)| total == 1));
+    }
+
+    #[test]
+    fn blocked_files_are_never_returned() {
+        let mut db = db_with_files(5);
+        let searcher = Searcher::new();
+        let before = searcher
+            .search_single_id("HH001", &db, 0.3, None, None)
+            .unwrap();
+        assert!(before
+            .iter()
+            .any(|r| r.file_path == "/scans/HH001_scan.tif"));
+
+        db.block_files(&["/scans/HH001_scan.tif"]).unwrap();
+        let after = searcher
+            .search_single_id("HH001", &db, 0.3, None, None)
+            .unwrap();
+        assert_eq!(after.len(), before.len() - 1);
+        assert!(after.iter().all(|r| r.file_path != "/scans/HH001_scan.tif"));
+    }
+
     #[test]
     fn longer_candidates_get_penalized() {
         let matcher = SkimMatcherV2::default();
@@ -0,0 +1,344 @@
+use log::{info, warn};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+use tiff_locator::controller::{Controller, SearchRequest};
+use tiff_locator::database::ResultOrder;
+
+/// Address `serve` listens on unless `--bind` is given; local only, so the
+/// cache isn't exposed to the network by accident.
+pub const DEFAULT_BIND: &str = "127.0.0.1:8080";
+
+/// Threshold used when a request leaves out `threshold`.
+const DEFAULT_THRESHOLD: f64 = 0.7;
+
+/// Longest request head read; anything larger is rejected.
+const MAX_HEAD_BYTES: usize = 16 * 1024;
+
+/// Slow or idle clients are dropped after this long.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Connections handled at once; others wait to be accepted.
+const WORKERS: usize = 4;
+
+/// Status code and JSON body of one response.
+#[derive(Debug, PartialEq)]
+struct Response {
+    status: u16,
+    body: Value,
+}
+
+impl Response {
+    fn ok(body: Value) -> Self {
+        Response { status: 200, body }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Response {
+            status,
+            body: json!({ "error": message.into() }),
+        }
+    }
+}
+
+/// Answer read-only queries against the cache until the process is stopped.
+/// `WORKERS` threads take turns accepting and answering connections.
+pub fn serve(controller: &Controller, bind: &str) -> Result<(), String> {
+    let listener =
+        TcpListener::bind(bind).map_err(|e| format!("Failed to listen on {}: {}", bind, e))?;
+    let address = listener
+        .local_addr()
+        .map_err(|e| format!("Failed to read listening address: {}", e))?;
+    if !address.ip().is_loopback() {
+        warn!(
+            "Serving on {}, which is reachable from other machines",
+            address
+        );
+    }
+    info!("Serving {} on http://{}", controller.cache_path(), address);
+    println!(
+        "Listening on http://{} (GET /search?id=HH001&threshold=0.8); Ctrl+C to stop",
+        address
+    );
+
+    let workers = (0..WORKERS)
+        .map(|_| {
+            let listener = listener
+                .try_clone()
+                .map_err(|e| format!("Failed to share the listener: {}", e))?;
+            let controller = controller.clone();
+            Ok(thread::spawn(move || {
+                accept_connections(&listener, &controller)
+            }))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    for worker in workers {
+        let _ = worker.join();
+    }
+    Ok(())
+}
+
+fn accept_connections(listener: &TcpListener, controller: &Controller) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Failed to accept connection: {}", e);
+                continue;
+            }
+        };
+        if let Err(e) = handle_connection(stream, controller) {
+            warn!("HTTP connection error: {}", e);
+        }
+    }
+}
+
+fn handle_connection(stream: TcpStream, controller: &Controller) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let peer = stream.peer_addr()?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let response = match read_request_line(&mut reader)? {
+        Some((method, target)) => {
+            let response = route(controller, &method, &target);
+            info!("{} {} {} -> {}", peer, method, target, response.status);
+            response
+        }
+        None => Response::error(400, "Malformed request"),
+    };
+    write_response(stream, &response)
+}
+
+/// Method and target of the request, with the headers read and dropped (no
+/// endpoint takes a body). `None` when the head is malformed or too large;
+/// at most `MAX_HEAD_BYTES` are read either way.
+fn read_request_line<R: BufRead>(reader: &mut R) -> std::io::Result<Option<(String, String)>> {
+    let mut head = reader.by_ref().take(MAX_HEAD_BYTES as u64);
+    let mut request_line = String::new();
+    head.read_line(&mut request_line)?;
+    loop {
+        let mut header = String::new();
+        if head.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+            break;
+        }
+    }
+    // The limit ran out before the blank line ending the head
+    if head.limit() == 0 {
+        return Ok(None);
+    }
+    let mut parts = request_line.split_whitespace();
+    Ok(match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version)) if version.starts_with("HTTP/") => {
+            Some((method.to_string(), target.to_string()))
+        }
+        _ => None,
+    })
+}
+
+fn write_response(mut stream: TcpStream, response: &Response) -> std::io::Result<()> {
+    let body = response.body.to_string();
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        response.status,
+        reason_phrase(response.status),
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Dispatch one request. Only GET is served, so nothing can change the cache.
+fn route(controller: &Controller, method: &str, target: &str) -> Response {
+    if method != "GET" {
+        return Response::error(405, format!("{} is not supported; use GET", method));
+    }
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    match path {
+        "/search" => search(controller, query),
+        _ => Response::error(404, format!("No endpoint at {}", path)),
+    }
+}
+
+/// `GET /search?id=HH001&threshold=0.8`: matches for one household ID at or
+/// above the threshold, as served by the GUI's search.
+fn search(controller: &Controller, query: &str) -> Response {
+    let params = parse_query(query);
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.trim())
+    };
+
+    let Some(hh_id) = param("id").filter(|id| !id.is_empty()) else {
+        return Response::error(400, "Missing required parameter: id");
+    };
+    let threshold = match param("threshold") {
+        None => DEFAULT_THRESHOLD,
+        Some(value) => match value.parse::<f64>() {
+            Ok(threshold) if (0.0..=1.0).contains(&threshold) => threshold,
+            _ => {
+                return Response::error(
+                    400,
+                    format!("Invalid threshold '{}': expected 0.0-1.0", value),
+                )
+            }
+        },
+    };
+
+    let request = SearchRequest {
+        hh_id: hh_id.to_string(),
+        min_similarity: threshold,
+        max_similarity: 1.0,
+        include_path_components: false,
+        case_sensitive: false,
+        page_size: None,
+        order: ResultOrder::default(),
+    };
+    match controller.search_read_only(&request) {
+        Ok(outcome) => Response::ok(json!({
+            "id": request.hh_id,
+            "threshold": threshold,
+            "total": outcome.total,
+            "from_cache": outcome.from_cache,
+            "results": outcome.results,
+        })),
+        Err(e) => Response::error(500, e),
+    }
+}
+
+/// Decoded `key=value` pairs of a query string, in order.
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect()
+}
+
+/// Undo URL encoding: `+` is a space and `%XX` a byte. Malformed escapes are
+/// kept as written.
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| bytes.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (_, Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', None) => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, None) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use tiff_locator::database::Database;
+
+    #[test]
+    fn query_strings_are_decoded() {
+        assert_eq!(
+            parse_query("id=HH%20001&threshold=0.8&&flag"),
+            [
+                ("id".to_string(), "HH 001".to_string()),
+                ("threshold".to_string(), "0.8".to_string()),
+                ("flag".to_string(), String::new()),
+            ]
+        );
+        assert_eq!(percent_decode("a+b%2Fc"), "a b/c");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+    }
+
+    #[test]
+    fn request_heads_are_parsed() {
+        let mut head = Cursor::new("GET /search?id=HH001 HTTP/1.1\r\nHost: x\r\n\r\n");
+        assert_eq!(
+            read_request_line(&mut head).unwrap(),
+            Some(("GET".to_string(), "/search?id=HH001".to_string()))
+        );
+        let mut garbage = Cursor::new("hello\r\n\r\n");
+        assert_eq!(read_request_line(&mut garbage).unwrap(), None);
+        let mut huge = Cursor::new(format!(
+            "GET / HTTP/1.1\r\nX: {}\r\n\r\n",
+            "a".repeat(MAX_HEAD_BYTES)
+        ));
+        assert_eq!(read_request_line(&mut huge).unwrap(), None);
+        // A line that never ends is cut off instead of read into memory
+        let mut endless = BufReader::new(std::io::repeat(b'a'));
+        assert_eq!(read_request_line(&mut endless).unwrap(), None);
+    }
+
+    #[test]
+    fn search_answers_with_json_without_caching() {
+        let db_path =
+            std::env::temp_dir().join(format!("tiff_locator_server_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&db_path);
+        let db_path = db_path.to_str().unwrap();
+        let controller = Controller::new(db_path);
+        let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/test_data/tiff_files");
+        controller
+            .scan(fixtures, None::<fn(usize, usize)>)
+            .expect("scan");
+
+        let response = route(&controller, "GET", "/search?id=HH001&threshold=0.2");
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body["id"], "HH001");
+        let results = response.body["results"].as_array().unwrap();
+        assert!(!results.is_empty());
+        assert_eq!(response.body["total"], results.len());
+        assert!(results
+            .iter()
+            .all(|result| result["similarity_score"].as_f64().unwrap() >= 0.2));
+        // Read-only: the fresh results were not stored as matches
+        assert_eq!(
+            Database::new(db_path).unwrap().get_match_count().unwrap(),
+            0
+        );
+
+        assert_eq!(route(&controller, "GET", "/search").status, 400);
+        assert_eq!(
+            route(&controller, "GET", "/search?id=HH001&threshold=2").status,
+            400
+        );
+        assert_eq!(route(&controller, "GET", "/matches").status, 404);
+        assert_eq!(route(&controller, "DELETE", "/search?id=HH001").status, 405);
+
+        let _ = std::fs::remove_file(db_path);
+    }
+}
@@ -0,0 +1,264 @@
+use chrono::{DateTime, Local};
+use log::{Level, Log, Metadata, Record};
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Entries kept by a [`SessionLog`]; older ones are dropped first.
+pub const SESSION_LOG_CAPACITY: usize = 200;
+
+/// Log target for records that are already in the session log, so the
+/// logger writes them to stderr and the file without copying them back.
+pub const SESSION_TARGET: &str = "tiff_locator::session";
+
+const LOG_FILE_NAME: &str = "tiff_locator.log";
+/// Size at which the log file is rotated to `tiff_locator.log.1`.
+const MAX_LOG_FILE_BYTES: u64 = 5 * 1024 * 1024;
+/// Rotated files kept next to the current one; the oldest is deleted.
+const KEPT_LOG_FILES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub time: DateTime<Local>,
+    pub level: LogLevel,
+    pub message: String,
+}
+
+/// Bounded history of what happened during this session, shared between the
+/// GUI and the global logger.
+#[derive(Clone, Default)]
+pub struct SessionLog {
+    entries: Arc<Mutex<VecDeque<LogEntry>>>,
+}
+
+impl SessionLog {
+    pub fn push(&self, level: LogLevel, message: impl Into<String>) {
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+        if entries.len() == SESSION_LOG_CAPACITY {
+            entries.pop_front();
+        }
+        entries.push_back(LogEntry {
+            time: Local::now(),
+            level,
+            message: message.into(),
+        });
+    }
+
+    /// Entries oldest first.
+    pub fn entries(&self) -> Vec<LogEntry> {
+        self.entries
+            .lock()
+            .map(|entries| entries.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.clear();
+        }
+    }
+}
+
+/// Directory holding the log files: `TIFF_LOG_DIR` when set, otherwise the
+/// platform's local data directory (e.g. `~/.local/share/tifflocator/logs`).
+pub fn log_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("TIFF_LOG_DIR").filter(|dir| !dir.is_empty()) {
+        return Some(PathBuf::from(dir));
+    }
+    directories::ProjectDirs::from("", "", "TiffLocator")
+        .map(|dirs| dirs.data_local_dir().join("logs"))
+}
+
+/// Logs through `env_logger` as before, appends the same records to a
+/// rotating file under [`log_dir`], and copies warnings and errors into
+/// `session` so problems reported by the engines show up in the GUI.
+///
+/// Returns the log file path, or `None` when it could not be opened (the
+/// reason is logged to stderr and the session).
+pub fn init_logger(session: SessionLog) -> Option<PathBuf> {
+    let inner = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
+        .format_timestamp_millis()
+        .build();
+    let file = match log_dir() {
+        Some(dir) => RotatingFile::open(&dir, MAX_LOG_FILE_BYTES)
+            .map_err(|e| format!("Could not open log file in '{}': {}", dir.display(), e)),
+        None => Err("No log directory available on this platform".to_string()),
+    };
+    let (file, file_error) = match file {
+        Ok(file) => (Some(file), None),
+        Err(error) => (None, Some(error)),
+    };
+    let path = file.as_ref().map(|file| file.path.clone());
+    log::set_max_level(inner.filter());
+    let _ = log::set_boxed_logger(Box::new(TeeLogger {
+        inner,
+        session,
+        file: file.map(Mutex::new),
+    }));
+    if let Some(error) = file_error {
+        log::warn!("{}", error);
+    }
+    path
+}
+
+struct TeeLogger {
+    inner: env_logger::Logger,
+    session: SessionLog,
+    file: Option<Mutex<RotatingFile>>,
+}
+
+/// Append-only log file that is renamed to `.1` (shifting older files up to
+/// [`KEPT_LOG_FILES`]) once the next line would take it past `max_bytes`.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_bytes: u64,
+}
+
+impl RotatingFile {
+    fn open(dir: &Path, max_bytes: u64) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(LOG_FILE_NAME);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            file,
+            size,
+            max_bytes,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        for index in (1..KEPT_LOG_FILES).rev() {
+            let from = rotated_path(&self.path, index);
+            if from.exists() {
+                fs::rename(&from, rotated_path(&self.path, index + 1))?;
+            }
+        }
+        fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+impl Log for TeeLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.inner.matches(record) {
+            return;
+        }
+        self.inner.log(record);
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                let line = format!(
+                    "{} {:<5} {}: {}\n",
+                    Local::now().format("%Y-%m-%dT%H:%M:%S%.3f%:z"),
+                    record.level(),
+                    record.target(),
+                    record.args()
+                );
+                // A full disk must not take the application down with it.
+                let _ = file.write_line(&line);
+            }
+        }
+        if record.target() == SESSION_TARGET {
+            return;
+        }
+        let level = match record.level() {
+            Level::Error => LogLevel::Error,
+            Level::Warn => LogLevel::Warning,
+            _ => return,
+        };
+        self.session.push(level, record.args().to_string());
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                let _ = file.file.flush();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oldest_entries_are_dropped_past_capacity() {
+        let log = SessionLog::default();
+        for i in 0..SESSION_LOG_CAPACITY + 5 {
+            log.push(LogLevel::Info, format!("entry {}", i));
+        }
+
+        let entries = log.entries();
+        assert_eq!(entries.len(), SESSION_LOG_CAPACITY);
+        assert_eq!(entries[0].message, "entry 5");
+        assert_eq!(
+            entries.last().unwrap().message,
+            format!("entry {}", SESSION_LOG_CAPACITY + 4)
+        );
+
+        log.clear();
+        assert!(log.entries().is_empty());
+    }
+
+    #[test]
+    fn log_file_rotates_and_keeps_a_bounded_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut file = RotatingFile::open(dir.path(), 20).unwrap();
+        for i in 0..6 {
+            file.write_line(&format!("line {:<9}\n", i)).unwrap();
+        }
+
+        let current = dir.path().join(LOG_FILE_NAME);
+        assert_eq!(fs::read_to_string(&current).unwrap(), "line 5        \n");
+        assert_eq!(
+            fs::read_to_string(rotated_path(&current, 1)).unwrap(),
+            "line 4        \n"
+        );
+        assert_eq!(
+            fs::read_to_string(rotated_path(&current, KEPT_LOG_FILES)).unwrap(),
+            "line 2        \n"
+        );
+        assert!(!rotated_path(&current, KEPT_LOG_FILES + 1).exists());
+    }
+}
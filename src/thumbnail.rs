@@ -0,0 +1,50 @@
+use eframe::egui::ColorImage;
+use image::ImageReader;
+
+/// Longest side of a result thumbnail, in pixels.
+pub const THUMBNAIL_SIZE: u32 = 64;
+
+/// Decode the image at `path` and shrink it to fit a `THUMBNAIL_SIZE` square,
+/// keeping its aspect ratio. Multi-page TIFFs yield their first page.
+pub fn decode_thumbnail(path: &str) -> Result<ColorImage, String> {
+    let image = ImageReader::open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path, e))?
+        .with_guessed_format()
+        .map_err(|e| format!("Failed to read {}: {}", path, e))?
+        .decode()
+        .map_err(|e| format!("Failed to decode {}: {}", path, e))?;
+
+    let rgba = image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE).to_rgba8();
+    let size = [rgba.width() as usize, rgba.height() as usize];
+    Ok(ColorImage::from_rgba_unmultiplied(size, rgba.as_raw()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageFormat, Rgb, RgbImage};
+    use std::path::Path;
+
+    #[test]
+    fn wide_tiff_is_scaled_to_fit() {
+        let path =
+            std::env::temp_dir().join(format!("tiff_locator_thumbnail_{}.tif", std::process::id()));
+        RgbImage::from_pixel(256, 128, Rgb([200, 10, 10]))
+            .save_with_format(&path, ImageFormat::Tiff)
+            .unwrap();
+
+        let thumbnail = decode_thumbnail(path.to_str().unwrap()).unwrap();
+        assert_eq!(thumbnail.size, [64, 32]);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn unreadable_files_are_errors() {
+        // The fixtures are empty placeholder files
+        let fixture =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("test_data/tiff_files/HH001_document.tif");
+        assert!(decode_thumbnail(fixture.to_str().unwrap()).is_err());
+        assert!(decode_thumbnail("/nonexistent/scan.tif").is_err());
+    }
+}
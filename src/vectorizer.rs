@@ -1,7 +1,32 @@
+use rayon::prelude::*;
 use std::borrow::Cow;
+use std::ops::Range;
 
 pub const VECTOR_SIZE: usize = 512;
 const NGRAM_LEN: usize = 3;
+/// Bump whenever `hash_bytes`, `normalize` or how n-grams are weighted change
+/// meaning, so vectors cached by an older build are re-encoded instead of
+/// reused.
+const HASH_SCHEME: u32 = 2;
+
+/// Names with more n-grams than this have their long runs weighted down,
+/// since one run would otherwise fill every slot and make the name look like
+/// every other long name.
+const LONG_NAME_NGRAMS: usize = VECTOR_SIZE;
+
+/// In a long name, an alphanumeric run with more n-grams than this counts as
+/// its own unit vector, shrunk in proportion to its length: auto-generated
+/// runs carry little that tells files apart.
+const MAX_TOKEN_NGRAMS: usize = VECTOR_SIZE / 8;
+
+/// Everything that decides how a name is encoded. Cached vectors are only
+/// valid for the scheme they were encoded with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VectorScheme {
+    pub dim: usize,
+    pub ngram_len: usize,
+    pub hash: u32,
+}
 
 #[derive(Default, Clone)]
 pub struct Vectorizer;
@@ -11,28 +36,104 @@ impl Vectorizer {
         Self
     }
 
+    pub fn scheme(&self) -> VectorScheme {
+        VectorScheme {
+            dim: VECTOR_SIZE,
+            ngram_len: NGRAM_LEN,
+            hash: HASH_SCHEME,
+        }
+    }
+
     pub fn encode(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0f32; VECTOR_SIZE];
+        self.encode_into(text, &mut vector);
+        vector
+    }
+
+    /// Encode every text into one flat buffer of `texts.len() * VECTOR_SIZE`
+    /// floats, ready to upload as a query or file buffer. Same vectors as
+    /// [`Vectorizer::encode`], written in place and in parallel.
+    pub fn encode_batch<S: AsRef<str> + Sync>(&self, texts: &[S]) -> Vec<f32> {
+        let mut data = vec![0.0f32; texts.len() * VECTOR_SIZE];
+        data.par_chunks_mut(VECTOR_SIZE)
+            .zip(texts.par_iter())
+            .for_each(|(vector, text)| self.encode_into(text.as_ref(), vector));
+        data
+    }
+
+    // `vector` is `VECTOR_SIZE` zeros on entry
+    fn encode_into(&self, text: &str, vector: &mut [f32]) {
         let normalized = normalize(text);
         if normalized.is_empty() {
-            return vec![0.0; VECTOR_SIZE];
+            return;
         }
 
-        let mut vector = vec![0.0f32; VECTOR_SIZE];
         let bytes = normalized.as_bytes();
 
+        let long_runs = if ngram_count(bytes) > LONG_NAME_NGRAMS {
+            long_runs(bytes)
+        } else {
+            Vec::new()
+        };
+
         if bytes.len() < NGRAM_LEN {
-            let idx = hash_bytes(bytes) % VECTOR_SIZE as u32;
-            vector[idx as usize] += 1.0;
+            vector[slot(bytes)] += 1.0;
         } else {
-            for window in bytes.windows(NGRAM_LEN) {
-                let idx = hash_bytes(window) % VECTOR_SIZE as u32;
-                vector[idx as usize] += 1.0;
+            // Everything outside the long runs is encoded as in a short name
+            for (start, window) in bytes.windows(NGRAM_LEN).enumerate() {
+                let end = start + NGRAM_LEN;
+                if !long_runs
+                    .iter()
+                    .any(|run| run.start <= start && end <= run.end)
+                {
+                    vector[slot(window)] += 1.0;
+                }
             }
         }
 
-        normalize_vector(&mut vector);
-        vector
+        for run in long_runs {
+            let run = &bytes[run];
+            let mut run_vector = vec![0.0f32; VECTOR_SIZE];
+            for window in run.windows(NGRAM_LEN) {
+                run_vector[slot(window)] += 1.0;
+            }
+            normalize_vector(&mut run_vector);
+            let weight = MAX_TOKEN_NGRAMS as f32 / ngram_count(run) as f32;
+            for (value, run_value) in vector.iter_mut().zip(run_vector) {
+                *value += weight * run_value;
+            }
+        }
+
+        normalize_vector(vector);
+    }
+}
+
+fn ngram_count(bytes: &[u8]) -> usize {
+    bytes.len().saturating_sub(NGRAM_LEN - 1).max(1)
+}
+
+/// Byte ranges of the alphanumeric runs with more than `MAX_TOKEN_NGRAMS`
+/// n-grams.
+fn long_runs(bytes: &[u8]) -> Vec<Range<usize>> {
+    let mut runs = Vec::new();
+    let mut start = None;
+    for (i, byte) in bytes.iter().chain([&b' ']).enumerate() {
+        match (byte.is_ascii_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(run_start)) => {
+                if ngram_count(&bytes[run_start..i]) > MAX_TOKEN_NGRAMS {
+                    runs.push(run_start..i);
+                }
+                start = None;
+            }
+            _ => {}
+        }
     }
+    runs
+}
+
+fn slot(ngram: &[u8]) -> usize {
+    (hash_bytes(ngram) % VECTOR_SIZE as u32) as usize
 }
 
 fn normalize(input: &str) -> Cow<'_, str> {
@@ -55,3 +156,74 @@ fn normalize_vector(vector: &mut [f32]) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn similarity(a: &str, b: &str) -> f32 {
+        let vectorizer = Vectorizer::new();
+        let (a, b) = (vectorizer.encode(a), vectorizer.encode(b));
+        a.iter().zip(&b).map(|(x, y)| x * y).sum()
+    }
+
+    // An auto-generated alphanumeric run, different for each seed
+    fn noise(seed: u64, len: usize) -> String {
+        const CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                CHARS[(state >> 33) as usize % CHARS.len()] as char
+            })
+            .collect()
+    }
+
+    #[test]
+    fn long_names_still_match_their_id() {
+        let short = "HH001_scan.tif";
+        let long = format!("HH001_{}_scan.tif", noise(1, 4000));
+        let other = format!("HH002_{}_scan.tif", noise(2, 4000));
+
+        // The ID scores against the long name about as well as the short one
+        let (to_short, to_long) = (similarity("HH001", short), similarity("HH001", &long));
+        assert!(to_long > 0.4, "{}", to_long);
+        assert!(
+            (to_short - to_long).abs() < 0.1,
+            "{} vs {}",
+            to_short,
+            to_long
+        );
+        assert!(similarity(short, &long) > 0.8);
+        // Long names with different IDs don't look alike just for their length
+        assert!(similarity(&long, &other) < similarity(short, &long));
+        assert!(similarity("HH001", &other) < to_long);
+    }
+
+    #[test]
+    fn batches_match_single_encodes_and_stay_fast() {
+        let names: Vec<String> = (0..5000)
+            .map(|i| match i % 3 {
+                0 => format!("HH{:05}.tif", i),
+                1 => format!("batch_{}_scan {}.TIFF", i / 7, i),
+                _ => format!("HH{:05}_{}.tif", i, noise(i, 600)),
+            })
+            .collect();
+        let vectorizer = Vectorizer::new();
+
+        let started = std::time::Instant::now();
+        let batch = vectorizer.encode_batch(&names);
+        let elapsed = started.elapsed();
+
+        assert_eq!(batch.len(), names.len() * VECTOR_SIZE);
+        for (name, vector) in names.iter().zip(batch.chunks(VECTOR_SIZE)) {
+            assert_eq!(vector, vectorizer.encode(name).as_slice(), "{}", name);
+        }
+        assert!(vectorizer.encode_batch::<&str>(&[]).is_empty());
+        // Generous even for an unoptimized build; a regression to quadratic
+        // work or per-name reallocation of the whole buffer would blow it
+        assert!(elapsed.as_secs() < 10, "5000 names took {:?}", elapsed);
+    }
+}
@@ -0,0 +1,88 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn fixture(path: &str) -> String {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join(path)
+        .to_string_lossy()
+        .to_string()
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tiff_locator_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    dir
+}
+
+fn run(db: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_tiff_locator"))
+        .arg("--headless")
+        .arg("--db")
+        .arg(db)
+        .args(args)
+        .env("RUST_LOG", "warn")
+        .output()
+        .expect("run tiff_locator")
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(
+        output.status.success(),
+        "command failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+#[test]
+fn full_pipeline_runs_headless() {
+    let dir = temp_dir("pipeline");
+    let db = dir.join("cache.db");
+
+    let scan = stdout(&run(&db, &["scan", &fixture("test_data/tiff_files")]));
+    assert!(scan.contains("Scanned 15 TIFF files"), "{}", scan);
+
+    let import = stdout(&run(&db, &["import", &fixture("sample_ids.csv")]));
+    assert!(import.contains("Imported 10 new IDs"), "{}", import);
+
+    let matched = stdout(&run(&db, &["match", "--threshold", "0.4"]));
+    assert!(matched.contains("using Cpu"), "{}", matched);
+
+    let csv_path = dir.join("matches.csv");
+    let export = stdout(&run(&db, &["export", csv_path.to_str().unwrap()]));
+    assert!(export.contains("Exported"), "{}", export);
+
+    let mut reader = csv::Reader::from_path(&csv_path).expect("exported csv");
+    let ids: Vec<String> = reader
+        .records()
+        .map(|record| record.expect("csv row")[0].to_string())
+        .collect();
+    assert!(ids.iter().any(|id| id == "ABC123"));
+    assert!(ids.iter().any(|id| id == "XYZ789"));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn match_without_scanned_files_fails() {
+    let dir = temp_dir("empty");
+    let db = dir.join("cache.db");
+    stdout(&run(&db, &["import", &fixture("sample_ids.csv")]));
+
+    let output = run(&db, &["match"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No files found"));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn bad_arguments_print_usage() {
+    let dir = temp_dir("usage");
+    let output = run(&dir.join("cache.db"), &["match", "--threshold", "2"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Usage:"));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
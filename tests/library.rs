@@ -0,0 +1,37 @@
+use std::path::Path;
+use tiff_locator::database::Database;
+use tiff_locator::matcher::Matcher;
+use tiff_locator::scanner::Scanner;
+use tiff_locator::searcher::Searcher;
+
+fn fixture(path: &str) -> String {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join(path)
+        .to_string_lossy()
+        .to_string()
+}
+
+#[test]
+fn scan_then_match_through_the_library() {
+    let db_path = std::env::temp_dir().join(format!("tiff_locator_lib_{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&db_path);
+    let mut db = Database::new(db_path.to_str().unwrap()).expect("open database");
+
+    let report = Scanner::new()
+        .scan_and_store(&fixture("test_data/tiff_files"), &mut db)
+        .expect("scan");
+    assert_eq!(report.discovered, 15);
+
+    let files = db.get_all_files().expect("files");
+    let matches = Matcher::new().match_ids(&["XYZ789".to_string()], &files, 0.4);
+    assert!(!matches.is_empty());
+    assert!(matches.iter().all(|m| m.hh_id == "XYZ789"));
+
+    let results = Searcher::new()
+        .search_single_id("ABC123", &db, 0.4, None, None)
+        .expect("search");
+    assert!(results.iter().any(|r| r.file_name == "ABC123-file.tif"));
+
+    drop(db);
+    let _ = std::fs::remove_file(&db_path);
+}
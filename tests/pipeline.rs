@@ -0,0 +1,92 @@
+use std::path::Path;
+use tempfile::TempDir;
+use tiff_locator::database::{Database, ResultOrder, SearchResult};
+use tiff_locator::match_engine::{self, MatchEngineKind};
+use tiff_locator::reference_loader::ReferenceLoader;
+use tiff_locator::scanner::Scanner;
+use tiff_locator::searcher::Searcher;
+
+const THRESHOLD: f64 = 0.5;
+
+/// Scan the fixtures into a fresh cache under `dir` and import `ids`.
+fn prepared_cache(dir: &TempDir, ids: &[&str]) -> Database {
+    let mut db = Database::new(dir.path().join("cache.db").to_str().unwrap()).expect("open cache");
+
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("test_data/tiff_files");
+    let scan = Scanner::new()
+        .scan_and_store(&fixtures.to_string_lossy(), &mut db)
+        .expect("scan fixtures");
+    assert_eq!(scan.discovered, 15);
+
+    let csv = dir.path().join("ids.csv");
+    std::fs::write(&csv, format!("hh_id\n{}\n", ids.join("\n"))).unwrap();
+    let report = ReferenceLoader::new()
+        .load_from_csv_with_progress(csv.to_str().unwrap(), &mut db, None::<fn(usize, u64, u64)>)
+        .expect("import ids");
+    assert_eq!(report.inserted, ids.len());
+
+    db
+}
+
+fn file_names(results: &[SearchResult]) -> Vec<&str> {
+    let mut names: Vec<&str> = results.iter().map(|r| r.file_name.as_str()).collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn cpu_matches_are_served_by_search_single_id() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = prepared_cache(&dir, &["ABC123", "XYZ789", "HH002"]);
+
+    let ids = db.get_all_reference_ids().unwrap();
+    let mut engine = match_engine::create_engine(MatchEngineKind::Cpu).unwrap();
+    let stored = engine
+        .match_and_store(&ids, &mut db, THRESHOLD, None)
+        .expect("match");
+    assert_eq!(stored, 2);
+
+    let abc = db
+        .search_single_id("ABC123", THRESHOLD, None, ResultOrder::FileName)
+        .unwrap();
+    assert_eq!(file_names(&abc), ["ABC123-file.tif"]);
+    assert_eq!(abc[0].engine.as_deref(), Some("cpu"));
+    assert_eq!(abc[0].threshold, Some(THRESHOLD));
+
+    let xyz = db
+        .search_single_id("XYZ789", THRESHOLD, None, ResultOrder::FileName)
+        .unwrap();
+    assert_eq!(file_names(&xyz), ["XYZ789_report.tif"]);
+
+    assert!(db
+        .search_single_id("HH002", THRESHOLD, None, ResultOrder::FileName)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn cached_search_matches_a_live_search() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut db = prepared_cache(&dir, &["ABC123"]);
+
+    let ids = db.get_all_reference_ids().unwrap();
+    let mut engine = match_engine::create_engine(MatchEngineKind::Cpu).unwrap();
+    engine
+        .match_and_store(&ids, &mut db, THRESHOLD, None)
+        .expect("match");
+
+    let cached = db
+        .search_single_id("ABC123", THRESHOLD, None, ResultOrder::FileName)
+        .unwrap();
+    let live = Searcher::new()
+        .search_single_id("ABC123", &db, THRESHOLD, None, None)
+        .unwrap();
+    assert_eq!(file_names(&cached), file_names(&live));
+    assert!((cached[0].similarity_score - live[0].similarity_score).abs() < 1e-9);
+
+    // A lower threshold than the stored run is not covered by the cache
+    assert!(db
+        .search_single_id("ABC123", 0.3, None, ResultOrder::FileName)
+        .unwrap()
+        .is_empty());
+}